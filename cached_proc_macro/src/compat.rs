@@ -0,0 +1,385 @@
+//! Which options of `#[cached]` can be combined
+//!
+//! Every rule is checked by `check` before any code is generated, and listed in the
+//! compatibility table of `docs/cached.md`, which a test keeps in sync with `RULES`.
+
+use syn::{FnArg, ReturnType, Signature};
+
+use crate::{impl_trait_binding, MacroArgs, SyncWrites};
+
+/// What the options are checked against, besides each other
+pub(crate) struct Function {
+    /// The function is async, or returns `impl Future` or `impl Stream`
+    pub(crate) async_cache: bool,
+    /// A non-async function returning `impl Future`
+    pub(crate) future: bool,
+    /// A non-async function returning `impl Stream`
+    pub(crate) stream: bool,
+    /// The function takes `self`
+    pub(crate) receiver: bool,
+}
+
+impl Function {
+    pub(crate) fn of(sig: &Signature) -> Function {
+        let (future, stream) = match &sig.output {
+            ReturnType::Type(_, ty) => (
+                impl_trait_binding(ty, "Future", "Output").is_some(),
+                impl_trait_binding(ty, "Stream", "Item").is_some(),
+            ),
+            ReturnType::Default => (false, false),
+        };
+        let async_fn = sig.asyncness.is_some();
+        Function {
+            async_cache: async_fn || future || stream,
+            future: future && !async_fn,
+            stream: stream && !async_fn,
+            receiver: matches!(sig.inputs.first(), Some(FnArg::Receiver(_))),
+        }
+    }
+
+    fn lazy(&self) -> bool {
+        self.future || self.stream
+    }
+}
+
+/// A combination of options, or of an option and the function, `#[cached]` rejects
+pub(crate) struct Rule {
+    /// The option the rule is listed under in the docs
+    #[cfg_attr(not(test), allow(dead_code))]
+    option: &'static str,
+    /// Why the combination is rejected, the message `check` panics with
+    message: &'static str,
+    broken: fn(&MacroArgs, &Function) -> bool,
+}
+
+fn lru(args: &MacroArgs) -> bool {
+    matches!(args.policy.as_deref(), None | Some("lru")) && !args.expiring
+}
+
+pub(crate) const RULES: &[Rule] = &[
+    Rule {
+        option: "key_args",
+        message: "key_args can't be combined with key or convert",
+        broken: |args, _| args.key_args.is_some() && (args.key.is_some() || args.convert.is_some()),
+    },
+    Rule {
+        option: "hash_key",
+        message: "hash_key can't be combined with key or convert",
+        broken: |args, _| args.hash_key && (args.key.is_some() || args.convert.is_some()),
+    },
+    Rule {
+        option: "key",
+        message: "key requires convert to be set",
+        broken: |args, _| args.key.is_some() && args.convert.is_none(),
+    },
+    Rule {
+        option: "convert",
+        message: "convert requires key or type to be set",
+        broken: |args, _| {
+            args.convert.is_some() && args.key.is_none() && args.cache_type.is_none()
+        },
+    },
+    Rule {
+        option: "convert",
+        message:
+            "methods require convert to key values by instance, with the instance id in the key",
+        broken: |args, function| function.receiver && args.convert.is_none(),
+    },
+    Rule {
+        option: "in_impl",
+        message: "methods and associated functions returning impl Future or impl Stream are not supported",
+        broken: |args, function| {
+            (function.receiver || args.in_impl.is_some()) && function.lazy()
+        },
+    },
+    Rule {
+        option: "file",
+        message: "file can't be combined with key or convert, include a cached::FileStamp in the key instead",
+        broken: |args, _| args.file.is_some() && (args.key.is_some() || args.convert.is_some()),
+    },
+    Rule {
+        option: "result",
+        message: "the result and option attributes are mutually exclusive",
+        broken: |args, _| args.result && args.option,
+    },
+    Rule {
+        option: "result",
+        message: "the result and option attributes are not supported for functions returning impl Stream",
+        broken: |args, function| {
+            (args.result || args.option) && args.negative_time.is_none() && function.stream
+        },
+    },
+    Rule {
+        option: "negative_time",
+        message: "negative_time requires result to be set",
+        broken: |args, _| args.negative_time.is_some() && !args.result,
+    },
+    Rule {
+        option: "negative_time",
+        message: "negative_time is not supported with wrap, with_age, with_cached_flag, with_ttl or lifespan",
+        broken: |args, _| {
+            args.negative_time.is_some()
+                && (args.wrap.is_some()
+                    || args.with_age
+                    || args.with_cached_flag
+                    || args.with_ttl
+                    || args.lifespan.is_some())
+        },
+    },
+    Rule {
+        option: "negative_time",
+        message: "negative_time requires time, or a type with per-value lifespans, to be set",
+        broken: |args, _| {
+            args.negative_time.is_some() && args.time.is_none() && args.cache_type.is_none()
+        },
+    },
+    Rule {
+        option: "wrap",
+        message: "the wrap attribute is not supported for functions returning impl Future or impl Stream",
+        broken: |args, function| args.wrap.is_some() && function.lazy(),
+    },
+    Rule {
+        option: "with_age",
+        message: "the with_age attribute is not supported for functions returning impl Future or impl Stream",
+        broken: |args, function| args.with_age && function.lazy(),
+    },
+    Rule {
+        option: "with_cached_flag",
+        message: "with_cached_flag is redundant with with_age, which also returns the flag",
+        broken: |args, _| args.with_cached_flag && args.with_age,
+    },
+    Rule {
+        option: "with_cached_flag",
+        message: "the with_cached_flag attribute is not supported for functions returning impl Future or impl Stream",
+        broken: |args, function| args.with_cached_flag && function.lazy(),
+    },
+    Rule {
+        option: "time_source",
+        message: "time_source requires time to be set",
+        broken: |args, _| args.time_source.is_some() && args.time.is_none(),
+    },
+    Rule {
+        option: "refresh",
+        message: "refresh requires time to be set, without size",
+        broken: |args, _| args.refresh && (args.time.is_none() || args.size.is_some()),
+    },
+    Rule {
+        option: "jitter",
+        message: "jitter requires time to be set, without size",
+        broken: |args, _| args.jitter.is_some() && (args.time.is_none() || args.size.is_some()),
+    },
+    Rule {
+        option: "policy",
+        message: "policy requires size to be set, without time",
+        broken: |args, _| args.policy.is_some() && (args.size.is_none() || args.time.is_some()),
+    },
+    Rule {
+        option: "expiring",
+        message: "expiring requires size to be set, without time or policy",
+        broken: |args, _| {
+            args.expiring && (args.size.is_none() || args.time.is_some() || args.policy.is_some())
+        },
+    },
+    Rule {
+        option: "expiring",
+        message: "expiring is not supported with with_age, values must expire on their own",
+        broken: |args, _| args.expiring && args.with_age,
+    },
+    Rule {
+        option: "budget",
+        message: "budget requires weigher to also be set",
+        broken: |args, _| args.budget.is_some() && args.weigher.is_none(),
+    },
+    Rule {
+        option: "weigher",
+        message: "weigher requires budget to also be set",
+        broken: |args, _| args.weigher.is_some() && args.budget.is_none(),
+    },
+    Rule {
+        option: "type",
+        message: "cache types (unbound, size, time, budget, or type and create) are mutually exclusive",
+        broken: |args, _| {
+            let types = [
+                args.unbound,
+                args.size.is_some() || args.time.is_some(),
+                args.budget.is_some(),
+                args.cache_type.is_some() || args.cache_create.is_some(),
+            ];
+            types.iter().filter(|&&set| set).count() > 1
+        },
+    },
+    Rule {
+        option: "type",
+        message: "type requires create to also be set",
+        broken: |args, _| args.cache_type.is_some() && args.cache_create.is_none(),
+    },
+    Rule {
+        option: "create",
+        message: "create requires type to also be set",
+        broken: |args, _| args.cache_create.is_some() && args.cache_type.is_none(),
+    },
+    // only the `UnboundCache`, `SizedCache` and `TimedCache` stores take a hasher
+    Rule {
+        option: "hasher",
+        message: "hasher is not supported with size and time together, policy, expiring, budget or type",
+        broken: |args, _| {
+            args.hasher.is_some()
+                && (args.cache_type.is_some()
+                    || args.budget.is_some()
+                    || (args.size.is_some() && (args.time.is_some() || !lru(args))))
+        },
+    },
+    // only the `SizedCache` and `TimedCache` stores notify evictions
+    Rule {
+        option: "on_evict",
+        message: "on_evict requires either size or time to be set, without policy, expiring, budget or type",
+        broken: |args, _| {
+            args.on_evict.is_some()
+                && (args.cache_type.is_some()
+                    || args.budget.is_some()
+                    || !lru(args)
+                    || args.size.is_some() == args.time.is_some())
+        },
+    },
+    Rule {
+        option: "preload",
+        message: "preload is not supported with concurrent or shards",
+        broken: |args, _| args.preload.is_some() && (args.concurrent || args.shards.is_some()),
+    },
+    Rule {
+        option: "lifespan",
+        message: "the with_ttl and lifespan attributes are mutually exclusive",
+        broken: |args, _| args.lifespan.is_some() && args.with_ttl,
+    },
+    Rule {
+        option: "lifespan",
+        message: "the lifespan attribute is not supported for functions returning impl Stream",
+        broken: |args, function| args.lifespan.is_some() && function.stream,
+    },
+    Rule {
+        option: "with_ttl",
+        message: "the with_ttl attribute is not supported for functions returning impl Future or impl Stream",
+        broken: |args, function| args.with_ttl && function.lazy(),
+    },
+    Rule {
+        option: "bypass",
+        message: "the bypass attribute is not supported for functions returning impl Future or impl Stream",
+        broken: |args, function| args.bypass.is_some() && function.lazy(),
+    },
+    Rule {
+        option: "shards",
+        message: "shards is only supported for non-async functions with the default mutex, without on_poison",
+        broken: |args, function| {
+            args.shards.is_some()
+                && (function.async_cache
+                    || !matches!(args.sync.as_deref(), None | Some("mutex"))
+                    || args.on_poison.is_some())
+        },
+    },
+    Rule {
+        option: "concurrent",
+        message: "concurrent is only supported for non-async functions, without sync or on_poison",
+        broken: |args, function| {
+            args.concurrent
+                && (function.async_cache || args.sync.is_some() || args.on_poison.is_some())
+        },
+    },
+    Rule {
+        option: "thread_local",
+        message: "thread_local is only supported for non-async functions, without sync, shards, concurrent, on_poison or sync_writes",
+        broken: |args, function| {
+            args.thread_local
+                && (function.async_cache
+                    || args.sync.is_some()
+                    || args.shards.is_some()
+                    || args.concurrent
+                    || args.on_poison.is_some()
+                    || args.sync_writes != SyncWrites::Off)
+        },
+    },
+    Rule {
+        option: "sync",
+        message: "sync = \"rwlock\", \"parking_lot\", \"parking_lot_rwlock\" and \"spin\" are not supported for async functions",
+        broken: |args, function| {
+            function.async_cache
+                && matches!(
+                    args.sync.as_deref(),
+                    Some("rwlock" | "parking_lot" | "parking_lot_rwlock" | "spin")
+                )
+        },
+    },
+    Rule {
+        option: "sync",
+        message: "sync = \"tokio\" is only supported for async functions",
+        broken: |args, function| !function.async_cache && args.sync.as_deref() == Some("tokio"),
+    },
+    Rule {
+        option: "sync_writes",
+        message: "sync_writes is not supported for functions returning impl Future or impl Stream",
+        broken: |args, function| args.sync_writes != SyncWrites::Off && function.lazy(),
+    },
+    Rule {
+        option: "sync_writes",
+        message: "sync_writes is not supported with on_poison",
+        broken: |args, _| args.sync_writes != SyncWrites::Off && args.on_poison.is_some(),
+    },
+    Rule {
+        option: "sync_writes",
+        message: "sync_writes = \"by_key\" is not supported with sync = \"spin\"",
+        broken: |args, _| {
+            args.sync_writes == SyncWrites::ByKey && args.sync.as_deref() == Some("spin")
+        },
+    },
+    Rule {
+        option: "on_poison",
+        message:
+            "on_poison is only supported for non-async functions with a mutex or rwlock cache",
+        broken: |args, function| {
+            matches!(
+                args.on_poison.as_deref(),
+                Some("recover" | "bypass" | "error")
+            ) && (function.async_cache
+                || !matches!(args.sync.as_deref(), None | Some("mutex" | "rwlock")))
+        },
+    },
+    Rule {
+        option: "on_poison",
+        message: "on_poison = \"error\" requires result to also be set",
+        broken: |args, _| args.on_poison.as_deref() == Some("error") && !args.result,
+    },
+];
+
+/// Panic with the message of the first rule the options break
+pub(crate) fn check(args: &MacroArgs, function: &Function) {
+    if let Some(rule) = RULES.iter().find(|rule| (rule.broken)(args, function)) {
+        panic!("{}", rule.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RULES;
+
+    /// The rows of the compatibility table of the docs, as `(option, rule)` pairs
+    fn documented() -> Vec<(String, String)> {
+        include_str!("../docs/cached.md")
+            .lines()
+            .skip_while(|line| *line != "## Compatibility")
+            .filter_map(|line| line.strip_prefix("| `"))
+            .map(|row| {
+                let (option, rule) = row.split_once("` | ").expect("malformed row");
+                let rule = rule.strip_suffix(" |").expect("malformed row");
+                (option.to_string(), rule.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn docs_list_every_rule() {
+        let rules = RULES
+            .iter()
+            .map(|rule| (rule.option.to_string(), rule.message.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(documented(), rules);
+    }
+}
@@ -0,0 +1,193 @@
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, parse_str, AttributeArgs, Block, Expr, FnArg, Ident, ItemFn,
+    Pat, ReturnType, Type,
+};
+
+use crate::wrap_output;
+
+#[derive(FromMeta)]
+struct IOMacroArgs {
+    #[darling(default)]
+    name: Option<String>,
+    #[darling(rename = "type")]
+    cache_type: String,
+    #[darling(rename = "create")]
+    cache_create: String,
+    map_error: String,
+    #[darling(default)]
+    convert: Option<String>,
+    #[darling(default)]
+    with_cached_flag: bool,
+    #[darling(default)]
+    create_async: bool,
+}
+
+pub(crate) fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    let args = match IOMacroArgs::from_list(&attr_args) {
+        Ok(v) => v,
+        Err(e) => {
+            return TokenStream::from(e.write_errors());
+        }
+    };
+    let input = parse_macro_input!(input as ItemFn);
+
+    let visibility = input.vis;
+    let mut signature = input.sig;
+    let body = input.block;
+
+    let fn_ident = signature.ident.clone();
+    let inputs = signature.inputs.clone();
+    let output = signature.output.clone();
+    let asyncness = signature.asyncness;
+    let (fn_generics, _, where_clause) = signature.generics.split_for_impl();
+
+    if matches!(inputs.first(), Some(FnArg::Receiver(_))) {
+        panic!("io_cached is not supported for methods");
+    }
+    if !returns_result(&output) {
+        panic!(
+            "io_cached functions must return a Result, store errors are mapped into its error type"
+        );
+    }
+
+    let input_names = inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Receiver(_) => None,
+            FnArg::Typed(pat_type) => Some(pat_type.pat.clone()),
+        })
+        .collect::<Vec<Box<Pat>>>();
+
+    // the store's type names the key type, so `convert` needs no `key`
+    let key_convert_block = match &args.convert {
+        Some(convert_str) => {
+            let key_convert_block =
+                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
+            quote! { #key_convert_block }
+        }
+        None => quote! { (#(#input_names.clone()),*) },
+    };
+    let cache_ty = parse_str::<Type>(&args.cache_type).expect("unable to parse cache type");
+    let cache_create =
+        parse_str::<Expr>(&args.cache_create).expect("unable to parse cache create block");
+    let map_error = parse_str::<Expr>(&args.map_error).expect("unable to parse map_error");
+
+    let cache_ident = match args.name {
+        Some(name) => Ident::new(&name, fn_ident.span()),
+        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
+    };
+
+    let (from_cache, from_result) = if args.with_cached_flag {
+        signature.output = wrap_output(&signature.output, true, |ty| {
+            parse_quote! { ::cached::Return<#ty> }
+        });
+        (
+            quote! { ::cached::Return::cached(result, ::std::time::Duration::from_secs(0)) },
+            quote! { ::cached::Return::new(result) },
+        )
+    } else {
+        (quote! { result }, quote! { result })
+    };
+
+    if args.create_async && asyncness.is_none() {
+        panic!("create_async is only supported for async functions");
+    }
+
+    // an async `create` is awaited on first use, or by `{name}_init_cache`
+    let (static_cache, store) = if args.create_async {
+        let init_ident = format_ident!("{}_init_cache", fn_ident);
+        (
+            quote! {
+                #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::AsyncOnce<#cache_ty>> =
+                    ::cached::once_cell::sync::Lazy::new(::cached::AsyncOnce::new);
+
+                #visibility async fn #init_ident() -> &'static #cache_ty {
+                    #cache_ident.get_or_init(|| #cache_create).await
+                }
+            },
+            quote! { #init_ident().await },
+        )
+    } else {
+        (
+            quote! {
+                #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> =
+                    ::cached::once_cell::sync::Lazy::new(|| #cache_create);
+            },
+            quote! { &*#cache_ident },
+        )
+    };
+
+    let (cached_trait, await_store, call_inner) = if asyncness.is_some() {
+        (
+            quote! { ::cached::IOCachedAsync },
+            quote! { .await },
+            quote! {
+                async fn inner #fn_generics (#inputs) #output #where_clause #body;
+                let result = inner(#(#input_names),*).await?;
+            },
+        )
+    } else {
+        (
+            quote! { ::cached::IOCached },
+            quote! {},
+            quote! {
+                fn inner #fn_generics (#inputs) #output #where_clause #body;
+                let result = inner(#(#input_names),*)?;
+            },
+        )
+    };
+
+    // caching is disabled for the whole build, the function runs its body every time but
+    // keeps the signature it has with caching, and the store static and `{name}_init_cache`
+    // stay available
+    if cfg!(feature = "disable_cache") {
+        return quote! {
+            #static_cache
+
+            #visibility #signature {
+                #call_inner
+                Ok(#from_result)
+            }
+        }
+        .into();
+    }
+
+    quote! {
+        #static_cache
+
+        #visibility #signature {
+            let key = #key_convert_block;
+            let store = #store;
+            let cached = #cached_trait::cache_get(store, &key)#await_store.map_err(#map_error)?;
+            if let Some(result) = cached {
+                return Ok(#from_cache);
+            }
+
+            // run the function and cache the result, store errors are returned
+            // even though the function succeeded
+            #call_inner
+            #cached_trait::cache_set(store, key, result.clone())#await_store.map_err(#map_error)?;
+            Ok(#from_result)
+        }
+    }
+    .into()
+}
+
+/// Return whether a function returns a `Result`
+fn returns_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(typepath) => typepath
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
@@ -1,11 +1,16 @@
 use darling::FromMeta;
 use proc_macro::TokenStream;
-use quote::quote;
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
 use syn::{
-    parse_macro_input, parse_str, AttributeArgs, Block, FnArg, Ident, ItemFn, Pat, PathArguments,
-    ReturnType, Type,
+    parse_macro_input, parse_quote, parse_str, AttributeArgs, Block, Expr, FnArg, GenericArgument,
+    Ident, ItemFn, Pat, PathArguments, ReturnType, Signature, Type, TypeParamBound, Visibility,
 };
 
+mod compat;
+mod io_cached;
+mod once;
+
 #[derive(FromMeta)]
 struct MacroArgs {
     #[darling(default)]
@@ -13,9 +18,9 @@ struct MacroArgs {
     #[darling(default)]
     unbound: bool,
     #[darling(default)]
-    size: Option<usize>,
+    size: Option<CacheParam>,
     #[darling(default)]
-    time: Option<u64>,
+    time: Option<CacheParam>,
     #[darling(default)]
     key: Option<String>,
     #[darling(default)]
@@ -28,24 +33,146 @@ struct MacroArgs {
     cache_type: Option<String>,
     #[darling(default, rename = "create")]
     cache_create: Option<String>,
+    #[darling(default)]
+    wrap: Option<String>,
+    #[darling(default)]
+    with_ttl: bool,
+    #[darling(default)]
+    with_age: bool,
+    #[darling(default)]
+    time_source: Option<String>,
+    #[darling(default)]
+    sync: Option<String>,
+    #[darling(default)]
+    initial: Option<String>,
+    #[darling(default)]
+    on_poison: Option<String>,
+    #[darling(default)]
+    file: Option<String>,
+    #[darling(default)]
+    lifespan: Option<String>,
+    #[darling(default)]
+    negative_time: Option<u64>,
+    #[darling(default)]
+    sync_writes: SyncWrites,
+    #[darling(default)]
+    refresh: bool,
+    #[darling(default)]
+    budget: Option<usize>,
+    #[darling(default)]
+    weigher: Option<String>,
+    #[darling(default)]
+    policy: Option<String>,
+    #[darling(default)]
+    expiring: bool,
+    #[darling(default)]
+    shards: Option<usize>,
+    #[darling(default)]
+    concurrent: bool,
+    #[darling(default)]
+    key_args: Option<String>,
+    #[darling(default)]
+    with_cached_flag: bool,
+    #[darling(default)]
+    trace_key: bool,
+    #[darling(default)]
+    jitter: Option<f64>,
+    #[darling(default)]
+    on_evict: Option<String>,
+    #[darling(default)]
+    hash_key: bool,
+    #[darling(default)]
+    thread_local: bool,
+    #[darling(default)]
+    bypass: Option<String>,
+    #[darling(default)]
+    cache_if: Option<String>,
+    #[darling(default)]
+    hasher: Option<String>,
+    #[darling(default)]
+    in_impl: Option<String>,
+    #[darling(default)]
+    preload: Option<String>,
 }
 
-/// # Attributes
-/// - **Cache Name:** Use `name = "CACHE_NAME"` to specify the name for the generated cache.
-/// - **Cache Type:** The default cache type is `UnboundCache`.
-/// You specify which of the built-in cache types to use with `unbound`, `size = cache_size`, or `time = lifetime_in_seconds`
-/// - **Cache Create:** You can specify the cache creation with `create = "{ CacheType::new() }"`.
-/// - **Custom Cache Type:** You can use `type = "CacheType"` to specify the type of cache to use.
-/// This requires create to also be set.
-/// - **Cache Key:** Use `key = "KeyType"` to specify what type to use for the cache key.
-/// This requires convert to also be set.
-/// - **Cache Key Convert:** Use `convert = "{ convert_inputs_to_key }"`.
-/// This requires either key or type to also be set.
-/// - **Caching Result/Option:** If your function returns a `Result` or `Option`
-/// you may want to use `result` or `option` to only cache when the output is `Ok` or `Some`
-/// ## Note
-/// The `type`, `create`, `key`, and `convert` attributes must be in a `String`
-/// This is because darling, which is used for parsing the attributes, does not support parsing attributes into `Type` or `Block`.
+/// A `size` or `time`, either an integer literal or an expression given as a string,
+/// evaluated when the cache is created on first use
+enum CacheParam {
+    Lit(u64),
+    Expr(Box<Expr>),
+}
+
+impl CacheParam {
+    fn is_zero(&self) -> bool {
+        matches!(self, CacheParam::Lit(0))
+    }
+}
+
+impl FromMeta for CacheParam {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value.parse() {
+            Ok(n) => Ok(CacheParam::Lit(n)),
+            Err(_) => parse_str::<Expr>(value)
+                .map(|expr| CacheParam::Expr(Box::new(expr)))
+                .map_err(|_| darling::Error::unknown_value(value)),
+        }
+    }
+
+    fn from_value(value: &syn::Lit) -> darling::Result<Self> {
+        match value {
+            syn::Lit::Str(s) => Self::from_string(&s.value()),
+            syn::Lit::Int(n) => n
+                .base10_parse()
+                .map(CacheParam::Lit)
+                .map_err(|_| darling::Error::unknown_value(n.base10_digits())),
+            _ => Err(darling::Error::unexpected_lit_type(value)),
+        }
+        .map_err(|e| e.with_span(value))
+    }
+}
+
+impl quote::ToTokens for CacheParam {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        match self {
+            CacheParam::Lit(n) => proc_macro2::Literal::u64_unsuffixed(*n).to_tokens(tokens),
+            CacheParam::Expr(expr) => quote! { (#expr) }.to_tokens(tokens),
+        }
+    }
+}
+
+/// How `sync_writes` synchronizes the computation of missing values
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum SyncWrites {
+    #[default]
+    Off,
+    /// Under the cache's lock
+    Global,
+    /// Under a lock per key
+    ByKey,
+}
+
+impl FromMeta for SyncWrites {
+    fn from_word() -> darling::Result<Self> {
+        Ok(SyncWrites::Global)
+    }
+
+    fn from_bool(value: bool) -> darling::Result<Self> {
+        Ok(if value {
+            SyncWrites::Global
+        } else {
+            SyncWrites::Off
+        })
+    }
+
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "by_key" => Ok(SyncWrites::ByKey),
+            other => Err(darling::Error::unknown_value(other)),
+        }
+    }
+}
+
+#[doc = include_str!("../docs/cached.md")]
 #[proc_macro_attribute]
 pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let attr_args = parse_macro_input!(args as AttributeArgs);
@@ -57,11 +184,66 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     };
     let input = parse_macro_input!(input as ItemFn);
 
+    // reject combinations of options before generating anything
+    let function = compat::Function::of(&input.sig);
+    compat::check(&args, &function);
+
+    // functions with an async cache get an async `{name}_cache_info`
+    let async_cache = function.async_cache;
+
+    // methods and associated functions keep their cache behind an associated function,
+    // statics can't be impl items
+    let receiver = function.receiver;
+    let method = receiver || args.in_impl.is_some();
+
+    // the arguments the default key is made of, all of them besides `self` unless `key_args`
+    // picks some
+    let key_inputs = match &args.key_args {
+        Some(names) => names
+            .split(',')
+            .map(|name| {
+                let name = name.trim();
+                input
+                    .sig
+                    .inputs
+                    .iter()
+                    .find_map(|input| match input {
+                        FnArg::Typed(pat_type)
+                            if pat_name(&pat_type.pat).as_deref() == Some(name) =>
+                        {
+                            Some(pat_type.clone())
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| panic!("key_args names `{}`, which isn't an argument", name))
+            })
+            .collect::<Vec<_>>(),
+        None => input
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                FnArg::Receiver(_) => None,
+                FnArg::Typed(pat_type) => Some(pat_type.clone()),
+            })
+            .collect(),
+    };
+
+    // functions keyed by a tuple of their arguments can evict all the keys starting with
+    // their first argument
+    let prefix_ty = match (&args.key, &args.convert, key_inputs.first()) {
+        (None, None, Some(pat_type)) if !receiver && !args.hash_key && key_inputs.len() > 1 => {
+            Some(owned_key_ty(&pat_type.ty))
+        }
+        _ => None,
+    };
+
     // pull out the parts of the input
     let _attributes = input.attrs;
     let visibility = input.vis;
-    let signature = input.sig;
+    let mut signature = input.sig;
     let body = input.block;
+    let no_cache_signature = signature.clone();
 
     // pull out the parts of the function signature
     let fn_ident = signature.ident.clone();
@@ -69,27 +251,58 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let output = signature.output.clone();
     let asyncness = signature.asyncness;
 
-    // pull out the names and types of the function inputs
-    let input_tys = inputs
+    // pull out the names of the function inputs, besides `self`
+    let input_names = inputs
         .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => pat_type.ty.clone(),
+        .filter_map(|input| match input {
+            FnArg::Receiver(_) => None,
+            FnArg::Typed(pat_type) => Some(pat_type.pat.clone()),
         })
-        .collect::<Vec<Box<Type>>>();
+        .collect::<Vec<Box<Pat>>>();
 
-    let input_names = inputs
+    // and the names and types of the inputs making up the default key, which owns copies
+    // of the arguments passed by reference
+    let key_tys = key_inputs
+        .iter()
+        .map(|input| owned_key_ty(&input.ty))
+        .collect::<Vec<_>>();
+    let key_names = key_inputs
         .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => pat_type.pat.clone(),
+        .map(|input| &input.pat)
+        .collect::<Vec<_>>();
+    let key_values = key_inputs
+        .iter()
+        .map(|input| {
+            let name = &input.pat;
+            match *input.ty {
+                Type::Reference(_) => quote! { ::std::borrow::ToOwned::to_owned(&*#name) },
+                _ => quote! { #name.clone() },
+            }
         })
-        .collect::<Vec<Box<Pat>>>();
+        .collect::<Vec<_>>();
+
+    // a non-async function returning `impl Future<Output = T>` is cached like an `async fn`
+    // returning `T`: the cache stores the awaited output, never the future itself.
+    let future_output_ty = match &output {
+        ReturnType::Type(_, ty) if asyncness.is_none() => {
+            impl_trait_binding(ty, "Future", "Output")
+        }
+        _ => None,
+    };
+
+    // a non-async function returning `impl Stream<Item = T>` is collected into a `Vec<T>` on
+    // the first call, later calls replay the cached items as a new stream.
+    let stream_item_ty = match &output {
+        ReturnType::Type(_, ty) if asyncness.is_none() => impl_trait_binding(ty, "Stream", "Item"),
+        _ => None,
+    };
 
     // pull out the output type
-    let output_ty = match &output {
-        ReturnType::Default => quote! {()},
-        ReturnType::Type(_, ty) => quote! {#ty},
+    let output_ty = match (&future_output_ty, &stream_item_ty, &output) {
+        (Some(ty), _, _) => quote! {#ty},
+        (None, Some(item_ty), _) => quote! {Vec<#item_ty>},
+        (None, None, ReturnType::Default) => quote! {()},
+        (None, None, ReturnType::Type(_, ty)) => quote! {#ty},
     };
 
     // Find the type of the value to store.
@@ -98,34 +311,113 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     // Option<u32>, store u32, for Result<i32, String>, store i32, etc.
     let cache_value_ty = match (&args.result, &args.option) {
         (false, false) => output_ty,
-        (true, true) => panic!("the result and option attributes are mutually exclusive"),
-        _ => match output.clone() {
-            ReturnType::Default => {
-                panic!("function must return something for result or option attributes")
-            }
-            ReturnType::Type(_, ty) => {
-                if let Type::Path(typepath) = *ty {
-                    let segments = typepath.path.segments;
-                    if let PathArguments::AngleBracketed(brackets) =
-                        &segments.last().unwrap().arguments
-                    {
-                        let inner_ty = brackets.args.first().unwrap();
-                        quote! {#inner_ty}
-                    } else {
-                        panic!("function return type has no inner type")
-                    }
+        // errors are cached along with values
+        (true, false) if args.negative_time.is_some() => output_ty,
+        _ => {
+            let ty = match (&future_output_ty, &output) {
+                (Some(ty), _) => ty.clone(),
+                (None, ReturnType::Type(_, ty)) => *ty.clone(),
+                (None, ReturnType::Default) => {
+                    panic!("function must return something for result or option attributes")
+                }
+            };
+            if let Type::Path(typepath) = ty {
+                let segments = typepath.path.segments;
+                if let PathArguments::AngleBracketed(brackets) = &segments.last().unwrap().arguments
+                {
+                    let inner_ty = brackets.args.first().unwrap();
+                    quote! {#inner_ty}
                 } else {
-                    panic!("function return type too complex")
+                    panic!("function return type has no inner type")
                 }
+            } else {
+                panic!("function return type too complex")
             }
-        },
+        }
+    };
+
+    // `wrap = "arc"` stores and returns the values in an `Arc`
+    let wrap_arc = match args.wrap.as_deref() {
+        None => false,
+        Some("arc") => true,
+        Some(other) => panic!("unknown wrap `{}`, expected \"arc\"", other),
     };
+    let cache_value_ty = if wrap_arc {
+        signature.output = wrap_output(&signature.output, args.result || args.option, |ty| {
+            parse_quote! { ::std::sync::Arc<#ty> }
+        });
+        quote! {::std::sync::Arc<#cache_value_ty>}
+    } else {
+        cache_value_ty
+    };
+
+    // `with_age` stores the time values were computed at, to return their age, on the clock
+    // of `time_source`
+    let (stamp_ty, age) = match args.time_source.as_deref() {
+        Some("wall_clock") => (
+            quote! { ::std::time::SystemTime },
+            quote! { result.0.elapsed().unwrap_or_default() },
+        ),
+        _ => (
+            quote! { ::std::time::Instant },
+            quote! { result.0.elapsed() },
+        ),
+    };
+    let cache_value_ty = if args.with_age {
+        signature.output = wrap_output(&signature.output, args.result || args.option, |ty| {
+            parse_quote! { ::cached::Return<#ty> }
+        });
+        quote! {(#stamp_ty, #cache_value_ty)}
+    } else {
+        cache_value_ty
+    };
+
+    // `with_cached_flag` returns whether values came from the cache, storing them as is
+    if args.with_cached_flag {
+        signature.output = wrap_output(&signature.output, args.result || args.option, |ty| {
+            parse_quote! { ::cached::Return<#ty> }
+        });
+    }
 
     // make the cache identifier
     let cache_ident = match args.name {
         Some(name) => Ident::new(&name, fn_ident.span()),
         None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
     };
+    let cache_fn_ident = format_ident!("{}_cache", fn_ident);
+
+    // with the `tracing` feature, log hits, misses and how long computing a missing value took
+    // the key is only recorded with `trace_key`, so keys needn't implement `Debug` otherwise
+    let (trace_hit, trace_miss, trace_computed) = if cfg!(feature = "tracing") {
+        let cache_name = cache_ident.to_string();
+        let key_field = if args.trace_key {
+            quote! { key = ?key, }
+        } else {
+            quote! {}
+        };
+        (
+            quote! { ::cached::tracing::debug!(cache = #cache_name, #key_field "cache hit"); },
+            quote! {
+                ::cached::tracing::debug!(cache = #cache_name, #key_field "cache miss");
+                let started = ::std::time::Instant::now();
+            },
+            quote! {
+                ::cached::tracing::debug!(
+                    cache = #cache_name,
+                    #key_field
+                    elapsed = ?started.elapsed(),
+                    "computed missing value"
+                );
+            },
+        )
+    } else {
+        (quote! {}, quote! {}, quote! {})
+    };
+    let cache_ref = if method {
+        quote! { Self::#cache_fn_ident() }
+    } else {
+        quote! { #cache_ident }
+    };
 
     // make the cache key type and block that converts the inputs into the key type
     let (cache_key_ty, key_convert_block) = match (&args.key, &args.convert, &args.cache_type) {
@@ -143,12 +435,60 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
 
             (quote! {}, quote! {#key_convert_block})
         }
-        (None, None, _) => (
-            quote! {(#(#input_tys),*)},
-            quote! {(#(#input_names.clone()),*)},
+        (None, None, _) if args.hash_key => {
+            let file_stamp = args.file.as_ref().map(|file| {
+                let file = Ident::new(file, fn_ident.span());
+                quote! { ::cached::FileStamp::of(&#file).ok() }
+            });
+            (
+                quote! {u128},
+                quote! {::cached::hash_key(&(#(&#key_names,)* #file_stamp))},
+            )
+        }
+        (None, None, _) => match &args.file {
+            // key by the file's stamp as well, so edits to it miss the cache
+            Some(file) => {
+                let file = Ident::new(file, fn_ident.span());
+                (
+                    quote! {(#(#key_tys,)* Option<::cached::FileStamp>)},
+                    quote! {(#(#key_values,)* ::cached::FileStamp::of(&#file).ok())},
+                )
+            }
+            None => (quote! {(#(#key_tys),*)}, quote! {(#(#key_values),*)}),
+        },
+        // `key` without `convert`, or `convert` without `key` or `type`
+        _ => unreachable!("rejected by compat::check"),
+    };
+
+    if args.size.as_ref().is_some_and(CacheParam::is_zero) {
+        panic!("size must be greater than zero");
+    }
+
+    if args.time.as_ref().is_some_and(CacheParam::is_zero) {
+        panic!("time must be greater than zero, values would expire right away");
+    }
+
+    let weighted = match (&args.budget, &args.weigher) {
+        (Some(budget), Some(weigher_str)) => {
+            let weigher = parse_str::<Expr>(weigher_str).expect("unable to parse weigher");
+            Some((budget, weigher))
+        }
+        _ => None,
+    };
+
+    let hasher = args
+        .hasher
+        .as_ref()
+        .map(|hasher_str| parse_str::<Type>(hasher_str).expect("unable to parse hasher"));
+    let unbound = match &hasher {
+        Some(hasher) => (
+            quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty, #hasher>},
+            quote! {cached::UnboundCache::with_hasher(<#hasher as ::core::default::Default>::default())},
+        ),
+        None => (
+            quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>},
+            quote! {cached::UnboundCache::new()},
         ),
-        (Some(_), None, _) => panic!("key requires convert to be set"),
-        (None, Some(_), None) => panic!("convert requires key or type to be set"),
     };
 
     // make the cache type and create statement
@@ -159,26 +499,86 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         &args.cache_type,
         &args.cache_create,
     ) {
-        (true, None, None, None, None) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
+        (false, None, None, None, None) if weighted.is_some() => {
+            let (budget, weigher) = weighted.unwrap();
+            let cache_ty = quote! {cached::WeightedCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create = quote! {
+                cached::WeightedCache::<#cache_key_ty, #cache_value_ty>::new(#budget, #weigher)
+            };
             (cache_ty, cache_create)
         }
+        (true, None, None, None, None) => unbound,
         (false, Some(size), None, None, None) => {
-            let cache_ty = quote! {cached::SizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::SizedCache::with_size(#size)};
-            (cache_ty, cache_create)
+            let store = if args.expiring {
+                format_ident!("ExpiringValueCache")
+            } else {
+                size_policy(args.policy.as_deref().unwrap_or("lru"))
+            };
+            match &hasher {
+                Some(hasher) => (
+                    quote! {cached::#store<#cache_key_ty, #cache_value_ty, #hasher>},
+                    quote! {
+                        cached::#store::with_size_and_hasher(
+                            #size,
+                            <#hasher as ::core::default::Default>::default(),
+                        )
+                    },
+                ),
+                None => (
+                    quote! {cached::#store<#cache_key_ty, #cache_value_ty>},
+                    quote! {cached::#store::with_size(#size)},
+                ),
+            }
         }
         (false, None, Some(time), None, None) => {
-            let cache_ty = quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::TimedCache::with_lifespan(#time)};
+            let refresh = args.refresh;
+            let (cache_ty, cache_create) = match &hasher {
+                Some(hasher) => (
+                    quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty, #hasher>},
+                    quote! {
+                        cached::TimedCache::with_lifespan_and_hasher(
+                            #time,
+                            <#hasher as ::core::default::Default>::default(),
+                        )
+                        .with_refresh(#refresh)
+                    },
+                ),
+                None => (
+                    quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty>},
+                    quote! {cached::TimedCache::with_lifespan_and_refresh(#time, #refresh)},
+                ),
+            };
+            let cache_create = match args.time_source.as_deref() {
+                None => cache_create,
+                Some(source) => {
+                    let source = time_source(source);
+                    quote! {
+                        #cache_create.with_time_source(cached::TimeSource::#source)
+                    }
+                }
+            };
+            let cache_create = match args.jitter {
+                None => cache_create,
+                Some(jitter) => quote! { #cache_create.with_jitter(#jitter) },
+            };
             (cache_ty, cache_create)
         }
-        (false, None, None, None, None) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
+        (false, Some(size), Some(time), None, None) => {
+            let cache_ty = quote! {cached::TimedSizedCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create =
+                quote! {cached::TimedSizedCache::with_size_and_lifespan(#size, #time)};
+            let cache_create = match args.time_source.as_deref() {
+                None => cache_create,
+                Some(source) => {
+                    let source = time_source(source);
+                    quote! {
+                        #cache_create.with_time_source(cached::TimeSource::#source)
+                    }
+                }
+            };
             (cache_ty, cache_create)
         }
+        (false, None, None, None, None) => unbound,
         (false, None, None, Some(type_str), Some(create_str)) => {
             let cache_type = parse_str::<Type>(type_str).expect("unable to parse cache type");
 
@@ -187,89 +587,1308 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
 
             (quote! { #cache_type }, quote! { #cache_create })
         }
-        (false, None, None, Some(_), None) => panic!("type requires create to also be set"),
-        (false, None, None, None, Some(_)) => panic!("create requires type to also be set"),
-        _ => panic!("cache types (unbound, size, time, or type and create) are mutually exclusive"),
+        // several cache types, or `type` and `create` without the other
+        _ => unreachable!("rejected by compat::check"),
+    };
+
+    // the cache is a single static, it can't depend on the type parameters of a generic function
+    for param in signature.generics.type_params() {
+        if uses_ident(cache_ty.clone(), &param.ident) {
+            panic!(
+                "the cache of a generic function can't use its type parameter `{}`, \
+                 use key and convert to key values by a concrete type",
+                param.ident
+            );
+        }
+    }
+    // nor on its lifetimes, e.g. those of a reference nested in an argument
+    for param in signature.generics.lifetimes() {
+        if uses_ident(cache_ty.clone(), &param.lifetime.ident) {
+            panic!(
+                "the cache of a function can't use its lifetime `{}`, \
+                 use key and convert to key values by an owned type",
+                param.lifetime
+            );
+        }
+    }
+    let (fn_generics, _, where_clause) = signature.generics.split_for_impl();
+
+    // a snapshot left by a previous run replaces the new store
+    let cache_create = match &args.preload {
+        Some(path) => quote! {
+            ::cached::load_or_else(#path, || -> #cache_ty { #cache_create })
+        },
+        None => cache_create,
+    };
+
+    let cache_create = match &args.on_evict {
+        Some(on_evict_str) => {
+            let on_evict = parse_str::<Expr>(on_evict_str).expect("unable to parse on_evict");
+            // the annotation lets the closure's argument types be inferred
+            quote! {{
+                let cache: #cache_ty = #cache_create;
+                cache.on_evict(#on_evict)
+            }}
+        }
+        None => cache_create,
+    };
+
+    // seed the cache with the initial entries
+    let cache_create = match &args.initial {
+        Some(initial_str) => {
+            let initial = parse_str::<Expr>(initial_str).expect("unable to parse initial entries");
+            quote! {{
+                let mut cache = #cache_create;
+                for (key, value) in #initial {
+                    ::cached::Cached::cache_set(&mut cache, key, value);
+                }
+                cache
+            }}
+        }
+        None => cache_create,
+    };
+
+    // the cache is held in a static, which can't name `Self`
+    let (cache_ty, cache_create, cache_key_ty) = match &args.in_impl {
+        Some(self_str) => {
+            let self_ty = parse_str::<Type>(self_str).expect("unable to parse in_impl type");
+            let self_ty = quote! { #self_ty };
+            (
+                replace_self(cache_ty, &self_ty),
+                replace_self(cache_create, &self_ty),
+                replace_self(cache_key_ty, &self_ty),
+            )
+        }
+        None => (cache_ty, cache_create, cache_key_ty),
+    };
+
+    // wrap the function's output before it's cached and returned
+    let wrap_block = match (wrap_arc, &args.result, &args.option) {
+        (false, _, _) => quote! {},
+        (true, false, false) => quote! { let result = ::std::sync::Arc::new(result); },
+        (true, _, _) => quote! { let result = result.map(::std::sync::Arc::new); },
+    };
+    let wrap_block = match (
+        args.with_age || args.with_cached_flag,
+        &args.result,
+        &args.option,
+    ) {
+        (false, _, _) => wrap_block,
+        (true, false, false) => quote! {
+            #wrap_block
+            let result = ::cached::Return::new(result);
+        },
+        (true, _, _) => quote! {
+            #wrap_block
+            let result = result.map(::cached::Return::new);
+        },
+    };
+
+    // convert between the returned `result` and the value kept in the cache
+    let (to_cache, from_cache) = if args.with_age {
+        (
+            quote! { (#stamp_ty::now(), result.value.clone()) },
+            quote! { ::cached::Return::cached(result.1.clone(), #age) },
+        )
+    } else if args.with_cached_flag {
+        (
+            quote! { result.value.clone() },
+            quote! { ::cached::Return::cached(result.clone(), ::std::time::Duration::from_secs(0)) },
+        )
+    } else {
+        (quote! { result.clone() }, quote! { result.clone() })
     };
 
-    // make the set cache and return cache blocks
-    let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
-        (false, false) => {
-            let set_cache_block = quote! { cache.cache_set(key, result.clone()); };
-            let return_cache_block = quote! { return result.clone(); };
-            (set_cache_block, return_cache_block)
+    // `with_ttl` calls pass the lifespan of the value they cache in `ttl`,
+    // `lifespan` computes it from the value
+    let cache_set = if let Some(lifespan_str) = &args.lifespan {
+        let lifespan = parse_str::<Expr>(lifespan_str).expect("unable to parse lifespan");
+        quote! {{
+            let ttl: u64 = #lifespan;
+            cache.cache_set_with_lifespan(key, #to_cache, ttl)
+        }}
+    } else if args.with_ttl {
+        quote! {
+            match ttl {
+                Some(ttl) => {
+                    cache.cache_set_with_lifespan(key, #to_cache, ttl);
+                }
+                None => {
+                    cache.cache_insert_if_absent(key, #to_cache);
+                }
+            }
         }
-        (true, false) => {
-            let set_cache_block = quote! {
+    } else {
+        quote! { cache.cache_insert_if_absent(key, #to_cache) }
+    };
+
+    // make the set cache and return cache blocks, `set_block` stores `result` with `cache_set`
+    let (result_attr, option_attr, negative_time) = (args.result, args.option, args.negative_time);
+    // `cache_if` values failing the predicate aren't cached
+    let cache_if = args
+        .cache_if
+        .as_ref()
+        .map(|cache_if_str| parse_str::<Expr>(cache_if_str).expect("unable to parse cache_if"));
+    // `cache_set` if `value`, a reference to the value to cache, passes the predicate. The
+    // helper's bound tells closures the type of their argument.
+    let cache_if_set = |cache_set: &TokenStream2, value: TokenStream2| match &cache_if {
+        Some(cache_if) => quote! {
+            fn cache_if<T: ?Sized>(value: &T, f: impl FnOnce(&T) -> bool) -> bool {
+                f(value)
+            }
+            if cache_if(#value, #cache_if) {
+                #cache_set;
+            }
+        },
+        None => quote! { #cache_set; },
+    };
+    let set_block = |cache_set: &TokenStream2| {
+        // `result` is a reference to the `Ok`/`Some` value with `result`/`option`
+        let (owned_set, ref_set) = (
+            cache_if_set(cache_set, quote! { &result }),
+            cache_if_set(cache_set, quote! { result }),
+        );
+        match (result_attr, option_attr, negative_time) {
+            (false, false, _) => owned_set,
+            (true, false, Some(negative_time)) => {
+                quote! {
+                    match &result {
+                        Ok(_) => {
+                            #owned_set
+                        }
+                        Err(_) => {
+                            cache.cache_set_with_lifespan(key, result.clone(), #negative_time);
+                        }
+                    }
+                }
+            }
+            (true, false, None) => quote! {
                 if let Ok(result) = &result {
-                    cache.cache_set(key, result.clone());
+                    #ref_set
+                }
+            },
+            (false, true, _) => quote! {
+                if let Some(result) = &result {
+                    #ref_set
+                }
+            },
+            (true, true, _) => unreachable!("rejected by compat::check"),
+        }
+    };
+    let set_cache_block = set_block(&cache_set);
+    let return_cache_block = match (&args.result, &args.option) {
+        (true, false) if args.negative_time.is_none() => quote! { return Ok(#from_cache); },
+        (false, true) => quote! { return Some(#from_cache); },
+        _ => quote! { return #from_cache; },
+    };
+    let return_cache_block = quote! {
+        #trace_hit
+        #return_cache_block
+    };
+    // priming replaces the cached value
+    let prime_set_block = if args.lifespan.is_some() {
+        set_block(&cache_set)
+    } else {
+        set_block(&quote! { cache.cache_set(key, #to_cache) })
+    };
+    let set_only_block = set_cache_block.clone();
+
+    // `bypass` calls skip the lookup, and replace the cached value with the one they computed
+    let bypass = args.bypass.as_ref().map(|bypass_str| {
+        let bypass = parse_str::<Expr>(bypass_str).expect("unable to parse bypass");
+        quote! { let bypass: bool = #bypass; }
+    });
+    let lookup = match &bypass {
+        Some(_) => quote! { if bypass { None } else { cache.cache_get(&key) } },
+        None => quote! { cache.cache_get(&key) },
+    };
+    let set_only_block = match &bypass {
+        Some(_) => {
+            let replace_set = if args.lifespan.is_some() {
+                cache_set.clone()
+            } else if args.with_ttl {
+                quote! {
+                    match ttl {
+                        Some(ttl) => cache.cache_set_with_lifespan(key, #to_cache, ttl),
+                        None => cache.cache_set(key, #to_cache),
+                    }
                 }
+            } else {
+                quote! { cache.cache_set(key, #to_cache) }
             };
-            let return_cache_block = quote! { return Ok(result.clone()); };
-            (set_cache_block, return_cache_block)
+            let replace_block = set_block(&replace_set);
+            quote! {
+                if bypass {
+                    #replace_block
+                } else {
+                    #set_only_block
+                }
+            }
         }
-        (false, true) => {
-            let set_cache_block = quote! {
-                if let Some(result) = &result {
-                    cache.cache_set(key, result.clone());
+        None => set_only_block,
+    };
+    let set_cache_block = quote! {
+        #wrap_block
+        #set_only_block
+    };
+
+    // a sharded cache locks the shard of the key instead of the whole cache
+    let sharded = args.shards.is_some() || args.concurrent;
+
+    // a thread-local cache is borrowed for `body`, which uses it as `cache`
+    let with_local = |body: TokenStream2| {
+        quote! {
+            #cache_ref.with(|cache| {
+                let mut cache = cache.borrow_mut();
+                #body
+            })
+        }
+    };
+    let lock_ref = if sharded {
+        quote! { #cache_ref.shard(&key) }
+    } else {
+        cache_ref.clone()
+    };
+    let shard_lock = if args.concurrent {
+        quote! { shard.write().unwrap() }
+    } else {
+        quote! { shard.lock().unwrap() }
+    };
+
+    // make the lock guarding the cache, and the expressions locking it for writing and reading
+    let (lock_ty, lock, read_lock) = match (args.sync.as_deref(), async_cache) {
+        (None, false) | (Some("mutex"), false) => (
+            quote! { ::std::sync::Mutex },
+            quote! { #lock_ref.lock().unwrap() },
+            None,
+        ),
+        (Some("rwlock"), false) => (
+            quote! { ::std::sync::RwLock },
+            quote! { #cache_ref.write().unwrap() },
+            Some(quote! { #cache_ref.read().unwrap() }),
+        ),
+        (Some("parking_lot"), false) => (
+            quote! { ::cached::parking_lot::Mutex },
+            quote! { #cache_ref.lock() },
+            None,
+        ),
+        (Some("parking_lot_rwlock"), false) => (
+            quote! { ::cached::parking_lot::RwLock },
+            quote! { #cache_ref.write() },
+            Some(quote! { #cache_ref.read() }),
+        ),
+        (Some("spin"), false) => (
+            quote! { ::cached::spin::Mutex },
+            quote! { #cache_ref.lock() },
+            None,
+        ),
+        (None, true) | (Some("mutex"), true) => (
+            quote! { ::cached::async_mutex::Mutex },
+            quote! { #cache_ref.lock().await },
+            None,
+        ),
+        (Some("tokio"), true) => (
+            quote! { ::cached::tokio::sync::Mutex },
+            quote! { #cache_ref.lock().await },
+            None,
+        ),
+        // locks rejected by `compat::check` for the function are known as well
+        (Some(other), _) => panic!(
+            "unknown sync `{}`, expected \"mutex\", \"rwlock\", \"parking_lot\", \"parking_lot_rwlock\", \"spin\" or \"tokio\"",
+            other
+        ),
+    };
+
+    // a concurrent cache serves hits under the read lock of their shard
+    let (lock, read_lock) = if args.concurrent {
+        (
+            quote! { #lock_ref.write().unwrap() },
+            Some(quote! { #lock_ref.read().unwrap() }),
+        )
+    } else {
+        (lock, read_lock)
+    };
+
+    // std locks are poisoned by panics, see `on_poison`
+    let on_poison = args.on_poison.as_deref().unwrap_or("panic");
+    let try_lock = match &read_lock {
+        Some(_) => quote! { #cache_ref.write() },
+        None => quote! { #cache_ref.lock() },
+    };
+    match on_poison {
+        "panic" | "recover" | "bypass" | "error" => (),
+        other => panic!(
+            "unknown on_poison `{}`, expected \"panic\", \"recover\", \"bypass\" or \"error\"",
+            other
+        ),
+    }
+
+    // lock the cache for `body`, which uses it as `cache`, handling a poisoned lock with
+    // `on_poison`. Only the lookup of `check` blocks returns errors.
+    let with_lock = |body: TokenStream2, check: bool| match on_poison {
+        "recover" => quote! {
+            {
+                let mut cache = match #try_lock {
+                    Ok(cache) => cache,
+                    Err(poisoned) => {
+                        let mut cache = poisoned.into_inner();
+                        cache.cache_clear();
+                        #cache_ref.clear_poison();
+                        cache
+                    }
+                };
+                #body
+            }
+        },
+        "bypass" => quote! {
+            if let Ok(mut cache) = #try_lock {
+                #body
+            }
+        },
+        "error" if check => quote! {
+            match #try_lock {
+                Ok(mut cache) => {
+                    #body
+                }
+                Err(_) => return Err(::cached::LockPoisoned.into()),
+            }
+        },
+        "error" => quote! {
+            if let Ok(mut cache) = #try_lock {
+                #body
+            }
+        },
+        _ => quote! {
+            {
+                let mut cache = #lock;
+                #body
+            }
+        },
+    };
+
+    // a read lock serves hits without updating the store, leaving misses to the write lock.
+    // Hits of `refresh` caches restart the lifespan of their value, and those of caches bound
+    // by `size` or `budget` update the recency, frequency or visited bit evicting by, so they
+    // take the write lock. So do `type` stores, which can't be told apart from those
+    let read_hits = read_lock.is_some()
+        && !args.refresh
+        && args.size.is_none()
+        && args.budget.is_none()
+        && args.cache_type.is_none();
+    let read_cache_block = match &read_lock {
+        _ if !read_hits => quote! {},
+        // a poisoned read lock is handled by the write lock
+        Some(_) if on_poison != "panic" => quote! {
+            if let Ok(cache) = #cache_ref.read() {
+                if let Some(result) = cache.cache_peek(&key) {
+                    #return_cache_block
+                }
+            }
+        },
+        Some(read_lock) => quote! {
+            {
+                let cache = #read_lock;
+                if let Some(result) = cache.cache_peek(&key) {
+                    #return_cache_block
+                }
+            }
+        },
+        None => quote! {},
+    };
+    let read_cache_block = match (&read_lock, &bypass) {
+        (Some(_), Some(_)) => quote! {
+            if !bypass {
+                #read_cache_block
+            }
+        },
+        _ => read_cache_block,
+    };
+
+    let cache_info = match (&read_lock, on_poison) {
+        (Some(_), "panic") | (None, "panic") => {
+            let lock = read_lock.as_ref().unwrap_or(&lock);
+            quote! { ::cached::CacheInfo::new(&*#lock) }
+        }
+        (Some(_), _) => quote! {
+            ::cached::CacheInfo::new(&*#cache_ref.read().unwrap_or_else(::std::sync::PoisonError::into_inner))
+        },
+        (None, _) => quote! {
+            ::cached::CacheInfo::new(&*#cache_ref.lock().unwrap_or_else(::std::sync::PoisonError::into_inner))
+        },
+    };
+    let cache_info = if sharded {
+        quote! { ::cached::CacheInfo::new(&*#cache_ref) }
+    } else if args.thread_local {
+        quote! { #cache_ref.with(|cache| ::cached::CacheInfo::new(&*cache.borrow())) }
+    } else {
+        cache_info
+    };
+    let cache_info = cache_info_fn(&visibility, &fn_ident, async_cache, cache_info);
+
+    let evict_prefix = match &prefix_ty {
+        Some(prefix_ty) => {
+            let lock = match on_poison {
+                "panic" => lock.clone(),
+                _ => quote! { #try_lock.unwrap_or_else(::std::sync::PoisonError::into_inner) },
+            };
+            let body = if sharded {
+                quote! {
+                    #cache_ref.shards().iter().map(|shard| {
+                        let mut cache = #shard_lock;
+                        ::cached::Cached::cache_remove_where(&mut *cache, |key| key.0 == *prefix)
+                    }).sum()
+                }
+            } else if args.thread_local {
+                with_local(quote! {
+                    ::cached::Cached::cache_remove_where(&mut *cache, |key| key.0 == *prefix)
+                })
+            } else {
+                quote! {
+                    let mut cache = #lock;
+                    ::cached::Cached::cache_remove_where(&mut *cache, |key| key.0 == *prefix)
+                }
+            };
+            evict_prefix_fn(&visibility, &fn_ident, async_cache, prefix_ty, body)
+        }
+        None => quote! {},
+    };
+
+    let (static_ty, static_create) = match (args.concurrent, args.shards) {
+        (true, Some(shards)) => (
+            quote! { ::cached::ConcurrentCache<#cache_ty> },
+            quote! { ::cached::ConcurrentCache::with_shards(#shards, || #cache_create) },
+        ),
+        (true, None) => (
+            quote! { ::cached::ConcurrentCache<#cache_ty> },
+            quote! { ::cached::ConcurrentCache::new(|| #cache_create) },
+        ),
+        (false, Some(shards)) => (
+            quote! { ::cached::ShardedCache<#cache_ty> },
+            quote! { ::cached::ShardedCache::new(#shards, || #cache_create) },
+        ),
+        (false, None) => (
+            quote! { #lock_ty<#cache_ty> },
+            quote! { #lock_ty::new(#cache_create) },
+        ),
+    };
+    let static_cache = if args.thread_local {
+        // `thread_local!` takes the visibility along with the static
+        let static_visibility = if method {
+            quote! {}
+        } else {
+            quote! { #visibility }
+        };
+        quote! {
+            ::std::thread_local! {
+                #static_visibility static #cache_ident: ::std::cell::RefCell<#cache_ty> = ::std::cell::RefCell::new(#cache_create);
+            }
+        }
+    } else {
+        // `spin` caches don't need `std`, neither does their static
+        let lazy = if args.sync.as_deref() == Some("spin") {
+            quote! { ::cached::spin::Lazy }
+        } else {
+            quote! { ::cached::once_cell::sync::Lazy }
+        };
+        quote! {
+            static #cache_ident: #lazy<#static_ty> = #lazy::new(|| #static_create);
+        }
+    };
+    let static_ty = if args.thread_local {
+        quote! { ::std::thread::LocalKey<::std::cell::RefCell<#cache_ty>> }
+    } else {
+        static_ty
+    };
+    let static_cache = match (method, args.thread_local) {
+        (true, _) => quote! {
+            #visibility fn #cache_fn_ident() -> &'static #static_ty {
+                #static_cache
+                &#cache_ident
+            }
+        },
+        (false, true) => static_cache,
+        (false, false) => quote! { #visibility #static_cache },
+    };
+
+    // methods and associated functions run their body in place, `inner` can't take `self`
+    // or name `Self`
+    let call_inner = match (method, &output) {
+        (false, _) if asyncness.is_some() => quote! {
+            async fn inner #fn_generics (#inputs) #output #where_clause #body;
+            let result = inner(#(#input_names),*).await;
+        },
+        (false, _) => quote! {
+            fn inner #fn_generics (#inputs) #output #where_clause #body;
+            let result = inner(#(#input_names),*);
+        },
+        (true, _) if asyncness.is_some() => quote! {
+            let result = async move #body.await;
+        },
+        (true, ReturnType::Type(_, ty)) => quote! {
+            let result = (|| -> #ty #body)();
+        },
+        (true, ReturnType::Default) => quote! {
+            let result = (|| #body)();
+        },
+    };
+    let no_cache = no_cache_fn(
+        &visibility,
+        &no_cache_signature,
+        quote! {{ #call_inner result }},
+    );
+
+    // caching is disabled for the whole build, the function runs its body every time but
+    // keeps the signature and companion functions it has with caching
+    if cfg!(feature = "disable_cache") {
+        let cache_info = cache_info_fn(
+            &visibility,
+            &fn_ident,
+            async_cache,
+            quote! { ::cached::CacheInfo::default() },
+        );
+        let evict_prefix = match &prefix_ty {
+            Some(prefix_ty) => evict_prefix_fn(
+                &visibility,
+                &fn_ident,
+                async_cache,
+                prefix_ty,
+                quote! { Some(0) },
+            ),
+            None => quote! {},
+        };
+        let uncached = quote! {
+            #call_inner
+            #wrap_block
+            result
+        };
+        // the lifespan of `{name}_with_ttl` calls is ignored
+        let wrapper = |signature: &Signature, ttl: TokenStream2| {
+            let ignore_ttl = if ttl.is_empty() {
+                quote! {}
+            } else {
+                quote! {
+                    #ttl
+                    let _ = ttl;
                 }
             };
-            let return_cache_block = quote! { return Some(result.clone()); };
-            (set_cache_block, return_cache_block)
+            quote! {
+                #visibility #signature {
+                    #ignore_ttl
+                    #uncached
+                }
+            }
+        };
+        let (wrapper, with_ttl_wrapper) = wrappers(&signature, args.with_ttl, wrapper);
+        let management = management_fns(
+            &visibility,
+            &signature,
+            async_cache,
+            &cache_value_ty,
+            Some(uncached.clone()),
+            quote! { false },
+            quote! { None },
+            quote! {},
+        );
+        // the cache static is kept for code using it, but nothing is ever cached in it
+        return quote! {
+            #[allow(dead_code)]
+            #static_cache
+            #wrapper
+            #with_ttl_wrapper
+            #cache_info
+            #evict_prefix
+            #management
+            #no_cache
         }
-        _ => panic!("the result and option attributes are mutually exclusive"),
+        .into();
+    }
+
+    let traced_call = quote! {
+        #trace_miss
+        #call_inner
+        #trace_computed
     };
 
     // put it all together
-    let expanded = if asyncness.is_some() {
+    let expanded = if future_output_ty.is_some() {
         quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_mutex::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_mutex::Mutex::new(#cache_create));
+            #static_cache
             #visibility #signature {
                 use cached::Cached;
                 let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let mut cache = #cache_ident.lock().await;
-                    if let Some(result) = cache.cache_get(&key) {
-                        #return_cache_block
+
+                // build the future up front so it doesn't borrow the arguments,
+                // it's only awaited if the result isn't cached
+                fn inner #fn_generics (#inputs) #output #where_clause #body;
+                let future = inner(#(#input_names),*);
+
+                async move {
+                    {
+                        // check if the result is cached
+                        let mut cache = #lock;
+                        if let Some(result) = cache.cache_get(&key) {
+                            #return_cache_block
+                        }
                     }
-                }
 
-                // run the function and cache the result
-                async fn inner(#inputs) #output #body;
-                let result = inner(#(#input_names),*).await;
+                    // await the function and cache the result
+                    #trace_miss
+                    let result = future.await;
+                    #trace_computed
 
-                let mut cache = #cache_ident.lock().await;
-                #set_cache_block
+                    let mut cache = #lock;
+                    #set_cache_block
 
-                result
+                    result
+                }
             }
         }
-    } else {
+    } else if stream_item_ty.is_some() {
+        let stream_set_block = cache_if_set(
+            &quote! { cache.cache_insert_if_absent(key, result.clone()) },
+            quote! { &result },
+        );
         quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(#cache_create));
+            #static_cache
             #visibility #signature {
                 use cached::Cached;
+                use cached::futures::stream::{self, StreamExt};
                 let key = #key_convert_block;
-                {
+
+                // build the stream up front so it doesn't borrow the arguments,
+                // it's only polled if the items aren't cached
+                fn inner #fn_generics (#inputs) #output #where_clause #body;
+                let items = inner(#(#input_names),*);
+
+                stream::once(async move {
+                    {
+                        // check if the items are cached
+                        let mut cache = #lock;
+                        if let Some(result) = cache.cache_get(&key) {
+                            #trace_hit
+                            return result.clone();
+                        }
+                    }
+
+                    // collect the stream and cache the items
+                    #trace_miss
+                    let result: Vec<_> = items.collect().await;
+                    #trace_computed
+
+                    let mut cache = #lock;
+                    #stream_set_block
+
+                    result
+                })
+                .map(stream::iter)
+                .flatten()
+            }
+        }
+    } else if args.sync_writes == SyncWrites::ByKey {
+        let gate_ty = if async_cache {
+            quote! { #lock_ty<()> }
+        } else {
+            quote! { ::std::sync::Mutex<()> }
+        };
+        let gate_lock = if async_cache {
+            quote! { gate.lock().await }
+        } else {
+            quote! { gate.lock().unwrap_or_else(::std::sync::PoisonError::into_inner) }
+        };
+        // the function and `{name}_with_ttl` share the locks of the keys being computed,
+        // held next to the cache like its static
+        let gates_ty = quote! { ::cached::KeyLocks<#cache_key_ty, #gate_ty> };
+        let (gates, gates_ref) = if method {
+            let gates_fn_ident = format_ident!("{}_gates", fn_ident);
+            (
+                quote! {
+                    fn #gates_fn_ident() -> &'static #gates_ty {
+                        static GATES: ::cached::once_cell::sync::Lazy<#gates_ty> =
+                            ::cached::once_cell::sync::Lazy::new(::cached::KeyLocks::new);
+                        &GATES
+                    }
+                },
+                quote! { Self::#gates_fn_ident() },
+            )
+        } else {
+            let gates_ident = format_ident!("{}_GATES", cache_ident);
+            (
+                quote! {
+                    static #gates_ident: ::cached::once_cell::sync::Lazy<#gates_ty> =
+                        ::cached::once_cell::sync::Lazy::new(::cached::KeyLocks::new);
+                },
+                quote! { #gates_ident },
+            )
+        };
+        let wrapper = |signature: &Signature, ttl: TokenStream2| {
+            quote! {
+                #visibility #signature {
+                    use cached::Cached;
+                    #ttl
+                    #bypass
+                    let key = #key_convert_block;
+                    #read_cache_block
+
+                    // wait for a concurrent call computing the same key
+                    let gate = #gates_ref.gate(&key);
+                    let _guard = #gate_lock;
+                    {
+                        let mut cache = #lock;
+                        if let Some(result) = #lookup {
+                            #return_cache_block
+                        }
+                    }
+
+                    // run the function and cache the result
+                    #traced_call
+
+                    let mut cache = #lock;
+                    #set_cache_block
+
+                    result
+                }
+            }
+        };
+        let (wrapper, with_ttl_wrapper) = wrappers(&signature, args.with_ttl, wrapper);
+        quote! {
+            #static_cache
+            #gates
+            #wrapper
+            #with_ttl_wrapper
+        }
+    } else if args.sync_writes == SyncWrites::Global {
+        let wrapper = |signature: &Signature, ttl: TokenStream2| {
+            quote! {
+                #visibility #signature {
+                    use cached::Cached;
+                    #ttl
+                    #bypass
+                    let key = #key_convert_block;
+                    #read_cache_block
+
+                    // hold the lock while computing, so concurrent callers wait for the result
+                    let mut cache = #lock;
+                    if let Some(result) = #lookup {
+                        #return_cache_block
+                    }
+
+                    // run the function and cache the result
+                    #traced_call
+
+                    #set_cache_block
+
+                    result
+                }
+            }
+        };
+        let (wrapper, with_ttl_wrapper) = wrappers(&signature, args.with_ttl, wrapper);
+        quote! {
+            #static_cache
+            #wrapper
+            #with_ttl_wrapper
+        }
+    } else if args.thread_local {
+        // the cache isn't borrowed while computing, so recursive calls can use it
+        let check_cache_block = with_local(quote! {
+            let cached = #lookup;
+            cached.cloned()
+        });
+        let set_cache_block = with_local(set_only_block);
+        let wrapper = |signature: &Signature, ttl: TokenStream2| {
+            quote! {
+                #visibility #signature {
+                    use cached::Cached;
+                    #ttl
+                    #bypass
+                    let key = #key_convert_block;
+
                     // check if the result is cached
-                    let mut cache = #cache_ident.lock().unwrap();
-                    if let Some(result) = cache.cache_get(&key) {
+                    if let Some(result) = #check_cache_block {
+                        let result = &result;
                         #return_cache_block
                     }
+
+                    // run the function and cache the result
+                    #traced_call
+
+                    #wrap_block
+                    #set_cache_block;
+
+                    result
+                }
+            }
+        };
+        let (wrapper, with_ttl_wrapper) = wrappers(&signature, args.with_ttl, wrapper);
+        quote! {
+            #static_cache
+            #wrapper
+            #with_ttl_wrapper
+        }
+    } else if asyncness.is_some() {
+        let wrapper = |signature: &Signature, ttl: TokenStream2| {
+            quote! {
+                #visibility #signature {
+                    use cached::Cached;
+                    #ttl
+                    #bypass
+                    let key = #key_convert_block;
+                    {
+                        // check if the result is cached
+                        let mut cache = #lock;
+                        if let Some(result) = #lookup {
+                            #return_cache_block
+                        }
+                    }
+
+                    // run the function and cache the result
+                    #traced_call
+
+                    let mut cache = #lock;
+                    #set_cache_block
+
+                    result
+                }
+            }
+        };
+        let (wrapper, with_ttl_wrapper) = wrappers(&signature, args.with_ttl, wrapper);
+        quote! {
+            #static_cache
+            #wrapper
+            #with_ttl_wrapper
+        }
+    } else {
+        let check_cache_block = with_lock(
+            quote! {
+                if let Some(result) = #lookup {
+                    #return_cache_block
                 }
+            },
+            true,
+        );
+        let set_cache_block = with_lock(set_only_block, false);
+        let wrapper = |signature: &Signature, ttl: TokenStream2| {
+            quote! {
+                #visibility #signature {
+                    use cached::Cached;
+                    #ttl
+                    #bypass
+                    let key = #key_convert_block;
+                    #read_cache_block
 
-                // run the function and cache the result
-                fn inner(#inputs) #output #body;
-                let result = inner(#(#input_names),*);
+                    // check if the result is cached
+                    #check_cache_block
+
+                    // run the function and cache the result
+                    #traced_call
 
-                let mut cache = #cache_ident.lock().unwrap();
-                #set_cache_block
+                    #wrap_block
+                    #set_cache_block
 
-                result
+                    result
+                }
             }
+        };
+        let (wrapper, with_ttl_wrapper) = wrappers(&signature, args.with_ttl, wrapper);
+        quote! {
+            #static_cache
+            #wrapper
+            #with_ttl_wrapper
         }
     };
 
-    expanded.into()
+    let prime_body = if future_output_ty.is_some() || stream_item_ty.is_some() {
+        None
+    } else if args.thread_local {
+        let prime_set_block = with_local(prime_set_block);
+        Some(quote! {
+            use cached::Cached;
+            let key = #key_convert_block;
+            #call_inner
+            #wrap_block
+            #prime_set_block;
+            result
+        })
+    } else {
+        Some(quote! {
+            use cached::Cached;
+            let key = #key_convert_block;
+            #call_inner
+            #wrap_block
+            let mut cache = #lock;
+            #prime_set_block
+            result
+        })
+    };
+    let (remove_body, with_ref_body, clear_body) = if args.thread_local {
+        (
+            with_local(quote! { cache.cache_remove(&key).is_some() }),
+            with_local(quote! { cache.cache_get(&key).map(f) }),
+            with_local(quote! { cache.cache_clear() }),
+        )
+    } else {
+        (
+            quote! {
+                let mut cache = #lock;
+                cache.cache_remove(&key).is_some()
+            },
+            quote! {
+                let mut cache = #lock;
+                cache.cache_get(&key).map(f)
+            },
+            quote! {
+                let mut cache = #lock;
+                cache.cache_clear();
+            },
+        )
+    };
+    let management = management_fns(
+        &visibility,
+        &signature,
+        async_cache,
+        &cache_value_ty,
+        prime_body,
+        quote! {
+            use cached::Cached;
+            let key = #key_convert_block;
+            #remove_body
+        },
+        quote! {
+            use cached::Cached;
+            let key = #key_convert_block;
+            #with_ref_body
+        },
+        if sharded {
+            quote! {
+                use cached::Cached;
+                for shard in #cache_ref.shards() {
+                    #shard_lock.cache_clear();
+                }
+            }
+        } else {
+            quote! {
+                use cached::Cached;
+                #clear_body
+            }
+        },
+    );
+
+    quote! {
+        #expanded
+        #cache_info
+        #evict_prefix
+        #management
+        #no_cache
+    }
+    .into()
+}
+
+/// # Attributes
+/// - `map_error`: (string, closure, required) maps the errors of the store into the
+///   function's error type, e.g. `map_error = r##"|e| MyError::Store(e)"##`
+/// - `type`: (string, type, required) the type of the store, implementing `cached::IOCached`,
+///   or `cached::IOCachedAsync` for async functions
+/// - `create`: (string, expression, required) creates the store when it's first used, e.g.
+///   opening its connection. It isn't async, even for async functions, see `create_async`
+/// - `name`: (optional, string) specify the name for the generated store, defaults to the
+///   function name uppercase
+/// - `convert`: (optional, string block) converts the arguments into the store's key type,
+///   which defaults to the tuple of the cloned arguments
+/// - `with_cached_flag`: (optional, bool) return a `cached::Return<T>` holding the `Ok` value
+///   along with whether it came from the store
+/// - `create_async`: (optional, bool) for async functions, `create` is a future creating the
+///   store, e.g. connecting to it, awaited on first use instead of blocking. The static holds a
+///   `cached::AsyncOnce`, and an `async fn {name}_init_cache()` is generated to create the store
+///   ahead of time, e.g. at startup, and return it
+///
+/// ## Note
+/// The function must return a `Result`, whose error type store errors are mapped into. Values
+/// are cached when the function returns `Ok`, errors are returned without caching them. A
+/// store error when setting a value is returned instead of the computed value.
+///
+/// When the `disable_cache` feature of `cached` is enabled, the function runs its body on every
+/// call without using the store, returning a `cached::Return` of a value that wasn't cached with
+/// `with_cached_flag`. The store static is still generated, created on first use, as is
+/// `{name}_init_cache` with `create_async`.
+#[proc_macro_attribute]
+pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
+    io_cached::io_cached(args, input)
+}
+
+/// # Attributes
+/// - `name`: (optional, string) specify the name for the generated static, defaults to the
+///   function name uppercase
+/// - `time`: (optional, u64) specify a lifespan in seconds, after which the value is computed again
+/// - `sync_writes`: (optional, bool) make calls missing the value wait for the one computing it
+///   instead of computing it concurrently
+/// - `result`: (optional, bool) only cache the `Ok` value of functions returning a `Result`
+/// - `option`: (optional, bool) only cache the `Some` value of functions returning an `Option`
+///
+/// ## Note
+/// A single value is held for the function, regardless of its arguments, e.g. for functions
+/// without any or reading a configuration. There's no key to hash and no map. Without `time`,
+/// the static of a non-async function is a `once_cell::sync::OnceCell` of the value, set once
+/// and read with an atomic load on hits, which can't be cleared. Otherwise it's an `Option` of
+/// the value, along with when it was computed with `time`, in a `std::sync::RwLock`, or an
+/// `async_mutex::Mutex` for async functions. Clear it with `*NAME.write().unwrap() = None`.
+#[proc_macro_attribute]
+pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
+    once::once(args, input)
+}
+
+/// Build the `{name}_prime_cache`, `{name}_cache_remove`, `{name}_with_ref` and
+/// `{name}_cache_clear` functions running `prime`, `remove`, `with_ref` and `clear`, without
+/// `{name}_prime_cache` if `prime` is `None`. `with_ref` calls `f` with a `&value_ty`
+#[allow(clippy::too_many_arguments)]
+fn management_fns(
+    visibility: &Visibility,
+    signature: &Signature,
+    async_cache: bool,
+    value_ty: &TokenStream2,
+    prime: Option<TokenStream2>,
+    remove: TokenStream2,
+    with_ref: TokenStream2,
+    clear: TokenStream2,
+) -> TokenStream2 {
+    let prime = prime.map(|prime| {
+        let mut prime_signature = signature.clone();
+        prime_signature.ident = format_ident!("{}_prime_cache", signature.ident);
+        quote! {
+            #visibility #prime_signature {
+                #prime
+            }
+        }
+    });
+    let mut remove_signature = signature.clone();
+    remove_signature.ident = format_ident!("{}_cache_remove", signature.ident);
+    remove_signature.output = parse_quote! { -> bool };
+    let mut with_ref_signature = signature.clone();
+    with_ref_signature.ident = format_ident!("{}_with_ref", signature.ident);
+    with_ref_signature.generics.params.push(parse_quote! { R });
+    with_ref_signature
+        .inputs
+        .push(parse_quote! { f: impl FnOnce(&#value_ty) -> R });
+    with_ref_signature.output = parse_quote! { -> Option<R> };
+    let asyncness = if async_cache {
+        remove_signature.asyncness = Some(Default::default());
+        with_ref_signature.asyncness = Some(Default::default());
+        quote! { async }
+    } else {
+        quote! {}
+    };
+    let clear_ident = format_ident!("{}_cache_clear", signature.ident);
+    quote! {
+        #prime
+        // `convert` may not use every argument
+        #[allow(unused_variables)]
+        #visibility #remove_signature {
+            #remove
+        }
+        #[allow(unused_variables)]
+        #visibility #with_ref_signature {
+            #with_ref
+        }
+        #visibility #asyncness fn #clear_ident() {
+            #clear
+        }
+    }
+}
+
+/// Build the `{name}_no_cache` function with the function's `signature`, running `body`
+fn no_cache_fn(visibility: &Visibility, signature: &Signature, body: TokenStream2) -> TokenStream2 {
+    let mut no_cache_signature = signature.clone();
+    no_cache_signature.ident = format_ident!("{}_no_cache", signature.ident);
+    quote! {
+        // only benchmarks and tests may call it
+        #[allow(dead_code)]
+        #visibility #no_cache_signature #body
+    }
+}
+
+/// Return whether `tokens` mention `ident`
+fn uses_ident(tokens: TokenStream2, ident: &Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(i) => i == *ident,
+        TokenTree::Group(group) => uses_ident(group.stream(), ident),
+        _ => false,
+    })
+}
+
+/// Replace the `Self` type in `tokens` with `self_ty`
+fn replace_self(tokens: TokenStream2, self_ty: &TokenStream2) -> TokenStream2 {
+    tokens
+        .into_iter()
+        .flat_map(|token| match token {
+            TokenTree::Ident(ident) if ident == "Self" => self_ty.clone(),
+            TokenTree::Group(group) => {
+                let mut replaced = proc_macro2::Group::new(
+                    group.delimiter(),
+                    replace_self(group.stream(), self_ty),
+                );
+                replaced.set_span(group.span());
+                TokenTree::Group(replaced).into()
+            }
+            other => other.into(),
+        })
+        .collect()
+}
+
+/// Build the `{name}_cache_info` function, returning `info`
+fn cache_info_fn(
+    visibility: &Visibility,
+    fn_ident: &Ident,
+    async_cache: bool,
+    info: TokenStream2,
+) -> TokenStream2 {
+    let info_ident = format_ident!("{}_cache_info", fn_ident);
+    let asyncness = if async_cache {
+        quote! { async }
+    } else {
+        quote! {}
+    };
+    quote! {
+        #visibility #asyncness fn #info_ident() -> ::cached::CacheInfo {
+            #info
+        }
+    }
+}
+
+/// Build the `{name}_evict_prefix` function taking the first argument as `prefix`, running `body`
+fn evict_prefix_fn(
+    visibility: &Visibility,
+    fn_ident: &Ident,
+    async_cache: bool,
+    prefix_ty: &Type,
+    body: TokenStream2,
+) -> TokenStream2 {
+    let evict_ident = format_ident!("{}_evict_prefix", fn_ident);
+    let asyncness = if async_cache {
+        quote! { async }
+    } else {
+        quote! {}
+    };
+    quote! {
+        #visibility #asyncness fn #evict_ident(prefix: &#prefix_ty) -> Option<usize> {
+            #body
+        }
+    }
+}
+
+/// Build the function wrapper from `wrapper`, along with its `{name}_with_ttl` companion
+/// taking the lifespan of the cached value if `with_ttl` is set
+fn wrappers(
+    signature: &Signature,
+    with_ttl: bool,
+    wrapper: impl Fn(&Signature, TokenStream2) -> TokenStream2,
+) -> (TokenStream2, TokenStream2) {
+    if !with_ttl {
+        return (wrapper(signature, quote! {}), quote! {});
+    }
+    let mut with_ttl_signature = signature.clone();
+    with_ttl_signature.ident = format_ident!("{}_with_ttl", signature.ident);
+    with_ttl_signature.inputs.push(parse_quote! { ttl: u64 });
+    (
+        wrapper(signature, quote! { let ttl: Option<u64> = None; }),
+        wrapper(&with_ttl_signature, quote! { let ttl = Some(ttl); }),
+    )
+}
+
+/// Name of an argument bound by `pat`, `None` for destructuring patterns
+fn pat_name(pat: &Pat) -> Option<String> {
+    match pat {
+        Pat::Ident(pat_ident) => Some(pat_ident.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Type the default key stores an argument of type `ty` as, the `ToOwned::Owned` form
+/// of references, e.g. `String` for `&str`
+fn owned_key_ty(ty: &Type) -> Type {
+    match ty {
+        Type::Reference(reference) => {
+            let elem = &reference.elem;
+            parse_quote! { <#elem as ::std::borrow::ToOwned>::Owned }
+        }
+        _ => ty.clone(),
+    }
+}
+
+/// If `ty` is `impl Trait<Assoc = T>`, e.g. `impl Future<Output = T>`, return `T`
+fn impl_trait_binding(ty: &Type, trait_name: &str, assoc_name: &str) -> Option<Type> {
+    let bounds = match ty {
+        Type::ImplTrait(impl_trait) => &impl_trait.bounds,
+        _ => return None,
+    };
+    bounds.iter().find_map(|bound| {
+        let segment = match bound {
+            TypeParamBound::Trait(trait_bound) => trait_bound.path.segments.last()?,
+            _ => return None,
+        };
+        if segment.ident != trait_name {
+            return None;
+        }
+        match &segment.arguments {
+            PathArguments::AngleBracketed(brackets) => {
+                brackets.args.iter().find_map(|arg| match arg {
+                    GenericArgument::Binding(binding) if binding.ident == assoc_name => {
+                        Some(binding.ty.clone())
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Wrap the return type with `wrap`, or its first type argument if `inner`,
+/// e.g. `Result<T, E>` becomes `Result<Arc<T>, E>`
+fn wrap_output(output: &ReturnType, inner: bool, wrap: impl Fn(&Type) -> Type) -> ReturnType {
+    let ty = match output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => panic!("function must return something to be wrapped"),
+    };
+    if !inner {
+        let ty = wrap(ty);
+        return parse_quote! { -> #ty };
+    }
+    let mut ty = *ty.clone();
+    let inner_ty = match &mut ty {
+        Type::Path(typepath) => match &mut typepath.path.segments.last_mut().unwrap().arguments {
+            PathArguments::AngleBracketed(brackets) => brackets.args.first_mut(),
+            _ => None,
+        },
+        _ => None,
+    };
+    match inner_ty {
+        Some(GenericArgument::Type(inner_ty)) => *inner_ty = wrap(inner_ty),
+        _ => panic!("function return type has no inner type"),
+    }
+    parse_quote! { -> #ty }
+}
+
+/// Store evicting values of a cache bound by `size` according to `policy`
+fn size_policy(policy: &str) -> Ident {
+    let store = match policy {
+        "lru" => "SizedCache",
+        "lfu" => "LfuCache",
+        "sieve" => "SieveCache",
+        other => panic!(
+            "unknown policy `{}`, expected \"lru\", \"lfu\" or \"sieve\"",
+            other
+        ),
+    };
+    format_ident!("{}", store)
+}
+
+/// Parse the `time_source` attribute into the name of a `cached::TimeSource` variant
+fn time_source(source: &str) -> Ident {
+    let variant = match source {
+        "monotonic" => "Monotonic",
+        "wall_clock" => "WallClock",
+        other => panic!(
+            "unknown time_source `{}`, expected \"monotonic\" or \"wall_clock\"",
+            other
+        ),
+    };
+    format_ident!("{}", variant)
 }
@@ -0,0 +1,239 @@
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse_macro_input, AttributeArgs, FnArg, GenericArgument, Ident, ItemFn, Pat, PathArguments,
+    ReturnType, Type,
+};
+
+#[derive(FromMeta)]
+struct OnceMacroArgs {
+    #[darling(default)]
+    name: Option<String>,
+    #[darling(default)]
+    time: Option<u64>,
+    #[darling(default)]
+    sync_writes: bool,
+    #[darling(default)]
+    result: bool,
+    #[darling(default)]
+    option: bool,
+}
+
+pub(crate) fn once(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    let args = match OnceMacroArgs::from_list(&attr_args) {
+        Ok(v) => v,
+        Err(e) => {
+            return TokenStream::from(e.write_errors());
+        }
+    };
+    let input = parse_macro_input!(input as ItemFn);
+
+    // caching is disabled for the whole build, leave the function as is
+    if cfg!(feature = "disable_cache") {
+        return quote! { #input }.into();
+    }
+
+    let visibility = input.vis;
+    let signature = input.sig;
+    let body = input.block;
+
+    let fn_ident = signature.ident.clone();
+    let inputs = signature.inputs.clone();
+    let output = signature.output.clone();
+    let asyncness = signature.asyncness;
+    let (fn_generics, _, where_clause) = signature.generics.split_for_impl();
+
+    if matches!(inputs.first(), Some(FnArg::Receiver(_))) {
+        panic!("once is not supported for methods");
+    }
+
+    let input_names = inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Receiver(_) => None,
+            FnArg::Typed(pat_type) => Some(pat_type.pat.clone()),
+        })
+        .collect::<Vec<Box<Pat>>>();
+
+    let output_ty = match &output {
+        ReturnType::Type(_, ty) => *ty.clone(),
+        ReturnType::Default => panic!("once functions must return something to be cached"),
+    };
+
+    // the value held, the first inner type of an `Option` or `Result` with the result and
+    // option attributes, and how it's matched from the returned value and returned when cached
+    let (value_ty, value_of, from_cache) = match (args.result, args.option) {
+        (false, false) => (output_ty, quote! { let value = &result; }, quote! { value }),
+        (true, false) => (
+            first_type_arg(&output_ty),
+            quote! { if let Ok(value) = &result },
+            quote! { Ok(value) },
+        ),
+        (false, true) => (
+            first_type_arg(&output_ty),
+            quote! { if let Some(value) = &result },
+            quote! { Some(value) },
+        ),
+        (true, true) => panic!("the result and option attributes are mutually exclusive"),
+    };
+
+    // values expiring after `time` are held along with when they were computed
+    let (stored_ty, lookup, to_store) = match args.time {
+        Some(time) => (
+            quote! { (::std::time::Instant, #value_ty) },
+            quote! {
+                (*cached)
+                    .as_ref()
+                    .filter(|(created, _)| created.elapsed().as_secs() < #time)
+                    .map(|(_, value)| value.clone())
+            },
+            quote! { (::std::time::Instant::now(), value.clone()) },
+        ),
+        None => (
+            quote! { #value_ty },
+            quote! { (*cached).clone() },
+            quote! { value.clone() },
+        ),
+    };
+
+    let cache_ident = match args.name {
+        Some(name) => Ident::new(&name, fn_ident.span()),
+        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
+    };
+
+    // a value that never expires is set once, so hits of non-async functions are an atomic
+    // load of a `OnceCell` instead of taking a lock
+    if asyncness.is_none() && args.time.is_none() {
+        let stored_value = match (args.result, args.option) {
+            (true, _) => quote! { result.as_ref().ok().cloned() },
+            (_, true) => quote! { result.clone() },
+            _ => quote! { Some(result.clone()) },
+        };
+        let call_inner = quote! {
+            fn inner #fn_generics (#inputs) #output #where_clause #body
+            let result = inner(#(#input_names),*);
+        };
+        // with sync_writes, concurrent calls missing the value wait for the one computing it
+        let compute = if args.sync_writes {
+            quote! {
+                let mut uncached = None;
+                let cached = #cache_ident.get_or_try_init(|| {
+                    #call_inner
+                    match #stored_value {
+                        Some(value) => Ok(value),
+                        None => {
+                            uncached = Some(result);
+                            Err(())
+                        }
+                    }
+                });
+                match cached {
+                    Ok(value) => {
+                        let value = value.clone();
+                        #from_cache
+                    }
+                    Err(()) => uncached.unwrap(),
+                }
+            }
+        } else {
+            quote! {
+                #call_inner
+                if let Some(value) = #stored_value {
+                    let _ = #cache_ident.set(value);
+                }
+                result
+            }
+        };
+        return quote! {
+            #visibility static #cache_ident: ::cached::once_cell::sync::OnceCell<#value_ty> =
+                ::cached::once_cell::sync::OnceCell::new();
+
+            #visibility #signature {
+                if let Some(value) = #cache_ident.get() {
+                    let value = value.clone();
+                    return #from_cache;
+                }
+                #compute
+            }
+        }
+        .into();
+    }
+
+    // async functions can't hold a `std` lock across the await point of computing the value
+    let (lock_ty, read_lock, write_lock, call_inner) = if asyncness.is_some() {
+        (
+            quote! { ::cached::async_mutex::Mutex },
+            quote! { #cache_ident.lock().await },
+            quote! { #cache_ident.lock().await },
+            quote! {
+                async fn inner #fn_generics (#inputs) #output #where_clause #body
+                let result = inner(#(#input_names),*).await;
+            },
+        )
+    } else {
+        (
+            quote! { ::std::sync::RwLock },
+            quote! { #cache_ident.read().unwrap() },
+            quote! { #cache_ident.write().unwrap() },
+            quote! {
+                fn inner #fn_generics (#inputs) #output #where_clause #body
+                let result = inner(#(#input_names),*);
+            },
+        )
+    };
+
+    // with sync_writes, concurrent calls missing the value wait for the one computing it
+    let compute = if args.sync_writes {
+        quote! {
+            let mut cached = #write_lock;
+            if let Some(value) = #lookup {
+                return #from_cache;
+            }
+            #call_inner
+            #value_of {
+                *cached = Some(#to_store);
+            }
+            result
+        }
+    } else {
+        quote! {
+            #call_inner
+            #value_of {
+                *#write_lock = Some(#to_store);
+            }
+            result
+        }
+    };
+
+    quote! {
+        #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#lock_ty<Option<#stored_ty>>> =
+            ::cached::once_cell::sync::Lazy::new(|| #lock_ty::new(None));
+
+        #visibility #signature {
+            {
+                let cached = #read_lock;
+                if let Some(value) = #lookup {
+                    return #from_cache;
+                }
+            }
+            #compute
+        }
+    }
+    .into()
+}
+
+/// Return the first type argument of `ty`, e.g. `T` of `Result<T, E>`
+fn first_type_arg(ty: &Type) -> Type {
+    if let Type::Path(typepath) = ty {
+        if let PathArguments::AngleBracketed(brackets) =
+            &typepath.path.segments.last().unwrap().arguments
+        {
+            if let Some(GenericArgument::Type(inner_ty)) = brackets.args.first() {
+                return inner_ty.clone();
+            }
+        }
+    }
+    panic!("function return type has no inner type")
+}
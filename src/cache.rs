@@ -0,0 +1,152 @@
+/*!
+Object-style cache combining a store with the function loading its values
+
+An alternative to the `cached!` macros and `#[cached]` for code that prefers an
+explicit cache object, e.g. one held in a struct and shared between threads,
+over a function backed by a global cache.
+*/
+
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard};
+
+use super::{Cached, UnboundCache};
+
+/// What `Cache::put` does with the cached copy of a value it writes through
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Write the value to the writer and to the store
+    #[default]
+    Through,
+    /// Write the value to the writer and remove the cached copy, so that
+    /// the next `get` loads it again
+    Invalidate,
+}
+
+type Loader<K, V> = dyn Fn(&K) -> V + Send + Sync;
+type Writer<K, V> = dyn Fn(&K, &V) + Send + Sync;
+
+/// Read-through, write-through cache over a `Cached` store
+///
+/// `get` returns the cached value, calling the loader on a miss (read-through).
+/// `put` hands the value to the writer, if any, before updating the store
+/// according to the `WritePolicy` (write-through).
+///
+/// The store is behind a lock that isn't held while loading or writing values,
+/// so concurrent misses on the same key may each call the loader.
+///
+/// ```rust
+/// use cached::{Cache, SizedCache};
+///
+/// let cache = Cache::new(SizedCache::with_size(100), |n: &u64| n * 2);
+/// assert_eq!(4, cache.get(&2));
+/// assert_eq!(Some(4), cache.invalidate(&2));
+/// cache.put(2, 5);
+/// assert_eq!(5, cache.get(&2));
+/// assert_eq!(Some(5), cache.with_ref(&2, |n| *n));
+/// ```
+pub struct Cache<K, V, S = UnboundCache<K, V>> {
+    store: Mutex<S>,
+    loader: Box<Loader<K, V>>,
+    writer: Option<Box<Writer<K, V>>>,
+    policy: WritePolicy,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V, S: Cached<K, V>> Cache<K, V, S> {
+    /// Creates a cache over `store`, loading missing values with `loader`
+    pub fn new<F>(store: S, loader: F) -> Cache<K, V, S>
+    where
+        F: Fn(&K) -> V + Send + Sync + 'static,
+    {
+        Cache {
+            store: Mutex::new(store),
+            loader: Box::new(loader),
+            writer: None,
+            policy: WritePolicy::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Call `f` with every value passed to `put`, before it's cached
+    pub fn writer<F>(mut self, f: F) -> Cache<K, V, S>
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.writer = Some(Box::new(f));
+        self
+    }
+
+    /// Set what `put` does with the store, see `WritePolicy`
+    pub fn write_policy(mut self, policy: WritePolicy) -> Cache<K, V, S> {
+        self.policy = policy;
+        self
+    }
+
+    /// Return the cached value for `key`, loading and caching it on a miss
+    pub fn get(&self, key: &K) -> V
+    where
+        K: Hash + Eq + Clone,
+        V: Clone,
+    {
+        if let Some(value) = self.lock().cache_get(key) {
+            return value.clone();
+        }
+        let value = (self.loader)(key);
+        // keep a value put while loading
+        self.lock()
+            .cache_insert_if_absent(key.clone(), value.clone());
+        value
+    }
+
+    /// Call `f` with a reference to the cached value for `key`, under the store's lock, and
+    /// return its result, to read the value without cloning it. Doesn't load missing values,
+    /// returning `None` for them.
+    pub fn with_ref<R>(&self, key: &K, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        K: Hash + Eq,
+    {
+        self.lock().cache_get(key).map(f)
+    }
+
+    /// Write `value` through the writer and update the store, see `WritePolicy`
+    pub fn put(&self, key: K, value: V)
+    where
+        K: Hash + Eq,
+    {
+        if let Some(writer) = &self.writer {
+            writer(&key, &value);
+        }
+        let mut store = self.lock();
+        match self.policy {
+            WritePolicy::Through => {
+                store.cache_set(key, value);
+            }
+            WritePolicy::Invalidate => {
+                store.cache_remove(&key);
+            }
+        }
+    }
+
+    /// Remove the cached value for `key`, returning it
+    pub fn invalidate(&self, key: &K) -> Option<V>
+    where
+        K: Hash + Eq,
+    {
+        self.lock().cache_remove(key)
+    }
+
+    /// Remove all cached values
+    pub fn invalidate_all(&self) {
+        self.lock().cache_clear();
+    }
+
+    /// Lock the underlying store, e.g. to read its metrics
+    pub fn store(&self) -> MutexGuard<'_, S> {
+        self.lock()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, S> {
+        self.store.lock().unwrap()
+    }
+}
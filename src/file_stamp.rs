@@ -0,0 +1,100 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Version of a file, to include in the keys of values derived from it
+///
+/// Keying a value by its file's stamp as well as by its path means edits to the file
+/// make later lookups miss, recomputing the value, without evicting anything by hand:
+///
+/// ```rust,no_run
+/// use std::path::PathBuf;
+/// use cached::proc_macro::cached;
+/// use cached::FileStamp;
+///
+/// #[cached(
+///     size = 100,
+///     key = "(PathBuf, Option<FileStamp>)",
+///     convert = r#"{ (path.clone(), FileStamp::of(&path).ok()) }"#
+/// )]
+/// fn line_count(path: PathBuf) -> usize {
+///     std::fs::read_to_string(&path).map_or(0, |text| text.lines().count())
+/// }
+/// ```
+///
+/// `#[cached(file = "path")]` does the same for the default key. Values computed from
+/// earlier versions of the file stay cached until they're evicted, so a size or time
+/// bound cache is preferable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    content: Option<u64>,
+}
+
+impl FileStamp {
+    /// Stamp the file at `path` with its modification time and length
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            content: None,
+        })
+    }
+
+    /// Stamp the file at `path` with a hash of its content as well, for file systems
+    /// with coarse modification times or files rewritten without changing their length.
+    /// This reads the whole file.
+    pub fn with_content<P: AsRef<Path>>(path: P) -> io::Result<FileStamp> {
+        let path = path.as_ref();
+        let mut stamp = FileStamp::of(path)?;
+        let mut hasher = DefaultHasher::new();
+        fs::read(path)?.hash(&mut hasher);
+        stamp.content = Some(hasher.finish());
+        Ok(stamp)
+    }
+
+    /// Return the file's modification time, if the platform records it
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Return the file's length in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Return whether the file was empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::FileStamp;
+
+    #[test]
+    fn file_stamps() {
+        let path = std::env::temp_dir().join(format!("cached-file-stamp-{}", std::process::id()));
+        fs::write(&path, "one").unwrap();
+        let stamp = FileStamp::of(&path).unwrap();
+        assert_eq!(3, stamp.len());
+        assert_eq!(stamp, FileStamp::of(&path).unwrap());
+
+        let content = FileStamp::with_content(&path).unwrap();
+        assert_ne!(stamp, content);
+        // same length, maybe within the same mtime tick
+        fs::write(&path, "two").unwrap();
+        assert_ne!(content, FileStamp::with_content(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+        assert!(FileStamp::of(&path).is_err());
+    }
+}
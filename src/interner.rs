@@ -0,0 +1,111 @@
+/*!
+Interning of `String` keys, sharing one allocation per distinct string
+
+Caches keyed by text that repeats heavily, e.g. tenant or route names, can key by
+`Arc<str>` from an `Interner` instead of by `String`: identical keys across entries and
+across caches then point to the same allocation. Use it with the `convert` macro
+attribute:
+
+```rust
+use std::sync::Arc;
+use cached::interner;
+use cached::proc_macro::cached;
+
+#[cached(
+    type = "cached::UnboundCache<Arc<str>, usize>",
+    create = "{ cached::UnboundCache::new() }",
+    convert = r#"{ interner::intern(&route) }"#
+)]
+fn route_len(route: &str) -> usize {
+    route.len()
+}
+
+assert_eq!(5, route_len("/home"));
+assert!(Arc::ptr_eq(&interner::intern("/home"), &interner::intern("/home")));
+```
+*/
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+/// Set of interned strings
+///
+/// Strings stay interned until `purge` is called, even once no cache holds them.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: Mutex<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    /// Creates an empty `Interner`
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    /// Return the shared copy of `s`, interning it if it isn't already
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap();
+        if let Some(interned) = strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        strings.insert(interned.clone());
+        interned
+    }
+
+    /// Return the number of interned strings
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap().len()
+    }
+
+    /// Return whether no strings are interned
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop the strings no longer referenced outside the interner,
+    /// returning the number dropped
+    pub fn purge(&self) -> usize {
+        let mut strings = self.strings.lock().unwrap();
+        let before = strings.len();
+        strings.retain(|s| Arc::strong_count(s) > 1);
+        before - strings.len()
+    }
+}
+
+static GLOBAL: Lazy<Interner> = Lazy::new(Interner::new);
+
+/// Return the process-wide interner
+pub fn global() -> &'static Interner {
+    &GLOBAL
+}
+
+/// Return the shared copy of `s` from the process-wide interner
+pub fn intern(s: &str) -> Arc<str> {
+    GLOBAL.intern(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Interner;
+
+    #[test]
+    fn intern_and_purge() {
+        let interner = Interner::new();
+        let a = interner.intern("key");
+        let b = interner.intern(&String::from("key"));
+        assert!(Arc::ptr_eq(&a, &b));
+        interner.intern("other");
+        assert_eq!(2, interner.len());
+
+        // "other" is only held by the interner
+        assert_eq!(1, interner.purge());
+        drop((a, b));
+        assert_eq!(1, interner.purge());
+        assert!(interner.is_empty());
+    }
+}
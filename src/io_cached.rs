@@ -0,0 +1,387 @@
+use std::fmt::Display;
+#[cfg(any(feature = "async", feature = "proc_macro"))]
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+#[cfg(feature = "proc_macro")]
+use once_cell::sync::OnceCell;
+
+use super::Cached;
+
+/// Cache store whose operations can fail, e.g. one backed by a database, a remote
+/// service or the disk
+///
+/// Unlike `Cached`, methods take `&self`: such stores synchronize access on their own,
+/// e.g. in their connection, so `#[io_cached]` functions hold them in a static without
+/// a lock around them.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::io;
+/// use std::sync::Mutex;
+/// use cached::IOCached;
+///
+/// struct Remote(Mutex<HashMap<u32, String>>);
+///
+/// impl IOCached<u32, String> for Remote {
+///     type Error = io::Error;
+///
+///     fn cache_get(&self, k: &u32) -> io::Result<Option<String>> {
+///         Ok(self.0.lock().unwrap().get(k).cloned())
+///     }
+///
+///     fn cache_set(&self, k: u32, v: String) -> io::Result<Option<String>> {
+///         Ok(self.0.lock().unwrap().insert(k, v))
+///     }
+///
+///     fn cache_remove(&self, k: &u32) -> io::Result<Option<String>> {
+///         Ok(self.0.lock().unwrap().remove(k))
+///     }
+/// }
+///
+/// let remote = Remote(Mutex::new(HashMap::new()));
+/// remote.cache_set(1, "one".to_string()).unwrap();
+/// assert_eq!(remote.cache_get(&1).unwrap(), Some("one".to_string()));
+/// ```
+pub trait IOCached<K, V> {
+    /// Error returned when the store can't be reached or fails
+    type Error;
+
+    /// Attempt to retrieve a cached value
+    fn cache_get(&self, k: &K) -> Result<Option<V>, Self::Error>;
+
+    /// Insert a key, value pair and return the previous value
+    fn cache_set(&self, k: K, v: V) -> Result<Option<V>, Self::Error>;
+
+    /// Remove a cached value
+    fn cache_remove(&self, k: &K) -> Result<Option<V>, Self::Error>;
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Cache store whose operations can fail and must be awaited, the async version of `IOCached`
+///
+/// Implementations may write the methods as `async fn`.
+#[cfg(feature = "async")]
+pub trait IOCachedAsync<K, V> {
+    /// Error returned when the store can't be reached or fails
+    type Error;
+
+    /// Attempt to retrieve a cached value
+    fn cache_get(&self, k: &K) -> impl Future<Output = Result<Option<V>, Self::Error>>;
+
+    /// Insert a key, value pair and return the previous value
+    fn cache_set(&self, k: K, v: V) -> impl Future<Output = Result<Option<V>, Self::Error>>;
+
+    /// Remove a cached value
+    fn cache_remove(&self, k: &K) -> impl Future<Output = Result<Option<V>, Self::Error>>;
+
+    /// Return the lifespan of cached values (time to eviction)
+    fn cache_lifespan(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Value created asynchronously the first time it's needed, e.g. a store connecting to
+/// its backend
+///
+/// Holds the stores of `#[io_cached(create_async = true)]` functions, whose `create`
+/// expression is a future awaited on first use instead of blocking in a `Lazy`.
+/// Concurrent first uses wait for the same creation:
+///
+/// ```rust
+/// # async fn run() {
+/// use cached::AsyncOnce;
+///
+/// let once = AsyncOnce::new();
+/// assert_eq!(once.get(), None);
+/// assert_eq!(*once.get_or_init(|| async { 1 }).await, 1);
+/// assert_eq!(*once.get_or_init(|| async { 2 }).await, 1);
+/// # }
+/// ```
+#[cfg(feature = "proc_macro")]
+#[derive(Debug, Default)]
+pub struct AsyncOnce<T> {
+    cell: OnceCell<T>,
+    init: async_mutex::Mutex<()>,
+}
+
+#[cfg(feature = "proc_macro")]
+impl<T> AsyncOnce<T> {
+    /// Creates an empty `AsyncOnce`
+    pub fn new() -> AsyncOnce<T> {
+        AsyncOnce {
+            cell: OnceCell::new(),
+            init: async_mutex::Mutex::new(()),
+        }
+    }
+
+    /// Return the value, if it was created
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Return the value, creating it by awaiting the future returned by `f` if it wasn't yet
+    pub async fn get_or_init<F, Fut>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.cell.get() {
+            return value;
+        }
+        let _guard = self.init.lock().await;
+        if let Some(value) = self.cell.get() {
+            return value;
+        }
+        let value = f().await;
+        self.cell.get_or_init(|| value)
+    }
+}
+
+/// External store wrapper keying values by `{namespace}:{version}:{key}`
+///
+/// Services sharing a backend use different namespaces, and bumping the version, e.g.
+/// to the crate's version, makes a deploy miss the values cached in an older format:
+///
+/// ```rust
+/// # use std::collections::HashMap;
+/// # use std::convert::Infallible;
+/// # use std::sync::Mutex;
+/// use cached::{IOCached, Namespaced};
+///
+/// # struct Remote(Mutex<HashMap<String, u32>>);
+/// # impl IOCached<String, u32> for Remote {
+/// #     type Error = Infallible;
+/// #     fn cache_get(&self, k: &String) -> Result<Option<u32>, Infallible> {
+/// #         Ok(self.0.lock().unwrap().get(k).copied())
+/// #     }
+/// #     fn cache_set(&self, k: String, v: u32) -> Result<Option<u32>, Infallible> {
+/// #         Ok(self.0.lock().unwrap().insert(k, v))
+/// #     }
+/// #     fn cache_remove(&self, k: &String) -> Result<Option<u32>, Infallible> {
+/// #         Ok(self.0.lock().unwrap().remove(k))
+/// #     }
+/// # }
+/// // `Remote` is an `IOCached<String, u32>` store
+/// let store = Namespaced::new(Remote(Mutex::new(HashMap::new())), "users", "v2");
+/// store.cache_set(7, 42).unwrap();
+/// assert_eq!(store.inner().cache_get(&"users:v2:7".to_string()).unwrap(), Some(42));
+/// ```
+///
+/// It implements `IOCached<K, V>` (and `IOCachedAsync<K, V>`) for any `K: Display` over a
+/// store keyed by `String`.
+#[derive(Clone, Debug)]
+pub struct Namespaced<S> {
+    store: S,
+    prefix: String,
+}
+
+impl<S> Namespaced<S> {
+    /// Wrap `store`, prefixing keys with `namespace` and `version`
+    pub fn new(store: S, namespace: &str, version: &str) -> Namespaced<S> {
+        Namespaced {
+            store,
+            prefix: format!("{}:{}:", namespace, version),
+        }
+    }
+
+    /// Return the prefix of the keys, `{namespace}:{version}:`
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Return the wrapped store
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    /// Return the wrapped store
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn key<K: Display>(&self, k: &K) -> String {
+        format!("{}{}", self.prefix, k)
+    }
+}
+
+impl<K: Display, V, S: IOCached<String, V>> IOCached<K, V> for Namespaced<S> {
+    type Error = S::Error;
+
+    fn cache_get(&self, k: &K) -> Result<Option<V>, S::Error> {
+        self.store.cache_get(&self.key(k))
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Result<Option<V>, S::Error> {
+        self.store.cache_set(self.key(&k), v)
+    }
+
+    fn cache_remove(&self, k: &K) -> Result<Option<V>, S::Error> {
+        self.store.cache_remove(&self.key(k))
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.store.cache_lifespan()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Display, V, S: IOCachedAsync<String, V>> IOCachedAsync<K, V> for Namespaced<S> {
+    type Error = S::Error;
+
+    async fn cache_get(&self, k: &K) -> Result<Option<V>, S::Error> {
+        self.store.cache_get(&self.key(k)).await
+    }
+
+    async fn cache_set(&self, k: K, v: V) -> Result<Option<V>, S::Error> {
+        self.store.cache_set(self.key(&k), v).await
+    }
+
+    async fn cache_remove(&self, k: &K) -> Result<Option<V>, S::Error> {
+        self.store.cache_remove(&self.key(k)).await
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.store.cache_lifespan()
+    }
+}
+
+/// Two-level store: a fast in-memory `Cached` store in front of a store whose operations
+/// can fail, e.g. a remote one shared between processes
+///
+/// Lookups missing the front store fall through to the back store, values found there are
+/// copied into the front store. Writes go through both, back first, so an error leaves the
+/// front store unchanged. It implements `IOCached` (and `IOCachedAsync`), so a single
+/// `#[io_cached]` function gets both:
+///
+/// ```rust
+/// # use std::collections::HashMap;
+/// # use std::convert::Infallible;
+/// # use std::sync::Mutex;
+/// use cached::{Cached, IOCached, SizedCache, TieredCache};
+///
+/// # struct Remote(Mutex<HashMap<u32, u32>>);
+/// # impl IOCached<u32, u32> for Remote {
+/// #     type Error = Infallible;
+/// #     fn cache_get(&self, k: &u32) -> Result<Option<u32>, Infallible> {
+/// #         Ok(self.0.lock().unwrap().get(k).copied())
+/// #     }
+/// #     fn cache_set(&self, k: u32, v: u32) -> Result<Option<u32>, Infallible> {
+/// #         Ok(self.0.lock().unwrap().insert(k, v))
+/// #     }
+/// #     fn cache_remove(&self, k: &u32) -> Result<Option<u32>, Infallible> {
+/// #         Ok(self.0.lock().unwrap().remove(k))
+/// #     }
+/// # }
+/// // `Remote` is an `IOCached<u32, u32>` store
+/// let remote = Remote(Mutex::new(HashMap::from([(1, 10)])));
+/// let store = TieredCache::new(SizedCache::with_size(100), remote);
+/// assert_eq!(store.cache_get(&1).unwrap(), Some(10));
+/// // the value was copied into the front store
+/// assert_eq!(store.front().cache_peek(&1), Some(&10));
+/// ```
+///
+/// The front store is behind a mutex, which isn't held while the back store is accessed.
+#[derive(Debug)]
+pub struct TieredCache<F, B> {
+    front: Mutex<F>,
+    back: B,
+}
+
+impl<F, B> TieredCache<F, B> {
+    /// Creates a store looking up `front` before `back`
+    pub fn new(front: F, back: B) -> TieredCache<F, B> {
+        TieredCache {
+            front: Mutex::new(front),
+            back,
+        }
+    }
+
+    /// Lock the front store, e.g. to read its metrics
+    pub fn front(&self) -> MutexGuard<'_, F> {
+        self.front.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Return the back store
+    pub fn back(&self) -> &B {
+        &self.back
+    }
+}
+
+impl<K, V, F, B> IOCached<K, V> for TieredCache<F, B>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    F: Cached<K, V>,
+    B: IOCached<K, V>,
+{
+    type Error = B::Error;
+
+    fn cache_get(&self, k: &K) -> Result<Option<V>, B::Error> {
+        if let Some(value) = self.front().cache_get(k) {
+            return Ok(Some(value.clone()));
+        }
+        let value = self.back.cache_get(k)?;
+        if let Some(value) = &value {
+            self.front().cache_set(k.clone(), value.clone());
+        }
+        Ok(value)
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Result<Option<V>, B::Error> {
+        let old = self.back.cache_set(k.clone(), v.clone())?;
+        self.front().cache_set(k, v);
+        Ok(old)
+    }
+
+    fn cache_remove(&self, k: &K) -> Result<Option<V>, B::Error> {
+        self.front().cache_remove(k);
+        self.back.cache_remove(k)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.back.cache_lifespan()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K, V, F, B> IOCachedAsync<K, V> for TieredCache<F, B>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    F: Cached<K, V>,
+    B: IOCachedAsync<K, V>,
+{
+    type Error = B::Error;
+
+    async fn cache_get(&self, k: &K) -> Result<Option<V>, B::Error> {
+        if let Some(value) = self.front().cache_get(k) {
+            return Ok(Some(value.clone()));
+        }
+        let value = self.back.cache_get(k).await?;
+        if let Some(value) = &value {
+            self.front().cache_set(k.clone(), value.clone());
+        }
+        Ok(value)
+    }
+
+    async fn cache_set(&self, k: K, v: V) -> Result<Option<V>, B::Error> {
+        let old = self.back.cache_set(k.clone(), v.clone()).await?;
+        self.front().cache_set(k, v);
+        Ok(old)
+    }
+
+    async fn cache_remove(&self, k: &K) -> Result<Option<V>, B::Error> {
+        self.front().cache_remove(k);
+        self.back.cache_remove(k).await
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.back.cache_lifespan()
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Set of locks, one per key being computed
+///
+/// Used by `#[cached(sync_writes = "by_key")]` so that concurrent misses on the same key
+/// are computed once, while misses on different keys are computed in parallel. `L` is the
+/// lock callers hold while computing, e.g. `std::sync::Mutex<()>`, or an async mutex for
+/// async functions. A key's lock is dropped once no caller holds its `KeyGate`.
+#[derive(Debug)]
+pub struct KeyLocks<K, L> {
+    locks: Mutex<HashMap<K, Arc<L>>>,
+}
+
+impl<K: Hash + Eq + Clone, L: Default> KeyLocks<K, L> {
+    /// Creates an empty `KeyLocks`
+    pub fn new() -> KeyLocks<K, L> {
+        KeyLocks {
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the gate for `key`, sharing its lock with the other callers holding one
+    pub fn gate(&self, key: &K) -> KeyGate<'_, K, L> {
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        let lock = locks.entry(key.clone()).or_default().clone();
+        KeyGate {
+            locks: self,
+            key: key.clone(),
+            lock: Some(lock),
+        }
+    }
+
+    /// Return the number of keys with a lock
+    pub fn len(&self) -> usize {
+        self.locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Return whether no key has a lock
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Hash + Eq + Clone, L: Default> Default for KeyLocks<K, L> {
+    fn default() -> Self {
+        KeyLocks::new()
+    }
+}
+
+/// Handle to the lock of a key in a `KeyLocks`, dereferencing to the lock
+#[derive(Debug)]
+pub struct KeyGate<'a, K: Hash + Eq, L> {
+    locks: &'a KeyLocks<K, L>,
+    key: K,
+    /// Only taken when the gate is dropped
+    lock: Option<Arc<L>>,
+}
+
+impl<K: Hash + Eq, L> Deref for KeyGate<'_, K, L> {
+    type Target = L;
+
+    fn deref(&self) -> &L {
+        self.lock.as_ref().unwrap()
+    }
+}
+
+impl<K: Hash + Eq, L> Drop for KeyGate<'_, K, L> {
+    fn drop(&mut self) {
+        let mut locks = self
+            .locks
+            .locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // release the lock under the map's lock, so that gates dropped concurrently can't
+        // both see the other's reference, and the last one sees only the map's
+        drop(self.lock.take());
+        if locks
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::KeyLocks;
+
+    #[test]
+    fn key_locks() {
+        let locks: KeyLocks<u32, Mutex<()>> = KeyLocks::new();
+        let a = locks.gate(&1);
+        let b = locks.gate(&1);
+        let c = locks.gate(&2);
+        assert_eq!(2, locks.len());
+        {
+            let _guard = a.lock().unwrap();
+            assert!(b.try_lock().is_err());
+            assert!(c.try_lock().is_ok());
+        }
+        drop((a, c));
+        assert_eq!(1, locks.len());
+        drop(b);
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn key_locks_concurrent_drops() {
+        let locks: Arc<KeyLocks<u32, Mutex<()>>> = Arc::new(KeyLocks::new());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let locks = locks.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        drop(locks.gate(&1));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(locks.is_empty());
+    }
+}
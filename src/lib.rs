@@ -10,21 +10,53 @@ for defining memoized functions.
 
 Memoized functions defined using `#[cached]`/`cached!` macros are thread-safe with the backing function-cache wrapped in mutex.
 The function-cache is **not** locked for the duration of the function's execution, so initial (on an empty cache)
-concurrent calls of long-running functions with the same arguments will each execute fully and each overwrite
-the memoized value as they complete. This mirrors the behavior of Python's `functools.lru_cache`.
+concurrent calls of long-running functions with the same arguments will each execute fully. The first to complete
+has its value memoized, the others return their own values without overwriting it. This mirrors the behavior
+of Python's `functools.lru_cache`.
 
 See [`cached::stores` docs](https://docs.rs/cached/latest/cached/stores/index.html) for details about the
 cache stores available.
 
+Without its default `std` feature, the crate is `no_std` and only needs `alloc`, e.g. for
+embedded firmware. The `Cached` trait, `UnboundCache`, `SizedCache` and `TickedCache`, whose
+values expire after a number of ticks counted by the caller, are then available. There's no
+default hasher without `std`, create them with a `BuildHasher` of your choice, e.g.
+`SizedCache::with_size_and_hasher`. The other stores require `std`, as do the macros, except
+for `#[cached(sync = "spin")]` with the `spin` feature: it guards a store set with `type` and
+`create`, e.g. an `ArrayCache`, with a `spin::Mutex` in a `spin::Lazy` static.
+
 ## Defining memoized functions using macros, `#[cached]` & `cached!`
 
 **Notes on the proc-macro version #[cached]**
 
 - enabled by default, but can be disabled by specifying `default-features = false`
-  (if you aren't using it and don't want to have to compile `syn`)
+  (if you aren't using it and don't want to have to compile `syn`), along with
+  `features = ["std"]` to keep the other `std` stores
 - supports most of the configuration params that the original `cached!` macros does
-- works with async functions
-- see `cached_proc_macro/src/lib.rs` for more details on macro arguments
+- works with async functions and functions returning `impl Future`
+- functions returning `impl Stream` have their items collected and replayed from the cache
+- `with_age = true` returns a `cached::Return` with the value's age, for `Age` headers or revalidation
+- read cached values without cloning them through `{name}_with_ref`, e.g.
+  `pages_with_ref(user, |pages| pages.len())`
+- a `{name}_cache_info()` function returning a `cached::CacheInfo` is generated for each cached function
+- enabling the `disable_cache` feature leaves `#[cached]` functions uncached, without any locking, their static staying empty
+- enabling the `tracing` feature makes `#[cached]` functions emit `tracing` events for cache hits, misses
+  and how long computing missing values took, with the name of the cache and the key
+- enabling the `sweeper` feature adds `cached::sweeper::Sweeper`, a background thread periodically purging
+  the expired values of registered timed caches
+- `#[io_cached]` caches functions returning a `Result` in stores whose operations can fail, e.g. remote
+  ones implementing `cached::IOCached`, mapping store errors with `map_error`
+- enabling the `disk_store` feature adds `cached::DiskCache`, an `IOCached` store keeping values on disk
+  across runs, with a per-value lifespan and a maximum size
+- enabling the `redis` feature adds `cached::RedisCache`, an `IOCached` store sharing values between
+  processes through Redis, and `cached::RedisInvalidator`, evicting the local copies of a `TieredCache`
+  in front of it when other processes change them
+- enabling the `redis_tls` feature connects `RedisCache` to `rediss://` URLs over TLS, with the root
+  certificates and client certificate of a `cached::RedisTls`
+- enabling the `redis_async` feature adds `cached::AsyncRedisCache`, an `IOCachedAsync` store on a
+  pool of Redis connections shared by concurrent calls
+- `#[once]` caches a single value per function, regardless of its arguments, optionally for `time` seconds
+- see `cached_proc_macro/docs/cached.md` for more details on macro arguments
 - see `examples/kitchen_sink_proc_macro.rs` for basic usage
 - relatively new so docs and tests need to be updated with details
 
@@ -94,9 +126,12 @@ Due to the requirements of storing arguments and return values in a global cache
 - Function arguments must either be owned and implement `Hash + Eq + Clone` OR the `cached_key!`
   macro must be used to convert arguments into an owned + `Hash + Eq + Clone` type.
 - Arguments and return values will be `cloned` in the process of insertion and retrieval.
+- `#[cached]` functions taking arguments by reference, e.g. `&str` or `&[T]`, key them by their
+  `ToOwned` form, e.g. `String` or `Vec<T>`.
 - `#[cached]`/`cached!` functions should not be used to produce side-effectual results!
-- `#[cached]`/`cached!` functions cannot live directly under `impl` blocks since `cached!` expands to a
-  `once_cell` initialization and a function definition.
+- `cached!` functions cannot live directly under `impl` blocks since `cached!` expands to a
+  `once_cell` initialization and a function definition. `#[cached]` methods can, as well as
+  associated functions with `in_impl = "Type"`.
 - `#[cached]`/`cached!` functions cannot accept `Self` types as a parameter.
 
 **NOTE**: Any custom cache that implements `cached::Cached` can be used with the `cached` macros in place of the built-ins.
@@ -297,36 +332,280 @@ cached_control!{
 
 */
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 pub extern crate once_cell;
 
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+#[cfg(feature = "async")]
+use core::convert::Infallible;
+use core::fmt;
+#[cfg(feature = "async")]
+use core::future::Future;
+use core::hash::Hash;
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+mod file_stamp;
+#[cfg(feature = "std")]
+pub mod interner;
+#[cfg(feature = "std")]
+mod io_cached;
+#[cfg(feature = "std")]
+mod key_locks;
 pub mod macros;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "serde")]
+mod snapshot;
 pub mod stores;
+#[cfg(feature = "sweeper")]
+pub mod sweeper;
 
-pub use stores::{SizedCache, TimedCache, UnboundCache};
-
+#[cfg(feature = "std")]
+pub use cache::{Cache, WritePolicy};
+#[cfg(feature = "std")]
+pub use file_stamp::FileStamp;
 #[cfg(feature = "proc_macro")]
+pub use io_cached::AsyncOnce;
+#[cfg(all(feature = "std", feature = "async"))]
+pub use io_cached::IOCachedAsync;
+#[cfg(feature = "std")]
+pub use io_cached::{IOCached, Namespaced, TieredCache};
+#[cfg(feature = "std")]
+pub use key_locks::{KeyGate, KeyLocks};
+#[cfg(feature = "serde")]
+pub use snapshot::{
+    dump, dump_with, load, load_or_else, load_with, JsonFormat, Persistent, SnapshotFormat,
+    SnapshotWorker,
+};
+#[cfg(feature = "redis_async")]
+pub use stores::AsyncRedisCache;
+#[cfg(feature = "moka")]
+pub use stores::MokaCache;
+#[cfg(feature = "redis_tls")]
+pub use stores::RedisTls;
+#[cfg(feature = "std")]
+pub use stores::{
+    hash_key, Admission, AppendCache, BuiltCache, CacheBuilder, CanExpire, CompactKey,
+    ConcurrentCache, EntryInfo, ExpiringValueCache, ExpiryStats, HashedKeyCache, Layered, LfuCache,
+    MemSize, MetadataCache, NoCache, SampledLruCache, SeedableState, ShardedCache, SieveCache,
+    TimeSource, TimedCache, TimedSizedCache, Tracked, TransformCache, WeakCache, WeightedCache,
+};
+pub use stores::{ArrayCache, AutoSize, EvictionReason, SizedCache, TickedCache, UnboundCache};
+#[cfg(feature = "disk_store")]
+pub use stores::{DiskCache, DiskCacheError};
+#[cfg(feature = "redis")]
+pub use stores::{RedisCache, RedisCacheError, RedisInvalidator};
+
+#[cfg(any(feature = "proc_macro", feature = "spin"))]
 pub mod proc_macro {
-    pub use cached_proc_macro::cached;
+    pub use cached_proc_macro::{cached, io_cached, once};
 }
 #[cfg(feature = "proc_macro")]
 pub use async_mutex;
+#[cfg(feature = "proc_macro")]
+pub use futures;
+#[cfg(feature = "parking_lot")]
+pub use parking_lot;
+#[cfg(feature = "spin")]
+pub use spin;
+#[cfg(feature = "tokio")]
+pub use tokio;
+#[cfg(feature = "tracing")]
+pub use tracing;
 
 /// Cache operations
+///
+/// Like `HashMap`, values are looked up and removed by any borrowed form of the key,
+/// e.g. a `&str` for `String` keys, without allocating a key:
+///
+/// ```rust
+/// use cached::{Cached, SizedCache};
+///
+/// let mut cache = SizedCache::with_size(10);
+/// cache.cache_set("answer".to_string(), 42);
+/// assert_eq!(cache.cache_get("answer"), Some(&42));
+/// ```
 pub trait Cached<K, V> {
     /// Attempt to retrieve a cached value
-    fn cache_get(&mut self, k: &K) -> Option<&V>;
+    fn cache_get<Q>(&mut self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
 
     /// Attempt to retrieve a cached value with mutable access
-    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V>;
+    fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    /// Look up every key of `keys`, e.g. a slice, in one pass, returning clones of the
+    /// cached values along with their keys, and the keys that missed
+    ///
+    /// Each key counts as a lookup, like `cache_get`.
+    fn cache_get_many<'k, Q, I>(&mut self, keys: I) -> (Vec<(&'k Q, V)>, Vec<&'k Q>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'k,
+        I: IntoIterator<Item = &'k Q>,
+        V: Clone,
+    {
+        let mut hits = Vec::new();
+        let mut misses = Vec::new();
+        for k in keys {
+            match self.cache_get(k) {
+                Some(v) => hits.push((k, v.clone())),
+                None => misses.push(k),
+            }
+        }
+        (hits, misses)
+    }
+
+    /// Attempt to retrieve a cached value without updating the cache, e.g. its recency
+    /// order or hit counters. Returns `None` for stores that can't look up values
+    /// without updating them.
+    fn cache_peek<Q>(&self, _k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        None
+    }
+
+    /// Attempt to retrieve a cached value along with the key it's stored under, like
+    /// `cache_peek`. Returns `None` for stores that don't keep their keys or can't look
+    /// up values without updating them.
+    fn cache_peek_key_value<Q>(&self, _k: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        None
+    }
+
+    /// Return when the value cached for `k` was inserted and last accessed, and how many
+    /// lookups found it, without updating them. Returns `None` for stores that don't track
+    /// it, wrap them in a `MetadataCache` to do so.
+    #[cfg(feature = "std")]
+    fn cache_entry_info<Q>(&self, _k: &Q) -> Option<EntryInfo>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        None
+    }
 
     /// Insert a key, value pair and return the previous value
     fn cache_set(&mut self, k: K, v: V) -> Option<V>;
 
+    /// Insert every key, value pair of `entries`, e.g. to warm the cache up with
+    /// known-hot entries at startup under a single lock:
+    ///
+    /// ```rust
+    /// use cached::proc_macro::cached;
+    /// use cached::Cached;
+    ///
+    /// #[cached(size = 100)]
+    /// fn square(n: u64) -> u64 {
+    ///     n * n
+    /// }
+    ///
+    /// SQUARE.lock().unwrap().cache_extend((0..10).map(|n| (n, n * n)));
+    /// assert_eq!(SQUARE.lock().unwrap().cache_size(), 10);
+    /// ```
+    fn cache_extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (k, v) in entries {
+            self.cache_set(k, v);
+        }
+    }
+
+    /// Insert every key, value pair of `entries` and return their previous values,
+    /// in order
+    fn cache_set_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) -> Vec<Option<V>> {
+        entries
+            .into_iter()
+            .map(|(k, v)| self.cache_set(k, v))
+            .collect()
+    }
+
+    /// Insert a key, value pair that expires after `seconds` instead of the cache's
+    /// lifespan, and return the previous value. Stores without per-value lifespans
+    /// ignore `seconds` and insert it like `cache_set`.
+    fn cache_set_with_lifespan(&mut self, k: K, v: V, _seconds: u64) -> Option<V> {
+        self.cache_set(k, v)
+    }
+
+    /// Insert a key, value pair only if the key isn't cached yet, e.g. to keep a value
+    /// cached concurrently while `v` was computed. Returns whether `v` was inserted.
+    ///
+    /// The default implementation uses `cache_get_or_set_with`, so it counts as a lookup
+    /// in stores that track hits and misses.
+    fn cache_insert_if_absent(&mut self, k: K, v: V) -> bool {
+        let mut inserted = false;
+        self.cache_get_or_set_with(k, || {
+            inserted = true;
+            v
+        });
+        inserted
+    }
+
     /// Get or insert a key, value pair
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V;
 
     /// Remove a cached value
-    fn cache_remove(&mut self, k: &K) -> Option<V>;
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    /// Remove the cached values whose keys match `f`, e.g. every key of a tuple
+    /// starting with some user id. Returns the number of values removed, `None`
+    /// for stores that can't go through their keys.
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, _f: F) -> Option<usize> {
+        None
+    }
+
+    /// Keep only the cached values for which `f` returns true, e.g. to invalidate the
+    /// values of a tenant after its data changed. Returns the number of values removed,
+    /// `None` for stores that can't go through their values.
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, _f: F) -> Option<usize> {
+        None
+    }
+
+    /// Exempt the value cached for `k` from eviction to make room for others, e.g. a
+    /// configuration every call needs, returning whether there is such a value. Pinned
+    /// values are still removed by `cache_remove` and still expire in timed stores.
+    /// Returns `false` for stores that can't pin values.
+    fn cache_pin<Q>(&mut self, _k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        false
+    }
+
+    /// Let the value cached for `k` be evicted again, returning whether it was pinned
+    fn cache_unpin<Q>(&mut self, _k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        false
+    }
+
+    /// Remove all the expired values, even those no call accesses again, which stores
+    /// that expire values lazily keep until then. Returns the number of values removed,
+    /// `None` for stores whose values don't expire.
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        None
+    }
 
     /// Remove all cached values. Keeps the allocated memory for reuse.
     fn cache_clear(&mut self);
@@ -352,6 +631,11 @@ pub trait Cached<K, V> {
         None
     }
 
+    /// Set the cache capacity, returns the old value
+    fn cache_set_capacity(&mut self, _size: usize) -> Option<usize> {
+        None
+    }
+
     /// Return the lifespan of cached values (time to eviction)
     fn cache_lifespan(&self) -> Option<u64> {
         None
@@ -361,4 +645,291 @@ pub trait Cached<K, V> {
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
     }
+
+    /// Remove the lifespan of cached values so they no longer expire, returns the old
+    /// value. A no-op for stores whose values don't expire.
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        None
+    }
+}
+
+/// Snapshot of a cache's size, metrics and configuration
+///
+/// Returned by the `{name}_cache_info` functions generated by `#[cached]`, e.g. to
+/// report the health of every cached function from a status endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheInfo {
+    /// Number of cached values
+    pub size: usize,
+    /// Maximum number of cached values, if bounded
+    pub capacity: Option<usize>,
+    /// Number of lookups that found a value, if the store counts them
+    pub hits: Option<u64>,
+    /// Number of lookups that didn't find a value, if the store counts them
+    pub misses: Option<u64>,
+    /// Lifespan of cached values in seconds, if they expire
+    pub lifespan: Option<u64>,
+}
+
+impl CacheInfo {
+    /// Take a snapshot of `cache`
+    pub fn new<K, V, C: Cached<K, V> + ?Sized>(cache: &C) -> CacheInfo {
+        CacheInfo {
+            size: cache.cache_size(),
+            capacity: cache.cache_capacity(),
+            hits: cache.cache_hits(),
+            misses: cache.cache_misses(),
+            lifespan: cache.cache_lifespan(),
+        }
+    }
+
+    /// Return the fraction of lookups that found a value, if any were counted
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let hits = self.hits?;
+        let lookups = hits + self.misses?;
+        if lookups == 0 {
+            return None;
+        }
+        Some(hits as f64 / lookups as f64)
+    }
+}
+
+/// Async cache operations, for stores used directly from async code
+///
+/// A missing value is computed by awaiting the future returned by `f` and inserted,
+/// all under a single `&mut` borrow of the store, so calls holding the store behind an
+/// async mutex fill a miss without blocking the executor:
+///
+/// ```rust
+/// # async fn run() {
+/// use cached::{CachedAsync, SizedCache};
+///
+/// let mut cache = SizedCache::with_size(10);
+/// let value = cache.get_or_set_with(1, || async { 100 }).await;
+/// assert_eq!(*value, 100);
+/// let value: Result<_, ()> = cache.try_get_or_set_with(1, || async { Err(()) }).await;
+/// assert_eq!(value, Ok(&mut 100));
+/// # }
+/// ```
+///
+/// Implementations may write the methods as `async fn`.
+#[cfg(feature = "async")]
+pub trait CachedAsync<K, V> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    fn get_or_set_with<'a, F, Fut>(&'a mut self, k: K, f: F) -> impl Future<Output = &'a mut V>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        async move {
+            let f = || async { Ok::<V, Infallible>(f().await) };
+            match self.try_get_or_set_with(k, f).await {
+                Ok(value) => value,
+                Err(never) => match never {},
+            }
+        }
+    }
+
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss.
+    /// If it fails, nothing is cached and its error is returned.
+    fn try_get_or_set_with<'a, F, Fut, E>(
+        &'a mut self,
+        k: K,
+        f: F,
+    ) -> impl Future<Output = Result<&'a mut V, E>>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>;
+}
+
+/// Shared handle access for caches holding `Arc` values
+///
+/// Implemented for every `Cached` store whose values are `Arc<T>`, such as the stores
+/// backing `#[cached(wrap = "arc")]` functions.
+pub trait CachedArc<K, T>: Cached<K, Arc<T>> {
+    /// Attempt to retrieve a cached value, returning a new handle to it
+    /// instead of cloning the underlying data
+    fn cache_get_arc<Q>(&mut self, k: &Q) -> Option<Arc<T>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache_get(k).map(Arc::clone)
+    }
+}
+
+impl<K, T, C: Cached<K, Arc<T>> + ?Sized> CachedArc<K, T> for C {}
+
+/// Object-safe cache operations for a fixed key and value type
+///
+/// `Cached` can't be made into a trait object, its lookups are generic over the borrowed
+/// form of the key. This subset takes the key itself, so stores of different types can be
+/// held behind a `Box<dyn CachedDyn<K, V>>`, e.g. to pick one from a configuration file.
+/// It's implemented for every `Cached` store.
+///
+/// ```rust
+/// use cached::{CachedDyn, SizedCache, UnboundCache};
+///
+/// let mut caches: Vec<Box<dyn CachedDyn<u32, u32>>> = vec![
+///     Box::new(SizedCache::with_size(10)),
+///     Box::new(UnboundCache::new()),
+/// ];
+/// for cache in caches.iter_mut() {
+///     cache.set(1, 100);
+///     assert_eq!(cache.get(&1), Some(&100));
+///     cache.clear();
+///     assert_eq!(cache.info().size, 0);
+/// }
+/// ```
+pub trait CachedDyn<K, V> {
+    /// Attempt to retrieve a cached value, see `Cached::cache_get`
+    fn get(&mut self, k: &K) -> Option<&V>;
+
+    /// Insert a key, value pair and return the previous value, see `Cached::cache_set`
+    fn set(&mut self, k: K, v: V) -> Option<V>;
+
+    /// Remove a cached value, see `Cached::cache_remove`
+    fn remove(&mut self, k: &K) -> Option<V>;
+
+    /// Remove all cached values, see `Cached::cache_clear`
+    fn clear(&mut self);
+
+    /// Take a snapshot of the cache's size, metrics and configuration
+    fn info(&self) -> CacheInfo;
+
+    /// Set the size limit, see `Cached::cache_set_capacity`
+    fn set_capacity(&mut self, size: usize) -> Option<usize>;
+
+    /// Set the lifespan of cached values in seconds, see `Cached::cache_set_lifespan`
+    fn set_lifespan(&mut self, seconds: u64) -> Option<u64>;
+}
+
+impl<K: Hash + Eq, V, C: Cached<K, V>> CachedDyn<K, V> for C {
+    fn get(&mut self, k: &K) -> Option<&V> {
+        self.cache_get(k)
+    }
+
+    fn set(&mut self, k: K, v: V) -> Option<V> {
+        self.cache_set(k, v)
+    }
+
+    fn remove(&mut self, k: &K) -> Option<V> {
+        self.cache_remove(k)
+    }
+
+    fn clear(&mut self) {
+        self.cache_clear();
+    }
+
+    fn info(&self) -> CacheInfo {
+        CacheInfo::new(self)
+    }
+
+    fn set_capacity(&mut self, size: usize) -> Option<usize> {
+        self.cache_set_capacity(size)
+    }
+
+    fn set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.cache_set_lifespan(seconds)
+    }
+}
+
+/// A value returned by a cached function, along with where it came from
+///
+/// Returned by `#[cached(with_age = true)]` functions, e.g. to emit HTTP `Age`
+/// headers or to revalidate values older than some limit, and by
+/// `#[cached(with_cached_flag = true)]` functions, which leave `age` at zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Return<T> {
+    /// The returned value
+    pub value: T,
+    /// Whether the value was retrieved from the cache instead of computed by this call
+    pub was_cached: bool,
+    /// Time elapsed since the value was computed, zero if computed by this call
+    pub age: Duration,
+}
+
+impl<T> Return<T> {
+    /// Wrap a value that was just computed
+    pub fn new(value: T) -> Return<T> {
+        Return {
+            value,
+            was_cached: false,
+            age: Duration::from_secs(0),
+        }
+    }
+
+    /// Wrap a value retrieved from the cache, computed `age` ago
+    pub fn cached(value: T, age: Duration) -> Return<T> {
+        Return {
+            value,
+            was_cached: true,
+            age,
+        }
+    }
+
+    /// Return whether the value is older than `max_age`
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age > max_age
+    }
+}
+
+impl<T> Deref for Return<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
 }
+
+impl<T> DerefMut for Return<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// Error returned by `#[cached(result = true, on_poison = "error")]` functions when
+/// their cache's lock was poisoned by a panic
+///
+/// The function's error type must implement `From<LockPoisoned>`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockPoisoned;
+
+impl fmt::Display for LockPoisoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cache lock poisoned by a panic")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LockPoisoned {}
+
+/// Error returned by the `try_` constructors of the stores, e.g. `SizedCache::try_with_size`,
+/// and by `registry::apply`, for a size or lifespan the store can't work with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The size is 0, the store couldn't hold any value
+    ZeroSize,
+    /// The lifespan is 0, every value would expire right away
+    ZeroLifespan,
+    /// The number of entries sampled per eviction is 0
+    ZeroSamples,
+    /// No store supports the combination of options given to a `CacheBuilder`
+    Unsupported,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigError::ZeroSize => "cache size must be greater than zero",
+            ConfigError::ZeroLifespan => "cache lifespan must be greater than zero",
+            ConfigError::ZeroSamples => "cache samples must be greater than zero",
+            ConfigError::Unsupported => "no cache store supports this combination of options",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigError {}
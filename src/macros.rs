@@ -29,7 +29,7 @@ macro_rules! cached {
             }
             let val = (||$body)();
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::Cached::cache_insert_if_absent(&mut *cache, key, val.clone());
             val
         }
     };
@@ -54,7 +54,7 @@ macro_rules! cached_key {
             }
             let val = (||$body)();
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::Cached::cache_insert_if_absent(&mut *cache, key, val.clone());
             val
         }
     };
@@ -82,7 +82,7 @@ macro_rules! cached_result {
             let val = ret?;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::Cached::cache_insert_if_absent(&mut *cache, key, val.clone());
             Ok(val)
         }
     };
@@ -111,7 +111,7 @@ macro_rules! cached_key_result {
             let val = ret?;
 
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, val.clone());
+            $crate::Cached::cache_insert_if_absent(&mut *cache, key, val.clone());
             Ok(val)
         }
     };
@@ -143,7 +143,7 @@ macro_rules! cached_control {
             let $body_value = (||$body)();
             let $set_value = $post_exec;
             let mut cache = $cachename.lock().unwrap();
-            $crate::Cached::cache_set(&mut *cache, key, $pre_set);
+            $crate::Cached::cache_insert_if_absent(&mut *cache, key, $pre_set);
             let $ret_value = $set_value;
             $return
         }
@@ -0,0 +1,452 @@
+/*!
+Process-wide registry of named caches, reconfigured at runtime
+
+Caches are registered under a name along with the lock guarding them, e.g. the
+`Mutex` behind a `cached!` static or a `Cache` shared in an `Arc`. A new
+`CacheConfig` can then be applied to all of them at once, e.g. after reloading a
+configuration file, resizing and re-timing the stores in place without dropping
+their contents. The registered caches can also be reported on with `report` and
+cleared with `clear_all`, whatever their store or key and value types.
+
+Caches backed by a remote store, e.g. a `RedisCache`, can register a health check with
+`register_health`, for readiness probes to include the state of their connection with
+`health_report`.
+
+```rust
+use std::sync::{Arc, Mutex};
+use cached::registry::{self, CacheConfig, CacheSettings};
+use cached::{Cached, SizedCache};
+
+let cache = Arc::new(Mutex::new(SizedCache::<u32, u32>::with_size(100)));
+let switch = registry::register("squares", cache.clone());
+
+let config = CacheConfig::new().cache(
+    "squares",
+    CacheSettings {
+        size: Some(10),
+        ..Default::default()
+    },
+);
+registry::apply(&config).unwrap();
+assert_eq!(Some(10), cache.lock().unwrap().cache_capacity());
+assert!(switch.is_enabled());
+```
+*/
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+use super::{CacheInfo, CachedDyn, ConfigError};
+
+/// Settings applied to a single registered cache, `None` leaves a setting unchanged
+///
+/// Settings the cache's store doesn't support, e.g. a lifespan for a `SizedCache`,
+/// are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheSettings {
+    /// New size limit, see `Cached::cache_set_capacity`
+    pub size: Option<usize>,
+    /// New lifespan in seconds, see `Cached::cache_set_lifespan`
+    pub lifespan: Option<u64>,
+    /// Enable or disable the cache. Disabling a cache clears it.
+    pub enabled: Option<bool>,
+}
+
+impl CacheSettings {
+    /// Check the settings can be applied, without a size or lifespan of 0
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.size == Some(0) {
+            return Err(ConfigError::ZeroSize);
+        }
+        if self.lifespan == Some(0) {
+            return Err(ConfigError::ZeroLifespan);
+        }
+        Ok(())
+    }
+}
+
+/// Settings for any number of registered caches, by name
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheConfig {
+    caches: HashMap<String, CacheSettings>,
+}
+
+impl CacheConfig {
+    /// Creates an empty configuration, leaving every cache unchanged
+    pub fn new() -> CacheConfig {
+        CacheConfig::default()
+    }
+
+    /// Apply `settings` to the cache registered as `name`
+    pub fn cache(mut self, name: &str, settings: CacheSettings) -> CacheConfig {
+        self.caches.insert(name.to_string(), settings);
+        self
+    }
+
+    /// Return the settings for the cache registered as `name`
+    pub fn get(&self, name: &str) -> Option<&CacheSettings> {
+        self.caches.get(name)
+    }
+}
+
+/// Whether a registered cache is currently enabled
+///
+/// The registry can't keep callers from using a disabled cache, code using it should
+/// check the switch and bypass the cache while it's off.
+#[derive(Clone, Debug)]
+pub struct Switch(Arc<AtomicBool>);
+
+impl Switch {
+    /// Return whether the cache is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Health of a cache's remote store, as reported by `health`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Health {
+    /// The store answered
+    Up,
+    /// The store couldn't be reached, or failed to answer
+    Down,
+}
+
+/// Type-erased access to a registered cache
+trait Reconfigure: Send + Sync {
+    fn reconfigure(&self, settings: &CacheSettings);
+    fn clear(&self);
+    fn info(&self) -> CacheInfo;
+}
+
+struct Handle<K, V, H> {
+    cache: H,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V, C, H> Reconfigure for Handle<K, V, H>
+where
+    C: CachedDyn<K, V> + ?Sized,
+    H: Deref<Target = Mutex<C>> + Send + Sync,
+{
+    fn reconfigure(&self, settings: &CacheSettings) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(size) = settings.size {
+            cache.set_capacity(size);
+        }
+        if let Some(seconds) = settings.lifespan {
+            cache.set_lifespan(seconds);
+        }
+    }
+
+    fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn info(&self) -> CacheInfo {
+        self.cache.lock().unwrap().info()
+    }
+}
+
+struct Registered {
+    cache: Box<dyn Reconfigure>,
+    enabled: Arc<AtomicBool>,
+}
+
+static REGISTRY: Lazy<Mutex<HashMap<String, Registered>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+type HealthCheck = Arc<dyn Fn() -> Health + Send + Sync>;
+
+static HEALTH: Lazy<Mutex<HashMap<String, HealthCheck>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Register `cache` as `name`, replacing any cache already registered under that name,
+/// and return its enabled switch. `cache` is either a `&'static Mutex` or an `Arc<Mutex>`,
+/// of any `Cached` store or of a `dyn CachedDyn`.
+pub fn register<K, V, C, H>(name: &str, cache: H) -> Switch
+where
+    K: 'static,
+    V: 'static,
+    C: CachedDyn<K, V> + ?Sized + 'static,
+    H: Deref<Target = Mutex<C>> + Send + Sync + 'static,
+{
+    let enabled = Arc::new(AtomicBool::new(true));
+    let registered = Registered {
+        cache: Box::new(Handle {
+            cache,
+            _marker: PhantomData,
+        }),
+        enabled: enabled.clone(),
+    };
+    REGISTRY
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), registered);
+    Switch(enabled)
+}
+
+/// Register `check` as the health check of the cache `name`, replacing any check already
+/// registered under that name, e.g. `move || redis.health()` for a `RedisCache` in an `Arc`
+pub fn register_health<F>(name: &str, check: F)
+where
+    F: Fn() -> Health + Send + Sync + 'static,
+{
+    HEALTH
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), Arc::new(check));
+}
+
+/// Remove the cache registered as `name` along with its health check, returning whether
+/// there was either
+pub fn unregister(name: &str) -> bool {
+    let cache = REGISTRY.lock().unwrap().remove(name).is_some();
+    let health = HEALTH.lock().unwrap().remove(name).is_some();
+    cache || health
+}
+
+/// Run the health check of the cache registered as `name`, `None` if it has none
+pub fn health(name: &str) -> Option<Health> {
+    // checks may block on the network, they run without holding the registry
+    let check = HEALTH.lock().unwrap().get(name).cloned()?;
+    Some(check())
+}
+
+/// Run every registered health check, returning their results along with the names of
+/// their caches, sorted by name
+pub fn health_report() -> Vec<(String, Health)> {
+    let mut checks: Vec<_> = HEALTH
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, check)| (name.clone(), check.clone()))
+        .collect();
+    checks.sort_by(|a, b| a.0.cmp(&b.0));
+    checks
+        .into_iter()
+        .map(|(name, check)| (name, check()))
+        .collect()
+}
+
+/// Return the names of all the registered caches
+pub fn names() -> Vec<String> {
+    REGISTRY.lock().unwrap().keys().cloned().collect()
+}
+
+/// Return whether the cache registered as `name` is enabled, `None` if there is no such cache
+pub fn is_enabled(name: &str) -> Option<bool> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .get(name)
+        .map(|registered| registered.enabled.load(Ordering::Acquire))
+}
+
+/// Return a snapshot of the cache registered as `name`, `None` if there is no such cache
+pub fn info(name: &str) -> Option<CacheInfo> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .get(name)
+        .map(|registered| registered.cache.info())
+}
+
+/// Return a snapshot of every registered cache along with its name, sorted by name,
+/// e.g. to report on all of them from a status endpoint
+pub fn report() -> Vec<(String, CacheInfo)> {
+    let mut report: Vec<_> = REGISTRY
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, registered)| (name.clone(), registered.cache.info()))
+        .collect();
+    report.sort_by(|a, b| a.0.cmp(&b.0));
+    report
+}
+
+/// Clear the cache registered as `name`, returning whether there was one
+pub fn clear(name: &str) -> bool {
+    match REGISTRY.lock().unwrap().get(name) {
+        Some(registered) => {
+            registered.cache.clear();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Clear every registered cache
+pub fn clear_all() {
+    for registered in REGISTRY.lock().unwrap().values() {
+        registered.cache.clear();
+    }
+}
+
+/// Apply `config` to the registered caches
+///
+/// The registry stays locked while the whole configuration is applied, so concurrent
+/// calls don't interleave and no cache is registered halfway through. Caches that
+/// aren't registered are skipped.
+///
+/// A configuration with a size or lifespan of 0 is rejected before any cache is
+/// reconfigured, the stores can't work with them.
+pub fn apply(config: &CacheConfig) -> Result<(), ConfigError> {
+    for settings in config.caches.values() {
+        settings.validate()?;
+    }
+    let registry = REGISTRY.lock().unwrap();
+    for (name, settings) in config.caches.iter() {
+        let registered = match registry.get(name) {
+            Some(registered) => registered,
+            None => continue,
+        };
+        registered.cache.reconfigure(settings);
+        match settings.enabled {
+            Some(true) => registered.enabled.store(true, Ordering::Release),
+            Some(false) => {
+                registered.enabled.store(false, Ordering::Release);
+                registered.cache.clear();
+            }
+            None => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use once_cell::sync::Lazy;
+
+    use super::{CacheConfig, CacheSettings, Health};
+    use crate::{Cached, CachedDyn, ConfigError, SizedCache, TimedCache, UnboundCache};
+
+    static TIMED: Lazy<Mutex<TimedCache<u32, u32>>> =
+        Lazy::new(|| Mutex::new(TimedCache::with_lifespan(60)));
+
+    #[test]
+    fn apply_config() {
+        let sized = Arc::new(Mutex::new(SizedCache::with_size(4)));
+        let sized_switch = super::register("registry_sized", sized.clone());
+        let timed_switch = super::register("registry_timed", &*TIMED);
+        for i in 0..4 {
+            sized.lock().unwrap().cache_set(i, i);
+        }
+        TIMED.lock().unwrap().cache_set(1, 1);
+        assert!(super::names().contains(&"registry_sized".to_string()));
+
+        let config = CacheConfig::new()
+            .cache(
+                "registry_sized",
+                CacheSettings {
+                    size: Some(2),
+                    ..Default::default()
+                },
+            )
+            .cache(
+                "registry_timed",
+                CacheSettings {
+                    lifespan: Some(30),
+                    enabled: Some(false),
+                    ..Default::default()
+                },
+            )
+            .cache("registry_missing", CacheSettings::default());
+        super::apply(&config).unwrap();
+
+        let sized = sized.lock().unwrap();
+        assert_eq!(Some(2), sized.cache_capacity());
+        assert_eq!(sized.key_order().collect::<Vec<_>>(), [&3, &2]);
+        assert!(sized_switch.is_enabled());
+        assert_eq!(Some(30), TIMED.lock().unwrap().cache_lifespan());
+        assert_eq!(0, TIMED.lock().unwrap().cache_size());
+        assert!(!timed_switch.is_enabled());
+        assert_eq!(Some(false), super::is_enabled("registry_timed"));
+        assert_eq!(None, super::is_enabled("registry_missing"));
+
+        assert!(super::unregister("registry_timed"));
+        assert!(!super::unregister("registry_timed"));
+    }
+
+    #[test]
+    fn report_dyn_caches() {
+        let unbound: Arc<Mutex<dyn CachedDyn<String, u32> + Send>> =
+            Arc::new(Mutex::new(UnboundCache::new()));
+        let sized: Arc<Mutex<dyn CachedDyn<String, u32> + Send>> =
+            Arc::new(Mutex::new(SizedCache::with_size(2)));
+        super::register("registry_report_unbound", unbound.clone());
+        super::register("registry_report_sized", sized.clone());
+        for cache in [&unbound, &sized] {
+            let mut cache = cache.lock().unwrap();
+            cache.set("a".to_string(), 1);
+            assert_eq!(cache.get(&"a".to_string()), Some(&1));
+        }
+
+        let report = super::report();
+        let names: Vec<_> = report.iter().map(|(name, _)| name.as_str()).collect();
+        let sized_index = names.binary_search(&"registry_report_sized").unwrap();
+        assert_eq!(report[sized_index].1.capacity, Some(2));
+        assert_eq!(report[sized_index].1.hits, Some(1));
+        assert!(names.binary_search(&"registry_report_unbound").is_ok());
+
+        assert!(super::clear("registry_report_sized"));
+        assert!(!super::clear("registry_report_missing"));
+        assert_eq!(super::info("registry_report_sized").unwrap().size, 0);
+        assert_eq!(super::info("registry_report_unbound").unwrap().size, 1);
+        assert_eq!(super::info("registry_report_missing"), None);
+        assert!(super::unregister("registry_report_unbound"));
+        assert!(super::unregister("registry_report_sized"));
+    }
+
+    #[test]
+    fn health_checks() {
+        let up = Arc::new(AtomicBool::new(true));
+        let check = up.clone();
+        super::register_health("registry_health_remote", move || {
+            if check.load(Ordering::Relaxed) {
+                Health::Up
+            } else {
+                Health::Down
+            }
+        });
+        super::register_health("registry_health_down", || Health::Down);
+        assert_eq!(super::health("registry_health_remote"), Some(Health::Up));
+        up.store(false, Ordering::Relaxed);
+        assert_eq!(super::health("registry_health_remote"), Some(Health::Down));
+        assert_eq!(super::health("registry_health_missing"), None);
+
+        let report = super::health_report();
+        let index = report
+            .binary_search_by(|(name, _)| name.as_str().cmp("registry_health_down"))
+            .unwrap();
+        assert_eq!(report[index].1, Health::Down);
+        assert!(super::unregister("registry_health_remote"));
+        assert!(super::unregister("registry_health_down"));
+        assert_eq!(super::health("registry_health_remote"), None);
+    }
+
+    #[test]
+    fn reject_zero_size() {
+        let sized = Arc::new(Mutex::new(SizedCache::<u32, u32>::with_size(4)));
+        super::register("registry_zero_size", sized.clone());
+        let config = CacheConfig::new().cache(
+            "registry_zero_size",
+            CacheSettings {
+                size: Some(0),
+                ..Default::default()
+            },
+        );
+        assert_eq!(super::apply(&config), Err(ConfigError::ZeroSize));
+
+        // neither the registry nor the cache were poisoned
+        assert_eq!(Some(4), sized.lock().unwrap().cache_capacity());
+        assert!(super::unregister("registry_zero_size"));
+    }
+}
@@ -0,0 +1,593 @@
+use std::borrow::Borrow;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Cached, EntryInfo};
+
+/// Encoding of the snapshots written by `dump_with` and read by `load_with`
+///
+/// `JsonFormat` is provided, other formats like bincode or MessagePack can be plugged in by
+/// implementing this trait over their serde support:
+///
+/// ```rust
+/// use std::io::{self, Read, Write};
+/// use cached::SnapshotFormat;
+/// use serde::de::DeserializeOwned;
+/// use serde::Serialize;
+///
+/// /// JSON with indentation, for snapshots read by humans
+/// struct PrettyJson;
+///
+/// impl SnapshotFormat for PrettyJson {
+///     fn write<C: Serialize, W: Write>(&self, writer: W, cache: &C) -> io::Result<()> {
+///         Ok(serde_json::to_writer_pretty(writer, cache)?)
+///     }
+///
+///     fn read<C: DeserializeOwned, R: Read>(&self, reader: R) -> io::Result<C> {
+///         Ok(serde_json::from_reader(reader)?)
+///     }
+/// }
+/// ```
+///
+/// Requires the `serde` feature.
+pub trait SnapshotFormat {
+    /// Encode `cache` to `writer`
+    fn write<C: Serialize, W: Write>(&self, writer: W, cache: &C) -> io::Result<()>;
+
+    /// Decode a cache from `reader`
+    fn read<C: DeserializeOwned, R: Read>(&self, reader: R) -> io::Result<C>;
+}
+
+/// Snapshots encoded as JSON, the format of `dump` and `load`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonFormat;
+
+impl SnapshotFormat for JsonFormat {
+    fn write<C: Serialize, W: Write>(&self, writer: W, cache: &C) -> io::Result<()> {
+        Ok(serde_json::to_writer(writer, cache)?)
+    }
+
+    fn read<C: DeserializeOwned, R: Read>(&self, reader: R) -> io::Result<C> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+/// Write a snapshot of `cache` to the file at `path` as JSON, to restore it with `load`
+///
+/// The snapshot is written to a temporary file next to `path` first, then renamed over
+/// it, so a crash while dumping leaves the previous snapshot intact:
+///
+/// ```rust,no_run
+/// use cached::{Cached, SizedCache};
+///
+/// let mut cache = SizedCache::with_size(100);
+/// cache.cache_set("answer".to_string(), 42);
+/// cached::dump(&cache, "cache.json").unwrap();
+///
+/// let mut cache: SizedCache<String, u32> = cached::load("cache.json").unwrap();
+/// assert_eq!(cache.cache_get(&"answer".to_string()), Some(&42));
+/// ```
+///
+/// Requires the `serde` feature.
+pub fn dump<C: Serialize, P: AsRef<Path>>(cache: &C, path: P) -> io::Result<()> {
+    dump_with(&JsonFormat, cache, path)
+}
+
+/// Write a snapshot of `cache` to the file at `path` in `format`, like `dump`
+///
+/// Requires the `serde` feature.
+pub fn dump_with<F, C, P>(format: &F, cache: &C, path: P) -> io::Result<()>
+where
+    F: SnapshotFormat,
+    C: Serialize,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    format.write(&mut writer, cache)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Restore a cache from a snapshot written by `dump` to the file at `path`
+///
+/// Requires the `serde` feature.
+pub fn load<C: DeserializeOwned, P: AsRef<Path>>(path: P) -> io::Result<C> {
+    load_with(&JsonFormat, path)
+}
+
+/// Restore a cache from a snapshot written by `dump_with` in `format` to the file at `path`
+///
+/// Requires the `serde` feature.
+pub fn load_with<F, C, P>(format: &F, path: P) -> io::Result<C>
+where
+    F: SnapshotFormat,
+    C: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    format.read(BufReader::new(File::open(path)?))
+}
+
+/// Restore a cache from a snapshot written by `dump` to the file at `path`, or create it with
+/// `create` if there is no such file or it can't be decoded
+///
+/// Used by `#[cached(preload = "path")]`, a snapshot is an optimization the cache can do without.
+///
+/// Requires the `serde` feature.
+pub fn load_or_else<C, P, G>(path: P, create: G) -> C
+where
+    C: DeserializeOwned,
+    P: AsRef<Path>,
+    G: FnOnce() -> C,
+{
+    load(path).unwrap_or_else(|_| create())
+}
+
+/// Wrapper saving a store to a snapshot file, and restoring it from there when opened
+///
+/// `open` loads the snapshot at `path` when there is one, otherwise it creates the store
+/// with `create`. The store is written back to `path` when the wrapper is dropped, or
+/// whenever `persist` is called:
+///
+/// ```rust,no_run
+/// use cached::{Cached, Persistent, SizedCache};
+///
+/// let mut cache = Persistent::open("cache.json", || SizedCache::with_size(100)).unwrap();
+/// if cache.cache_get(&"answer".to_string()).is_none() {
+///     cache.cache_set("answer".to_string(), 42);
+/// }
+/// // the next run finds the answer in `cache.json`
+/// ```
+///
+/// Errors writing the snapshot on drop are ignored, call `persist` to handle them. Statics,
+/// like the stores of `#[cached]` functions, are never dropped and have to be persisted
+/// explicitly, or periodically: `with_snapshot_every` writes a snapshot every given number
+/// of changes, and a `SnapshotWorker` every given interval, so a crash loses a bounded
+/// window of cached values.
+///
+/// Requires the `serde` feature.
+#[derive(Debug)]
+pub struct Persistent<C: Serialize, F: SnapshotFormat = JsonFormat> {
+    inner: C,
+    path: PathBuf,
+    format: F,
+    persist_on_drop: bool,
+    /// Changes since the last snapshot
+    pending: AtomicU64,
+    snapshot_every: Option<u64>,
+}
+
+impl<C: Serialize + DeserializeOwned> Persistent<C> {
+    /// Restore the store from the JSON snapshot at `path`, or create it with `create` if
+    /// there is no such file
+    pub fn open<P, G>(path: P, create: G) -> io::Result<Persistent<C>>
+    where
+        P: Into<PathBuf>,
+        G: FnOnce() -> C,
+    {
+        Persistent::open_with(JsonFormat, path, create)
+    }
+}
+
+impl<C: Serialize + DeserializeOwned, F: SnapshotFormat> Persistent<C, F> {
+    /// Restore the store from the snapshot at `path` in `format`, or create it with
+    /// `create` if there is no such file
+    pub fn open_with<P, G>(format: F, path: P, create: G) -> io::Result<Persistent<C, F>>
+    where
+        P: Into<PathBuf>,
+        G: FnOnce() -> C,
+    {
+        let path = path.into();
+        let inner = match load_with(&format, &path) {
+            Ok(inner) => inner,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => create(),
+            Err(e) => return Err(e),
+        };
+        Ok(Persistent {
+            inner,
+            path,
+            format,
+            persist_on_drop: true,
+            pending: AtomicU64::new(0),
+            snapshot_every: None,
+        })
+    }
+}
+
+impl<C: Serialize, F: SnapshotFormat> Persistent<C, F> {
+    /// Write a snapshot of the store to its file
+    pub fn persist(&self) -> io::Result<()> {
+        let pending = self.pending.swap(0, Ordering::Relaxed);
+        dump_with(&self.format, &self.inner, &self.path).inspect_err(|_| {
+            // the changes are still to be written
+            self.pending.fetch_add(pending, Ordering::Relaxed);
+        })
+    }
+
+    /// Whether to write a snapshot of the store when the wrapper is dropped, `true` by default
+    pub fn set_persist_on_drop(&mut self, persist_on_drop: bool) {
+        self.persist_on_drop = persist_on_drop;
+    }
+
+    /// Write a snapshot every `changes` changes of the store, e.g. values set or removed,
+    /// instead of only when dropped or persisted explicitly
+    ///
+    /// The snapshot is written by the change reaching `changes`, except for
+    /// `cache_get_or_set_with`, which leaves it to the next change. Errors writing it are
+    /// ignored, as on drop.
+    ///
+    /// Will panic if `changes` is 0.
+    pub fn with_snapshot_every(mut self, changes: u64) -> Persistent<C, F> {
+        if changes == 0 {
+            panic!("`changes` between snapshots of `Persistent` must be greater than zero.")
+        }
+        self.snapshot_every = Some(changes);
+        self
+    }
+
+    /// Return the number of changes since the last snapshot
+    pub fn pending_changes(&self) -> u64 {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Return the path of the snapshot file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the inner store
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Return the inner store mutably
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<C: Serialize, F: SnapshotFormat> Persistent<C, F> {
+    /// Count `changes` changes, writing a snapshot if they make enough
+    fn changed(&self, changes: u64) {
+        let pending = self.pending.fetch_add(changes, Ordering::Relaxed) + changes;
+        if matches!(self.snapshot_every, Some(every) if pending >= every) {
+            let _ = self.persist();
+        }
+    }
+
+    /// Count the changes reported by `cache_remove_where` or `cache_retain`
+    fn changed_some(&self, removed: Option<usize>) -> Option<usize> {
+        // stores that can't count what they removed may have removed anything
+        match removed {
+            Some(0) => (),
+            Some(n) => self.changed(n as u64),
+            None => self.changed(1),
+        }
+        removed
+    }
+}
+
+impl<C: Serialize, F: SnapshotFormat> Drop for Persistent<C, F> {
+    fn drop(&mut self) {
+        if self.persist_on_drop {
+            let _ = self.persist();
+        }
+    }
+}
+
+impl<K, V, C, F> Cached<K, V> for Persistent<C, F>
+where
+    C: Cached<K, V> + Serialize,
+    F: SnapshotFormat,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_get(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // the value may be changed through the reference, write the previous changes first
+        self.changed(0);
+        let value = self.inner.cache_get_mut(key);
+        if value.is_some() {
+            self.pending.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_peek(key)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_peek_key_value(key)
+    }
+
+    fn cache_entry_info<Q>(&self, key: &Q) -> Option<EntryInfo>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_entry_info(key)
+    }
+
+    fn cache_pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_pin(key)
+    }
+
+    fn cache_unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_unpin(key)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.inner.cache_set(key, val);
+        self.changed(1);
+        old
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        let old = self.inner.cache_set_with_lifespan(key, val, seconds);
+        self.changed(1);
+        old
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        let inserted = self.inner.cache_insert_if_absent(key, val);
+        if inserted {
+            self.changed(1);
+        }
+        inserted
+    }
+
+    fn cache_get_or_set_with<G: FnOnce() -> V>(&mut self, key: K, f: G) -> &mut V {
+        // the returned reference keeps the store borrowed, a due snapshot is left to the
+        // next change
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.inner.cache_get_or_set_with(key, f)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let old = self.inner.cache_remove(key);
+        if old.is_some() {
+            self.changed(1);
+        }
+        old
+    }
+    fn cache_remove_where<G: FnMut(&K) -> bool>(&mut self, f: G) -> Option<usize> {
+        let removed = self.inner.cache_remove_where(f);
+        self.changed_some(removed)
+    }
+    fn cache_retain<G: FnMut(&K, &V) -> bool>(&mut self, f: G) -> Option<usize> {
+        let removed = self.inner.cache_retain(f);
+        self.changed_some(removed)
+    }
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        let removed = self.inner.cache_flush_expired();
+        self.changed_some(removed)
+    }
+    fn cache_clear(&mut self) {
+        self.inner.cache_clear();
+        self.changed(1);
+    }
+    fn cache_reset(&mut self) {
+        self.inner.cache_reset();
+        self.changed(1);
+    }
+    fn cache_size(&self) -> usize {
+        self.inner.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        let old = self.inner.cache_set_capacity(size);
+        self.changed(1);
+        old
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.inner.cache_set_lifespan(seconds);
+        self.changed(1);
+        old
+    }
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        let old = self.inner.cache_unset_lifespan();
+        self.changed(1);
+        old
+    }
+}
+
+/// Handle to a background thread writing snapshots of a `Persistent` store at an interval
+///
+/// Every `interval`, the thread locks the store and writes a snapshot if it changed since
+/// the last one, so a crash loses at most about `interval` worth of changes:
+///
+/// ```rust,no_run
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+/// use cached::{Persistent, SizedCache, SnapshotWorker};
+///
+/// let cache = Arc::new(Mutex::new(
+///     Persistent::open("cache.json", || SizedCache::<u32, u32>::with_size(100)).unwrap(),
+/// ));
+/// let worker = SnapshotWorker::spawn(cache.clone(), Duration::from_secs(30));
+/// ```
+///
+/// Errors writing the snapshots are ignored, as on drop. The thread stops when the
+/// `SnapshotWorker` is dropped.
+#[derive(Debug)]
+pub struct SnapshotWorker {
+    _stop: Sender<()>,
+}
+
+impl SnapshotWorker {
+    /// Spawn a thread writing a snapshot of `persistent` every `interval` it changed in.
+    /// `persistent` is either a `&'static Mutex` or an `Arc<Mutex>`.
+    ///
+    /// Will panic if the thread can't be spawned
+    pub fn spawn<C, F, H>(persistent: H, interval: Duration) -> SnapshotWorker
+    where
+        C: Serialize,
+        F: SnapshotFormat,
+        H: Deref<Target = Mutex<Persistent<C, F>>> + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        thread::Builder::new()
+            .name("cached-snapshots".to_string())
+            .spawn(move || {
+                // nothing is ever sent, the channel disconnects when the handle is dropped
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let persistent = persistent.lock().unwrap_or_else(PoisonError::into_inner);
+                    if persistent.pending_changes() > 0 {
+                        let _ = persistent.persist();
+                    }
+                }
+            })
+            .expect("failed to spawn the cached snapshot thread");
+        SnapshotWorker { _stop: stop }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    use super::{dump, load, Persistent, SnapshotWorker};
+    use crate::{Cached, SizedCache, TimedCache};
+
+    #[test]
+    fn dump_and_load() {
+        let path = std::env::temp_dir().join(format!("cached-snapshot-{}", std::process::id()));
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, "one".to_string());
+        dump(&c, &path).unwrap();
+        let mut c: TimedCache<u32, String> = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(c.cache_get(&1).map(String::as_str), Some("one"));
+        assert!(load::<TimedCache<u32, String>, _>(&path).is_err());
+    }
+
+    #[test]
+    fn persistent() {
+        let path = std::env::temp_dir().join(format!("cached-persistent-{}", std::process::id()));
+        let mut c = Persistent::open(&path, || TimedCache::with_lifespan(60)).unwrap();
+        assert!(c.cache_get(&1).is_none());
+        c.cache_set(1, "one".to_string());
+        c.persist().unwrap();
+        c.cache_set(2, "two".to_string());
+        drop(c);
+
+        let mut c: Persistent<TimedCache<u32, String>> =
+            Persistent::open(&path, || unreachable!()).unwrap();
+        assert_eq!(c.cache_get(&1).map(String::as_str), Some("one"));
+        assert_eq!(c.cache_get(&2).map(String::as_str), Some("two"));
+        c.cache_clear();
+        c.set_persist_on_drop(false);
+        drop(c);
+
+        let c: Persistent<TimedCache<u32, String>> =
+            Persistent::open(&path, || unreachable!()).unwrap();
+        assert_eq!(c.cache_size(), 2);
+        std::fs::remove_file(c.path()).unwrap();
+    }
+
+    #[test]
+    fn snapshot_every_changes() {
+        let path = std::env::temp_dir().join(format!("cached-every-{}", std::process::id()));
+        let mut c = Persistent::open(&path, || SizedCache::with_size(10))
+            .unwrap()
+            .with_snapshot_every(2);
+        c.set_persist_on_drop(false);
+        c.cache_set(1, 1);
+        assert!(!path.exists());
+        assert_eq!(c.pending_changes(), 1);
+        c.cache_set(2, 2);
+        assert_eq!(c.pending_changes(), 0);
+        let snapshot: SizedCache<u32, u32> = load(&path).unwrap();
+        assert_eq!(snapshot.cache_size(), 2);
+
+        // reads aren't changes
+        c.cache_get(&1);
+        c.cache_remove(&3);
+        assert_eq!(c.pending_changes(), 0);
+        c.cache_remove(&1);
+        c.cache_clear();
+        let snapshot: SizedCache<u32, u32> = load(&path).unwrap();
+        assert_eq!(snapshot.cache_size(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_worker() {
+        let path = std::env::temp_dir().join(format!("cached-worker-{}", std::process::id()));
+        let c = Arc::new(Mutex::new(
+            Persistent::open(&path, || SizedCache::with_size(10)).unwrap(),
+        ));
+        c.lock().unwrap().set_persist_on_drop(false);
+        let worker = SnapshotWorker::spawn(c.clone(), Duration::from_millis(10));
+        c.lock().unwrap().cache_set(1, "one".to_string());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Ok(snapshot) = load::<SizedCache<u32, String>, _>(&path) {
+                assert_eq!(snapshot.cache_peek(&1).map(String::as_str), Some("one"));
+                break;
+            }
+            assert!(Instant::now() < deadline, "no snapshot was written");
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(c.lock().unwrap().pending_changes(), 0);
+        drop(worker);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
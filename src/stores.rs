@@ -3,14 +3,160 @@ Implementation of various caches
 
 */
 
-use std::cmp::Eq;
-use std::collections::HashMap;
-use std::hash::Hash;
-use std::time::Instant;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Eq;
+#[cfg(feature = "async")]
+use core::future::Future;
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::collections::{hash_map::Entry as StdEntry, HashMap as StdHashMap};
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+use hashbrown::hash_map::Entry;
+use hashbrown::{HashMap, HashTable};
 
 use super::Cached;
-
-use std::collections::hash_map::Entry;
+#[cfg(feature = "async")]
+use super::CachedAsync;
+#[cfg(feature = "std")]
+use super::ConfigError;
+
+#[cfg(feature = "std")]
+mod admission;
+#[cfg(feature = "std")]
+mod append;
+mod array;
+#[cfg(feature = "redis_async")]
+mod async_redis;
+mod auto_size;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod compact_key;
+#[cfg(feature = "std")]
+mod concurrent;
+#[cfg(feature = "disk_store")]
+mod disk;
+#[cfg(feature = "std")]
+mod dropper;
+#[cfg(feature = "std")]
+mod expiring;
+#[cfg(feature = "std")]
+mod hashed_key;
+#[cfg(feature = "std")]
+mod hasher;
+#[cfg(feature = "std")]
+mod layered;
+#[cfg(feature = "std")]
+mod lfu;
+mod listener;
+#[cfg(feature = "std")]
+mod mem_size;
+#[cfg(feature = "std")]
+mod metadata;
+#[cfg(feature = "moka")]
+mod moka;
+#[cfg(feature = "std")]
+mod no_cache;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "std")]
+mod sampled;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "std")]
+mod sieve;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod ticked;
+#[cfg(feature = "std")]
+mod time_source;
+#[cfg(feature = "std")]
+mod timed_sized;
+#[cfg(feature = "std")]
+mod timing_wheel;
+#[cfg(feature = "std")]
+mod transform;
+#[cfg(feature = "std")]
+mod weak;
+#[cfg(feature = "std")]
+mod weighted;
+
+use listener::Listener;
+#[cfg(feature = "std")]
+use time_source::Stamp;
+#[cfg(feature = "std")]
+use timing_wheel::TimingWheel;
+
+#[cfg(feature = "redis_async")]
+pub use self::async_redis::AsyncRedisCache;
+#[cfg(feature = "disk_store")]
+pub use self::disk::{DiskCache, DiskCacheError};
+#[cfg(feature = "moka")]
+pub use self::moka::MokaCache;
+#[cfg(feature = "redis_tls")]
+pub use self::redis::RedisTls;
+#[cfg(feature = "redis")]
+pub use self::redis::{RedisCache, RedisCacheError, RedisInvalidator};
+#[cfg(feature = "std")]
+pub use admission::Admission;
+#[cfg(feature = "std")]
+pub use append::AppendCache;
+pub use array::ArrayCache;
+pub use auto_size::AutoSize;
+#[cfg(feature = "std")]
+pub use builder::{BuiltCache, CacheBuilder};
+#[cfg(feature = "std")]
+pub use compact_key::CompactKey;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentCache;
+#[cfg(feature = "std")]
+pub use expiring::{CanExpire, ExpiringValueCache};
+#[cfg(feature = "std")]
+pub use hashed_key::{hash_key, HashedKeyCache};
+#[cfg(feature = "std")]
+pub use hasher::SeedableState;
+#[cfg(feature = "std")]
+pub use layered::Layered;
+#[cfg(feature = "std")]
+pub use lfu::LfuCache;
+pub use listener::EvictionReason;
+#[cfg(feature = "std")]
+pub use mem_size::MemSize;
+#[cfg(feature = "std")]
+pub use metadata::{EntryInfo, MetadataCache, Tracked};
+#[cfg(feature = "std")]
+pub use no_cache::NoCache;
+#[cfg(feature = "std")]
+pub use sampled::SampledLruCache;
+#[cfg(feature = "std")]
+pub use sharded::ShardedCache;
+#[cfg(feature = "std")]
+pub use sieve::SieveCache;
+pub use ticked::TickedCache;
+#[cfg(feature = "std")]
+pub use time_source::TimeSource;
+#[cfg(feature = "std")]
+pub use timed_sized::TimedSizedCache;
+#[cfg(feature = "std")]
+pub use transform::TransformCache;
+#[cfg(feature = "std")]
+pub use weak::WeakCache;
+#[cfg(feature = "std")]
+pub use weighted::WeightedCache;
+
+/// Rebuild `map` with a state seeded with `seed`, rehashing its entries,
+/// like `hasher::reseed` for the `hashbrown` maps of the stores below
+#[cfg(feature = "std")]
+fn reseed<K: Hash + Eq, V>(map: &mut HashMap<K, V, SeedableState>, seed: u64) {
+    let state = SeedableState::with_seed(seed);
+    let mut reseeded = HashMap::with_capacity_and_hasher(map.capacity(), state);
+    reseeded.extend(map.drain());
+    *map = reseeded;
+}
 
 /// Default unbounded cache
 ///
@@ -18,59 +164,111 @@ use std::collections::hash_map::Entry;
 ///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct UnboundCache<K, V> {
-    store: HashMap<K, V>,
+pub struct UnboundCache<
+    K,
+    V,
+    #[cfg(feature = "std")] S = SeedableState,
+    #[cfg(not(feature = "std"))] S,
+> {
+    store: HashMap<K, V, S>,
     hits: u64,
     misses: u64,
     initial_capacity: Option<usize>,
 }
 
-impl<K, V> PartialEq for UnboundCache<K, V>
+impl<K, V, S> PartialEq for UnboundCache<K, V, S>
 where
     K: Eq + Hash,
     V: PartialEq,
+    S: BuildHasher,
 {
-    fn eq(&self, other: &UnboundCache<K, V>) -> bool {
+    fn eq(&self, other: &UnboundCache<K, V, S>) -> bool {
         self.store.eq(&other.store)
     }
 }
 
-impl<K, V> Eq for UnboundCache<K, V>
+impl<K, V, S> Eq for UnboundCache<K, V, S>
 where
     K: Eq + Hash,
     V: PartialEq,
+    S: BuildHasher,
 {
 }
 
+#[cfg(feature = "std")]
 impl<K: Hash + Eq, V> UnboundCache<K, V> {
     /// Creates an empty `UnboundCache`
     #[allow(clippy::new_without_default)]
     pub fn new() -> UnboundCache<K, V> {
+        Self::with_hasher(SeedableState::new())
+    }
+
+    /// Creates an empty `UnboundCache` with a given pre-allocated capacity
+    pub fn with_capacity(size: usize) -> UnboundCache<K, V> {
+        Self::with_capacity_and_hasher(size, SeedableState::new())
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`
+    pub fn with_hash_seed(mut self, seed: u64) -> UnboundCache<K, V> {
+        reseed(&mut self.store, seed);
+        self
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> UnboundCache<K, V, S> {
+    /// Creates an empty `UnboundCache` hashing keys with `hasher`, e.g. a faster hasher
+    /// than the default one for small keys
+    pub fn with_hasher(hasher: S) -> UnboundCache<K, V, S> {
         UnboundCache {
-            store: Self::new_store(None),
+            store: Self::new_store(None, hasher),
             hits: 0,
             misses: 0,
             initial_capacity: None,
         }
     }
 
-    /// Creates an empty `UnboundCache` with a given pre-allocated capacity
-    pub fn with_capacity(size: usize) -> UnboundCache<K, V> {
+    /// Creates an empty `UnboundCache` with a given pre-allocated capacity, hashing
+    /// keys with `hasher`
+    pub fn with_capacity_and_hasher(size: usize, hasher: S) -> UnboundCache<K, V, S> {
         UnboundCache {
-            store: Self::new_store(Some(size)),
+            store: Self::new_store(Some(size), hasher),
             hits: 0,
             misses: 0,
             initial_capacity: Some(size),
         }
     }
 
-    fn new_store(capacity: Option<usize>) -> HashMap<K, V> {
-        capacity.map_or_else(HashMap::new, HashMap::with_capacity)
+    /// Return the hasher of the keys
+    pub fn hasher(&self) -> &S {
+        self.store.hasher()
+    }
+
+    /// Return an iterator of the cached key, value pairs, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.store.iter()
+    }
+
+    /// Return an iterator of the cached keys, in arbitrary order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.store.keys()
+    }
+
+    /// Return an iterator of the cached values, in arbitrary order
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.store.values()
+    }
+
+    fn new_store(capacity: Option<usize>, state: S) -> HashMap<K, V, S> {
+        HashMap::with_capacity_and_hasher(capacity.unwrap_or(0), state)
     }
 }
 
-impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
-    fn cache_get(&mut self, key: &K) -> Option<&V> {
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cached<K, V> for UnboundCache<K, V, S> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         match self.store.get(key) {
             Some(v) => {
                 self.hits += 1;
@@ -82,7 +280,25 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
             }
         }
     }
-    fn cache_get_mut(&mut self, key: &K) -> std::option::Option<&mut V> {
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get(key)
+    }
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get_key_value(key)
+    }
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         match self.store.get_mut(key) {
             Some(v) => {
                 self.hits += 1;
@@ -97,6 +313,15 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
         self.store.insert(key, val)
     }
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        match self.store.entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(vacant) => {
+                vacant.insert(val);
+                true
+            }
+        }
+    }
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
         match self.store.entry(key) {
             Entry::Occupied(occupied) => {
@@ -110,14 +335,28 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
             }
         }
     }
-    fn cache_remove(&mut self, k: &K) -> Option<V> {
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.store.remove(k)
     }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.store.len();
+        self.store.retain(|key, _| !f(key));
+        Some(before - self.store.len())
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.store.len();
+        self.store.retain(|key, value| f(key, value));
+        Some(before - self.store.len())
+    }
     fn cache_clear(&mut self) {
         self.store.clear();
     }
     fn cache_reset(&mut self) {
-        self.store = Self::new_store(self.initial_capacity);
+        self.store = Self::new_store(self.initial_capacity, self.store.hasher().clone());
     }
     fn cache_size(&self) -> usize {
         self.store.len()
@@ -130,6 +369,39 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
     }
 }
 
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> UnboundCache<K, V, S> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedAsync<K, V> for UnboundCache<K, V, S> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        Ok(match self.store.entry(key) {
+            Entry::Occupied(occupied) => {
+                self.hits += 1;
+                occupied.into_mut()
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                vacant.insert(f().await?)
+            }
+        })
+    }
+}
+
 /// Limited functionality doubly linked list using Vec as storage.
 #[derive(Clone, Debug)]
 struct LRUList<T> {
@@ -185,7 +457,7 @@ impl<T> LRUList<T> {
         self.link_after(index, Self::OCCUPIED);
     }
 
-    fn push_front(&mut self, value: Option<T>) -> usize {
+    fn push_front(&mut self, value: T) -> usize {
         if self.values[Self::FREE].next == Self::FREE {
             self.values.push(ListEntry::<T> {
                 value: None,
@@ -195,7 +467,7 @@ impl<T> LRUList<T> {
             self.values[Self::FREE].next = self.values.len() - 1;
         }
         let index = self.values[Self::FREE].next;
-        self.values[index].value = value;
+        self.values[index].value = Some(value);
         self.unlink(index);
         self.link_after(index, Self::OCCUPIED);
         index
@@ -207,13 +479,18 @@ impl<T> LRUList<T> {
         self.values[index].value.take().expect("invalid index")
     }
 
+    #[cfg(feature = "std")]
+    fn front(&self) -> usize {
+        self.values[Self::OCCUPIED].next
+    }
+
     fn back(&self) -> usize {
         self.values[Self::OCCUPIED].prev
     }
 
-    fn pop_back(&mut self) -> T {
-        let index = self.back();
-        self.remove(index)
+    /// Index of the entry before `index`, towards the front
+    fn prev(&self, index: usize) -> usize {
+        self.values[index].prev
     }
 
     fn get(&self, index: usize) -> &T {
@@ -224,10 +501,6 @@ impl<T> LRUList<T> {
         self.values[index].value.as_mut().expect("invalid index")
     }
 
-    fn set(&mut self, index: usize, value: T) -> Option<T> {
-        std::mem::replace(&mut self.values[index].value, Some(value))
-    }
-
     fn clear(&mut self) {
         self.values.clear();
         self.values.push(ListEntry::<T> {
@@ -242,7 +515,7 @@ impl<T> LRUList<T> {
         });
     }
 
-    fn iter(&self) -> LRUListIterator<T> {
+    fn iter(&self) -> LRUListIterator<'_, T> {
         LRUListIterator::<T> {
             list: self,
             index: Self::OCCUPIED,
@@ -271,38 +544,69 @@ impl<'a, T> Iterator for LRUListIterator<'a, T> {
     }
 }
 
+type DropEvicted<K, V> = fn(Vec<(K, V)>);
+
 /// Least Recently Used / `Sized` Cache
 ///
 /// Stores up to a specified size before beginning
 /// to evict the least recently used keys
 ///
+/// Keys are only stored once, alongside their values in the recency list.
+/// The lookup table only holds indices into that list.
+///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct SizedCache<K, V> {
-    store: HashMap<K, usize>,
+pub struct SizedCache<
+    K,
+    V,
+    #[cfg(feature = "std")] S = SeedableState,
+    #[cfg(not(feature = "std"))] S,
+> {
+    store: HashTable<usize>,
+    hash_builder: S,
     order: LRUList<(K, V)>,
     capacity: usize,
     hits: u64,
     misses: u64,
+    listener: Listener<K, V>,
+    auto_size: Option<AutoSize>,
+    #[cfg(feature = "std")]
+    admission: Option<Admission>,
+    /// Last value computed for a key that wasn't admitted
+    rejected: Option<V>,
+    /// Indices in the order list of the entries exempt from eviction
+    pinned: BTreeSet<usize>,
+    /// Number of entries evicted at once when the cache is full
+    eviction_batch: usize,
+    /// Drops batches of evicted entries, see `with_deferred_drops`
+    drop_evicted: Option<DropEvicted<K, V>>,
 }
 
-impl<K, V> PartialEq for SizedCache<K, V>
+impl<K, V, S> PartialEq for SizedCache<K, V, S>
 where
     K: Eq + Hash,
     V: PartialEq,
+    S: BuildHasher,
 {
-    fn eq(&self, other: &SizedCache<K, V>) -> bool {
-        self.store.eq(&other.store)
+    fn eq(&self, other: &SizedCache<K, V, S>) -> bool {
+        self.store.len() == other.store.len()
+            && self.order.iter().all(|(key, value)| {
+                other
+                    .find(key)
+                    .is_some_and(|index| other.order.get(index).1 == *value)
+            })
     }
 }
 
-impl<K, V> Eq for SizedCache<K, V>
+impl<K, V, S> Eq for SizedCache<K, V, S>
 where
     K: Eq + Hash,
     V: PartialEq,
+    S: BuildHasher,
 {
 }
 
+#[cfg(feature = "std")]
 impl<K: Hash + Eq, V> SizedCache<K, V> {
     #[deprecated(since = "0.5.1", note = "method renamed to `with_size`")]
     pub fn with_capacity(size: usize) -> SizedCache<K, V> {
@@ -311,18 +615,173 @@ impl<K: Hash + Eq, V> SizedCache<K, V> {
 
     /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data
     pub fn with_size(size: usize) -> SizedCache<K, V> {
+        Self::with_size_and_hasher(size, SeedableState::new())
+    }
+
+    /// Like `with_size`, but returns an error instead of panicking if size is 0
+    pub fn try_with_size(size: usize) -> Result<SizedCache<K, V>, ConfigError> {
+        if size == 0 {
+            return Err(ConfigError::ZeroSize);
+        }
+        Ok(Self::with_size(size))
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`
+    pub fn with_hash_seed(mut self, seed: u64) -> SizedCache<K, V> {
+        self.hash_builder = SeedableState::with_seed(seed);
+        let Self {
+            store,
+            hash_builder,
+            order,
+            ..
+        } = &mut self;
+        let indices = std::mem::replace(store, HashTable::with_capacity(store.capacity()));
+        for index in indices {
+            let hash = hash_builder.hash_one(&order.get(index).0);
+            store.insert_unique(hash, index, |&i| hash_builder.hash_one(&order.get(i).0));
+        }
+        self
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> SizedCache<K, V, S> {
+    /// Creates a new `SizedCache` with a given size limit, hashing keys with `hasher`,
+    /// e.g. a faster hasher than the default one for small keys
+    pub fn with_size_and_hasher(size: usize, hasher: S) -> SizedCache<K, V, S> {
         if size == 0 {
             panic!("`size` of `SizedCache` must be greater than zero.")
         }
         SizedCache {
-            store: HashMap::with_capacity(size),
+            store: HashTable::with_capacity(size),
+            hash_builder: hasher,
             order: LRUList::<(K, V)>::with_capacity(size),
             capacity: size,
             hits: 0,
             misses: 0,
+            listener: Listener::new(),
+            auto_size: None,
+            #[cfg(feature = "std")]
+            admission: None,
+            rejected: None,
+            pinned: BTreeSet::new(),
+            eviction_batch: 1,
+            drop_evicted: None,
+        }
+    }
+
+    /// Return the hasher of the keys
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Only admit new keys once they've been looked up often enough, see `Admission`
+    ///
+    /// Keys are counted on every miss, `cache_set` drops values for keys that haven't
+    /// been admitted yet. `cache_get_or_set_with` still returns the computed value,
+    /// without storing it.
+    #[cfg(feature = "std")]
+    pub fn with_admission(mut self, admission: Admission) -> SizedCache<K, V, S> {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// Let `auto_size` adjust the size limit based on the observed hit ratio,
+    /// starting from the current size limit clamped to its bounds
+    pub fn with_auto_size(mut self, auto_size: AutoSize) -> SizedCache<K, V, S> {
+        let capacity = auto_size.clamp(self.capacity);
+        self.auto_size = Some(auto_size);
+        self.resize(capacity);
+        self
+    }
+
+    /// Evict `batch` entries at once when the cache is full, instead of one per insertion,
+    /// e.g. a tenth of its size. Insertions evict less often, but hold up to `batch - 1`
+    /// fewer values.
+    pub fn with_eviction_batch(mut self, batch: usize) -> SizedCache<K, V, S> {
+        if batch == 0 {
+            panic!("`batch` of `SizedCache` must be greater than zero.")
+        }
+        self.eviction_batch = batch;
+        self
+    }
+
+    /// Drop evicted entries on a background thread instead of the thread evicting them,
+    /// so that callers holding a lock around the cache don't hold it while large values
+    /// or batches of values are freed. Eviction listeners are still called in place.
+    #[cfg(feature = "std")]
+    pub fn with_deferred_drops(mut self) -> SizedCache<K, V, S>
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        self.drop_evicted = Some(dropper::defer_drop::<Vec<(K, V)>>);
+        self
+    }
+
+    /// Call `f` with every entry leaving the cache and the reason it left
+    pub fn on_evict<F>(mut self, f: F) -> SizedCache<K, V, S>
+    where
+        F: Fn(&K, &V, EvictionReason) + Send + Sync + 'static,
+    {
+        self.listener.set(f);
+        self
+    }
+
+    /// Exempt the value cached for `key` from eviction to make room for others, returning
+    /// whether there is such a value. The cache grows past its size limit rather than evict
+    /// pinned values, a value stays pinned until it's unpinned or removed.
+    pub fn pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
+                self.pinned.insert(index);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Let the value cached for `key` be evicted again, returning whether it was pinned
+    pub fn unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => self.pinned.remove(&index),
+            None => false,
+        }
+    }
+
+    /// Return whether the value cached for `key` is pinned
+    pub fn is_pinned<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key)
+            .is_some_and(|index| self.pinned.contains(&index))
+    }
+
+    /// Change the size limit at runtime and return the previous one, keeping the cached values.
+    /// Shrinking evicts the least recently used values above the new limit.
+    /// With `with_auto_size`, the limit is clamped to the auto-sizing bounds.
+    pub fn set_capacity(&mut self, size: usize) -> usize {
+        if size == 0 {
+            panic!("`size` of `SizedCache` must be greater than zero.")
+        }
+        let old = self.capacity;
+        let size = match &self.auto_size {
+            Some(auto_size) => auto_size.clamp(size),
+            None => size,
+        };
+        self.resize(size);
+        old
+    }
+
     /// Return an iterator of keys in the current order from most
     /// to least recently used.
     pub fn key_order(&self) -> impl Iterator<Item = &K> {
@@ -335,93 +794,362 @@ impl<K: Hash + Eq, V> SizedCache<K, V> {
         self.order.iter().map(|(_k, v)| v)
     }
 
+    /// Return an iterator of the cached key, value pairs in the current order
+    /// from most to least recently used.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.order.iter().map(|(k, v)| (k, v))
+    }
+
+    /// Call `f` with every cached value, without updating the order
+    #[cfg(feature = "std")]
+    fn for_each_value_mut<F: FnMut(&mut V)>(&mut self, mut f: F) {
+        let Self { store, order, .. } = self;
+        for &index in store.iter() {
+            f(&mut order.get_mut(index).1);
+        }
+    }
+
+    /// Return the most recently used key, value pair without updating the order
+    pub fn peek_mru(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// Return the key, value pair that's evicted next once the cache is full, the least
+    /// recently used one that isn't pinned, without updating the order
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.lru_index().map(|index| {
+            let (key, value) = self.order.get(index);
+            (key, value)
+        })
+    }
+
+    /// Find the index of `key` in the order list
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.store
+            .find(hash, |&i| self.order.get(i).0.borrow() == key)
+            .copied()
+    }
+
+    /// Remove `key` from the lookup table, returning its index in the order list
+    fn remove_index<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        let order = &self.order;
+        match self
+            .store
+            .find_entry(hash, |&i| order.get(i).0.borrow() == key)
+        {
+            Ok(entry) => Some(entry.remove().0),
+            Err(_) => None,
+        }
+    }
+
+    /// Push a new entry to the front of the order list and index it
+    fn insert_new(&mut self, hash: u64, key: K, val: V) -> usize {
+        let Self {
+            store,
+            hash_builder,
+            order,
+            ..
+        } = self;
+        let index = order.push_front((key, val));
+        store.insert_unique(hash, index, |&i| hash_builder.hash_one(&order.get(i).0));
+        index
+    }
+
     fn check_capacity(&mut self) {
+        // store has reached capacity, evict the oldest unpinned items, a batch at a time.
+        // the store may be over capacity from pinned items that were unpinned since.
         if self.store.len() >= self.capacity {
-            // store has reached capacity, evict the oldest item.
-            // store capacity cannot be zero, so there must be content in `self.order`.
-            let (key, _value) = self.order.pop_back();
-            self.store
-                .remove(&key)
-                .expect("SizedCache::cache_set failed evicting cache key");
+            self.evict_to(self.capacity.saturating_sub(self.eviction_batch));
+        }
+    }
+
+    /// Change the size limit, evicting the least recently used entries above it
+    fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict_to(capacity);
+    }
+
+    /// Evict the least recently used unpinned entries until at most `size` are left,
+    /// handing them to `drop_evicted` if set
+    fn evict_to(&mut self, size: usize) {
+        match self.drop_evicted {
+            None => while self.store.len() > size && self.evict_lru().is_some() {},
+            Some(drop_evicted) => {
+                let mut evicted = Vec::new();
+                while self.store.len() > size {
+                    match self.evict_lru() {
+                        Some(entry) => evicted.push(entry),
+                        None => break,
+                    }
+                }
+                drop_evicted(evicted);
+            }
+        }
+    }
+
+    /// Let the auto-sizing controller, if any, know about a lookup
+    fn record_lookup(&mut self, hit: bool) {
+        let capacity = self.capacity;
+        if let Some(capacity) = self
+            .auto_size
+            .as_mut()
+            .and_then(|auto_size| auto_size.record(hit, capacity))
+        {
+            self.resize(capacity);
+        }
+    }
+
+    /// Let the admission policy, if any, count a miss for the key hashing to `hash`
+    #[cfg(feature = "std")]
+    fn record_miss(&mut self, hash: u64) {
+        if let Some(admission) = self.admission.as_mut() {
+            admission.record(hash);
         }
     }
+
+    /// There's no admission policy without `std`
+    #[cfg(not(feature = "std"))]
+    fn record_miss(&mut self, _hash: u64) {}
+
+    /// Whether a new key hashing to `hash` may be stored
+    #[cfg(feature = "std")]
+    fn admits(&mut self, hash: u64) -> bool {
+        self.admission
+            .as_mut()
+            .is_none_or(|admission| admission.admits(hash))
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn admits(&mut self, _hash: u64) -> bool {
+        true
+    }
+
+    /// Remove the entries `f` returns true for, notifying the listener with `reason`,
+    /// returning how many were removed
+    fn remove_entries_where<F>(&mut self, mut f: F, reason: EvictionReason) -> usize
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let Self {
+            store,
+            order,
+            listener,
+            pinned,
+            ..
+        } = self;
+        let before = store.len();
+        store.retain(|&mut index| {
+            let (key, value) = order.get(index);
+            if !f(key, value) {
+                return true;
+            }
+            pinned.remove(&index);
+            let (key, value) = order.remove(index);
+            listener.notify(&key, &value, reason);
+            false
+        });
+        before - store.len()
+    }
+
+    /// Find the index of the least recently used entry that isn't pinned
+    fn lru_index(&self) -> Option<usize> {
+        let mut index = self.order.back();
+        while self.pinned.contains(&index) {
+            index = self.order.prev(index);
+        }
+        if index == LRUList::<(K, V)>::OCCUPIED {
+            return None;
+        }
+        Some(index)
+    }
+
+    /// Evict and return the least recently used entry that isn't pinned
+    fn evict_lru(&mut self) -> Option<(K, V)> {
+        let index = self.lru_index()?;
+        let hash = self.hash_builder.hash_one(&self.order.get(index).0);
+        self.store
+            .find_entry(hash, |&i| i == index)
+            .expect("SizedCache::cache_set failed evicting cache key")
+            .remove();
+        let (key, value) = self.order.remove(index);
+        self.listener.notify(&key, &value, EvictionReason::Capacity);
+        Some((key, value))
+    }
 }
 
-impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
-    fn cache_get(&mut self, key: &K) -> Option<&V> {
-        let val = self.store.get(key);
-        match val {
-            Some(&index) => {
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cached<K, V> for SizedCache<K, V, S> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
                 self.order.move_to_front(index);
                 self.hits += 1;
+                self.record_lookup(true);
                 Some(&self.order.get(index).1)
             }
             None => {
                 self.misses += 1;
+                self.record_lookup(false);
+                self.record_miss(self.hash_builder.hash_one(key));
                 None
             }
         }
     }
 
-    fn cache_get_mut(&mut self, key: &K) -> std::option::Option<&mut V> {
-        let val = self.store.get(key);
-        match val {
-            Some(&index) => {
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|index| &self.order.get(index).1)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|index| {
+            let (key, value) = self.order.get(index);
+            (key, value)
+        })
+    }
+
+    fn cache_pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pin(key)
+    }
+
+    fn cache_unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.unpin(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
                 self.order.move_to_front(index);
                 self.hits += 1;
+                self.record_lookup(true);
                 Some(&mut self.order.get_mut(index).1)
             }
             None => {
                 self.misses += 1;
+                self.record_lookup(false);
+                self.record_miss(self.hash_builder.hash_one(key));
                 None
             }
         }
     }
 
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        match self.find(&key) {
+            Some(index) => {
+                self.order.move_to_front(index);
+                let (key, value) = self.order.get_mut(index);
+                let old = core::mem::replace(value, val);
+                self.listener.notify(key, &old, EvictionReason::Replaced);
+                Some(old)
+            }
+            None => {
+                let hash = self.hash_builder.hash_one(&key);
+                if self.admits(hash) {
+                    self.check_capacity();
+                    self.insert_new(hash, key, val);
+                }
+                None
+            }
+        }
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        if self.find(&key).is_some() {
+            return false;
+        }
+        let hash = self.hash_builder.hash_one(&key);
+        if !self.admits(hash) {
+            return false;
+        }
         self.check_capacity();
-        let Self { store, order, .. } = self;
-        let index = *store
-            .entry(key.clone())
-            .or_insert_with(|| order.push_front(None));
-        order.set(index, (key, val)).map(|(_, v)| v)
+        self.insert_new(hash, key, val);
+        true
     }
 
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
-        self.check_capacity();
-        let val = self.store.entry(key);
-        let Self { order, .. } = self;
-        match val {
-            Entry::Occupied(occupied) => {
-                let index = *occupied.get();
-                order.move_to_front(index);
+        let index = match self.find(&key) {
+            Some(index) => {
+                self.order.move_to_front(index);
                 self.hits += 1;
-                &mut order.get_mut(index).1
+                self.record_lookup(true);
+                index
             }
-            Entry::Vacant(vacant) => {
-                let key = vacant.key().clone();
+            None => {
                 self.misses += 1;
-                let index = *vacant.insert(order.push_front(None));
-                order.set(index, (key, f()));
-                &mut order.get_mut(index).1
+                self.record_lookup(false);
+                let hash = self.hash_builder.hash_one(&key);
+                self.record_miss(hash);
+                if !self.admits(hash) {
+                    return self.rejected.insert(f());
+                }
+                self.check_capacity();
+                self.insert_new(hash, key, f())
             }
-        }
+        };
+        &mut self.order.get_mut(index).1
     }
 
-    fn cache_remove(&mut self, k: &K) -> Option<V> {
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         // try and remove item from mapping, and then from order list if it was in mapping
-        if let Some(index) = self.store.remove(k) {
-            // need to remove the key in the order list
-            let (_key, value) = self.order.remove(index);
-            Some(value)
-        } else {
-            None
-        }
+        let index = self.remove_index(k)?;
+        self.pinned.remove(&index);
+        let (key, value) = self.order.remove(index);
+        self.listener.notify(&key, &value, EvictionReason::Explicit);
+        Some(value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        Some(self.remove_entries_where(|key, _| f(key), EvictionReason::Explicit))
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        Some(self.remove_entries_where(|key, value| !f(key, value), EvictionReason::Explicit))
     }
     fn cache_clear(&mut self) {
+        if self.listener.is_set() {
+            for (key, value) in self.order.iter() {
+                self.listener.notify(key, value, EvictionReason::Cleared);
+            }
+        }
         // clear both the store and the order list
         self.store.clear();
         self.order.clear();
+        self.rejected = None;
+        self.pinned.clear();
     }
     fn cache_reset(&mut self) {
         // SizedCache uses cache_clear because capacity is fixed.
@@ -439,8 +1167,56 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
     fn cache_capacity(&self) -> Option<usize> {
         Some(self.capacity)
     }
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        Some(self.set_capacity(size))
+    }
 }
 
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> SizedCache<K, V, S> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> CachedAsync<K, V> for SizedCache<K, V, S> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let index = match self.find(&key) {
+            Some(index) => {
+                self.order.move_to_front(index);
+                self.hits += 1;
+                self.record_lookup(true);
+                index
+            }
+            None => {
+                self.misses += 1;
+                self.record_lookup(false);
+                let hash = self.hash_builder.hash_one(&key);
+                self.record_miss(hash);
+                let val = f().await?;
+                if !self.admits(hash) {
+                    return Ok(self.rejected.insert(val));
+                }
+                self.check_capacity();
+                self.insert_new(hash, key, val)
+            }
+        };
+        Ok(&mut self.order.get_mut(index).1)
+    }
+}
+
+#[cfg(feature = "std")]
 /// Enum used for defining the status of time-cached values
 #[derive(Debug)]
 enum Status {
@@ -449,143 +1225,649 @@ enum Status {
     Expired,
 }
 
+#[cfg(feature = "std")]
+/// Timestamped value held by a `TimedCache`
+#[derive(Clone, Debug)]
+struct TimedEntry<V> {
+    stamp: Stamp,
+    /// Overrides the cache's lifespan for this entry
+    lifespan: Option<u64>,
+    /// Exempt from expiry
+    pinned: bool,
+    value: V,
+}
+
+#[cfg(feature = "std")]
+impl<V> TimedEntry<V> {
+    fn lifespan(&self, default: u64) -> u64 {
+        self.lifespan.unwrap_or(default)
+    }
+
+    fn is_expired(&self, default: u64) -> bool {
+        !self.pinned && self.stamp.elapsed().as_secs() >= self.lifespan(default)
+    }
+
+    /// Time left before the entry expires, `None` once it has or if it's pinned
+    fn remaining(&self, default: u64) -> Option<Duration> {
+        if self.pinned || self.is_expired(default) {
+            return None;
+        }
+        Some(Duration::from_secs(self.lifespan(default)).saturating_sub(self.stamp.elapsed()))
+    }
+}
+
+#[cfg(feature = "std")]
+/// Number of entries of a `TimedCache` by how soon they expire
+///
+/// Entries expiring soon will be recomputed on their next access, a large
+/// `within_10s` count can be used to anticipate a burst of recomputations and
+/// pre-warm the cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExpiryStats {
+    /// Expired entries that haven't been purged yet
+    pub expired: usize,
+    /// Pinned entries, which don't expire
+    pub pinned: usize,
+    /// Entries expiring in the next 10 seconds
+    pub within_10s: usize,
+    /// Entries expiring in 10 seconds to a minute
+    pub within_1m: usize,
+    /// Entries expiring in one to ten minutes
+    pub within_10m: usize,
+    /// Entries expiring in more than ten minutes
+    pub later: usize,
+}
+
+#[cfg(feature = "std")]
 /// Cache store bound by time
 ///
 /// Values are timestamped when inserted and are
-/// evicted if expired at time of retrieval.
+/// evicted if expired at time of retrieval. Expired values
+/// that are never retrieved again are purged as new values
+/// are inserted.
 ///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct TimedCache<K, V> {
-    store: HashMap<K, (Instant, V)>,
+pub struct TimedCache<K, V, S = SeedableState> {
+    store: HashMap<K, TimedEntry<V>, S>,
+    expiry: TimingWheel<K>,
     seconds: u64,
     hits: u64,
     misses: u64,
     initial_capacity: Option<usize>,
+    listener: Listener<K, V>,
+    /// Ceiling on the lifespan of every entry
+    max_lifespan: Option<u64>,
+    time_source: TimeSource,
+    /// Restart the lifespan of values when they're read
+    refresh: bool,
+    /// Fraction of the lifespan that new values are randomly shortened by
+    jitter: f64,
+    rng: u64,
 }
 
+#[cfg(feature = "std")]
 impl<K: Hash + Eq, V> TimedCache<K, V> {
     /// Creates a new `TimedCache` with a specified lifespan
     pub fn with_lifespan(seconds: u64) -> TimedCache<K, V> {
-        TimedCache {
-            store: Self::new_store(None),
-            seconds,
-            hits: 0,
-            misses: 0,
-            initial_capacity: None,
+        Self::with_lifespan_and_hasher(seconds, SeedableState::new())
+    }
+
+    /// Like `with_lifespan`, but returns an error if seconds is 0 instead of creating a
+    /// cache whose values expire right away
+    pub fn try_with_lifespan(seconds: u64) -> Result<TimedCache<K, V>, ConfigError> {
+        if seconds == 0 {
+            return Err(ConfigError::ZeroLifespan);
         }
+        Ok(Self::with_lifespan(seconds))
     }
 
     /// Creates a new `TimedCache` with a specified lifespan and
     /// cache-store with the specified pre-allocated capacity
     pub fn with_lifespan_and_capacity(seconds: u64, size: usize) -> TimedCache<K, V> {
+        Self::new(seconds, Some(size), SeedableState::new())
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan, restarted every time a value
+    /// is read if `refresh` is set: values then only expire once they went unread for
+    /// `seconds`. Values aren't refreshed once a ceiling is set with `with_max_lifespan`.
+    pub fn with_lifespan_and_refresh(seconds: u64, refresh: bool) -> TimedCache<K, V> {
+        TimedCache::with_lifespan(seconds).with_refresh(refresh)
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`.
+    /// This also seeds the jitter of lifespans, so expiries are reproducible.
+    pub fn with_hash_seed(mut self, seed: u64) -> TimedCache<K, V> {
+        reseed(&mut self.store, seed);
+        self.rng = hasher::rng_seed(self.store.hasher(), 0);
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Hash + Eq, V, S: BuildHasher> TimedCache<K, V, S> {
+    /// Creates a new `TimedCache` with a specified lifespan, hashing keys with `hasher`,
+    /// e.g. a faster hasher than the default one for small keys
+    pub fn with_lifespan_and_hasher(seconds: u64, hasher: S) -> TimedCache<K, V, S> {
+        Self::new(seconds, None, hasher)
+    }
+
+    fn new(seconds: u64, capacity: Option<usize>, hasher: S) -> TimedCache<K, V, S> {
         TimedCache {
-            store: Self::new_store(Some(size)),
+            store: Self::new_store(capacity, hasher),
+            expiry: TimingWheel::new(Duration::from_secs(1)),
             seconds,
             hits: 0,
             misses: 0,
-            initial_capacity: Some(size),
+            initial_capacity: capacity,
+            listener: Listener::new(),
+            max_lifespan: None,
+            time_source: TimeSource::Monotonic,
+            refresh: false,
+            jitter: 0.0,
+            rng: 1,
+        }
+    }
+
+    /// Restart the lifespan of values every time they're read if `refresh` is set,
+    /// see `with_lifespan_and_refresh`
+    pub fn with_refresh(mut self, refresh: bool) -> TimedCache<K, V, S> {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Return whether the lifespan of values restarts when they're read
+    pub fn refresh(&self) -> bool {
+        self.refresh
+    }
+
+    /// Return the hasher of the keys
+    pub fn hasher(&self) -> &S {
+        self.store.hasher()
+    }
+
+    /// Shorten the lifespan of every new value by a random part of up to `fraction` of
+    /// the cache's lifespan, so values inserted in a burst don't all expire at once, e.g.
+    /// between 54 and 60 seconds for a `fraction` of 0.1 and a lifespan of 60 seconds.
+    /// Values keep the lifespan they got when they were inserted, lifespans requested
+    /// with `cache_set_with_lifespan` aren't jittered.
+    ///
+    /// Will panic if fraction isn't between 0 and 1
+    pub fn with_jitter(mut self, fraction: f64) -> TimedCache<K, V, S> {
+        if !(0.0..=1.0).contains(&fraction) {
+            panic!("`jitter` of `TimedCache` must be between 0 and 1.");
+        }
+        self.jitter = fraction;
+        self.rng = hasher::rng_seed(self.store.hasher(), 0);
+        self
+    }
+
+    /// Return the fraction of the lifespan that new values are randomly shortened by
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Call `f` with every entry leaving the cache and the reason it left.
+    /// Expired entries are only noticed when they're accessed or purged on insertion.
+    pub fn on_evict<F>(mut self, f: F) -> TimedCache<K, V, S>
+    where
+        F: Fn(&K, &V, EvictionReason) + Send + Sync + 'static,
+    {
+        self.listener.set(f);
+        self
+    }
+
+    /// Clamp the lifespan of every value to at most `seconds`, including the cache's
+    /// lifespan and those requested with `cache_set_with_lifespan`, so that no value
+    /// is ever returned more than `seconds` after it was inserted.
+    pub fn with_max_lifespan(mut self, seconds: u64) -> TimedCache<K, V, S>
+    where
+        K: Clone,
+    {
+        self.max_lifespan = Some(seconds);
+        self.seconds = self.clamp_lifespan(self.seconds);
+        for entry in self.store.values_mut() {
+            entry.lifespan = entry.lifespan.map(|s| s.min(seconds));
         }
+        self.reschedule();
+        self
+    }
+
+    /// Stamp and expire values using `source`, see `TimeSource`.
+    /// Values already in the cache keep their age.
+    pub fn with_time_source(mut self, source: TimeSource) -> TimedCache<K, V, S>
+    where
+        K: Clone,
+    {
+        self.time_source = source;
+        for entry in self.store.values_mut() {
+            entry.stamp = entry.stamp.to_source(source);
+        }
+        // the new source's clock is on another timeline
+        self.expiry = TimingWheel::new(Duration::from_secs(1));
+        self.reschedule();
+        self
+    }
+
+    /// Return the clock used to stamp and expire values
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
     }
 
-    fn new_store(capacity: Option<usize>) -> HashMap<K, (Instant, V)> {
-        capacity.map_or_else(HashMap::new, HashMap::with_capacity)
+    /// Return the ceiling on the lifespan of values, if any
+    pub fn max_lifespan(&self) -> Option<u64> {
+        self.max_lifespan
+    }
+
+    fn clamp_lifespan(&self, seconds: u64) -> u64 {
+        self.max_lifespan.map_or(seconds, |max| seconds.min(max))
+    }
+
+    fn new_store(capacity: Option<usize>, state: S) -> HashMap<K, TimedEntry<V>, S> {
+        HashMap::with_capacity_and_hasher(capacity.unwrap_or(0), state)
+    }
+
+    /// Remove the values that expired since the last purge.
+    /// Keys are scheduled on every insert, so a key popping out of the wheel
+    /// may have been re-inserted or removed since and is checked before removal.
+    /// Values outliving the span of the wheel pop out early and are left in place.
+    fn purge_expired(&mut self) {
+        if self.expiry.len() == 0 {
+            return;
+        }
+        for key in self.expiry.advance(self.time_source.timeline()) {
+            let entry = match self.store.get(&key) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if entry.is_expired(self.seconds) {
+                self.remove_expired(&key);
+            } else if self.refresh && !entry.pinned {
+                // read since it was scheduled, expire it at its refreshed deadline
+                let (stamp, lifespan) = (entry.stamp, entry.lifespan(self.seconds));
+                schedule_expiry(&mut self.expiry, key, stamp, lifespan);
+            }
+        }
+    }
+
+    fn remove_expired<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, entry) = self.store.remove_entry(key).unwrap();
+        self.listener
+            .notify(&key, &entry.value, EvictionReason::Expired);
+    }
+
+    /// Notify the listener of a value overwritten by a new insertion
+    fn replaced(&self, key: &K, old: TimedEntry<V>) -> V {
+        let reason = if old.is_expired(self.seconds) {
+            EvictionReason::Expired
+        } else {
+            EvictionReason::Replaced
+        };
+        self.listener.notify(key, &old.value, reason);
+        old.value
+    }
+
+    /// Count the entries by how soon they expire, see `ExpiryStats`.
+    /// This visits every entry.
+    pub fn expiry_stats(&self) -> ExpiryStats {
+        let mut stats = ExpiryStats::default();
+        for entry in self.store.values() {
+            let bucket = match entry.remaining(self.seconds).map(|d| d.as_secs()) {
+                None if entry.pinned => &mut stats.pinned,
+                None => &mut stats.expired,
+                Some(0..=9) => &mut stats.within_10s,
+                Some(10..=59) => &mut stats.within_1m,
+                Some(60..=599) => &mut stats.within_10m,
+                Some(_) => &mut stats.later,
+            };
+            *bucket += 1;
+        }
+        stats
+    }
+
+    /// Return how long the value cached for `key` has left before it expires, e.g. to
+    /// refresh it ahead of time. `None` if there's no such value, it expired or it's pinned.
+    pub fn cache_ttl_remaining<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get(key)?.remaining(self.seconds)
+    }
+
+    /// Return an iterator of the unexpired key, value pairs, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let seconds = self.seconds;
+        self.store
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(seconds))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Return an iterator of the keys of unexpired values, in arbitrary order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Return an iterator of the unexpired values, in arbitrary order
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Restart the lifespan of the value cached for `key`, if values are refreshed on reads
+    fn touch<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.refresh && self.max_lifespan.is_none() {
+            let now = self.time_source.now();
+            if let Some(entry) = self.store.get_mut(key) {
+                entry.stamp = now;
+            }
+        }
+    }
+
+    fn status<Q>(&self, key: &Q) -> Status
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get(key) {
+            Some(entry) if entry.is_expired(self.seconds) => Status::Expired,
+            Some(_) => Status::Found,
+            None => Status::NotFound,
+        }
     }
 }
 
-impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
-    fn cache_get(&mut self, key: &K) -> Option<&V> {
-        let status = {
-            let val = self.store.get(key);
-            if let Some(&(instant, _)) = val {
-                if instant.elapsed().as_secs() < self.seconds {
-                    Status::Found
-                } else {
-                    Status::Expired
-                }
-            } else {
-                Status::NotFound
+#[cfg(feature = "std")]
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> TimedCache<K, V, S> {
+    /// Exempt the value cached for `key` from expiry, returning whether there is such
+    /// a value that hasn't expired. The value stays pinned until it's unpinned or removed,
+    /// including when it's overwritten.
+    pub fn pin(&mut self, key: &K) -> bool {
+        let seconds = self.seconds;
+        match self.store.get_mut(key) {
+            Some(entry) if !entry.is_expired(seconds) => {
+                entry.pinned = true;
+                true
             }
+            _ => false,
+        }
+    }
+
+    /// Let the value cached for `key` expire again, returning whether it was pinned.
+    /// It expires right away if its lifespan passed while it was pinned.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        let entry = match self.store.get_mut(key) {
+            Some(entry) if entry.pinned => entry,
+            _ => return false,
+        };
+        entry.pinned = false;
+        let (stamp, lifespan) = (entry.stamp, entry.lifespan(self.seconds));
+        schedule_expiry(&mut self.expiry, key.clone(), stamp, lifespan);
+        true
+    }
+
+    /// Return whether the value cached for `key` is pinned
+    pub fn is_pinned(&self, key: &K) -> bool {
+        self.store.get(key).is_some_and(|entry| entry.pinned)
+    }
+
+    fn insert(&mut self, key: K, value: V, lifespan: Option<u64>) -> Option<V> {
+        self.purge_expired();
+        let entry = TimedEntry {
+            stamp: self.time_source.now(),
+            lifespan: match lifespan {
+                Some(seconds) => Some(self.clamp_lifespan(seconds)),
+                None => self.jittered_lifespan(),
+            },
+            // pins outlive overwrites
+            pinned: self.store.get(&key).is_some_and(|entry| entry.pinned),
+            value,
         };
-        match status {
+        schedule_expiry(
+            &mut self.expiry,
+            key.clone(),
+            entry.stamp,
+            entry.lifespan(self.seconds),
+        );
+        let old = self.store.insert(key.clone(), entry)?;
+        Some(self.replaced(&key, old))
+    }
+
+    fn notify_cleared(&self) {
+        if self.listener.is_set() {
+            for (key, entry) in self.store.iter() {
+                self.listener
+                    .notify(key, &entry.value, EvictionReason::Cleared);
+            }
+        }
+    }
+
+    /// Reschedule the expiry of every value after lifespans changed
+    fn reschedule(&mut self) {
+        self.expiry.clear();
+        for (key, entry) in self.store.iter() {
+            schedule_expiry(
+                &mut self.expiry,
+                key.clone(),
+                entry.stamp,
+                entry.lifespan(self.seconds),
+            );
+        }
+    }
+
+    /// Stamp a newly computed value, scheduling its expiry
+    fn new_entry(&mut self, key: &K, value: V) -> TimedEntry<V> {
+        let now = self.time_source.now();
+        let lifespan = self.jittered_lifespan();
+        schedule_expiry(
+            &mut self.expiry,
+            key.clone(),
+            now,
+            lifespan.unwrap_or(self.seconds),
+        );
+        TimedEntry {
+            stamp: now,
+            lifespan,
+            pinned: false,
+            value,
+        }
+    }
+
+    /// Lifespan of a new value shortened by a random part of the jitter window,
+    /// `None` to use the cache's lifespan
+    fn jittered_lifespan(&mut self) -> Option<u64> {
+        let window = (self.seconds as f64 * self.jitter) as u64;
+        if window == 0 || self.seconds == UNSET_LIFESPAN {
+            return None;
+        }
+        Some(self.seconds - hasher::next_random(&mut self.rng) % (window + 1))
+    }
+}
+
+#[cfg(feature = "std")]
+/// Lifespan of timed stores whose lifespan was unset, too long for values to ever expire
+const UNSET_LIFESPAN: u64 = u64::MAX;
+
+#[cfg(feature = "std")]
+/// The lifespan of a timed store, `None` once it was unset
+fn lifespan(seconds: u64) -> Option<u64> {
+    Some(seconds).filter(|&seconds| seconds != UNSET_LIFESPAN)
+}
+
+#[cfg(feature = "std")]
+/// Schedule `key`, inserted at `stamp`, to be purged once its lifespan passes
+fn schedule_expiry<K>(expiry: &mut TimingWheel<K>, key: K, stamp: Stamp, seconds: u64) {
+    // lifespans too long to represent never expire
+    if let Some(deadline) = stamp.deadline(seconds) {
+        expiry.schedule(key, deadline, stamp.timeline());
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Hash + Eq + Clone, V, S: BuildHasher + Clone> Cached<K, V> for TimedCache<K, V, S> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.status(key) {
             Status::NotFound => {
                 self.misses += 1;
                 None
             }
             Status::Found => {
                 self.hits += 1;
-                self.store.get(key).map(|stamped| &stamped.1)
+                self.touch(key);
+                self.store.get(key).map(|entry| &entry.value)
             }
             Status::Expired => {
                 self.misses += 1;
-                self.store.remove(key).unwrap();
+                self.remove_expired(key);
                 None
             }
         }
     }
 
-    fn cache_get_mut(&mut self, key: &K) -> Option<&mut V> {
-        let status = {
-            let val = self.store.get(key);
-            if let Some(&(instant, _)) = val {
-                if instant.elapsed().as_secs() < self.seconds {
-                    Status::Found
-                } else {
-                    Status::Expired
-                }
-            } else {
-                Status::NotFound
-            }
-        };
-        match status {
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get(key) {
+            Some(entry) if !entry.is_expired(self.seconds) => Some(&entry.value),
+            _ => None,
+        }
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get_key_value(key) {
+            Some((key, entry)) if !entry.is_expired(self.seconds) => Some((key, &entry.value)),
+            _ => None,
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.status(key) {
             Status::NotFound => {
                 self.misses += 1;
                 None
             }
             Status::Found => {
                 self.hits += 1;
-                self.store.get_mut(key).map(|stamped| &mut stamped.1)
+                self.touch(key);
+                self.store.get_mut(key).map(|entry| &mut entry.value)
             }
             Status::Expired => {
                 self.misses += 1;
-                self.store.remove(key).unwrap();
+                self.remove_expired(key);
                 None
             }
         }
     }
 
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
-        match self.store.entry(key) {
-            Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
-                    self.hits += 1;
-                } else {
-                    self.misses += 1;
-                    let val = f();
-                    occupied.insert((Instant::now(), val));
-                }
-                &mut occupied.into_mut().1
+        self.purge_expired();
+        match self.status(&key) {
+            Status::Found => {
+                self.hits += 1;
+                self.touch(&key);
             }
-            Entry::Vacant(vacant) => {
+            Status::NotFound | Status::Expired => {
                 self.misses += 1;
-                let val = f();
-                &mut vacant.insert((Instant::now(), val)).1
+                let entry = self.new_entry(&key, f());
+                if let Some(old) = self.store.insert(key.clone(), entry) {
+                    self.replaced(&key, old);
+                }
             }
         }
+        &mut self.store.get_mut(&key).unwrap().value
     }
 
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = (Instant::now(), val);
-        self.store.insert(key, stamped).map(|(_, v)| v)
+        self.insert(key, val, None)
     }
-    fn cache_remove(&mut self, k: &K) -> Option<V> {
-        self.store.remove(k).map(|(_, v)| v)
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        self.insert(key, val, Some(seconds))
+    }
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        // expired values are replaced
+        if self.cache_peek(&key).is_some() {
+            return false;
+        }
+        self.insert(key, val, None);
+        true
+    }
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, entry) = self.store.remove_entry(k)?;
+        self.listener
+            .notify(&key, &entry.value, EvictionReason::Explicit);
+        Some(entry.value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let listener = &self.listener;
+        let before = self.store.len();
+        self.store.retain(|key, entry| {
+            if !f(key) {
+                return true;
+            }
+            listener.notify(key, &entry.value, EvictionReason::Explicit);
+            false
+        });
+        Some(before - self.store.len())
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let listener = &self.listener;
+        let before = self.store.len();
+        self.store.retain(|key, entry| {
+            if f(key, &entry.value) {
+                return true;
+            }
+            listener.notify(key, &entry.value, EvictionReason::Explicit);
+            false
+        });
+        Some(before - self.store.len())
+    }
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        let (listener, seconds) = (&self.listener, self.seconds);
+        let before = self.store.len();
+        self.store.retain(|key, entry| {
+            if !entry.is_expired(seconds) {
+                return true;
+            }
+            listener.notify(key, &entry.value, EvictionReason::Expired);
+            false
+        });
+        Some(before - self.store.len())
     }
     fn cache_clear(&mut self) {
+        self.notify_cleared();
         self.store.clear();
+        self.expiry.clear();
     }
     fn cache_reset(&mut self) {
-        self.store = Self::new_store(self.initial_capacity);
+        self.notify_cleared();
+        self.store = Self::new_store(self.initial_capacity, self.store.hasher().clone());
+        self.expiry.clear();
     }
     fn cache_size(&self) -> usize {
         self.store.len()
@@ -597,21 +1879,89 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
         Some(self.misses)
     }
     fn cache_lifespan(&self) -> Option<u64> {
-        Some(self.seconds)
+        lifespan(self.seconds)
     }
 
     fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
-        let old = self.seconds;
-        self.seconds = seconds;
-        Some(old)
+        let old = self.cache_lifespan();
+        self.seconds = self.clamp_lifespan(seconds);
+        self.reschedule();
+        old
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        // values can't outlive a `max_lifespan`, if any
+        self.cache_set_lifespan(UNSET_LIFESPAN)
+    }
+}
+
+#[cfg(all(feature = "std", feature = "async"))]
+impl<K: Hash + Eq + Clone, V, S: BuildHasher + Clone> TimedCache<K, V, S> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(all(feature = "std", feature = "async"))]
+impl<K: Hash + Eq + Clone, V, S: BuildHasher + Clone> CachedAsync<K, V> for TimedCache<K, V, S> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        self.purge_expired();
+        match self.status(&key) {
+            Status::Found => {
+                self.hits += 1;
+                self.touch(&key);
+            }
+            Status::NotFound | Status::Expired => {
+                self.misses += 1;
+                let val = f().await?;
+                let entry = self.new_entry(&key, val);
+                if let Some(old) = self.store.insert(key.clone(), entry) {
+                    self.replaced(&key, old);
+                }
+            }
+        }
+        Ok(&mut self.store.get_mut(&key).unwrap().value)
     }
 }
 
-impl<K: Hash + Eq, V> Cached<K, V> for HashMap<K, V> {
-    fn cache_get(&mut self, k: &K) -> Option<&V> {
+#[cfg(feature = "std")]
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cached<K, V> for StdHashMap<K, V, S> {
+    fn cache_get<Q>(&mut self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(k)
+    }
+    fn cache_peek<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.get(k)
     }
-    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+    fn cache_peek_key_value<Q>(&self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(k)
+    }
+    fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.get_mut(k)
     }
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
@@ -620,28 +1970,54 @@ impl<K: Hash + Eq, V> Cached<K, V> for HashMap<K, V> {
     fn cache_set(&mut self, k: K, v: V) -> Option<V> {
         self.insert(k, v)
     }
-    fn cache_remove(&mut self, k: &K) -> Option<V> {
+    fn cache_insert_if_absent(&mut self, k: K, v: V) -> bool {
+        match self.entry(k) {
+            StdEntry::Occupied(_) => false,
+            StdEntry::Vacant(vacant) => {
+                vacant.insert(v);
+                true
+            }
+        }
+    }
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.remove(k)
     }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.len();
+        self.retain(|key, _| !f(key));
+        Some(before - self.len())
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.len();
+        self.retain(|key, value| f(key, value));
+        Some(before - self.len())
+    }
     fn cache_clear(&mut self) {
         self.clear();
     }
     fn cache_reset(&mut self) {
-        *self = HashMap::new();
+        *self = StdHashMap::with_hasher(self.hasher().clone());
     }
     fn cache_size(&self) -> usize {
         self.len()
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 /// Cache store tests
 mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::thread::sleep;
     use std::time::Duration;
 
     use super::Cached;
 
+    use super::EvictionReason;
     use super::SizedCache;
     use super::TimedCache;
     use super::UnboundCache;
@@ -711,6 +2087,616 @@ mod tests {
         assert_eq!(c.cache_set(4, 100), None);
     }
 
+    #[test]
+    fn sized_cache_non_clone_keys() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Key(String);
+
+        let mut c = SizedCache::with_size(2).with_hash_seed(3);
+        assert_eq!(c.cache_set(Key("a".into()), 1), None);
+        assert_eq!(c.cache_set(Key("b".into()), 2), None);
+        assert_eq!(c.cache_set(Key("a".into()), 3), Some(1));
+        assert_eq!(c.cache_set(Key("c".into()), 4), None);
+        assert!(c.cache_get(&Key("b".into())).is_none());
+        assert_eq!(c.cache_get(&Key("a".into())), Some(&3));
+        assert_eq!(c.cache_remove(&Key("c".into())), Some(4));
+
+        let mut other = SizedCache::with_size(2);
+        assert_ne!(c, other);
+        other.cache_set(Key("a".into()), 3);
+        assert_eq!(c, other);
+    }
+
+    type Evictions = Arc<Mutex<Vec<(u32, u32, EvictionReason)>>>;
+
+    fn record_evictions() -> (
+        Evictions,
+        impl Fn(&u32, &u32, EvictionReason) + Send + Sync + 'static,
+    ) {
+        let evicted = Evictions::default();
+        let recorder = evicted.clone();
+        (evicted, move |k: &u32, v: &u32, reason| {
+            recorder.lock().unwrap().push((*k, *v, reason))
+        })
+    }
+
+    #[test]
+    fn sized_cache_eviction_reasons() {
+        let (evicted, on_evict) = record_evictions();
+        let mut c = SizedCache::with_size(2).on_evict(on_evict);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        c.cache_set(1, 101);
+        c.cache_set(3, 300);
+        c.cache_remove(&1);
+        c.cache_clear();
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                (1, 100, EvictionReason::Replaced),
+                (2, 200, EvictionReason::Capacity),
+                (1, 101, EvictionReason::Explicit),
+                (3, 300, EvictionReason::Cleared),
+            ]
+        );
+    }
+
+    #[test]
+    fn timed_cache_eviction_reasons() {
+        let (evicted, on_evict) = record_evictions();
+        let mut c = TimedCache::with_lifespan(3600).on_evict(on_evict);
+        c.cache_set_with_lifespan(1, 100, 1);
+        c.cache_set(2, 200);
+        c.cache_set(2, 201);
+        sleep(Duration::new(1, 0));
+        assert!(c.cache_get(&1).is_none());
+        c.cache_remove(&2);
+        c.cache_set(3, 300);
+        c.cache_reset();
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                (2, 200, EvictionReason::Replaced),
+                (1, 100, EvictionReason::Expired),
+                (2, 201, EvictionReason::Explicit),
+                (3, 300, EvictionReason::Cleared),
+            ]
+        );
+    }
+
+    #[test]
+    fn sized_cache_auto_size() {
+        let auto_size = super::AutoSize::new(2, 8).interval(4).step(0.5);
+        let mut c = SizedCache::with_size(16).with_auto_size(auto_size);
+        assert_eq!(Some(8), c.cache_capacity());
+
+        // only misses, grows up to the max
+        for i in 0..8 {
+            assert!(c.cache_get(&i).is_none());
+            c.cache_set(i, i);
+        }
+        assert_eq!(Some(8), c.cache_capacity());
+
+        // only hits, shrinks and evicts the least recently used entries
+        for _ in 0..4 {
+            assert!(c.cache_get(&7).is_some());
+        }
+        assert_eq!(Some(4), c.cache_capacity());
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [7, 6, 5, 4]);
+        for _ in 0..8 {
+            assert!(c.cache_get(&7).is_some());
+        }
+        assert_eq!(Some(2), c.cache_capacity());
+        assert_eq!(2, c.cache_size());
+    }
+
+    #[test]
+    fn cache_peek() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        assert!(c.cache_peek(&3).is_none());
+        // not promoted, still evicted first
+        c.cache_set(3, 300);
+        assert!(c.cache_peek(&1).is_none());
+        assert_eq!(Some(0), c.cache_hits());
+        assert_eq!(Some(0), c.cache_misses());
+
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        assert!(c.cache_peek(&2).is_none());
+
+        let mut c = UnboundCache::new();
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        assert_eq!(Some(0), c.cache_hits());
+    }
+
+    #[test]
+    fn cache_remove_where() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener = evicted.clone();
+        let mut c = SizedCache::with_size(4)
+            .on_evict(move |key: &(u32, u32), _: &u32, _| listener.lock().unwrap().push(*key));
+        for key in [(1, 1), (2, 1), (1, 2), (2, 2)] {
+            c.cache_set(key, 0);
+        }
+        assert_eq!(Some(2), c.cache_remove_where(|key| key.0 == 1));
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&(2, 2), &(2, 1)]);
+        // in no particular order
+        let mut evicted = evicted.lock().unwrap().clone();
+        evicted.sort();
+        assert_eq!(evicted, [(1, 1), (1, 2)]);
+        // freed cells are reused
+        c.cache_set((3, 1), 0);
+        assert_eq!(3, c.cache_size());
+
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set((1, 1), 0);
+        c.cache_set((2, 1), 0);
+        assert_eq!(Some(1), c.cache_remove_where(|key| key.0 == 1));
+        assert_eq!(1, c.cache_size());
+
+        let mut c = UnboundCache::new();
+        c.cache_set((1, 1), 0);
+        assert_eq!(Some(0), c.cache_remove_where(|key| key.0 == 2));
+        assert_eq!(1, c.cache_size());
+    }
+
+    #[test]
+    fn cache_retain() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener = evicted.clone();
+        let mut c = SizedCache::with_size(4)
+            .on_evict(move |key: &u32, _: &u32, _| listener.lock().unwrap().push(*key));
+        for key in 1..=4 {
+            c.cache_set(key, key * 100);
+        }
+        assert_eq!(Some(2), c.cache_retain(|key, value| key + value > 202));
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&4, &3]);
+        let mut evicted = evicted.lock().unwrap().clone();
+        evicted.sort();
+        assert_eq!(evicted, [1, 2]);
+
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, "keep");
+        c.cache_set(2, "drop");
+        assert_eq!(Some(1), c.cache_retain(|_, value| *value == "keep"));
+        assert_eq!(c.cache_get(&1), Some(&"keep"));
+
+        let mut c = UnboundCache::new();
+        c.cache_set(1, 100);
+        assert_eq!(Some(0), c.cache_retain(|_, _| true));
+        assert_eq!(Some(1), c.cache_retain(|_, _| false));
+        assert_eq!(0, c.cache_size());
+    }
+
+    #[test]
+    fn borrowed_key_lookups() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set("one".to_string(), 1);
+        assert_eq!(c.cache_get("one"), Some(&1));
+        assert_eq!(c.cache_peek("one"), Some(&1));
+        assert_eq!(
+            c.cache_peek_key_value("one"),
+            Some((&"one".to_string(), &1))
+        );
+        *c.cache_get_mut("one").unwrap() += 1;
+        assert_eq!(c.cache_remove("one"), Some(2));
+
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(vec![1, 2], 1);
+        assert_eq!(c.cache_get(&[1, 2][..]), Some(&1));
+
+        // values found in the back layer are copied forward under the stored key
+        let mut c = super::Layered::new(SizedCache::with_size(1), UnboundCache::new());
+        c.cache_set("one".to_string(), 1);
+        c.cache_set("two".to_string(), 2);
+        assert_eq!(c.front().cache_peek("one"), None);
+        assert_eq!(c.cache_get("one"), Some(&1));
+        assert_eq!(c.front().cache_peek("one"), Some(&1));
+    }
+
+    #[test]
+    fn cache_flush_expired() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener = evicted.clone();
+        let mut c = TimedCache::with_lifespan(60).on_evict(move |key: &u32, _: &u32, reason| {
+            listener.lock().unwrap().push((*key, reason))
+        });
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        c.cache_set_with_lifespan(3, 300, 0);
+        assert_eq!(3, c.cache_size());
+        assert_eq!(Some(2), c.cache_flush_expired());
+        assert_eq!(1, c.cache_size());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        let mut evicted = evicted.lock().unwrap().clone();
+        evicted.sort_by_key(|(key, _)| *key);
+        assert_eq!(
+            evicted,
+            [(2, EvictionReason::Expired), (3, EvictionReason::Expired)]
+        );
+
+        let mut c = UnboundCache::new();
+        c.cache_set(1, 100);
+        assert_eq!(None, c.cache_flush_expired());
+    }
+
+    #[test]
+    fn cache_iter() {
+        let mut c = SizedCache::with_size(3);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        c.cache_get(&1);
+        assert_eq!(c.iter().collect::<Vec<_>>(), [(&1, &100), (&2, &200)]);
+
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        assert_eq!(c.iter().collect::<Vec<_>>(), [(&1, &100)]);
+        assert_eq!(c.keys().collect::<Vec<_>>(), [&1]);
+        assert_eq!(c.values().collect::<Vec<_>>(), [&100]);
+
+        let mut c = UnboundCache::new();
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        let mut keys = c.keys().copied().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, [1, 2]);
+        assert_eq!(300, c.values().sum::<u32>());
+        assert_eq!(2, c.iter().count());
+    }
+
+    #[test]
+    fn cache_insert_if_absent() {
+        let mut c = SizedCache::with_size(2);
+        assert!(c.cache_insert_if_absent(1, 100));
+        assert!(!c.cache_insert_if_absent(1, 101));
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        assert_eq!(Some(0), c.cache_hits());
+        assert_eq!(Some(0), c.cache_misses());
+
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set_with_lifespan(1, 100, 0);
+        // expired, so replaced
+        assert!(c.cache_insert_if_absent(1, 101));
+        assert!(!c.cache_insert_if_absent(1, 102));
+        assert_eq!(c.cache_peek(&1), Some(&101));
+
+        let mut c = UnboundCache::new();
+        assert!(c.cache_insert_if_absent(1, 100));
+        assert!(!c.cache_insert_if_absent(1, 101));
+        assert_eq!(c.cache_peek(&1), Some(&100));
+
+        // the default implementation counts a lookup
+        let mut c = super::AppendCache::with_max_items(2);
+        assert!(c.cache_insert_if_absent(1, std::collections::VecDeque::from(vec![100])));
+        assert_eq!(Some(1), c.cache_misses());
+    }
+
+    #[test]
+    fn sized_cache_set_capacity() {
+        let mut c = SizedCache::with_size(4);
+        for i in 0..4 {
+            c.cache_set(i, i);
+        }
+        assert_eq!(4, c.set_capacity(6));
+        c.cache_set(4, 4);
+        assert_eq!(5, c.cache_size());
+
+        assert_eq!(Some(6), c.cache_set_capacity(2));
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [4, 3]);
+        c.cache_set(5, 5);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [5, 4]);
+
+        let mut c: SizedCache<u32, u32> =
+            SizedCache::with_size(4).with_auto_size(super::AutoSize::new(2, 8));
+        c.set_capacity(100);
+        assert_eq!(Some(8), c.cache_capacity());
+    }
+
+    #[test]
+    fn sized_cache_admission() {
+        use std::time::Duration;
+
+        let admission = super::Admission::new(2, Duration::from_secs(60));
+        let mut c = SizedCache::with_size(2).with_admission(admission);
+
+        // first request is only computed
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(0, c.cache_size());
+        assert_eq!(c.cache_get_or_set_with(2, || 200), &200);
+        assert_eq!(0, c.cache_size());
+
+        // second request is admitted
+        assert!(c.cache_get(&1).is_none());
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_get_or_set_with(2, || 200), &200);
+        assert_eq!(2, c.cache_size());
+
+        // one-off keys don't evict admitted ones
+        assert_eq!(c.cache_get_or_set_with(3, || 300), &300);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(c.cache_get(&1), Some(&100));
+    }
+
+    #[test]
+    fn timed_cache_jitter() {
+        let mut c = TimedCache::with_lifespan(100)
+            .with_jitter(0.5)
+            .with_hash_seed(1);
+        for i in 0..50 {
+            c.cache_set(i, i);
+        }
+        c.cache_get_or_set_with(50, || 50);
+        c.cache_set_with_lifespan(51, 51, 100);
+        let lifespans = (0..=50)
+            .map(|i| c.store[&i].lifespan.unwrap())
+            .collect::<Vec<_>>();
+        assert!(lifespans.iter().all(|s| (50..=100).contains(s)));
+        assert!(lifespans.iter().any(|s| *s != lifespans[0]));
+        assert_eq!(Some(100), c.store[&51].lifespan);
+        assert_eq!(0.5, c.jitter());
+
+        // nothing to jitter under a second
+        let mut c = TimedCache::with_lifespan(1).with_jitter(0.5);
+        c.cache_set(1, 1);
+        assert_eq!(None, c.store[&1].lifespan);
+    }
+
+    #[test]
+    #[should_panic]
+    fn timed_cache_jitter_out_of_range() {
+        TimedCache::<u32, u32>::with_lifespan(1).with_jitter(1.5);
+    }
+
+    #[test]
+    fn cache_extend() {
+        let mut c = SizedCache::with_size(3);
+        c.cache_extend((1..=4).map(|n| (n, n * 100)));
+        assert_eq!(3, c.cache_size());
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(
+            vec![Some(200), None],
+            c.cache_set_many(vec![(2, 201), (5, 500)])
+        );
+        assert_eq!(Some(&201), c.cache_get(&2));
+    }
+
+    #[test]
+    fn cache_get_many() {
+        let mut c = UnboundCache::new();
+        c.cache_extend(vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+        let (hits, misses) = c.cache_get_many(["a", "c", "b"]);
+        assert_eq!(vec![("a", 1), ("b", 2)], hits);
+        assert_eq!(vec!["c"], misses);
+        assert_eq!(Some(2), c.cache_hits());
+        assert_eq!(Some(1), c.cache_misses());
+    }
+
+    #[test]
+    fn try_constructors() {
+        use crate::{ConfigError, SampledLruCache, TimedSizedCache};
+
+        assert!(SizedCache::<u32, u32>::try_with_size(1).is_ok());
+        assert_eq!(
+            SizedCache::<u32, u32>::try_with_size(0).err(),
+            Some(ConfigError::ZeroSize)
+        );
+        assert!(TimedCache::<u32, u32>::try_with_lifespan(1).is_ok());
+        assert_eq!(
+            TimedCache::<u32, u32>::try_with_lifespan(0).err(),
+            Some(ConfigError::ZeroLifespan)
+        );
+        assert_eq!(
+            TimedSizedCache::<u32, u32>::try_with_size_and_lifespan(1, 0).err(),
+            Some(ConfigError::ZeroLifespan)
+        );
+        assert_eq!(
+            SampledLruCache::<u32, u32>::try_with_size_and_samples(1, 0).err(),
+            Some(ConfigError::ZeroSamples)
+        );
+    }
+
+    #[test]
+    fn timed_cache_ttl_remaining() {
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        c.cache_set(3, 300);
+        c.pin(&3);
+        let remaining = c.cache_ttl_remaining(&1).unwrap();
+        assert!(remaining > Duration::from_secs(59) && remaining <= Duration::from_secs(60));
+        assert_eq!(None, c.cache_ttl_remaining(&2));
+        assert_eq!(None, c.cache_ttl_remaining(&3));
+        assert_eq!(None, c.cache_ttl_remaining(&4));
+    }
+
+    #[test]
+    fn timed_cache_max_lifespan() {
+        let mut c = TimedCache::with_lifespan(3600).with_max_lifespan(60);
+        assert_eq!(Some(60), c.cache_lifespan());
+        assert_eq!(Some(60), c.max_lifespan());
+        c.cache_set_with_lifespan(1, 100, 7200);
+        c.cache_set_with_lifespan(2, 200, 1);
+        assert_eq!(Some(60), c.cache_set_lifespan(600));
+        assert_eq!(Some(60), c.cache_lifespan());
+        assert_eq!(
+            c.expiry_stats(),
+            super::ExpiryStats {
+                within_10s: 1,
+                within_1m: 1,
+                ..Default::default()
+            }
+        );
+
+        c.cache_set_with_lifespan(3, 300, 0);
+        assert!(c.cache_get(&3).is_none());
+    }
+
+    #[test]
+    fn timed_cache_unset_lifespan() {
+        let mut c = TimedCache::with_lifespan(1);
+        c.cache_set(1, 100);
+        assert_eq!(Some(1), c.cache_unset_lifespan());
+        assert_eq!(None, c.cache_lifespan());
+        sleep(Duration::new(1, 0));
+        assert_eq!(Some(&100), c.cache_get(&1));
+        assert_eq!(None, c.cache_unset_lifespan());
+        assert_eq!(None, c.cache_set_lifespan(60));
+        assert_eq!(Some(60), c.cache_lifespan());
+
+        // new values aren't jittered while the lifespan is unset
+        let mut c = TimedCache::with_lifespan(60).with_jitter(1.0);
+        c.cache_unset_lifespan();
+        c.cache_set(1, 100);
+        assert_eq!(Some(&100), c.cache_get(&1));
+
+        let mut c = TimedCache::<u32, u32>::with_lifespan(3600).with_max_lifespan(60);
+        assert_eq!(Some(60), c.cache_unset_lifespan());
+        assert_eq!(Some(60), c.cache_lifespan());
+    }
+
+    #[test]
+    fn timed_cache_wall_clock() {
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, 100);
+        let mut c = c.with_time_source(super::TimeSource::WallClock);
+        assert_eq!(super::TimeSource::WallClock, c.time_source());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        c.cache_set(2, 200);
+        c.cache_set_with_lifespan(3, 300, 0);
+        assert_eq!(c.cache_get(&2), Some(&200));
+        assert!(c.cache_get(&3).is_none());
+        assert_eq!(c.expiry_stats().within_1m, 2);
+    }
+
+    #[test]
+    fn timed_cache_expiry_stats() {
+        let mut c = TimedCache::with_lifespan(30);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        c.cache_set_with_lifespan(3, 300, 5);
+        c.cache_set_with_lifespan(4, 400, 120);
+        c.cache_set_with_lifespan(5, 500, 3600);
+        c.cache_set_with_lifespan(6, 600, 0);
+        assert_eq!(
+            c.expiry_stats(),
+            super::ExpiryStats {
+                expired: 1,
+                within_10s: 1,
+                within_1m: 2,
+                within_10m: 1,
+                later: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn sized_cache_eviction_batch() {
+        let mut c = SizedCache::with_size(10).with_eviction_batch(4);
+        for i in 0..10 {
+            c.cache_set(i, i);
+        }
+        assert_eq!(10, c.cache_size());
+        // evicts the 4 least recently used to make room
+        c.cache_set(10, 10);
+        assert_eq!(7, c.cache_size());
+        assert_eq!(c.key_order().last(), Some(&4));
+        // shrinking evicts only what's needed
+        c.set_capacity(5);
+        assert_eq!(5, c.cache_size());
+    }
+
+    #[test]
+    fn sized_cache_deferred_drops() {
+        let value = Arc::new(());
+        let mut c = SizedCache::with_size(2).with_deferred_drops();
+        c.cache_set(1, value.clone());
+        c.cache_set(2, value.clone());
+        c.cache_set(3, value.clone());
+        assert_eq!(2, c.cache_size());
+        // the evicted clone is dropped on the background thread
+        for _ in 0..100 {
+            if Arc::strong_count(&value) == 3 {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(3, Arc::strong_count(&value));
+    }
+
+    #[test]
+    fn sized_cache_peek_order() {
+        let mut c = SizedCache::with_size(3);
+        assert_eq!(None, c.peek_lru());
+        assert_eq!(None, c.peek_mru());
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        c.cache_set(3, 300);
+        c.cache_get(&1);
+        assert_eq!(Some((&1, &100)), c.peek_mru());
+        assert_eq!(Some((&2, &200)), c.peek_lru());
+
+        // pinned values aren't evicted next
+        c.pin(&2);
+        assert_eq!(Some((&3, &300)), c.peek_lru());
+        c.pin(&3);
+        c.pin(&1);
+        assert_eq!(None, c.peek_lru());
+    }
+
+    #[test]
+    fn sized_cache_pinned() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert!(c.pin(&1));
+        assert!(!c.pin(&3));
+        assert!(c.is_pinned(&1));
+
+        // 1 is least recently used but pinned
+        c.cache_set(3, 300);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&3, &1]);
+
+        // grows past its size rather than evict pinned values
+        assert!(c.pin(&3));
+        c.cache_set(4, 400);
+        assert_eq!(3, c.cache_size());
+        assert!(c.unpin(&3));
+        assert!(!c.unpin(&3));
+        c.cache_set(5, 500);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&5, &1]);
+
+        assert_eq!(c.cache_remove(&1), Some(100));
+        assert!(!c.is_pinned(&1));
+    }
+
+    #[test]
+    fn timed_cache_pinned() {
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set_with_lifespan(1, 100, 1);
+        assert!(c.pin(&1));
+        assert!(c.is_pinned(&1));
+        sleep(Duration::new(1, 0));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        c.cache_set_with_lifespan(1, 101, 0);
+        assert!(c.is_pinned(&1));
+        assert_eq!(c.expiry_stats().pinned, 1);
+
+        // expires right away once unpinned
+        assert!(c.unpin(&1));
+        assert!(c.cache_get(&1).is_none());
+        c.cache_set_with_lifespan(2, 200, 0);
+        assert!(!c.pin(&2));
+    }
+
     #[test]
     fn timed_cache() {
         let mut c = TimedCache::with_lifespan(2);
@@ -745,6 +2731,70 @@ mod tests {
         assert_eq!(3, misses);
     }
 
+    #[test]
+    fn timed_cache_set_with_lifespan() {
+        let mut c = TimedCache::with_lifespan(3600);
+        assert_eq!(c.cache_set_with_lifespan(1, 100, 1), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+
+        // the override is kept when the cache's lifespan changes
+        c.cache_set_lifespan(7200);
+        sleep(Duration::new(1, 0));
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_get(&2), Some(&200));
+
+        // a regular insert goes back to the cache's lifespan
+        assert_eq!(c.cache_set_with_lifespan(2, 300, 1), Some(200));
+        assert_eq!(c.cache_set(2, 400), Some(300));
+        sleep(Duration::new(1, 0));
+        assert_eq!(c.cache_get(&2), Some(&400));
+    }
+
+    #[test]
+    fn timed_cache_refresh() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        fn now() -> Duration {
+            Duration::from_secs(NOW.load(Ordering::SeqCst))
+        }
+
+        let mut c = TimedCache::with_lifespan_and_refresh(10, true)
+            .with_time_source(super::TimeSource::Custom(now));
+        assert!(c.refresh());
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        for _ in 0..3 {
+            NOW.fetch_add(6, Ordering::SeqCst);
+            assert_eq!(c.cache_get(&1), Some(&100));
+        }
+        // 2 went unread, 1 is purged once it does too
+        assert!(c.cache_peek(&2).is_none());
+        NOW.fetch_add(10, Ordering::SeqCst);
+        c.cache_set(3, 300);
+        assert_eq!(1, c.cache_size());
+    }
+
+    #[test]
+    fn timed_cache_purges_expired_on_insert() {
+        let mut c = TimedCache::with_lifespan(1);
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(2, c.cache_size());
+
+        // expiry is scheduled with a one second resolution
+        sleep(Duration::new(2, 0));
+        // neither 1 nor 2 are retrieved, but inserting purges them
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(1, c.cache_size());
+        assert_eq!(0, c.cache_misses().unwrap());
+
+        // a lifespan change reschedules the values already cached
+        c.cache_set_lifespan(3600);
+        sleep(Duration::new(2, 0));
+        assert_eq!(c.cache_get_or_set_with(4, || 400), &400);
+        assert_eq!(2, c.cache_size());
+    }
+
     #[test]
     fn clear() {
         let mut c = UnboundCache::new();
@@ -963,6 +3013,29 @@ mod tests {
         assert_eq!(c.cache_misses(), None);
     }
 
+    #[test]
+    fn seeded_hash_order() {
+        let build = || {
+            let mut c = UnboundCache::new().with_hash_seed(1);
+            for i in 0..64 {
+                c.cache_set(i, i);
+            }
+            c
+        };
+        let keys = |c: &UnboundCache<u32, u32>| c.store.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&build()), keys(&build()));
+
+        // reseeding rehashes the existing entries
+        let mut c = UnboundCache::new();
+        assert_eq!(c.cache_set(1, 100), None);
+        let mut c = c.with_hash_seed(1);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(Some(1), c.store.hasher().seed());
+
+        c.cache_reset();
+        assert_eq!(Some(1), c.store.hasher().seed());
+    }
+
     #[test]
     fn get_or_set_with() {
         let mut c = SizedCache::with_size(5);
@@ -1036,4 +3109,46 @@ mod tests {
 
         assert_eq!(c.cache_misses(), Some(7));
     }
+
+    #[cfg(feature = "async")]
+    #[async_std::test]
+    async fn get_or_set_with_async() {
+        let mut c = SizedCache::with_size(2);
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 1 }).await, &1);
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 2 }).await, &1);
+        assert_eq!(c.cache_get_or_set_with_async(2, || async { 2 }).await, &2);
+        assert_eq!(c.cache_get_or_set_with_async(3, || async { 3 }).await, &3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(4));
+
+        let mut c = UnboundCache::new();
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 1 }).await, &1);
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 2 }).await, &1);
+        assert_eq!(c.cache_misses(), Some(1));
+
+        let mut c = TimedCache::with_lifespan(1);
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 1 }).await, &1);
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 2 }).await, &1);
+        sleep(Duration::new(1, 0));
+        assert_eq!(c.cache_get_or_set_with_async(1, || async { 3 }).await, &3);
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+
+    #[cfg(feature = "async")]
+    #[async_std::test]
+    async fn try_get_or_set_with_async() {
+        use crate::CachedAsync;
+
+        let mut c = SizedCache::with_size(2);
+        let res: Result<_, ()> = c.try_get_or_set_with(1, || async { Err(()) }).await;
+        assert_eq!(res, Err(()));
+        assert!(c.cache_get(&1).is_none());
+        let res: Result<_, ()> = c.try_get_or_set_with(1, || async { Ok(1) }).await;
+        assert_eq!(res, Ok(&mut 1));
+        let res: Result<_, ()> = c.try_get_or_set_with(1, || async { Err(()) }).await;
+        assert_eq!(res, Ok(&mut 1));
+        assert_eq!(c.get_or_set_with(2, || async { 2 }).await, &2);
+        assert_eq!(c.cache_size(), 2);
+    }
 }
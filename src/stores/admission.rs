@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+/// Rows of counters in the sketch, each indexed by a different hash of the key
+const DEPTH: usize = 4;
+
+/// Odd multipliers remixing the key's hash for every row
+const MIXERS: [u64; DEPTH] = [
+    0x9E37_79B9_7F4A_7C15,
+    0xC2B2_AE3D_27D4_EB4F,
+    0x1656_67B1_9E37_79F9,
+    0xD6E8_FEB8_6659_FD93,
+];
+
+/// Admission policy for a `SizedCache`
+///
+/// A key is only admitted into the cache once it has been looked up `requests`
+/// times within the same `window`, so that one-off computations don't take up
+/// capacity (and evict entries that are actually reused).
+///
+/// Lookups are counted in a fixed size count-min sketch rather than per key, so
+/// memory use doesn't grow with the number of distinct keys. Collisions can only
+/// overestimate a count, letting a key in early, never keep it out. All counts
+/// are reset at the end of every window.
+#[derive(Clone, Debug)]
+pub struct Admission {
+    requests: u8,
+    window: Duration,
+    started: Instant,
+    mask: usize,
+    counters: Vec<u8>,
+}
+
+impl Admission {
+    /// Creates a policy admitting keys on their `requests`th lookup within `window`,
+    /// counting lookups in a sketch 1024 counters wide
+    pub fn new(requests: u8, window: Duration) -> Admission {
+        if requests == 0 {
+            panic!("`requests` of `Admission` must be greater than zero.")
+        }
+        Admission {
+            requests,
+            window,
+            started: Instant::now(),
+            mask: 0,
+            counters: Vec::new(),
+        }
+        .width(1024)
+    }
+
+    /// Use `width` counters per row of the sketch, rounded up to a power of two.
+    /// Wider sketches collide less when many distinct keys are looked up per window
+    pub fn width(mut self, width: usize) -> Admission {
+        if width == 0 {
+            panic!("`width` of `Admission` must be greater than zero.")
+        }
+        let width = width.next_power_of_two();
+        self.mask = width - 1;
+        self.counters = vec![0; width * DEPTH];
+        self
+    }
+
+    /// Count a lookup of the key hashing to `hash`
+    pub(super) fn record(&mut self, hash: u64) {
+        self.roll();
+        let width = self.mask + 1;
+        for row in 0..DEPTH {
+            let index = row * width + self.index(hash, row);
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    /// Whether the key hashing to `hash` has been looked up often enough to be admitted
+    pub(super) fn admits(&mut self, hash: u64) -> bool {
+        self.roll();
+        let width = self.mask + 1;
+        let estimate = (0..DEPTH)
+            .map(|row| self.counters[row * width + self.index(hash, row)])
+            .min()
+            .unwrap_or(0);
+        estimate >= self.requests
+    }
+
+    /// Reset all counts if the current window is over
+    fn roll(&mut self) {
+        if self.started.elapsed() >= self.window {
+            self.counters.iter_mut().for_each(|c| *c = 0);
+            self.started = Instant::now();
+        }
+    }
+
+    /// Column of the key hashing to `hash` in `row`
+    fn index(&self, hash: u64, row: usize) -> usize {
+        let mixed = hash.wrapping_mul(MIXERS[row]);
+        (mixed >> 32) as usize & self.mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Admission;
+
+    #[test]
+    fn admits_on_nth_request() {
+        let mut admission = Admission::new(3, Duration::from_secs(60));
+        admission.record(1);
+        admission.record(1);
+        admission.record(2);
+        assert!(!admission.admits(1));
+        admission.record(1);
+        assert!(admission.admits(1));
+        assert!(!admission.admits(2));
+    }
+
+    #[test]
+    fn counts_reset_every_window() {
+        let mut admission = Admission::new(1, Duration::from_millis(0));
+        admission.record(1);
+        assert!(!admission.admits(1));
+    }
+}
@@ -0,0 +1,322 @@
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::Hash;
+
+use super::hasher::{self, SeedableState};
+use super::Cached;
+#[cfg(feature = "async")]
+use super::CachedAsync;
+
+/// Append-per-key cache
+///
+/// Each key holds a bounded collection of values rather than a single value.
+/// `cache_push` appends an item to a key's collection, dropping the oldest
+/// item once the collection holds `max_items`. `cache_get` returns the whole
+/// collection, ordered from oldest to newest.
+///
+/// Useful for caching the most recent events per entity.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct AppendCache<K, V> {
+    store: HashMap<K, VecDeque<V>, SeedableState>,
+    max_items: usize,
+    hits: u64,
+    misses: u64,
+    initial_capacity: Option<usize>,
+}
+
+impl<K, V> PartialEq for AppendCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &AppendCache<K, V>) -> bool {
+        self.store.eq(&other.store)
+    }
+}
+
+impl<K, V> Eq for AppendCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+}
+
+impl<K: Hash + Eq, V> AppendCache<K, V> {
+    /// Creates an empty `AppendCache` holding up to `max_items` values per key
+    pub fn with_max_items(max_items: usize) -> AppendCache<K, V> {
+        Self::new(max_items, None)
+    }
+
+    /// Creates an empty `AppendCache` holding up to `max_items` values per key,
+    /// with a given pre-allocated capacity for keys
+    pub fn with_max_items_and_capacity(max_items: usize, size: usize) -> AppendCache<K, V> {
+        Self::new(max_items, Some(size))
+    }
+
+    fn new(max_items: usize, capacity: Option<usize>) -> AppendCache<K, V> {
+        if max_items == 0 {
+            panic!("`max_items` of `AppendCache` must be greater than zero.")
+        }
+        AppendCache {
+            store: Self::new_store(capacity, SeedableState::new()),
+            max_items,
+            hits: 0,
+            misses: 0,
+            initial_capacity: capacity,
+        }
+    }
+
+    fn new_store(
+        capacity: Option<usize>,
+        state: SeedableState,
+    ) -> HashMap<K, VecDeque<V>, SeedableState> {
+        HashMap::with_capacity_and_hasher(capacity.unwrap_or(0), state)
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`
+    pub fn with_hash_seed(mut self, seed: u64) -> AppendCache<K, V> {
+        hasher::reseed(&mut self.store, seed);
+        self
+    }
+
+    /// Return the maximum number of values held per key
+    pub fn max_items(&self) -> usize {
+        self.max_items
+    }
+
+    /// Append a value to the collection of `key`, returning the oldest value
+    /// if it had to be dropped to stay within `max_items`
+    pub fn cache_push(&mut self, key: K, val: V) -> Option<V> {
+        let max_items = self.max_items;
+        let items = self
+            .store
+            .entry(key)
+            .or_insert_with(|| VecDeque::with_capacity(max_items));
+        let dropped = if items.len() >= max_items {
+            items.pop_front()
+        } else {
+            None
+        };
+        items.push_back(val);
+        dropped
+    }
+}
+
+/// Drop the oldest items so that at most `max_items` remain
+fn truncate<V>(items: &mut VecDeque<V>, max_items: usize) {
+    let excess = items.len().saturating_sub(max_items);
+    items.drain(..excess);
+}
+
+impl<K: Hash + Eq, V> Cached<K, VecDeque<V>> for AppendCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&VecDeque<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get(key) {
+            Some(v) => {
+                self.hits += 1;
+                Some(v)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&VecDeque<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get(key)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &VecDeque<V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get_key_value(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut VecDeque<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get_mut(key) {
+            Some(v) => {
+                self.hits += 1;
+                Some(v)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_set(&mut self, key: K, mut val: VecDeque<V>) -> Option<VecDeque<V>> {
+        truncate(&mut val, self.max_items);
+        self.store.insert(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> VecDeque<V>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> &mut VecDeque<V> {
+        match self.store.entry(key) {
+            Entry::Occupied(occupied) => {
+                self.hits += 1;
+                occupied.into_mut()
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                let mut val = f();
+                truncate(&mut val, self.max_items);
+                vacant.insert(val)
+            }
+        }
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<VecDeque<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.remove(k)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.store.len();
+        self.store.retain(|key, _| !f(key));
+        Some(before - self.store.len())
+    }
+    fn cache_retain<F>(&mut self, mut f: F) -> Option<usize>
+    where
+        F: FnMut(&K, &VecDeque<V>) -> bool,
+    {
+        let before = self.store.len();
+        self.store.retain(|key, values| f(key, values));
+        Some(before - self.store.len())
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+    }
+    fn cache_reset(&mut self) {
+        self.store = Self::new_store(self.initial_capacity, self.store.hasher().clone());
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V> AppendCache<K, V> {
+    /// Get or insert a key, collection pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut VecDeque<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = VecDeque<V>>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V> CachedAsync<K, VecDeque<V>> for AppendCache<K, V> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(
+        &'a mut self,
+        key: K,
+        f: F,
+    ) -> Result<&'a mut VecDeque<V>, E>
+    where
+        VecDeque<V>: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<VecDeque<V>, E>>,
+    {
+        Ok(match self.store.entry(key) {
+            Entry::Occupied(occupied) => {
+                self.hits += 1;
+                occupied.into_mut()
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                let mut val = f().await?;
+                truncate(&mut val, self.max_items);
+                vacant.insert(val)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::AppendCache;
+    use super::Cached;
+
+    #[test]
+    fn append_cache() {
+        let mut c = AppendCache::with_max_items(3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(1, c.cache_misses().unwrap());
+
+        assert_eq!(c.cache_push(1, 100), None);
+        assert_eq!(c.cache_push(1, 200), None);
+        assert_eq!(c.cache_push(2, 300), None);
+        assert_eq!(c.cache_get(&1), Some(&VecDeque::from(vec![100, 200])));
+        assert_eq!(1, c.cache_hits().unwrap());
+        assert_eq!(2, c.cache_size());
+
+        assert_eq!(c.cache_push(1, 300), None);
+        assert_eq!(c.cache_push(1, 400), Some(100));
+        assert_eq!(c.cache_push(1, 500), Some(200));
+        assert_eq!(c.cache_get(&1), Some(&VecDeque::from(vec![300, 400, 500])));
+
+        assert_eq!(c.cache_remove(&2), Some(VecDeque::from(vec![300])));
+        assert_eq!(1, c.cache_size());
+    }
+
+    #[test]
+    fn append_cache_set_truncates() {
+        let mut c = AppendCache::with_max_items(2);
+
+        assert_eq!(c.cache_set(1, VecDeque::from(vec![1, 2, 3, 4])), None);
+        assert_eq!(c.cache_get(&1), Some(&VecDeque::from(vec![3, 4])));
+
+        assert_eq!(
+            c.cache_get_or_set_with(2, || VecDeque::from(vec![5, 6, 7])),
+            &[6, 7]
+        );
+        assert_eq!(
+            c.cache_get_or_set_with(2, || VecDeque::from(vec![8])),
+            &[6, 7]
+        );
+        assert_eq!(c.cache_misses(), Some(1));
+        assert_eq!(c.cache_hits(), Some(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn append_cache_zero_max_items() {
+        AppendCache::<u32, u32>::with_max_items(0);
+    }
+}
@@ -0,0 +1,273 @@
+use core::borrow::Borrow;
+use core::hash::Hash;
+
+use super::Cached;
+
+/// Entry of an `ArrayCache` slot, along with when it was last used
+#[derive(Clone, Debug)]
+struct Slot<K, V> {
+    key: K,
+    value: V,
+    used: u64,
+}
+
+/// Fixed-capacity cache store holding up to `N` entries inline, without allocating
+///
+/// Entries live in an array of `N` slots, looked up by comparing keys one by one, and
+/// the least recently used one is replaced once the slots are full. There's no hashing
+/// and no heap allocation, which suits embedded and latency-critical code caching a few
+/// values, where scanning the slots is cheaper than a `HashMap`. Prefer `SizedCache` for
+/// more than a few dozen entries.
+///
+/// ```rust
+/// use cached::{ArrayCache, Cached};
+///
+/// let mut cache: ArrayCache<u32, u32, 2> = ArrayCache::new();
+/// cache.cache_set(1, 100);
+/// cache.cache_set(2, 200);
+/// cache.cache_get(&1);
+/// cache.cache_set(3, 300);
+/// assert_eq!(cache.cache_get(&2), None);
+/// assert_eq!(cache.cache_get(&1), Some(&100));
+/// ```
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct ArrayCache<K, V, const N: usize> {
+    slots: [Option<Slot<K, V>>; N],
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Eq, V, const N: usize> Default for ArrayCache<K, V, N> {
+    fn default() -> ArrayCache<K, V, N> {
+        ArrayCache::new()
+    }
+}
+
+impl<K: Eq, V, const N: usize> ArrayCache<K, V, N> {
+    /// Creates an empty `ArrayCache` holding up to `N` entries
+    pub fn new() -> ArrayCache<K, V, N> {
+        if N == 0 {
+            panic!("`N` of `ArrayCache` must be greater than zero.")
+        }
+        ArrayCache {
+            slots: core::array::from_fn(|_| None),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return an iterator of keys and values, in slot order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| (&slot.key, &slot.value))
+    }
+
+    /// Index of the slot holding `key`
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.slots.iter().position(|slot| match slot {
+            Some(slot) => slot.key.borrow() == key,
+            None => false,
+        })
+    }
+
+    /// Index of the slot holding `key`, marking it used and counting a hit if found
+    fn lookup<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
+                self.hits += 1;
+                self.touch(index);
+                Some(index)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn touch(&mut self, index: usize) {
+        self.clock += 1;
+        if let Some(slot) = &mut self.slots[index] {
+            slot.used = self.clock;
+        }
+    }
+
+    /// Index of the slot a new entry goes into, an empty one or the least recently used
+    fn vacancy(&self) -> usize {
+        self.slots
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, slot)| slot.as_ref().map(|slot| slot.used))
+            .map(|(index, _)| index)
+            .unwrap()
+    }
+
+    /// Insert a new entry into a vacant or evicted slot, returning its index
+    fn insert(&mut self, key: K, value: V) -> usize {
+        let index = self.vacancy();
+        self.clock += 1;
+        self.slots[index] = Some(Slot {
+            key,
+            value,
+            used: self.clock,
+        });
+        index
+    }
+
+    fn value_mut(&mut self, index: usize) -> &mut V {
+        &mut self.slots[index].as_mut().unwrap().value
+    }
+}
+
+impl<K: Hash + Eq, V, const N: usize> Cached<K, V> for ArrayCache<K, V, N> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.lookup(key)?;
+        self.slots[index].as_ref().map(|slot| &slot.value)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.lookup(key)?;
+        Some(self.value_mut(index))
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache_peek_key_value(key).map(|(_, value)| value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index]
+            .as_ref()
+            .map(|slot| (&slot.key, &slot.value))
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        match self.find(&key) {
+            Some(index) => {
+                self.touch(index);
+                Some(core::mem::replace(self.value_mut(index), val))
+            }
+            None => {
+                self.insert(key, val);
+                None
+            }
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let index = match self.lookup(&key) {
+            Some(index) => index,
+            None => self.insert(key, f()),
+        };
+        self.value_mut(index)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index].take().map(|slot| slot.value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.cache_retain(|key, _| !f(key))
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let mut removed = 0;
+        for slot in self.slots.iter_mut() {
+            if matches!(slot, Some(entry) if !f(&entry.key, &entry.value)) {
+                *slot = None;
+                removed += 1;
+            }
+        }
+        Some(removed)
+    }
+    fn cache_clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            *slot = None;
+        }
+    }
+    fn cache_reset(&mut self) {
+        self.cache_clear();
+        self.clock = 0;
+    }
+    fn cache_size(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(N)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::ArrayCache;
+    use super::Cached;
+
+    #[test]
+    fn array_cache() {
+        let mut c: ArrayCache<u32, u32, 3> = ArrayCache::new();
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_set(2, 201), Some(200));
+
+        // 3 is the least recently used
+        assert_eq!(*c.cache_get_or_set_with(4, || 400), 400);
+        assert!(c.cache_peek(&3).is_none());
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_capacity(), Some(3));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+
+        assert_eq!(c.cache_remove(&1), Some(100));
+        assert_eq!(c.cache_set(5, 500), None);
+        assert_eq!(c.cache_peek(&2), Some(&201));
+        assert_eq!(c.cache_remove_where(|key| *key > 3), Some(2));
+        assert_eq!(c.iter().collect::<Vec<_>>(), [(&2, &201)]);
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bb8::{ManageConnection, Pool, PooledConnection, RunError};
+use redis::aio::MultiplexedConnection;
+use redis::{Client, ConnectionInfo, ErrorKind, IntoConnectionInfo, RedisError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::redis::decode;
+use crate::registry::Health;
+use crate::{IOCachedAsync, RedisCacheError};
+
+/// Opens the connections of an `AsyncRedisCache`'s pool
+struct Manager {
+    client: Client,
+}
+
+#[async_trait]
+impl ManageConnection for Manager {
+    type Connection = MultiplexedConnection;
+    type Error = RedisError;
+
+    async fn connect(&self) -> Result<MultiplexedConnection, RedisError> {
+        self.client.get_multiplexed_async_connection().await
+    }
+
+    async fn is_valid(&self, conn: &mut MultiplexedConnection) -> Result<(), RedisError> {
+        redis::cmd("PING").query_async::<String>(conn).await?;
+        Ok(())
+    }
+
+    fn has_broken(&self, _conn: &mut MultiplexedConnection) -> bool {
+        // broken connections are found by `is_valid` when they're checked out
+        false
+    }
+}
+
+/// Async store keeping values in Redis, on a pool of connections
+///
+/// Enabled by the `redis_async` feature, it stores values like a `RedisCache`, encoded as
+/// JSON under `{prefix}{key}` and expiring after the lifespan set with `with_lifespan`, but
+/// implements `IOCachedAsync`. Concurrent calls each check a connection out of a pool,
+/// instead of waiting for a single connection, and return it for the next calls. Connections
+/// are opened on demand, up to the pool size, and checked with a `PING` when checked out,
+/// so a broken one is replaced rather than failing a call. Calls fail when no connection
+/// could be checked out within the pool's timeout, e.g. while Redis can't be reached.
+///
+/// The pool runs on `tokio`, the store has to be opened in a `tokio` runtime. Use
+/// `create_async` to open it on the first call of an `#[io_cached]` function:
+///
+/// ```rust,no_run
+/// use cached::proc_macro::io_cached;
+/// use cached::{AsyncRedisCache, RedisCacheError};
+///
+/// #[derive(Debug)]
+/// enum Error {
+///     Cache(RedisCacheError),
+/// }
+///
+/// #[io_cached(
+///     type = "AsyncRedisCache<u32, String>",
+///     create = r#"async { AsyncRedisCache::open("redis://127.0.0.1/", "users:").await.unwrap().with_lifespan(60) }"#,
+///     create_async = true,
+///     map_error = r#"|e| Error::Cache(e)"#
+/// )]
+/// async fn user_name(id: u32) -> Result<String, Error> {
+///     Ok(format!("user {}", id))
+/// }
+/// ```
+pub struct AsyncRedisCache<K, V> {
+    pool: Pool<Manager>,
+    info: ConnectionInfo,
+    prefix: String,
+    lifespan: Option<u64>,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V> fmt::Debug for AsyncRedisCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncRedisCache")
+            .field("connection_info", &self.info)
+            .field("prefix", &self.prefix)
+            .field("lifespan", &self.lifespan)
+            .field("connections", &self.pool.state().connections)
+            .finish()
+    }
+}
+
+/// Default number of connections of an `AsyncRedisCache`'s pool
+const POOL_SIZE: u32 = 16;
+
+/// Default time an `AsyncRedisCache` call waits for a connection of its pool
+const POOL_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl<K, V> AsyncRedisCache<K, V> {
+    /// Creates an `AsyncRedisCache` for the server at `info`, e.g. a `redis://host:port/db`
+    /// URL, keeping values under keys starting with `prefix`, on a pool of up to 16
+    /// connections, calls waiting up to a second for one. Doesn't connect yet, but fails
+    /// if `info` isn't valid.
+    pub async fn open<T: IntoConnectionInfo>(
+        info: T,
+        prefix: &str,
+    ) -> Result<AsyncRedisCache<K, V>, RedisCacheError> {
+        AsyncRedisCache::open_with_pool(info, prefix, POOL_SIZE, POOL_TIMEOUT).await
+    }
+
+    /// Creates an `AsyncRedisCache` like `open`, on a pool of up to `size` connections,
+    /// failing calls that waited `timeout` for one
+    ///
+    /// Will panic if `size` is 0.
+    pub async fn open_with_pool<T: IntoConnectionInfo>(
+        info: T,
+        prefix: &str,
+        size: u32,
+        timeout: Duration,
+    ) -> Result<AsyncRedisCache<K, V>, RedisCacheError> {
+        if size == 0 {
+            panic!("`size` of `AsyncRedisCache` must be greater than zero.")
+        }
+        let client = Client::open(info)?;
+        let info = client.get_connection_info().clone();
+        let pool = Pool::builder()
+            .max_size(size)
+            .connection_timeout(timeout)
+            // a cache call fails rather than waiting for Redis to come back
+            .retry_connection(false)
+            .build(Manager { client })
+            .await?;
+        Ok(AsyncRedisCache {
+            pool,
+            info,
+            prefix: prefix.to_string(),
+            lifespan: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Expire values `seconds` after they're set, instead of keeping them until they're
+    /// removed or evicted by the server
+    pub fn with_lifespan(mut self, seconds: u64) -> AsyncRedisCache<K, V> {
+        if seconds == 0 {
+            panic!("`lifespan` of `AsyncRedisCache` must be greater than zero.")
+        }
+        self.lifespan = Some(seconds);
+        self
+    }
+
+    /// Return the prefix of the keys values are stored under
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Return the number of connections of the pool, idle or checked out
+    pub fn connections(&self) -> u32 {
+        self.pool.state().connections
+    }
+
+    /// Probe the server with a `PING` on a pooled connection
+    pub async fn health(&self) -> Health {
+        match self.connection().await {
+            Ok(mut conn) => match redis::cmd("PING").query_async::<String>(&mut *conn).await {
+                Ok(_) => Health::Up,
+                Err(_) => Health::Down,
+            },
+            Err(_) => Health::Down,
+        }
+    }
+
+    fn key(&self, k: &K) -> String
+    where
+        K: Display,
+    {
+        format!("{}{}", self.prefix, k)
+    }
+
+    /// Check a connection out of the pool, it's returned when dropped
+    async fn connection(&self) -> Result<PooledConnection<'_, Manager>, RedisCacheError> {
+        self.pool.get().await.map_err(|e| match e {
+            RunError::User(e) => e.into(),
+            RunError::TimedOut => RedisError::from((
+                ErrorKind::IoError,
+                "timed out waiting for a pooled connection",
+            ))
+            .into(),
+        })
+    }
+}
+
+impl<K, V> IOCachedAsync<K, V> for AsyncRedisCache<K, V>
+where
+    K: Display + Sync,
+    V: Serialize + DeserializeOwned + Send,
+{
+    type Error = RedisCacheError;
+
+    async fn cache_get(&self, k: &K) -> Result<Option<V>, RedisCacheError> {
+        let key = self.key(k);
+        let mut conn = self.connection().await?;
+        let value: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *conn).await?;
+        decode(value)
+    }
+
+    async fn cache_set(&self, k: K, v: V) -> Result<Option<V>, RedisCacheError> {
+        let key = self.key(&k);
+        let value = serde_json::to_string(&v)?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().cmd("GET").arg(&key);
+        match self.lifespan {
+            Some(seconds) => pipe.cmd("SETEX").arg(&key).arg(seconds).arg(value),
+            None => pipe.cmd("SET").arg(&key).arg(value),
+        }
+        .ignore();
+        let mut conn = self.connection().await?;
+        let (old,): (Option<String>,) = pipe.query_async(&mut *conn).await?;
+        decode(old)
+    }
+
+    async fn cache_remove(&self, k: &K) -> Result<Option<V>, RedisCacheError> {
+        let key = self.key(k);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("GET")
+            .arg(&key)
+            .cmd("DEL")
+            .arg(&key)
+            .ignore();
+        let mut conn = self.connection().await?;
+        let (old,): (Option<String>,) = pipe.query_async(&mut *conn).await?;
+        decode(old)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.lifespan
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::sync::Arc;
+use core::fmt;
+
+/// Adaptive capacity controller for a `SizedCache`
+///
+/// Every `interval` lookups, the hit ratio observed since the last adjustment is
+/// compared against a target range:
+///
+/// - below the range, the capacity grows by `step` (a fraction of the current capacity)
+///   since more entries may turn misses into hits
+/// - above the range, the capacity shrinks by `step` to release memory that is
+///   likely not needed to sustain the hit ratio
+/// - if the memory pressure callback returns `true`, the capacity shrinks regardless
+///
+/// The capacity always stays within `min..=max`. Shrinking evicts the least recently
+/// used entries.
+#[derive(Clone)]
+pub struct AutoSize {
+    min: usize,
+    max: usize,
+    interval: u64,
+    low: f64,
+    high: f64,
+    step: f64,
+    pressure: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+    lookups: u64,
+    hits: u64,
+}
+
+impl fmt::Debug for AutoSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoSize")
+            .field("min", &self.min)
+            .field("max", &self.max)
+            .field("interval", &self.interval)
+            .field("low", &self.low)
+            .field("high", &self.high)
+            .field("step", &self.step)
+            .field("pressure", &self.pressure.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl AutoSize {
+    /// Creates a controller keeping the capacity within `min..=max`, adjusting every
+    /// 1000 lookups to keep the hit ratio between 0.8 and 0.95 in steps of 10%
+    pub fn new(min: usize, max: usize) -> AutoSize {
+        if min == 0 {
+            panic!("`min` of `AutoSize` must be greater than zero.")
+        }
+        if min > max {
+            panic!("`min` of `AutoSize` must not be greater than `max`.")
+        }
+        AutoSize {
+            min,
+            max,
+            interval: 1000,
+            low: 0.8,
+            high: 0.95,
+            step: 0.1,
+            pressure: None,
+            lookups: 0,
+            hits: 0,
+        }
+    }
+
+    /// Adjust the capacity every `lookups` lookups
+    pub fn interval(mut self, lookups: u64) -> AutoSize {
+        if lookups == 0 {
+            panic!("`interval` of `AutoSize` must be greater than zero.")
+        }
+        self.interval = lookups;
+        self
+    }
+
+    /// Grow when the hit ratio is below `low`, shrink when it's above `high`
+    pub fn hit_ratio(mut self, low: f64, high: f64) -> AutoSize {
+        if !(0.0..=1.0).contains(&low) || !(low..=1.0).contains(&high) {
+            panic!("`AutoSize` hit ratios must satisfy 0 <= low <= high <= 1.")
+        }
+        self.low = low;
+        self.high = high;
+        self
+    }
+
+    /// Grow or shrink by `step` times the current capacity, at least one entry
+    pub fn step(mut self, step: f64) -> AutoSize {
+        if step.is_nan() || step <= 0.0 {
+            panic!("`step` of `AutoSize` must be greater than zero.")
+        }
+        self.step = step;
+        self
+    }
+
+    /// Shrink whenever `f` returns `true` at an adjustment, e.g. when the process
+    /// is nearing its memory limit
+    pub fn memory_pressure<F>(mut self, f: F) -> AutoSize
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.pressure = Some(Arc::new(f));
+        self
+    }
+
+    /// Clamp a capacity to the controller's bounds
+    pub(super) fn clamp(&self, capacity: usize) -> usize {
+        capacity.clamp(self.min, self.max)
+    }
+
+    /// Record a lookup, returning the new capacity when an adjustment is due
+    pub(super) fn record(&mut self, hit: bool, capacity: usize) -> Option<usize> {
+        self.lookups += 1;
+        if hit {
+            self.hits += 1;
+        }
+        if self.lookups < self.interval {
+            return None;
+        }
+        let ratio = self.hits as f64 / self.lookups as f64;
+        self.lookups = 0;
+        self.hits = 0;
+
+        let step = ((capacity as f64 * self.step) as usize).max(1);
+        let under_pressure = self.pressure.as_ref().is_some_and(|f| f());
+        let capacity = if under_pressure || ratio > self.high {
+            capacity.saturating_sub(step)
+        } else if ratio < self.low {
+            capacity.saturating_add(step)
+        } else {
+            capacity
+        };
+        Some(self.clamp(capacity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AutoSize;
+
+    #[test]
+    fn adjusts_within_bounds() {
+        let mut auto = AutoSize::new(5, 20).interval(4).step(0.5);
+        // all misses, grow
+        assert_eq!(None, auto.record(false, 10));
+        assert_eq!(None, auto.record(false, 10));
+        assert_eq!(None, auto.record(false, 10));
+        assert_eq!(Some(15), auto.record(false, 10));
+        // capped at `max`
+        for _ in 0..3 {
+            auto.record(false, 15);
+        }
+        assert_eq!(Some(20), auto.record(false, 15));
+        // all hits, shrink
+        for _ in 0..3 {
+            auto.record(true, 20);
+        }
+        assert_eq!(Some(10), auto.record(true, 20));
+        // within the target hit ratio
+        let mut auto = AutoSize::new(5, 20).interval(4).hit_ratio(0.5, 0.9);
+        for hit in [true, true, false] {
+            assert_eq!(None, auto.record(hit, 10));
+        }
+        assert_eq!(Some(10), auto.record(true, 10));
+    }
+
+    #[test]
+    fn shrinks_under_memory_pressure() {
+        let mut auto = AutoSize::new(5, 20).interval(1).memory_pressure(|| true);
+        assert_eq!(Some(9), auto.record(false, 10));
+        assert_eq!(Some(5), auto.record(false, 5));
+    }
+}
@@ -0,0 +1,392 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use super::{
+    Cached, ConfigError, EvictionReason, Listener, SeedableState, SizedCache, TimedCache,
+    TimedSizedCache, UnboundCache,
+};
+
+/// Builder picking the store matching a combination of options
+///
+/// Set the options the cache needs, `build` creates the store supporting them:
+///
+/// | Options                          | Store             |
+/// |----------------------------------|-------------------|
+/// | none                             | `UnboundCache`    |
+/// | `size`                           | `SizedCache`      |
+/// | `time_to_live` or `time_to_idle` | `TimedCache`      |
+/// | `size` and `time_to_live`        | `TimedSizedCache` |
+///
+/// `hasher` and `on_evict` are supported by all of them but the `TimedSizedCache`, `jitter`
+/// only by the `TimedCache`. Other combinations are rejected with `ConfigError::Unsupported`.
+///
+/// ```rust
+/// use cached::{CacheBuilder, Cached};
+///
+/// let mut cache = CacheBuilder::new()
+///     .size(100)
+///     .on_evict(|key: &u32, _: &String, reason| println!("{} left: {:?}", key, reason))
+///     .build();
+/// cache.cache_set(1, "one".to_string());
+/// assert_eq!(cache.cache_capacity(), Some(100));
+///
+/// let mut sessions = CacheBuilder::new().time_to_idle(1800).jitter(0.1).build();
+/// sessions.cache_set("token", 1);
+/// assert_eq!(sessions.cache_lifespan(), Some(1800));
+/// ```
+pub struct CacheBuilder<K, V, S = SeedableState> {
+    size: Option<usize>,
+    time_to_live: Option<u64>,
+    time_to_idle: Option<u64>,
+    hasher: S,
+    custom_hasher: bool,
+    listener: Listener<K, V>,
+    jitter: Option<f64>,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V> CacheBuilder<K, V> {
+    /// Creates a builder without any option, building an `UnboundCache`
+    pub fn new() -> CacheBuilder<K, V> {
+        CacheBuilder {
+            size: None,
+            time_to_live: None,
+            time_to_idle: None,
+            hasher: SeedableState::new(),
+            custom_hasher: false,
+            listener: Listener::new(),
+            jitter: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for CacheBuilder<K, V> {
+    fn default() -> CacheBuilder<K, V> {
+        CacheBuilder::new()
+    }
+}
+
+impl<K, V, S> CacheBuilder<K, V, S> {
+    /// Hold at most `size` values, evicting the least recently used ones
+    pub fn size(mut self, size: usize) -> CacheBuilder<K, V, S> {
+        self.size = Some(size);
+        self
+    }
+
+    /// Expire values `seconds` after they were inserted
+    pub fn time_to_live(mut self, seconds: u64) -> CacheBuilder<K, V, S> {
+        self.time_to_live = Some(seconds);
+        self
+    }
+
+    /// Expire values once they went unread for `seconds`
+    pub fn time_to_idle(mut self, seconds: u64) -> CacheBuilder<K, V, S> {
+        self.time_to_idle = Some(seconds);
+        self
+    }
+
+    /// Hash keys with `hasher` instead of the default `SeedableState`
+    pub fn hasher<H: BuildHasher>(self, hasher: H) -> CacheBuilder<K, V, H> {
+        CacheBuilder {
+            size: self.size,
+            time_to_live: self.time_to_live,
+            time_to_idle: self.time_to_idle,
+            hasher,
+            custom_hasher: true,
+            listener: self.listener,
+            jitter: self.jitter,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Call `f` with every entry leaving the cache and the reason it left
+    pub fn on_evict<F>(mut self, f: F) -> CacheBuilder<K, V, S>
+    where
+        F: Fn(&K, &V, EvictionReason) + Send + Sync + 'static,
+    {
+        self.listener.set(f);
+        self
+    }
+
+    /// Shorten the lifespan of every new value by a random part of up to `fraction` of
+    /// it, see `TimedCache::with_jitter`
+    ///
+    /// Will panic if fraction isn't between 0 and 1
+    pub fn jitter(mut self, fraction: f64) -> CacheBuilder<K, V, S> {
+        if !(0.0..=1.0).contains(&fraction) {
+            panic!("`jitter` of `CacheBuilder` must be between 0 and 1.");
+        }
+        self.jitter = Some(fraction);
+        self
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> CacheBuilder<K, V, S> {
+    /// Creates the store supporting the options
+    ///
+    /// Will panic if the size or lifespan is 0, or if no store supports the combination
+    /// of options
+    pub fn build(self) -> BuiltCache<K, V, S> {
+        match self.try_build() {
+            Ok(cache) => cache,
+            Err(e) => panic!("`CacheBuilder` can't build a cache: {}", e),
+        }
+    }
+
+    /// Like `build`, but returns an error instead of panicking
+    pub fn try_build(self) -> Result<BuiltCache<K, V, S>, ConfigError> {
+        if self.size == Some(0) {
+            return Err(ConfigError::ZeroSize);
+        }
+        if self.time_to_live == Some(0) || self.time_to_idle == Some(0) {
+            return Err(ConfigError::ZeroLifespan);
+        }
+        let lifespan = match (self.time_to_live, self.time_to_idle) {
+            (Some(_), Some(_)) => return Err(ConfigError::Unsupported),
+            (Some(seconds), None) => Some((seconds, false)),
+            (None, Some(seconds)) => Some((seconds, true)),
+            (None, None) => None,
+        };
+        match (self.size, lifespan) {
+            (None, None) if !self.listener.is_set() && self.jitter.is_none() => {
+                Ok(BuiltCache::Unbound(UnboundCache::with_hasher(self.hasher)))
+            }
+            (Some(size), None) if self.jitter.is_none() => {
+                let mut cache = SizedCache::with_size_and_hasher(size, self.hasher);
+                cache.listener = self.listener;
+                Ok(BuiltCache::Sized(cache))
+            }
+            (None, Some((seconds, refresh))) => {
+                let mut cache = TimedCache::with_lifespan_and_hasher(seconds, self.hasher)
+                    .with_refresh(refresh);
+                if let Some(fraction) = self.jitter {
+                    cache = cache.with_jitter(fraction);
+                }
+                cache.listener = self.listener;
+                Ok(BuiltCache::Timed(cache))
+            }
+            (Some(size), Some((seconds, false)))
+                if !self.custom_hasher && !self.listener.is_set() && self.jitter.is_none() =>
+            {
+                Ok(BuiltCache::TimedSized(
+                    TimedSizedCache::with_size_and_lifespan(size, seconds),
+                ))
+            }
+            _ => Err(ConfigError::Unsupported),
+        }
+    }
+}
+
+/// Store built by a `CacheBuilder`, one of the stores supporting its options
+///
+/// It implements `Cached` by forwarding every operation to the store it holds.
+#[derive(Clone, Debug)]
+pub enum BuiltCache<K, V, S = SeedableState> {
+    /// Built without any option
+    Unbound(UnboundCache<K, V, S>),
+    /// Built with a `size`
+    Sized(SizedCache<K, V, S>),
+    /// Built with a `time_to_live` or `time_to_idle`
+    Timed(TimedCache<K, V, S>),
+    /// Built with a `size` and a `time_to_live`
+    TimedSized(TimedSizedCache<K, V>),
+}
+
+/// Run `$body` with `$cache` bound to the store held by `$built`
+macro_rules! dispatch {
+    ($built:expr, $cache:ident => $body:expr) => {
+        match $built {
+            BuiltCache::Unbound($cache) => $body,
+            BuiltCache::Sized($cache) => $body,
+            BuiltCache::Timed($cache) => $body,
+            BuiltCache::TimedSized($cache) => $body,
+        }
+    };
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher + Clone> Cached<K, V> for BuiltCache<K, V, S> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_get(key))
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_get_mut(key))
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_peek(key))
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_peek_key_value(key))
+    }
+
+    fn cache_pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_pin(key))
+    }
+
+    fn cache_unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_unpin(key))
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        dispatch!(self, c => c.cache_set(key, val))
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        dispatch!(self, c => c.cache_set_with_lifespan(key, val, seconds))
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        dispatch!(self, c => c.cache_insert_if_absent(key, val))
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        dispatch!(self, c => c.cache_get_or_set_with(key, f))
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(self, c => c.cache_remove(key))
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, f: F) -> Option<usize> {
+        dispatch!(self, c => c.cache_remove_where(f))
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) -> Option<usize> {
+        dispatch!(self, c => c.cache_retain(f))
+    }
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        dispatch!(self, c => c.cache_flush_expired())
+    }
+    fn cache_clear(&mut self) {
+        dispatch!(self, c => c.cache_clear())
+    }
+    fn cache_reset(&mut self) {
+        dispatch!(self, c => c.cache_reset())
+    }
+    fn cache_size(&self) -> usize {
+        dispatch!(self, c => c.cache_size())
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        dispatch!(self, c => c.cache_hits())
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        dispatch!(self, c => c.cache_misses())
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        dispatch!(self, c => c.cache_capacity())
+    }
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        dispatch!(self, c => c.cache_set_capacity(size))
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        dispatch!(self, c => c.cache_lifespan())
+    }
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        dispatch!(self, c => c.cache_set_lifespan(seconds))
+    }
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        dispatch!(self, c => c.cache_unset_lifespan())
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{BuiltCache, CacheBuilder};
+    use crate::{Cached, ConfigError, EvictionReason};
+
+    #[test]
+    fn builds_matching_store() {
+        let c: BuiltCache<u32, u32> = CacheBuilder::new().build();
+        assert!(matches!(c, BuiltCache::Unbound(_)));
+        let c: BuiltCache<u32, u32> = CacheBuilder::new().size(2).build();
+        assert!(matches!(c, BuiltCache::Sized(_)));
+        let c: BuiltCache<u32, u32> = CacheBuilder::new().time_to_idle(60).jitter(0.1).build();
+        match &c {
+            BuiltCache::Timed(timed) => {
+                assert!(timed.refresh());
+                assert_eq!(timed.jitter(), 0.1);
+            }
+            _ => panic!("expected a TimedCache"),
+        }
+        let c: BuiltCache<u32, u32> = CacheBuilder::new().size(2).time_to_live(60).build();
+        assert!(matches!(c, BuiltCache::TimedSized(_)));
+        assert_eq!(c.cache_capacity(), Some(2));
+        assert_eq!(c.cache_lifespan(), Some(60));
+    }
+
+    #[test]
+    fn rejects_unsupported_options() {
+        let built = CacheBuilder::<u32, u32>::new().size(0).try_build();
+        assert_eq!(built.err(), Some(ConfigError::ZeroSize));
+        let built = CacheBuilder::<u32, u32>::new().time_to_live(0).try_build();
+        assert_eq!(built.err(), Some(ConfigError::ZeroLifespan));
+        let built = CacheBuilder::<u32, u32>::new()
+            .time_to_live(60)
+            .time_to_idle(10)
+            .try_build();
+        assert_eq!(built.err(), Some(ConfigError::Unsupported));
+        let built = CacheBuilder::<u32, u32>::new()
+            .size(2)
+            .jitter(0.1)
+            .try_build();
+        assert_eq!(built.err(), Some(ConfigError::Unsupported));
+        let built = CacheBuilder::<u32, u32>::new()
+            .size(2)
+            .time_to_idle(60)
+            .try_build();
+        assert_eq!(built.err(), Some(ConfigError::Unsupported));
+    }
+
+    #[test]
+    fn forwards_to_store() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener = evicted.clone();
+        let mut c = CacheBuilder::new()
+            .size(2)
+            .hasher(std::collections::hash_map::RandomState::new())
+            .on_evict(move |key: &u32, _: &u32, reason| {
+                listener.lock().unwrap().push((*key, reason))
+            })
+            .build();
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        c.cache_set(3, 300);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(*evicted.lock().unwrap(), [(2, EvictionReason::Capacity)]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+const INLINE: usize = 22;
+
+/// Byte or string key stored inline when short
+///
+/// Keys of up to 22 bytes are kept inside the `CompactKey` itself, which is no larger
+/// than a `String`, so they cost no heap allocation of their own and are compared
+/// without chasing a pointer. Longer keys are boxed. Use it in place of `String` or
+/// `Vec<u8>` keys with any store, e.g. through the `convert` macro attribute:
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::CompactKey;
+///
+/// #[cached(
+///     type = "cached::SizedCache<CompactKey, usize>",
+///     create = "{ cached::SizedCache::with_size(100) }",
+///     convert = r#"{ CompactKey::from(word) }"#
+/// )]
+/// fn vowels(word: &str) -> usize {
+///     word.chars().filter(|c| "aeiou".contains(*c)).count()
+/// }
+///
+/// assert_eq!(2, vowels("cache"));
+/// ```
+#[derive(Clone)]
+pub struct CompactKey(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, bytes: [u8; INLINE] },
+    Heap(Box<[u8]>),
+}
+
+impl CompactKey {
+    /// Return the key's bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+
+    /// Return the key as a `str`, `None` if it isn't valid UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.as_bytes()).ok()
+    }
+
+    /// Return whether the key is stored inline
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+}
+
+impl From<&[u8]> for CompactKey {
+    fn from(key: &[u8]) -> CompactKey {
+        if key.len() <= INLINE {
+            let mut bytes = [0; INLINE];
+            bytes[..key.len()].copy_from_slice(key);
+            CompactKey(Repr::Inline {
+                len: key.len() as u8,
+                bytes,
+            })
+        } else {
+            CompactKey(Repr::Heap(key.into()))
+        }
+    }
+}
+
+impl From<Vec<u8>> for CompactKey {
+    fn from(key: Vec<u8>) -> CompactKey {
+        if key.len() <= INLINE {
+            CompactKey::from(key.as_slice())
+        } else {
+            CompactKey(Repr::Heap(key.into_boxed_slice()))
+        }
+    }
+}
+
+impl From<&str> for CompactKey {
+    fn from(key: &str) -> CompactKey {
+        CompactKey::from(key.as_bytes())
+    }
+}
+
+impl From<String> for CompactKey {
+    fn from(key: String) -> CompactKey {
+        CompactKey::from(key.into_bytes())
+    }
+}
+
+impl Deref for CompactKey {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for CompactKey {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PartialEq for CompactKey {
+    fn eq(&self, other: &CompactKey) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for CompactKey {}
+
+impl Hash for CompactKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state);
+    }
+}
+
+impl fmt::Debug for CompactKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_str() {
+            Some(s) => fmt::Debug::fmt(s, f),
+            None => fmt::Debug::fmt(self.as_bytes(), f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use super::CompactKey;
+    use crate::{Cached, SizedCache};
+
+    #[test]
+    fn compact_key() {
+        assert_eq!(size_of::<String>(), size_of::<CompactKey>());
+
+        let short = CompactKey::from("short key");
+        assert!(short.is_inline());
+        assert_eq!(Some("short key"), short.as_str());
+        assert_eq!(short, CompactKey::from(String::from("short key")));
+
+        let long = CompactKey::from("a key too long to be inlined");
+        assert!(!long.is_inline());
+        assert_eq!(long, CompactKey::from(long.to_vec()));
+        assert_eq!(&long[..5], b"a key");
+
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(short.clone(), 1);
+        c.cache_set(long.clone(), 2);
+        assert_eq!(c.cache_get(&CompactKey::from("short key")), Some(&1));
+        assert_eq!(c.cache_get(&long), Some(&2));
+    }
+}
@@ -0,0 +1,296 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{PoisonError, RwLock};
+
+use super::{Cached, SeedableState};
+
+/// Number of shards when not specified
+const DEFAULT_SHARDS: usize = 16;
+
+/// Cache shared between threads without an outer lock
+///
+/// Keys are spread by hash over shards, each behind its own `RwLock`, so lookups of
+/// cached values only take a read lock on their shard and run in parallel, while
+/// writes only block the lookups of keys in the same shard. Each shard is a separate
+/// store, e.g. an `UnboundCache`:
+///
+/// ```rust
+/// use cached::{ConcurrentCache, UnboundCache};
+///
+/// let cache = ConcurrentCache::new(|| UnboundCache::new());
+/// cache.insert(1, "one");
+/// assert_eq!(cache.get(&1), Some("one"));
+/// assert_eq!(cache.remove(&1), Some("one"));
+/// ```
+///
+/// `get` reads values with `Cached::cache_peek`, so it doesn't update the recency
+/// order or hit counters of the shards, and finds nothing in stores that can't peek.
+/// `ConcurrentCache` implements `Cached` as well, for exclusive access without locking.
+#[derive(Debug)]
+pub struct ConcurrentCache<C> {
+    shards: Box<[RwLock<C>]>,
+    hash_builder: SeedableState,
+}
+
+impl<C> ConcurrentCache<C> {
+    /// Creates a new `ConcurrentCache` of 16 shards built by `create`
+    pub fn new<F: FnMut() -> C>(create: F) -> ConcurrentCache<C> {
+        Self::with_shards(DEFAULT_SHARDS, create)
+    }
+
+    /// Creates a new `ConcurrentCache` of `shards` shards built by `create`
+    ///
+    /// Will panic if shards is 0
+    pub fn with_shards<F: FnMut() -> C>(shards: usize, mut create: F) -> ConcurrentCache<C> {
+        if shards == 0 {
+            panic!("`shards` of `ConcurrentCache` must be greater than zero.");
+        }
+        ConcurrentCache {
+            shards: (0..shards).map(|_| RwLock::new(create())).collect(),
+            hash_builder: SeedableState::new(),
+        }
+    }
+
+    /// Return the shard holding `key`
+    pub fn shard<K: Hash + ?Sized>(&self, key: &K) -> &RwLock<C> {
+        &self.shards[self.index(key)]
+    }
+
+    /// Return all the shards
+    pub fn shards(&self) -> &[RwLock<C>] {
+        &self.shards
+    }
+
+    /// Return a clone of the value cached for `key`, under a read lock of its shard
+    pub fn get<K, Q, V: Clone>(&self, key: &Q) -> Option<V>
+    where
+        C: Cached<K, V>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_peek(key)
+            .cloned()
+    }
+
+    /// Insert a key, value pair and return the previous value
+    pub fn insert<K: Hash, V>(&self, key: K, val: V) -> Option<V>
+    where
+        C: Cached<K, V>,
+    {
+        self.shard(&key)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_set(key, val)
+    }
+
+    /// Remove a cached value
+    pub fn remove<K, Q, V>(&self, key: &Q) -> Option<V>
+    where
+        C: Cached<K, V>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_remove(key)
+    }
+
+    fn index<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        (self.hash_builder.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    /// Return the store of the shard holding `key`, without locking it
+    fn shard_mut<K: Hash + ?Sized>(&mut self, key: &K) -> &mut C {
+        let index = self.index(key);
+        self.shards[index]
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sum `f` over every shard, `None` if any shard returns `None`
+    fn sum<T: std::iter::Sum<T>>(&self, f: impl Fn(&C) -> Option<T>) -> Option<T> {
+        self.shards
+            .iter()
+            .map(|shard| f(&shard.read().unwrap_or_else(PoisonError::into_inner)))
+            .sum()
+    }
+
+    /// Call `f` with the store of every shard, without locking them
+    fn for_each_mut(&mut self, mut f: impl FnMut(&mut C)) {
+        for shard in self.shards.iter_mut() {
+            f(shard.get_mut().unwrap_or_else(PoisonError::into_inner));
+        }
+    }
+}
+
+impl<K: Hash, V, C: Cached<K, V>> Cached<K, V> for ConcurrentCache<C> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard_mut(key).cache_get(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard_mut(key).cache_get_mut(key)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.shard_mut(&key).cache_set(key, val)
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        self.shard_mut(&key)
+            .cache_set_with_lifespan(key, val, seconds)
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        self.shard_mut(&key).cache_insert_if_absent(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        self.shard_mut(&key).cache_get_or_set_with(key, f)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard_mut(key).cache_remove(key)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_remove_where(&mut f)
+            })
+            .sum()
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_retain(&mut f)
+            })
+            .sum()
+    }
+
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_flush_expired()
+            })
+            .sum()
+    }
+
+    fn cache_clear(&mut self) {
+        self.for_each_mut(|shard| shard.cache_clear());
+    }
+
+    fn cache_reset(&mut self) {
+        self.for_each_mut(|shard| shard.cache_reset());
+    }
+
+    fn cache_size(&self) -> usize {
+        self.sum(|shard| Some(shard.cache_size())).unwrap_or(0)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.sum(C::cache_hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.sum(C::cache_misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.sum(C::cache_capacity)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.shards[0]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_lifespan()
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let mut old = None;
+        self.for_each_mut(|shard| old = shard.cache_set_lifespan(seconds).or(old));
+        old
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        let mut old = None;
+        self.for_each_mut(|shard| old = shard.cache_unset_lifespan().or(old));
+        old
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::super::UnboundCache;
+    use super::Cached;
+    use super::ConcurrentCache;
+
+    #[test]
+    fn concurrent_cache() {
+        let c = Arc::new(ConcurrentCache::with_shards(4, UnboundCache::new));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let c = c.clone();
+                thread::spawn(move || {
+                    for i in 0..10 {
+                        c.insert(t * 10 + i, i);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(40, c.cache_size());
+        assert_eq!(c.get(&25), Some(5));
+        assert_eq!(c.remove(&25), Some(5));
+        assert_eq!(c.get(&25), None::<u32>);
+
+        let mut c = Arc::try_unwrap(c).unwrap();
+        assert_eq!(c.cache_get(&1), Some(&1));
+        assert_eq!(Some(1), c.cache_hits());
+        assert_eq!(Some(10), c.cache_remove_where(|k| *k < 10));
+        c.cache_clear();
+        assert_eq!(0, c.cache_size());
+    }
+
+    #[test]
+    #[should_panic]
+    fn concurrent_cache_zero_shards() {
+        ConcurrentCache::with_shards(0, UnboundCache::<u32, u32>::new);
+    }
+}
@@ -0,0 +1,273 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::IOCached;
+
+/// Error of a `DiskCache` operation
+#[derive(Debug)]
+pub enum DiskCacheError {
+    /// The store's files couldn't be opened, read or written
+    Sled(sled::Error),
+    /// A key or value couldn't be encoded to or decoded from JSON
+    Json(serde_json::Error),
+}
+
+impl Display for DiskCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskCacheError::Sled(e) => write!(f, "disk cache error: {}", e),
+            DiskCacheError::Json(e) => write!(f, "disk cache value error: {}", e),
+        }
+    }
+}
+
+impl Error for DiskCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DiskCacheError::Sled(e) => Some(e),
+            DiskCacheError::Json(e) => Some(e),
+        }
+    }
+}
+
+impl From<sled::Error> for DiskCacheError {
+    fn from(e: sled::Error) -> DiskCacheError {
+        DiskCacheError::Sled(e)
+    }
+}
+
+impl From<serde_json::Error> for DiskCacheError {
+    fn from(e: serde_json::Error) -> DiskCacheError {
+        DiskCacheError::Json(e)
+    }
+}
+
+/// Value stored by a `DiskCache`, with the time it expires at and the id ordering its
+/// insertion
+#[derive(Serialize, Deserialize)]
+struct Entry<V> {
+    /// Seconds since the Unix epoch
+    expires: Option<u64>,
+    id: u64,
+    value: V,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Store keeping values on disk, in a `sled` database, surviving restarts of the process
+///
+/// Enabled by the `disk_store` feature. Keys and values are encoded as JSON. Each value
+/// expires after the lifespan set with `with_lifespan` when it was set, even when the
+/// store is reopened with another lifespan, and is removed when it's read after expiring.
+/// With a size set by `with_max_size`, the values set longest ago are evicted to keep the
+/// store under that many values. It implements `IOCached`, use it with `#[io_cached]`:
+///
+/// ```rust,no_run
+/// use cached::proc_macro::io_cached;
+/// use cached::{DiskCache, DiskCacheError};
+///
+/// #[derive(Debug)]
+/// enum Error {
+///     Cache(DiskCacheError),
+/// }
+///
+/// #[io_cached(
+///     type = "DiskCache<String, u64>",
+///     create = r#"{ DiskCache::open("/tmp/my_tool/sizes").unwrap().with_lifespan(3600) }"#,
+///     map_error = r#"|e| Error::Cache(e)"#
+/// )]
+/// fn dir_size(path: String) -> Result<u64, Error> {
+///     Ok(path.len() as u64)
+/// }
+/// ```
+///
+/// Writes reach the disk in the background, at most half a second later, and when the
+/// store is dropped. `flush` writes them right away. A directory can only be opened by one
+/// process at a time.
+pub struct DiskCache<K, V> {
+    db: sled::Db,
+    /// Ids of the values, in the order they were set
+    order: sled::Tree,
+    /// Number of values, locked by the writes, which update both trees
+    len: Mutex<usize>,
+    lifespan: Option<u64>,
+    max_size: Option<usize>,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V> fmt::Debug for DiskCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskCache")
+            .field("lifespan", &self.lifespan)
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+impl<K, V> DiskCache<K, V> {
+    /// Open the store in the directory `dir`, creating it if it doesn't exist yet, with the
+    /// values left by previous runs
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<DiskCache<K, V>, DiskCacheError> {
+        let db = sled::open(dir)?;
+        let order = db.open_tree("order")?;
+        let len = order.len();
+        Ok(DiskCache {
+            db,
+            order,
+            len: Mutex::new(len),
+            lifespan: None,
+            max_size: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Expire values `seconds` after they're set, instead of keeping them until they're
+    /// removed or evicted
+    pub fn with_lifespan(mut self, seconds: u64) -> DiskCache<K, V> {
+        if seconds == 0 {
+            panic!("`lifespan` of `DiskCache` must be greater than zero.")
+        }
+        self.lifespan = Some(seconds);
+        self
+    }
+
+    /// Keep at most `size` values, evicting the values set longest ago
+    ///
+    /// Values beyond `size` left by a previous run are evicted on the next `cache_set`.
+    pub fn with_max_size(mut self, size: usize) -> DiskCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `DiskCache` must be greater than zero.")
+        }
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Return the number of values stored, expired or not
+    pub fn len(&self) -> usize {
+        *self.lock()
+    }
+
+    /// Return whether no values are stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the pending changes to the disk, returning the number of bytes written
+    pub fn flush(&self) -> Result<usize, DiskCacheError> {
+        Ok(self.db.flush()?)
+    }
+
+    /// Remove every value
+    pub fn clear(&self) -> Result<(), DiskCacheError> {
+        let mut len = self.lock();
+        self.db.clear()?;
+        self.order.clear()?;
+        *len = 0;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.len.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn expired(expires: Option<u64>) -> bool {
+    matches!(expires, Some(at) if at <= now())
+}
+
+impl<K, V> IOCached<K, V> for DiskCache<K, V>
+where
+    K: Serialize,
+    V: Serialize + DeserializeOwned,
+{
+    type Error = DiskCacheError;
+
+    fn cache_get(&self, k: &K) -> Result<Option<V>, DiskCacheError> {
+        let key = serde_json::to_vec(k)?;
+        let bytes = match self.db.get(&key)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let entry: Entry<V> = serde_json::from_slice(&bytes)?;
+        if expired(entry.expires) {
+            let mut len = self.lock();
+            // unless it was set again in between
+            if self.db.get(&key)?.as_ref() == Some(&bytes) {
+                self.db.remove(&key)?;
+                self.order.remove(entry.id.to_be_bytes())?;
+                *len -= 1;
+            }
+            return Ok(None);
+        }
+        Ok(Some(entry.value))
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Result<Option<V>, DiskCacheError> {
+        let key = serde_json::to_vec(&k)?;
+        let mut len = self.lock();
+        let entry = Entry {
+            expires: self.lifespan.map(|seconds| now().saturating_add(seconds)),
+            id: self.db.generate_id()?,
+            value: v,
+        };
+        let old = self.db.insert(&key, serde_json::to_vec(&entry)?)?;
+        self.order.insert(entry.id.to_be_bytes(), key)?;
+        let old = match old {
+            Some(bytes) => {
+                let old: Entry<V> = serde_json::from_slice(&bytes)?;
+                self.order.remove(old.id.to_be_bytes())?;
+                if expired(old.expires) {
+                    None
+                } else {
+                    Some(old.value)
+                }
+            }
+            None => {
+                *len += 1;
+                None
+            }
+        };
+        if let Some(max_size) = self.max_size {
+            while *len > max_size {
+                let (_, oldest) = match self.order.pop_min()? {
+                    Some(oldest) => oldest,
+                    None => break,
+                };
+                self.db.remove(oldest)?;
+                *len -= 1;
+            }
+        }
+        Ok(old)
+    }
+
+    fn cache_remove(&self, k: &K) -> Result<Option<V>, DiskCacheError> {
+        let key = serde_json::to_vec(k)?;
+        let mut len = self.lock();
+        let bytes = match self.db.remove(&key)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        *len -= 1;
+        let entry: Entry<V> = serde_json::from_slice(&bytes)?;
+        self.order.remove(entry.id.to_be_bytes())?;
+        if expired(entry.expires) {
+            return Ok(None);
+        }
+        Ok(Some(entry.value))
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.lifespan
+    }
+}
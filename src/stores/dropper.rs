@@ -0,0 +1,28 @@
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use once_cell::sync::Lazy;
+
+type Garbage = Box<dyn Send>;
+
+/// Channel to the thread dropping values handed to `defer_drop`
+static DROPPER: Lazy<Mutex<Sender<Garbage>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<Garbage>();
+    thread::Builder::new()
+        .name("cached-dropper".to_string())
+        .spawn(move || receiver.into_iter().for_each(drop))
+        .expect("failed to spawn the cached dropper thread");
+    Mutex::new(sender)
+});
+
+/// Drop `value` on a background thread instead of the calling one,
+/// e.g. to keep freeing a batch of evicted entries out of a cache's lock
+pub(super) fn defer_drop<T: Send + 'static>(value: T) {
+    let sender = DROPPER.lock().unwrap_or_else(|e| e.into_inner());
+    // the thread only stops if dropping a value panicked, drop it here instead
+    if let Err(mpsc::SendError(value)) = sender.send(Box::new(value)) {
+        drop(sender);
+        drop(value);
+    }
+}
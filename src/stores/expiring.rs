@@ -0,0 +1,262 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::{Cached, ConfigError, EvictionReason, SizedCache};
+
+/// Values that know when they expire, see `ExpiringValueCache`
+pub trait CanExpire {
+    /// Whether the value expired and mustn't be returned from a cache anymore
+    fn is_expired(&self) -> bool;
+}
+
+impl<T: CanExpire + ?Sized> CanExpire for Arc<T> {
+    fn is_expired(&self) -> bool {
+        (**self).is_expired()
+    }
+}
+
+/// Cache store of values that carry their own expiry
+///
+/// Useful when the lifespan of a value is only known from the value itself, e.g. an
+/// access token holding the instant it expires at. Values are evicted in least recently
+/// used order once the cache is full, like a `SizedCache`, and are treated as missing
+/// once `CanExpire::is_expired` returns true:
+///
+/// ```rust
+/// use std::time::{Duration, Instant};
+/// use cached::{CanExpire, Cached, ExpiringValueCache};
+///
+/// struct Token {
+///     secret: String,
+///     expires_at: Instant,
+/// }
+///
+/// impl CanExpire for Token {
+///     fn is_expired(&self) -> bool {
+///         Instant::now() >= self.expires_at
+///     }
+/// }
+///
+/// let mut cache = ExpiringValueCache::with_size(10);
+/// let expires_at = Instant::now() + Duration::from_secs(60);
+/// cache.cache_set("api", Token { secret: "s3cr3t".to_string(), expires_at });
+/// cache.cache_set("old", Token { secret: "0ld".to_string(), expires_at: Instant::now() });
+/// assert!(cache.cache_get(&"api").is_some());
+/// assert!(cache.cache_get(&"old").is_none());
+/// ```
+///
+/// Expired values are removed when they're accessed, evicted as the least recently used
+/// ones, or removed all at once with `flush`, so they count towards `cache_size` until then.
+#[derive(Clone, Debug)]
+pub struct ExpiringValueCache<K, V> {
+    store: SizedCache<K, V>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Hash + Eq, V: CanExpire> ExpiringValueCache<K, V> {
+    /// Creates a new `ExpiringValueCache` holding at most `size` values
+    ///
+    /// Will panic if size is 0
+    pub fn with_size(size: usize) -> ExpiringValueCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `ExpiringValueCache` must be greater than zero.");
+        }
+        ExpiringValueCache {
+            store: SizedCache::with_size(size),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Like `with_size`, but returns an error instead of panicking if size is 0
+    pub fn try_with_size(size: usize) -> Result<ExpiringValueCache<K, V>, ConfigError> {
+        if size == 0 {
+            return Err(ConfigError::ZeroSize);
+        }
+        Ok(Self::with_size(size))
+    }
+
+    /// Remove all the expired values, returning how many were removed
+    pub fn flush(&mut self) -> usize {
+        self.store
+            .remove_entries_where(|_, value| value.is_expired(), EvictionReason::Expired)
+    }
+
+    /// Remove the value cached for `key` if it expired, returning whether
+    /// an unexpired value is left
+    fn check<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.cache_peek(key) {
+            Some(value) if value.is_expired() => {
+                self.store.cache_remove(key);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V: CanExpire> Cached<K, V> for ExpiringValueCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.check(key) {
+            self.hits += 1;
+            self.store.cache_get(key)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_peek(key)
+            .filter(|value| !value.is_expired())
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_peek_key_value(key)
+            .filter(|(_, value)| !value.is_expired())
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.check(key) {
+            self.hits += 1;
+            self.store.cache_get_mut(key)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.store.cache_set(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        if self.check(&key) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        self.store.cache_get_or_set_with(key, f)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_remove(key)
+            .filter(|value| !value.is_expired())
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, f: F) -> Option<usize> {
+        self.store.cache_remove_where(f)
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) -> Option<usize> {
+        self.store.cache_retain(f)
+    }
+
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        Some(self.flush())
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.cache_clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.store.cache_reset();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.store.cache_capacity()
+    }
+
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        self.store.cache_set_capacity(size)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::{Cached, CanExpire, ExpiringValueCache};
+
+    #[derive(Debug, PartialEq)]
+    struct Token(u32, bool);
+
+    impl CanExpire for Token {
+        fn is_expired(&self) -> bool {
+            self.1
+        }
+    }
+
+    #[test]
+    fn expiring_value_cache() {
+        let mut c = ExpiringValueCache::with_size(2);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, Token(100, false)), None);
+        assert_eq!(c.cache_set(2, Token(200, true)), None);
+        assert_eq!(c.cache_get(&1), Some(&Token(100, false)));
+        assert!(c.cache_peek(&2).is_none());
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(1, c.cache_size());
+        assert_eq!(Some(1), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+
+        assert_eq!(c.cache_get_or_set_with(2, || Token(201, false)).0, 201);
+        // 1 is the least recently used
+        c.cache_set(3, Token(300, true));
+        assert!(c.cache_peek(&1).is_none());
+        assert_eq!(1, c.flush());
+        assert_eq!(1, c.cache_size());
+        assert!(c.cache_remove(&3).is_none());
+        assert_eq!(c.cache_remove(&2), Some(Token(201, false)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn expiring_value_cache_zero_size() {
+        ExpiringValueCache::<u32, Token>::with_size(0);
+    }
+}
@@ -0,0 +1,237 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use once_cell::sync::Lazy;
+
+use super::{Cached, SeedableState};
+
+/// Hashers of `hash_key`, keyed randomly once per process
+static KEY_STATES: Lazy<(SeedableState, SeedableState)> =
+    Lazy::new(|| (SeedableState::new(), SeedableState::new()));
+
+/// Hash `key` into 128 bits, like `HashedKeyCache` does, with hashers shared by the
+/// whole process. This is the key of `#[cached(hash_key = true)]` functions, which
+/// hash their arguments by reference instead of cloning them into a tuple.
+///
+/// ```rust
+/// let key = cached::hash_key(&("a rather long key", 1));
+/// assert_eq!(key, cached::hash_key(&("a rather long key", 1)));
+/// assert_ne!(key, cached::hash_key(&("a rather long key", 2)));
+/// ```
+pub fn hash_key<T: Hash + ?Sized>(key: &T) -> u128 {
+    hash_128(&KEY_STATES.0, &KEY_STATES.1, key)
+}
+
+fn hash_128<T: Hash + ?Sized>(high: &SeedableState, low: &SeedableState, key: &T) -> u128 {
+    u128::from(high.hash_one(key)) << 64 | u128::from(low.hash_one(key))
+}
+
+/// Wrapper storing a 128-bit hash of each key in place of the key itself
+///
+/// The inner store is keyed by `u128`, so caches keyed by long `String`s or large
+/// structs only keep 16 bytes per key. Keys are hashed with two independently
+/// keyed hashers, so two different keys are only mistaken for one another, and
+/// return each other's values, if both 64-bit halves collide: a chance of about
+/// one in 2^128 for any pair of keys. Randomly keyed hashers (the default) also
+/// keep an adversary from crafting colliding keys.
+///
+/// ```rust
+/// use cached::{Cached, HashedKeyCache, SizedCache};
+///
+/// let mut cache = HashedKeyCache::new(SizedCache::with_size(100));
+/// cache.cache_set("a rather long key".to_string(), 1);
+/// assert_eq!(cache.cache_get(&"a rather long key".to_string()), Some(&1));
+/// ```
+pub struct HashedKeyCache<K, C> {
+    inner: C,
+    high: SeedableState,
+    low: SeedableState,
+    _marker: PhantomData<fn(&K)>,
+}
+
+impl<K, C: Clone> Clone for HashedKeyCache<K, C> {
+    fn clone(&self) -> Self {
+        HashedKeyCache {
+            inner: self.inner.clone(),
+            high: self.high.clone(),
+            low: self.low.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, C: fmt::Debug> fmt::Debug for HashedKeyCache<K, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashedKeyCache")
+            .field("inner", &self.inner)
+            .field("high", &self.high)
+            .field("low", &self.low)
+            .finish()
+    }
+}
+
+impl<K: Hash, C> HashedKeyCache<K, C> {
+    /// Creates a wrapper around `inner`, which should be empty
+    pub fn new(inner: C) -> HashedKeyCache<K, C> {
+        HashedKeyCache {
+            inner,
+            high: SeedableState::new(),
+            low: SeedableState::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`.
+    /// Keys already cached are no longer found.
+    pub fn with_hash_seed(mut self, seed: u64) -> HashedKeyCache<K, C> {
+        self.high = SeedableState::with_seed(seed);
+        self.low = SeedableState::with_seed(!seed);
+        self
+    }
+
+    /// Return the hash `key` is stored under
+    pub fn key_hash<Q>(&self, key: &Q) -> u128
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        hash_128(&self.high, &self.low, key)
+    }
+
+    /// Return the inner store, keyed by hash
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Return the inner store mutably, keyed by hash
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<K: Hash, V, C: Cached<u128, V>> Cached<K, V> for HashedKeyCache<K, C> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.key_hash(key);
+        self.inner.cache_get(&hash)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.key_hash(key);
+        self.inner.cache_get_mut(&hash)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_peek(&self.key_hash(key))
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let hash = self.key_hash(&key);
+        self.inner.cache_set(hash, val)
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        let hash = self.key_hash(&key);
+        self.inner.cache_set_with_lifespan(hash, val, seconds)
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        let hash = self.key_hash(&key);
+        self.inner.cache_insert_if_absent(hash, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let hash = self.key_hash(&key);
+        self.inner.cache_get_or_set_with(hash, f)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.key_hash(key);
+        self.inner.cache_remove(&hash)
+    }
+    fn cache_clear(&mut self) {
+        self.inner.cache_clear();
+    }
+    fn cache_reset(&mut self) {
+        self.inner.cache_reset();
+    }
+    fn cache_size(&self) -> usize {
+        self.inner.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        self.inner.cache_set_capacity(size)
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        self.inner.cache_unset_lifespan()
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::HashedKeyCache;
+    use crate::stores::{SizedCache, UnboundCache};
+
+    #[test]
+    fn hashed_key_cache() {
+        let mut c = HashedKeyCache::new(SizedCache::with_size(2));
+        let key = |n: u32| format!("key number {}", n);
+        assert!(c.cache_get(&key(1)).is_none());
+        assert_eq!(c.cache_set(key(1), 100), None);
+        assert_eq!(c.cache_set(key(1), 101), Some(100));
+        assert_eq!(c.cache_get(&key(1)), Some(&101));
+        assert_eq!(c.cache_peek(&key(1)), Some(&101));
+        assert_eq!(c.cache_get_or_set_with(key(2), || 200), &200);
+        assert_eq!(c.inner().cache_peek(&c.key_hash(&key(2))), Some(&200));
+        assert_eq!(c.cache_remove(&key(1)), Some(101));
+        assert_eq!(1, c.cache_size());
+        assert_eq!(Some(1), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+    }
+
+    #[test]
+    fn seeded_hashes() {
+        let a: HashedKeyCache<&str, UnboundCache<u128, u32>> =
+            HashedKeyCache::new(UnboundCache::new()).with_hash_seed(1);
+        let b: HashedKeyCache<&str, UnboundCache<u128, u32>> =
+            HashedKeyCache::new(UnboundCache::new()).with_hash_seed(1);
+        assert_eq!(a.key_hash(&"key"), b.key_hash(&"key"));
+        assert_ne!(a.key_hash(&"key"), a.key_hash(&"other key"));
+        // the two halves are hashed differently
+        let hash = a.key_hash(&"key");
+        assert_ne!(hash >> 64, hash & u128::from(u64::MAX));
+    }
+}
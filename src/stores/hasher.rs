@@ -0,0 +1,108 @@
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// `BuildHasher` used by the cache stores
+///
+/// By default this is randomly keyed, the same as a `HashMap`'s `RandomState`.
+/// A seeded state hashes deterministically instead, so that iteration order and
+/// eviction tie-breaks are reproducible between runs, e.g. in tests and benchmarks.
+///
+/// Note: A seeded state doesn't offer any protection against hash flooding
+#[derive(Clone, Debug)]
+pub struct SeedableState {
+    seed: Option<u64>,
+    random: RandomState,
+}
+
+impl SeedableState {
+    /// Creates a randomly keyed state
+    pub fn new() -> SeedableState {
+        SeedableState {
+            seed: None,
+            random: RandomState::new(),
+        }
+    }
+
+    /// Creates a state that always hashes the same way for a given `seed`
+    pub fn with_seed(seed: u64) -> SeedableState {
+        SeedableState {
+            seed: Some(seed),
+            random: RandomState::new(),
+        }
+    }
+
+    /// Return the seed, if this state is deterministic
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+}
+
+impl Default for SeedableState {
+    fn default() -> SeedableState {
+        SeedableState::new()
+    }
+}
+
+impl BuildHasher for SeedableState {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        match self.seed {
+            Some(seed) => {
+                // `DefaultHasher::new` uses fixed keys, mix the seed in first
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(seed);
+                hasher
+            }
+            None => self.random.build_hasher(),
+        }
+    }
+}
+
+/// Rebuild `map` with a state seeded with `seed`, rehashing its entries
+pub(super) fn reseed<K: Hash + Eq, V>(map: &mut HashMap<K, V, SeedableState>, seed: u64) {
+    let state = SeedableState::with_seed(seed);
+    let old = std::mem::replace(
+        map,
+        HashMap::with_capacity_and_hasher(map.capacity(), state),
+    );
+    map.extend(old);
+}
+
+/// Any non-zero seed works for xorshift, borrow the randomness of the hasher keys
+pub(super) fn rng_seed<S: BuildHasher>(state: &S, salt: usize) -> u64 {
+    let mut hasher = state.build_hasher();
+    hasher.write_usize(salt);
+    hasher.finish() | 1
+}
+
+/// xorshift64*, plenty for picking eviction candidates or jittering lifespans
+pub(super) fn next_random(rng: &mut u64) -> u64 {
+    *rng ^= *rng >> 12;
+    *rng ^= *rng << 25;
+    *rng ^= *rng >> 27;
+    rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use super::SeedableState;
+
+    fn hash(state: &SeedableState, value: &str) -> u64 {
+        state.hash_one(value)
+    }
+
+    #[test]
+    fn seeded_state_is_deterministic() {
+        let a = SeedableState::with_seed(7);
+        let b = SeedableState::with_seed(7);
+        let c = SeedableState::with_seed(8);
+        assert_eq!(hash(&a, "key"), hash(&b, "key"));
+        assert_ne!(hash(&a, "key"), hash(&c, "key"));
+        assert_eq!(Some(7), a.seed());
+        assert_eq!(None, SeedableState::new().seed());
+    }
+}
@@ -0,0 +1,287 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use super::Cached;
+
+/// Chain of cache stores, looked up front to back
+///
+/// Built from a front and a back layer, further layers are appended behind the
+/// existing ones with `then`, e.g. a small `SizedCache` in front of a larger
+/// `TimedCache`:
+///
+/// ```rust
+/// use cached::{Cached, Layered, SizedCache, TimedCache, UnboundCache};
+///
+/// let mut cache = Layered::new(SizedCache::with_size(10), TimedCache::with_lifespan(60))
+///     .then(UnboundCache::new());
+/// cache.cache_set(1, "one");
+/// assert_eq!(cache.cache_get(&1), Some(&"one"));
+/// ```
+///
+/// - values are written to every layer
+/// - a value found in a back layer is copied into the layers in front of it, if the
+///   back layer returns the key it's stored under from `Cached::cache_peek_key_value`
+///   (`HashedKeyCache` doesn't keep keys, values found in it aren't copied forward)
+/// - removing or clearing applies to every layer
+///
+/// Hits and misses are counted for the chain as a whole, the layers' own counters
+/// also see the lookups made while copying values forward. Values changed through
+/// `cache_get_mut` are only changed in the front-most layer holding them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layered<F, B> {
+    front: F,
+    back: B,
+    hits: u64,
+    misses: u64,
+}
+
+impl<F, B> Layered<F, B> {
+    /// Creates a chain looking up `front` before `back`
+    pub fn new(front: F, back: B) -> Layered<F, B> {
+        Layered {
+            front,
+            back,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Append `layer` behind the existing layers
+    pub fn then<L>(self, layer: L) -> Layered<Layered<F, B>, L> {
+        Layered::new(self, layer)
+    }
+
+    /// Return the front layer, itself a `Layered` if the chain was built with `then`
+    pub fn front(&self) -> &F {
+        &self.front
+    }
+
+    /// Return the back-most layer
+    pub fn back(&self) -> &B {
+        &self.back
+    }
+}
+
+impl<F, B> Layered<F, B> {
+    /// Copy the value cached for `key` in the back layer into the front one,
+    /// under the key the back layer keeps
+    fn promote<K, V, Q>(&mut self, key: &Q)
+    where
+        K: Clone + Borrow<Q>,
+        V: Clone,
+        Q: Hash + Eq + ?Sized,
+        F: Cached<K, V>,
+        B: Cached<K, V>,
+    {
+        if let Some((key, value)) = self.back.cache_peek_key_value(key) {
+            let (key, value) = (key.clone(), value.clone());
+            self.front.cache_set(key, value);
+        }
+    }
+}
+
+impl<K, V, F, B> Cached<K, V> for Layered<F, B>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    F: Cached<K, V>,
+    B: Cached<K, V>,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.front.cache_get(key).is_none() {
+            if self.back.cache_get(key).is_none() {
+                self.misses += 1;
+                return None;
+            }
+            self.promote(key);
+        }
+        self.hits += 1;
+        // the front layer may not have kept the value
+        match self.front.cache_get(key) {
+            Some(value) => Some(value),
+            None => self.back.cache_get(key),
+        }
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.front
+            .cache_peek(key)
+            .or_else(|| self.back.cache_peek(key))
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.front
+            .cache_peek_key_value(key)
+            .or_else(|| self.back.cache_peek_key_value(key))
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.front.cache_get(key).is_none() {
+            if self.back.cache_get(key).is_none() {
+                self.misses += 1;
+                return None;
+            }
+            self.promote(key);
+        }
+        self.hits += 1;
+        match self.front.cache_get_mut(key) {
+            Some(value) => Some(value),
+            None => self.back.cache_get_mut(key),
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.back.cache_set(key.clone(), val.clone());
+        self.front.cache_set(key, val).or(old)
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        let old = self
+            .back
+            .cache_set_with_lifespan(key.clone(), val.clone(), seconds);
+        self.front
+            .cache_set_with_lifespan(key, val, seconds)
+            .or(old)
+    }
+
+    /// Insert into every layer if the back-most layer doesn't hold the key yet
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        if !self.back.cache_insert_if_absent(key.clone(), val.clone()) {
+            return false;
+        }
+        self.front.cache_set(key, val);
+        true
+    }
+
+    fn cache_get_or_set_with<G: FnOnce() -> V>(&mut self, key: K, f: G) -> &mut V {
+        let back = &mut self.back;
+        let mut computed = false;
+        let value = self
+            .front
+            .cache_get_or_set_with(key.clone(), || match back.cache_get(&key) {
+                Some(value) => value.clone(),
+                None => {
+                    computed = true;
+                    let value = f();
+                    back.cache_set(key, value.clone());
+                    value
+                }
+            });
+        if computed {
+            self.misses += 1;
+        } else {
+            self.hits += 1;
+        }
+        value
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let old = self.back.cache_remove(key);
+        self.front.cache_remove(key).or(old)
+    }
+    /// Return the number of values removed from the back-most layer,
+    /// `None` if any layer can't go through its keys
+    fn cache_remove_where<G: FnMut(&K) -> bool>(&mut self, mut f: G) -> Option<usize> {
+        let front = self.front.cache_remove_where(&mut f);
+        let back = self.back.cache_remove_where(&mut f);
+        front.and(back)
+    }
+    fn cache_retain<G: FnMut(&K, &V) -> bool>(&mut self, mut f: G) -> Option<usize> {
+        let front = self.front.cache_retain(&mut f);
+        let back = self.back.cache_retain(&mut f);
+        front.and(back)
+    }
+    fn cache_clear(&mut self) {
+        self.front.cache_clear();
+        self.back.cache_clear();
+    }
+    fn cache_reset(&mut self) {
+        self.front.cache_reset();
+        self.back.cache_reset();
+    }
+    /// Return the size of the back-most layer
+    fn cache_size(&self) -> usize {
+        self.back.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    /// Return the capacity of the back-most layer
+    fn cache_capacity(&self) -> Option<usize> {
+        self.back.cache_capacity()
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::Layered;
+    use crate::stores::{SizedCache, UnboundCache};
+
+    #[test]
+    fn layered() {
+        let mut c = Layered::new(SizedCache::with_size(1), UnboundCache::new());
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(1, c.front().cache_size());
+        assert_eq!(2, c.cache_size());
+
+        // found in the back layer and copied forward
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.front().key_order().collect::<Vec<_>>(), [&1]);
+        *c.cache_get_mut(&1).unwrap() += 1;
+        assert_eq!(c.front().value_order().collect::<Vec<_>>(), [&101]);
+
+        assert_eq!(c.cache_get_or_set_with(2, || 0), &200);
+        assert_eq!(c.cache_get_or_set_with(3, || 300), &300);
+        assert_eq!(3, c.back().cache_size());
+        assert_eq!(Some(3), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+
+        assert_eq!(c.cache_remove(&3), Some(300));
+        assert_eq!(2, c.cache_size());
+        c.cache_clear();
+        assert_eq!(0, c.cache_size());
+    }
+
+    #[test]
+    fn layered_then() {
+        let mut c = Layered::new(SizedCache::with_size(1), SizedCache::with_size(2))
+            .then(UnboundCache::new());
+        for i in 0..4 {
+            c.cache_set(i, i * 10);
+        }
+        assert_eq!(4, c.cache_size());
+        assert_eq!(2, c.front().cache_size());
+
+        // copied into both front layers
+        assert_eq!(c.cache_get(&0), Some(&0));
+        assert_eq!(c.front().front().key_order().collect::<Vec<_>>(), [&0]);
+        assert_eq!(c.front().back().key_order().collect::<Vec<_>>(), [&0, &3]);
+    }
+}
@@ -0,0 +1,373 @@
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::Hash;
+
+use super::hasher::{self, SeedableState};
+use super::Cached;
+#[cfg(feature = "async")]
+use super::CachedAsync;
+use super::ConfigError;
+
+#[derive(Clone, Debug)]
+struct LfuEntry<V> {
+    value: V,
+    uses: u64,
+    last_used: u64,
+}
+
+impl<V> LfuEntry<V> {
+    /// Position in the eviction order, least frequently then least recently used first
+    fn rank(&self) -> (u64, u64) {
+        (self.uses, self.last_used)
+    }
+}
+
+/// Least Frequently Used Cache
+///
+/// Stores up to a specified size before beginning to evict the least frequently
+/// used keys, the least recently used one among them when several were used as
+/// often. Values read once, e.g. by a scan over many keys, are evicted before
+/// the values read over and over, which a `SizedCache` would evict instead:
+///
+/// ```rust
+/// use cached::{Cached, LfuCache};
+///
+/// let mut cache = LfuCache::with_size(2);
+/// cache.cache_set(1, "hot");
+/// cache.cache_get(&1);
+/// cache.cache_set(2, "scanned");
+/// cache.cache_set(3, "scanned");
+/// // 2 is evicted although 1 is less recently used
+/// assert_eq!(cache.cache_get(&1), Some(&"hot"));
+/// assert_eq!(cache.cache_get(&2), None);
+/// ```
+///
+/// Use counts are never decayed, so values that were hot once keep their place
+/// until they're removed or the cache is cleared.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct LfuCache<K, V> {
+    store: HashMap<K, LfuEntry<V>, SeedableState>,
+    order: BTreeMap<(u64, u64), K>,
+    capacity: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K, V> PartialEq for LfuCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &LfuCache<K, V>) -> bool {
+        self.store.len() == other.store.len()
+            && self.store.iter().all(|(key, entry)| {
+                other
+                    .store
+                    .get(key)
+                    .is_some_and(|other| other.value == entry.value)
+            })
+    }
+}
+
+impl<K, V> Eq for LfuCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+}
+
+impl<K: Hash + Eq + Clone, V> LfuCache<K, V> {
+    /// Creates a new `LfuCache` with a given size limit and pre-allocated backing data
+    pub fn with_size(size: usize) -> LfuCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `LfuCache` must be greater than zero.")
+        }
+        LfuCache {
+            store: HashMap::with_capacity_and_hasher(size, SeedableState::new()),
+            order: BTreeMap::new(),
+            capacity: size,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Like `with_size`, but returns an error instead of panicking if size is 0
+    pub fn try_with_size(size: usize) -> Result<LfuCache<K, V>, ConfigError> {
+        if size == 0 {
+            return Err(ConfigError::ZeroSize);
+        }
+        Ok(Self::with_size(size))
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`
+    pub fn with_hash_seed(mut self, seed: u64) -> LfuCache<K, V> {
+        hasher::reseed(&mut self.store, seed);
+        self
+    }
+
+    /// Return the number of times the value cached for `key` was used,
+    /// counting the insertion, without using it
+    pub fn uses(&self, key: &K) -> Option<u64> {
+        self.store.get(key).map(|entry| entry.uses)
+    }
+
+    /// Return an iterator of keys in the current order from least to most frequently
+    /// used, i.e. the order they'd be evicted in.
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.order.values()
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Count a use of the value cached for `key`, returning it
+    fn touch<Q>(&mut self, key: &Q) -> Option<&mut LfuEntry<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let tick = self.next_tick();
+        let entry = self.store.get_mut(key)?;
+        let key = self
+            .order
+            .remove(&entry.rank())
+            .expect("LfuCache order out of sync with store");
+        entry.uses += 1;
+        entry.last_used = tick;
+        self.order.insert(entry.rank(), key);
+        Some(entry)
+    }
+
+    fn check_capacity(&mut self) {
+        // store has reached capacity, evict the least frequently used entry
+        if self.store.len() >= self.capacity {
+            if let Some((_, key)) = self.order.pop_first() {
+                self.store.remove(&key);
+            }
+        }
+    }
+
+    /// Store a value for a key that isn't cached yet, counting this as its first use
+    fn insert_new(&mut self, key: K, value: V) -> &mut LfuEntry<V> {
+        self.check_capacity();
+        let entry = LfuEntry {
+            value,
+            uses: 1,
+            last_used: self.next_tick(),
+        };
+        self.order.insert(entry.rank(), key.clone());
+        self.store.entry(key).or_insert(entry)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for LfuCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache_get_mut(key).map(|value| &*value)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get(key).map(|entry| &entry.value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .get_key_value(key)
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.store.contains_key(key) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        self.touch(key).map(|entry| &mut entry.value)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        match self.touch(&key) {
+            Some(entry) => Some(std::mem::replace(&mut entry.value, val)),
+            None => {
+                self.insert_new(key, val);
+                None
+            }
+        }
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        if self.store.contains_key(&key) {
+            return false;
+        }
+        self.insert_new(key, val);
+        true
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        if self.store.contains_key(&key) {
+            self.hits += 1;
+            let entry = self.touch(&key).expect("LfuCache lost a cached key");
+            return &mut entry.value;
+        }
+        self.misses += 1;
+        &mut self.insert_new(key, f()).value
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.store.remove(k)?;
+        self.order.remove(&entry.rank());
+        Some(entry.value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let Self { store, order, .. } = self;
+        let before = store.len();
+        store.retain(|key, entry| {
+            if !f(key) {
+                return true;
+            }
+            order.remove(&entry.rank());
+            false
+        });
+        Some(before - store.len())
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let Self { store, order, .. } = self;
+        let before = store.len();
+        store.retain(|key, entry| {
+            if f(key, &entry.value) {
+                return true;
+            }
+            order.remove(&entry.rank());
+            false
+        });
+        Some(before - store.len())
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.order.clear();
+    }
+    fn cache_reset(&mut self) {
+        // LfuCache uses cache_clear because capacity is fixed.
+        self.cache_clear();
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq + Clone, V> LfuCache<K, V> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq + Clone, V> CachedAsync<K, V> for LfuCache<K, V> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        if self.store.contains_key(&key) {
+            self.hits += 1;
+            let entry = self.touch(&key).expect("LfuCache lost a cached key");
+            return Ok(&mut entry.value);
+        }
+        self.misses += 1;
+        let value = f().await?;
+        Ok(&mut self.insert_new(key, value).value)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::LfuCache;
+
+    #[test]
+    fn lfu_cache() {
+        let mut c = LfuCache::with_size(3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&3), Some(&300));
+        assert_eq!(Some(3), c.uses(&1));
+        assert_eq!(vec![&2, &3, &1], c.key_order().collect::<Vec<_>>());
+
+        // 2 is the least frequently used
+        assert_eq!(c.cache_set(4, 400), None);
+        assert!(c.cache_peek(&2).is_none());
+        // then 4, newer but used less than 3
+        assert_eq!(c.cache_get_or_set_with(5, || 500), &500);
+        assert!(c.cache_peek(&4).is_none());
+        assert_eq!(3, c.cache_size());
+        assert_eq!(Some(3), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+
+        // replacing a value counts as a use
+        assert_eq!(c.cache_set(5, 501), Some(500));
+        assert_eq!(Some(2), c.uses(&5));
+        assert!(!c.cache_insert_if_absent(5, 502));
+        assert_eq!(c.cache_remove(&5), Some(501));
+        assert_eq!(c.cache_remove(&5), None);
+        assert_eq!(Some(1), c.cache_remove_where(|k| *k == 3));
+        assert_eq!(vec![&1], c.key_order().collect::<Vec<_>>());
+        c.cache_clear();
+        assert_eq!(0, c.cache_size());
+        assert_eq!(Some(3), c.cache_capacity());
+    }
+
+    #[test]
+    #[should_panic]
+    fn lfu_cache_zero_size() {
+        LfuCache::<u32, u32>::with_size(0);
+    }
+}
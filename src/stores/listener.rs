@@ -0,0 +1,59 @@
+use alloc::sync::Arc;
+use core::fmt;
+
+/// Why an entry left a cache, passed to eviction listeners
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EvictionReason {
+    /// The entry outlived its lifespan
+    Expired,
+    /// The entry was evicted to make room for another one
+    Capacity,
+    /// The entry's value was overwritten by a new value for the same key
+    Replaced,
+    /// The entry was removed with `cache_remove`
+    Explicit,
+    /// The entry was dropped by `cache_clear` or `cache_reset`
+    Cleared,
+}
+
+type Callback<K, V> = dyn Fn(&K, &V, EvictionReason) + Send + Sync;
+
+/// Callback notified of entries leaving a store
+pub(super) struct Listener<K, V>(Option<Arc<Callback<K, V>>>);
+
+impl<K, V> Listener<K, V> {
+    pub(super) fn new() -> Listener<K, V> {
+        Listener(None)
+    }
+
+    pub(super) fn set<F>(&mut self, f: F)
+    where
+        F: Fn(&K, &V, EvictionReason) + Send + Sync + 'static,
+    {
+        self.0 = Some(Arc::new(f));
+    }
+
+    pub(super) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub(super) fn notify(&self, key: &K, value: &V, reason: EvictionReason) {
+        if let Some(f) = &self.0 {
+            f(key, value, reason);
+        }
+    }
+}
+
+impl<K, V> Clone for Listener<K, V> {
+    fn clone(&self) -> Listener<K, V> {
+        Listener(self.0.clone())
+    }
+}
+
+impl<K, V> fmt::Debug for Listener<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Listener")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
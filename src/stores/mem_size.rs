@@ -0,0 +1,142 @@
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// Estimate of the memory used by a value, see `WeightedCache::with_memory_budget`
+///
+/// The estimate is the size of the value itself plus the size of the heap memory it
+/// owns, e.g. the capacity of a `String`. Implementations for your own types can add
+/// up the sizes of their fields:
+///
+/// ```rust
+/// use std::mem::size_of;
+/// use cached::MemSize;
+///
+/// struct Page {
+///     title: String,
+///     links: Vec<u64>,
+/// }
+///
+/// impl MemSize for Page {
+///     fn mem_size(&self) -> usize {
+///         size_of::<Page>() + self.title.capacity() + self.links.capacity() * size_of::<u64>()
+///     }
+/// }
+/// ```
+///
+/// Allocator overhead and the bookkeeping of the stores aren't counted, so the
+/// estimate is below the actual memory usage.
+pub trait MemSize {
+    /// Return the estimated number of bytes used by the value and the heap memory it owns
+    fn mem_size(&self) -> usize;
+}
+
+macro_rules! inline_mem_size {
+    ($($t:ty),*) => {
+        $(
+            impl MemSize for $t {
+                fn mem_size(&self) -> usize {
+                    size_of::<$t>()
+                }
+            }
+        )*
+    };
+}
+
+inline_mem_size!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+impl MemSize for String {
+    fn mem_size(&self) -> usize {
+        size_of::<String>() + self.capacity()
+    }
+}
+
+impl<T: MemSize> MemSize for Vec<T> {
+    fn mem_size(&self) -> usize {
+        let spare = self.capacity() - self.len();
+        size_of::<Vec<T>>() + self.iter().map(T::mem_size).sum::<usize>() + spare * size_of::<T>()
+    }
+}
+
+impl<T: MemSize + ?Sized> MemSize for Box<T> {
+    fn mem_size(&self) -> usize {
+        size_of::<Box<T>>() + (**self).mem_size()
+    }
+}
+
+/// The shared value is counted in full, although other handles may keep it alive
+impl<T: MemSize + ?Sized> MemSize for Arc<T> {
+    fn mem_size(&self) -> usize {
+        size_of::<Arc<T>>() + (**self).mem_size()
+    }
+}
+
+impl<T: MemSize> MemSize for Option<T> {
+    fn mem_size(&self) -> usize {
+        match self {
+            // the value is stored inline, only its heap memory is extra
+            Some(value) => size_of::<Option<T>>() + value.mem_size() - size_of::<T>(),
+            None => size_of::<Option<T>>(),
+        }
+    }
+}
+
+macro_rules! tuple_mem_size {
+    ($($t:ident),*) => {
+        impl<$($t: MemSize),*> MemSize for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn mem_size(&self) -> usize {
+                let ($($t,)*) = self;
+                // the fields are stored inline, only their heap memory is extra
+                size_of::<Self>() $(+ $t.mem_size() - size_of::<$t>())*
+            }
+        }
+    };
+}
+
+tuple_mem_size!(A);
+tuple_mem_size!(A, B);
+tuple_mem_size!(A, B, C);
+tuple_mem_size!(A, B, C, D);
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::mem::size_of;
+
+    use super::MemSize;
+
+    #[test]
+    fn mem_size() {
+        assert_eq!(4, 1u32.mem_size());
+        let s = String::with_capacity(10);
+        assert_eq!(size_of::<String>() + 10, s.mem_size());
+        let v = vec![String::with_capacity(10), String::new()];
+        assert_eq!(
+            size_of::<Vec<String>>() + 2 * size_of::<String>() + 10,
+            v.mem_size()
+        );
+        assert_eq!(
+            size_of::<Option<String>>() + 10,
+            Some(String::with_capacity(10)).mem_size()
+        );
+        assert_eq!(size_of::<(u32, String)>() + 10, (1u32, s).mem_size());
+    }
+}
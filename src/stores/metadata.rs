@@ -0,0 +1,270 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Instant;
+
+use super::Cached;
+
+/// When a cached value was inserted and last accessed, and how many lookups found it
+///
+/// Returned by `Cached::cache_entry_info`, e.g. for admin tooling telling when a value was
+/// computed and whether its key is actually hot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// When the value was inserted
+    pub inserted: Instant,
+    /// When a lookup last found the value, `inserted` if none did
+    pub last_access: Instant,
+    /// Number of lookups that found the value
+    pub hits: u64,
+}
+
+impl EntryInfo {
+    fn new() -> EntryInfo {
+        let now = Instant::now();
+        EntryInfo {
+            inserted: now,
+            last_access: now,
+            hits: 0,
+        }
+    }
+
+    fn hit(&mut self) {
+        self.last_access = Instant::now();
+        self.hits += 1;
+    }
+}
+
+/// A value stored by a `MetadataCache`, along with its `EntryInfo`
+#[derive(Clone, Debug)]
+pub struct Tracked<V> {
+    /// The cached value
+    pub value: V,
+    /// When the value was inserted and last accessed, and how many lookups found it
+    pub info: EntryInfo,
+}
+
+impl<V> Tracked<V> {
+    fn new(value: V) -> Tracked<V> {
+        Tracked {
+            value,
+            info: EntryInfo::new(),
+        }
+    }
+}
+
+/// Wrapper tracking when each value was inserted and last accessed, and how many lookups
+/// found it
+///
+/// The inner store holds each value in a `Tracked`, so the metadata of a value leaves
+/// along with it, e.g. when it's evicted. Read it with `cache_entry_info`:
+///
+/// ```rust
+/// use cached::{Cached, MetadataCache, SizedCache};
+///
+/// let mut cache = MetadataCache::new(SizedCache::with_size(100));
+/// cache.cache_set(1, "one");
+/// cache.cache_get(&1);
+/// let info = cache.cache_entry_info(&1).unwrap();
+/// assert_eq!(info.hits, 1);
+/// assert!(info.last_access >= info.inserted);
+/// ```
+///
+/// Lookups through `cache_get`, `cache_get_mut` and `cache_get_or_set_with` update the
+/// metadata, `cache_peek` doesn't.
+#[derive(Clone, Debug)]
+pub struct MetadataCache<C> {
+    inner: C,
+}
+
+impl<C> MetadataCache<C> {
+    /// Creates a wrapper around `inner`, which should be empty
+    pub fn new(inner: C) -> MetadataCache<C> {
+        MetadataCache { inner }
+    }
+
+    /// Return the inner store, holding `Tracked` values
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Return the inner store mutably, holding `Tracked` values
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<K, V, C: Cached<K, Tracked<V>>> Cached<K, V> for MetadataCache<C> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache_get_mut(key).map(|value| &*value)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let tracked = self.inner.cache_get_mut(key)?;
+        tracked.info.hit();
+        Some(&mut tracked.value)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_peek(key).map(|tracked| &tracked.value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner
+            .cache_peek_key_value(key)
+            .map(|(key, tracked)| (key, &tracked.value))
+    }
+
+    fn cache_pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_pin(key)
+    }
+
+    fn cache_unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_unpin(key)
+    }
+
+    fn cache_entry_info<Q>(&self, key: &Q) -> Option<EntryInfo>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache_peek(key).map(|tracked| tracked.info)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.inner.cache_set(key, Tracked::new(val))?;
+        Some(old.value)
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        let old = self
+            .inner
+            .cache_set_with_lifespan(key, Tracked::new(val), seconds)?;
+        Some(old.value)
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        self.inner.cache_insert_if_absent(key, Tracked::new(val))
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let mut inserted = false;
+        let tracked = self.inner.cache_get_or_set_with(key, || {
+            inserted = true;
+            Tracked::new(f())
+        });
+        if !inserted {
+            tracked.info.hit();
+        }
+        &mut tracked.value
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let old = self.inner.cache_remove(key)?;
+        Some(old.value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, f: F) -> Option<usize> {
+        self.inner.cache_remove_where(f)
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.inner
+            .cache_retain(|key, tracked| f(key, &tracked.value))
+    }
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        self.inner.cache_flush_expired()
+    }
+    fn cache_clear(&mut self) {
+        self.inner.cache_clear();
+    }
+    fn cache_reset(&mut self) {
+        self.inner.cache_reset();
+    }
+    fn cache_size(&self) -> usize {
+        self.inner.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        self.inner.cache_set_capacity(size)
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        self.inner.cache_unset_lifespan()
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::MetadataCache;
+    use crate::stores::SizedCache;
+
+    #[test]
+    fn metadata_cache() {
+        let mut c = MetadataCache::new(SizedCache::with_size(2));
+        assert!(c.cache_entry_info(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        let inserted = c.cache_entry_info(&1).unwrap();
+        assert_eq!(inserted.hits, 0);
+        assert_eq!(inserted.inserted, inserted.last_access);
+
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get_or_set_with(1, || 101), &100);
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        let info = c.cache_entry_info(&1).unwrap();
+        assert_eq!(info.hits, 2);
+        assert_eq!(info.inserted, inserted.inserted);
+        assert!(info.last_access >= inserted.last_access);
+
+        // replacing a value resets its metadata
+        assert_eq!(c.cache_set(1, 102), Some(100));
+        assert_eq!(c.cache_entry_info(&1).unwrap().hits, 0);
+
+        // metadata is evicted along with values
+        c.cache_set(2, 200);
+        c.cache_set(3, 300);
+        assert!(c.cache_entry_info(&1).is_none());
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_hits(), Some(2));
+    }
+}
@@ -0,0 +1,196 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::Hash;
+
+use super::Cached;
+
+/// Adapter backing the `Cached` trait with a concurrent `moka::sync::Cache`
+///
+/// Enabled by the `moka` feature. Use it with the `type`/`create` macro attributes
+/// to keep the `#[cached]` ergonomics with moka's eviction and expiry policies:
+///
+/// ```rust,ignore
+/// #[cached(type = "MokaCache<u32, u32>", create = "{ MokaCache::new(1000) }")]
+/// fn square(n: u32) -> u32 {
+///     n * n
+/// }
+/// ```
+///
+/// Moka hands out clones of its values, so the adapter holds the last value read
+/// to return a reference to it. It can't lend mutable access to cached values:
+/// `cache_get_mut` always returns `None`, and changes made through the reference
+/// returned by `cache_get_or_set_with` aren't written back. Use `cache_set` instead.
+///
+/// Moka evicts and expires entries in the background of later operations, so
+/// `cache_size` is approximate.
+#[derive(Clone)]
+pub struct MokaCache<K, V> {
+    cache: moka::sync::Cache<K, V>,
+    scratch: Option<V>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K, V> fmt::Debug for MokaCache<K, V>
+where
+    K: fmt::Debug + Hash + Eq + Send + Sync + 'static,
+    V: fmt::Debug + Clone + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MokaCache")
+            .field("cache", &self.cache)
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+impl<K, V> MokaCache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates a `MokaCache` holding up to `max_capacity` entries
+    pub fn new(max_capacity: u64) -> MokaCache<K, V> {
+        MokaCache::from_cache(moka::sync::Cache::new(max_capacity))
+    }
+
+    /// Creates a `MokaCache` over a cache configured with `moka::sync::Cache::builder`,
+    /// e.g. to set a time to live or to share the cache with other handles
+    pub fn from_cache(cache: moka::sync::Cache<K, V>) -> MokaCache<K, V> {
+        MokaCache {
+            cache,
+            scratch: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return the underlying moka cache
+    pub fn inner(&self) -> &moka::sync::Cache<K, V> {
+        &self.cache
+    }
+}
+
+impl<K, V> Cached<K, V> for MokaCache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.cache.get(key) {
+            Some(value) => {
+                self.hits += 1;
+                Some(self.scratch.insert(value))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Always `None`, changes to a clone of the value wouldn't reach the cache
+    fn cache_get_mut<Q>(&mut self, _key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        None
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.cache.get(&key);
+        self.cache.insert(key, val);
+        old
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        self.cache.entry(key).or_insert(val).is_fresh()
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let entry = self.cache.entry(key).or_insert_with(f);
+        if entry.is_fresh() {
+            self.misses += 1;
+        } else {
+            self.hits += 1;
+        }
+        self.scratch.insert(entry.into_value())
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.remove(k)
+    }
+    fn cache_clear(&mut self) {
+        self.cache.invalidate_all();
+        self.scratch = None;
+    }
+    fn cache_reset(&mut self) {
+        self.cache_clear();
+    }
+    fn cache_size(&self) -> usize {
+        self.cache.run_pending_tasks();
+        self.cache.entry_count() as usize
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.cache.policy().max_capacity().map(|c| c as usize)
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.cache.policy().time_to_live().map(|d| d.as_secs())
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::time::Duration;
+
+    use super::Cached;
+    use super::MokaCache;
+
+    #[test]
+    fn moka_cache() {
+        let mut c = MokaCache::new(10);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get_mut(&1), None);
+        assert_eq!(c.cache_set(1, 101), Some(100));
+        assert_eq!(c.cache_get_or_set_with(1, || 0), &101);
+        assert_eq!(c.cache_get_or_set_with(2, || 200), &200);
+        assert_eq!(2, c.cache_size());
+        assert_eq!(Some(2), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+        assert_eq!(Some(10), c.cache_capacity());
+
+        assert_eq!(c.cache_remove(&1), Some(101));
+        c.cache_clear();
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(0, c.cache_size());
+    }
+
+    #[test]
+    fn moka_cache_from_builder() {
+        let cache = moka::sync::Cache::builder()
+            .max_capacity(10)
+            .time_to_live(Duration::from_secs(60))
+            .build();
+        let c: MokaCache<u32, u32> = MokaCache::from_cache(cache);
+        assert_eq!(Some(60), c.cache_lifespan());
+    }
+}
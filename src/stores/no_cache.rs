@@ -0,0 +1,155 @@
+use std::borrow::Borrow;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use super::Cached;
+#[cfg(feature = "async")]
+use super::CachedAsync;
+
+/// Pass-through cache that never retains anything
+///
+/// Every lookup is a miss and `cache_set` is a no-op. Useful for disabling
+/// caching through the `type`/`create` macro attributes, or for measuring the
+/// overhead of the caching machinery itself.
+///
+/// `cache_get_or_set_with` has to hand out a reference to the computed value,
+/// so the last computed value is held until the next call, but it is never
+/// returned by a lookup.
+#[derive(Clone, Debug)]
+pub struct NoCache<K, V> {
+    scratch: Option<V>,
+    misses: u64,
+    _key: PhantomData<K>,
+}
+
+impl<K, V> PartialEq for NoCache<K, V> {
+    fn eq(&self, _other: &NoCache<K, V>) -> bool {
+        // all `NoCache`s are equally empty
+        true
+    }
+}
+
+impl<K, V> Eq for NoCache<K, V> {}
+
+impl<K, V> NoCache<K, V> {
+    /// Creates a `NoCache`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> NoCache<K, V> {
+        NoCache {
+            scratch: None,
+            misses: 0,
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Cached<K, V> for NoCache<K, V> {
+    fn cache_get<Q>(&mut self, _key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.misses += 1;
+        None
+    }
+
+    fn cache_get_mut<Q>(&mut self, _key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.misses += 1;
+        None
+    }
+
+    fn cache_set(&mut self, _key: K, _val: V) -> Option<V> {
+        None
+    }
+
+    fn cache_insert_if_absent(&mut self, _key: K, _val: V) -> bool {
+        false
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, _key: K, f: F) -> &mut V {
+        self.misses += 1;
+        self.scratch.insert(f())
+    }
+
+    fn cache_remove<Q>(&mut self, _k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        None
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, _f: F) -> Option<usize> {
+        Some(0)
+    }
+    fn cache_clear(&mut self) {
+        self.scratch = None;
+    }
+    fn cache_reset(&mut self) {
+        self.scratch = None;
+    }
+    fn cache_size(&self) -> usize {
+        0
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(0)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(0)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K, V> NoCache<K, V> {
+    /// Always awaits the future returned by `f`, see `cache_get_or_set_with`
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K, V> CachedAsync<K, V> for NoCache<K, V> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, _key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        self.misses += 1;
+        Ok(self.scratch.insert(f().await?))
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::NoCache;
+
+    #[test]
+    fn no_cache() {
+        let mut c = NoCache::new();
+        assert_eq!(c.cache_set(1, 100), None);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_get_or_set_with(1, || 200), &200);
+        assert_eq!(c.cache_get_or_set_with(1, || 300), &300);
+        assert!(c.cache_get_mut(&1).is_none());
+        assert_eq!(c.cache_remove(&1), None);
+
+        assert_eq!(0, c.cache_size());
+        assert_eq!(Some(0), c.cache_hits());
+        assert_eq!(Some(4), c.cache_misses());
+    }
+}
@@ -0,0 +1,507 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use redis::{Client, Connection, ErrorKind, IntoConnectionInfo, RedisError, RedisResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::registry::Health;
+use crate::{Cached, IOCached, TieredCache};
+
+/// Error of a `RedisCache` operation
+#[derive(Debug)]
+pub enum RedisCacheError {
+    /// Redis couldn't be reached, or failed to run a command
+    Redis(RedisError),
+    /// A value couldn't be encoded to or decoded from JSON
+    Json(serde_json::Error),
+}
+
+impl Display for RedisCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisCacheError::Redis(e) => write!(f, "redis cache error: {}", e),
+            RedisCacheError::Json(e) => write!(f, "redis cache value error: {}", e),
+        }
+    }
+}
+
+impl Error for RedisCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RedisCacheError::Redis(e) => Some(e),
+            RedisCacheError::Json(e) => Some(e),
+        }
+    }
+}
+
+impl From<RedisError> for RedisCacheError {
+    fn from(e: RedisError) -> RedisCacheError {
+        RedisCacheError::Redis(e)
+    }
+}
+
+impl From<serde_json::Error> for RedisCacheError {
+    fn from(e: serde_json::Error) -> RedisCacheError {
+        RedisCacheError::Json(e)
+    }
+}
+
+/// TLS settings of a `RedisCache` connecting to a `rediss://` URL, see `RedisCache::open_tls`
+///
+/// By default the server's certificate is verified against the system's root certificates,
+/// and the client doesn't authenticate. Enabled by the `redis_tls` feature.
+#[cfg(feature = "redis_tls")]
+#[derive(Clone, Default)]
+pub struct RedisTls {
+    root_cert: Option<Vec<u8>>,
+    client_auth: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+#[cfg(feature = "redis_tls")]
+impl fmt::Debug for RedisTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the client's private key stays out of logs
+        f.debug_struct("RedisTls")
+            .field("root_cert", &self.root_cert.is_some())
+            .field("client_auth", &self.client_auth.is_some())
+            .finish()
+    }
+}
+
+#[cfg(feature = "redis_tls")]
+impl RedisTls {
+    /// Creates `RedisTls` settings verifying the server against the system's root certificates
+    pub fn new() -> RedisTls {
+        RedisTls::default()
+    }
+
+    /// Verify the server's certificate against the certificates of `pem` instead of the
+    /// system's root certificates, e.g. the CA of a managed Redis offering
+    pub fn with_root_cert(mut self, pem: &[u8]) -> RedisTls {
+        self.root_cert = Some(pem.to_vec());
+        self
+    }
+
+    /// Authenticate to the server with the certificate chain of `cert_pem` and the private
+    /// key of `key_pem`, for servers requiring client certificates
+    pub fn with_client_auth(mut self, cert_pem: &[u8], key_pem: &[u8]) -> RedisTls {
+        self.client_auth = Some((cert_pem.to_vec(), key_pem.to_vec()));
+        self
+    }
+}
+
+/// Store keeping values in Redis, shared by every process using the same server and prefix
+///
+/// Enabled by the `redis` feature. Values are encoded as JSON under `{prefix}{key}`, and
+/// expire on the server after the lifespan set with `with_lifespan`, written with `SETEX`.
+/// It implements `IOCached`, use it with `#[io_cached]`:
+///
+/// ```rust,no_run
+/// use cached::proc_macro::io_cached;
+/// use cached::{RedisCache, RedisCacheError};
+///
+/// #[derive(Debug)]
+/// enum Error {
+///     Cache(RedisCacheError),
+/// }
+///
+/// #[io_cached(
+///     type = "RedisCache<u32, String>",
+///     create = r#"{ RedisCache::open("redis://127.0.0.1/", "users:").unwrap().with_lifespan(60) }"#,
+///     map_error = r#"|e| Error::Cache(e)"#
+/// )]
+/// fn user_name(id: u32) -> Result<String, Error> {
+///     Ok(format!("user {}", id))
+/// }
+/// ```
+///
+/// A single connection is opened on first use and shared by the calls, each taking it in
+/// turn. A connection that broke is dropped, the next call opens a new one. After a failed
+/// connection, calls fail without connecting until a backoff doubling with each failure
+/// elapses, see `with_backoff`. `health` probes the server, e.g. for a readiness check
+/// through `registry::register_health`. With the `redis_tls` feature, `rediss://` URLs
+/// connect over TLS, see `open_tls` to set certificates.
+pub struct RedisCache<K, V> {
+    client: Client,
+    link: Mutex<Link>,
+    prefix: String,
+    lifespan: Option<u64>,
+    backoff: (Duration, Duration),
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+/// Connection of a `RedisCache`, with the failures since it was last up
+#[derive(Default)]
+struct Link {
+    connection: Option<Connection>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl<K, V> fmt::Debug for RedisCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisCache")
+            .field("connection_info", self.client.get_connection_info())
+            .field("prefix", &self.prefix)
+            .field("lifespan", &self.lifespan)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+impl<K, V> RedisCache<K, V> {
+    /// Creates a `RedisCache` for the server at `info`, e.g. a `redis://host:port/db` URL,
+    /// keeping values under keys starting with `prefix`. Doesn't connect yet, but fails if
+    /// `info` isn't valid.
+    pub fn open<T: IntoConnectionInfo>(
+        info: T,
+        prefix: &str,
+    ) -> Result<RedisCache<K, V>, RedisCacheError> {
+        Ok(RedisCache::with_client(Client::open(info)?, prefix))
+    }
+
+    /// Creates a `RedisCache` for the server at `info`, a `rediss://host:port/db` URL,
+    /// connecting over TLS with the certificates of `tls`. Fails if `info` isn't a
+    /// `rediss://` URL or the certificates can't be parsed.
+    ///
+    /// ```rust,no_run
+    /// use cached::{RedisCache, RedisTls};
+    ///
+    /// let tls = RedisTls::new()
+    ///     .with_root_cert(&std::fs::read("ca.pem").unwrap())
+    ///     .with_client_auth(
+    ///         &std::fs::read("client.pem").unwrap(),
+    ///         &std::fs::read("client.key").unwrap(),
+    ///     );
+    /// let cache: RedisCache<u32, String> =
+    ///     RedisCache::open_tls("rediss://redis.example.com:6380/", "users:", tls).unwrap();
+    /// ```
+    ///
+    /// Requires the `redis_tls` feature.
+    #[cfg(feature = "redis_tls")]
+    pub fn open_tls<T: IntoConnectionInfo>(
+        info: T,
+        prefix: &str,
+        tls: RedisTls,
+    ) -> Result<RedisCache<K, V>, RedisCacheError> {
+        let certificates = redis::TlsCertificates {
+            client_tls: tls
+                .client_auth
+                .map(|(client_cert, client_key)| redis::ClientTlsConfig {
+                    client_cert,
+                    client_key,
+                }),
+            root_cert: tls.root_cert,
+        };
+        let client = Client::build_with_tls(info, certificates)?;
+        Ok(RedisCache::with_client(client, prefix))
+    }
+
+    fn with_client(client: Client, prefix: &str) -> RedisCache<K, V> {
+        RedisCache {
+            client,
+            link: Mutex::new(Link::default()),
+            prefix: prefix.to_string(),
+            lifespan: None,
+            backoff: (Duration::from_millis(100), Duration::from_secs(30)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Expire values `seconds` after they're set, instead of keeping them until they're
+    /// removed or evicted by the server
+    pub fn with_lifespan(mut self, seconds: u64) -> RedisCache<K, V> {
+        if seconds == 0 {
+            panic!("`lifespan` of `RedisCache` must be greater than zero.")
+        }
+        self.lifespan = Some(seconds);
+        self
+    }
+
+    /// Wait `initial` before reconnecting after a first failure, doubling the wait with
+    /// each failure up to `max`, instead of 100 milliseconds up to 30 seconds. Calls made
+    /// while waiting fail without trying to connect.
+    ///
+    /// Will panic if `initial` is greater than `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> RedisCache<K, V> {
+        if initial > max {
+            panic!("`initial` backoff of `RedisCache` must not be greater than `max`.")
+        }
+        self.backoff = (initial, max);
+        self
+    }
+
+    /// Probe the server with a `PING`, reconnecting if the connection is down and its
+    /// backoff elapsed. Returns `Health::Down` without connecting while waiting to reconnect.
+    pub fn health(&self) -> Health {
+        match self.with_connection(|conn| redis::cmd("PING").query::<String>(conn)) {
+            Ok(_) => Health::Up,
+            Err(_) => Health::Down,
+        }
+    }
+
+    /// Return the prefix of the keys values are stored under
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn key(&self, k: &K) -> String
+    where
+        K: Display,
+    {
+        format!("{}{}", self.prefix, k)
+    }
+
+    /// Run `f` on the connection, opening it first if there's none and the backoff since
+    /// the last failure elapsed. The connection is dropped if `f` finds it broken.
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> RedisResult<T>,
+    ) -> Result<T, RedisCacheError> {
+        let mut link = self.link.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut conn = match link.connection.take() {
+            Some(conn) => conn,
+            None => {
+                if matches!(link.retry_at, Some(at) if Instant::now() < at) {
+                    return Err(RedisError::from((
+                        ErrorKind::IoError,
+                        "waiting to reconnect after a failed connection",
+                    ))
+                    .into());
+                }
+                match self.client.get_connection() {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        self.failed(&mut link);
+                        return Err(e.into());
+                    }
+                }
+            }
+        };
+        let result = f(&mut conn);
+        match &result {
+            Err(e) if e.is_unrecoverable_error() => self.failed(&mut link),
+            _ => {
+                link.connection = Some(conn);
+                link.failures = 0;
+                link.retry_at = None;
+            }
+        }
+        Ok(result?)
+    }
+
+    /// Record a failed connection, doubling the backoff before the next attempt
+    fn failed(&self, link: &mut Link) {
+        let (initial, max) = self.backoff;
+        let backoff = initial
+            .checked_mul(1 << link.failures.min(31))
+            .map_or(max, |backoff| backoff.min(max));
+        link.failures = link.failures.saturating_add(1);
+        link.retry_at = Some(Instant::now() + backoff);
+    }
+}
+
+pub(super) fn decode<V: DeserializeOwned>(
+    value: Option<String>,
+) -> Result<Option<V>, RedisCacheError> {
+    Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+}
+
+impl<K, V> IOCached<K, V> for RedisCache<K, V>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+{
+    type Error = RedisCacheError;
+
+    fn cache_get(&self, k: &K) -> Result<Option<V>, RedisCacheError> {
+        let key = self.key(k);
+        let value: Option<String> =
+            self.with_connection(|conn| redis::cmd("GET").arg(&key).query(conn))?;
+        decode(value)
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Result<Option<V>, RedisCacheError> {
+        let key = self.key(&k);
+        let value = serde_json::to_string(&v)?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().cmd("GET").arg(&key);
+        match self.lifespan {
+            Some(seconds) => pipe.cmd("SETEX").arg(&key).arg(seconds).arg(value),
+            None => pipe.cmd("SET").arg(&key).arg(value),
+        }
+        .ignore();
+        let (old,): (Option<String>,) = self.with_connection(|conn| pipe.query(conn))?;
+        decode(old)
+    }
+
+    fn cache_remove(&self, k: &K) -> Result<Option<V>, RedisCacheError> {
+        let key = self.key(k);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("GET")
+            .arg(&key)
+            .cmd("DEL")
+            .arg(&key)
+            .ignore();
+        let (old,): (Option<String>,) = self.with_connection(|conn| pipe.query(conn))?;
+        decode(old)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.lifespan
+    }
+}
+
+/// How often the invalidation thread checks whether its `RedisInvalidator` was dropped
+const INVALIDATOR_POLL: Duration = Duration::from_millis(100);
+
+/// How long the invalidation thread waits before subscribing again after losing its connection
+const INVALIDATOR_RETRY: Duration = Duration::from_secs(1);
+
+/// Handle to a background thread evicting the local copies of a `TieredCache` in front of a
+/// `RedisCache` when their Redis entries change
+///
+/// Instances sharing a `RedisCache` each keep their own in-memory front store, which goes
+/// stale when another instance sets or removes a value. The thread subscribes to the Redis
+/// keyspace notifications of the keys under the store's prefix, and removes the key of each
+/// notification from the front store, so the next lookup reads the new value from Redis.
+/// Changes made by the instance itself evict its own copy too.
+///
+/// The server must publish keyspace notifications for generic, string and expiry events,
+/// e.g. with `CONFIG SET notify-keyspace-events Kg$x`. Keys of notifications are parsed
+/// back with `FromStr`, those that don't parse are ignored. If the subscription is lost,
+/// the thread subscribes again and clears the front store, whose values may have changed
+/// in between.
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use cached::{RedisCache, RedisInvalidator, SizedCache, TieredCache};
+///
+/// let redis: RedisCache<u32, String> = RedisCache::open("redis://127.0.0.1/", "users:").unwrap();
+/// let tiered = Arc::new(TieredCache::new(SizedCache::with_size(1000), redis));
+/// let invalidator = RedisInvalidator::spawn(tiered.clone()).unwrap();
+/// ```
+///
+/// The thread stops when the `RedisInvalidator` is dropped.
+#[derive(Debug)]
+pub struct RedisInvalidator {
+    stopped: Arc<AtomicBool>,
+}
+
+impl RedisInvalidator {
+    /// Subscribe to the keyspace notifications of the back store of `tiered`, evicting the
+    /// changed keys from its front store. `tiered` is either a `&'static TieredCache`, e.g.
+    /// the static of an `#[io_cached]` function, or an `Arc<TieredCache>`.
+    ///
+    /// Fails if the first subscription fails. Will panic if the thread can't be spawned.
+    pub fn spawn<F, K, V, H>(tiered: H) -> Result<RedisInvalidator, RedisCacheError>
+    where
+        F: Cached<K, V>,
+        K: FromStr + Hash + Eq,
+        H: Deref<Target = TieredCache<F, RedisCache<K, V>>> + Send + 'static,
+    {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let (subscribed, first) = mpsc::channel();
+        thread::Builder::new()
+            .name("cached-redis-invalidator".to_string())
+            .spawn(move || invalidate(&*tiered, &thread_stopped, subscribed))
+            .expect("failed to spawn the cached redis invalidator thread");
+        // the thread reports the first subscription before reading notifications
+        match first.recv() {
+            Ok(Err(e)) => Err(e),
+            _ => Ok(RedisInvalidator { stopped }),
+        }
+    }
+}
+
+impl Drop for RedisInvalidator {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Escape the glob characters of `s` for a Redis pattern
+fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Evict the keys of the keyspace notifications of `tiered`'s back store from its front
+/// store until `stopped`, reporting the first subscription to `subscribed`
+fn invalidate<F, K, V>(
+    tiered: &TieredCache<F, RedisCache<K, V>>,
+    stopped: &AtomicBool,
+    subscribed: mpsc::Sender<Result<(), RedisCacheError>>,
+) where
+    F: Cached<K, V>,
+    K: FromStr + Hash + Eq,
+{
+    let redis = tiered.back();
+    let channel_prefix = format!(
+        "__keyspace@{}__:{}",
+        redis.client.get_connection_info().redis.db,
+        redis.prefix
+    );
+    let pattern = format!("{}*", escape_pattern(&channel_prefix));
+    let mut first = true;
+    while !stopped.load(Ordering::Relaxed) {
+        let subscribe = || -> RedisResult<Connection> {
+            let mut conn = redis.client.get_connection()?;
+            conn.set_read_timeout(Some(INVALIDATOR_POLL))?;
+            conn.as_pubsub().psubscribe(&pattern)?;
+            Ok(conn)
+        };
+        let mut conn = match subscribe() {
+            Ok(conn) => conn,
+            Err(e) if first => {
+                let _ = subscribed.send(Err(e.into()));
+                return;
+            }
+            Err(_) => {
+                thread::sleep(INVALIDATOR_RETRY);
+                continue;
+            }
+        };
+        if first {
+            let _ = subscribed.send(Ok(()));
+            first = false;
+        } else {
+            // notifications were missed while unsubscribed
+            tiered.front().cache_clear();
+        }
+        let mut pubsub = conn.as_pubsub();
+        while !stopped.load(Ordering::Relaxed) {
+            match pubsub.get_message() {
+                Ok(message) => {
+                    let key = message
+                        .get_channel_name()
+                        .strip_prefix(&channel_prefix)
+                        .and_then(|key| key.parse().ok());
+                    if let Some(key) = key {
+                        tiered.front().cache_remove(&key);
+                    }
+                }
+                Err(e) if e.is_timeout() => (),
+                Err(_) => break,
+            }
+        }
+    }
+}
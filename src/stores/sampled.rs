@@ -0,0 +1,478 @@
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::collections::HashMap;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::Hash;
+
+use super::hasher::{self, SeedableState};
+use super::Cached;
+#[cfg(feature = "async")]
+use super::CachedAsync;
+use super::ConfigError;
+
+/// Number of entries sampled per eviction when not specified, same as Redis' default
+const DEFAULT_SAMPLES: usize = 5;
+
+#[derive(Clone, Debug)]
+struct SampledEntry<K, V> {
+    key: K,
+    value: V,
+    last_used: u64,
+}
+
+/// Approximate Least Recently Used Cache
+///
+/// Stores up to a specified size before beginning to evict entries. Instead of
+/// maintaining an exact recency order, each eviction samples a handful of
+/// random entries and evicts the least recently used one among them, the same
+/// approach Redis takes for its `allkeys-lru` policy.
+///
+/// Hits only update an access stamp, so reads and writes are cheaper than with
+/// `SizedCache` at the cost of occasionally evicting an entry that isn't the
+/// globally least recently used one. Sampling more entries gets closer to exact LRU.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct SampledLruCache<K, V> {
+    store: HashMap<K, usize, SeedableState>,
+    entries: Vec<SampledEntry<K, V>>,
+    capacity: usize,
+    samples: usize,
+    tick: u64,
+    rng: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K, V> PartialEq for SampledLruCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &SampledLruCache<K, V>) -> bool {
+        self.entries.len() == other.entries.len()
+            && self.entries.iter().all(|entry| {
+                other
+                    .store
+                    .get(&entry.key)
+                    .is_some_and(|&index| other.entries[index].value == entry.value)
+            })
+    }
+}
+
+impl<K, V> Eq for SampledLruCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+}
+
+impl<K: Hash + Eq, V> SampledLruCache<K, V> {
+    /// Creates a new `SampledLruCache` with a given size limit, sampling
+    /// 5 entries per eviction
+    pub fn with_size(size: usize) -> SampledLruCache<K, V> {
+        Self::with_size_and_samples(size, DEFAULT_SAMPLES)
+    }
+
+    /// Creates a new `SampledLruCache` with a given size limit, sampling
+    /// `samples` entries per eviction
+    pub fn with_size_and_samples(size: usize, samples: usize) -> SampledLruCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `SampledLruCache` must be greater than zero.")
+        }
+        if samples == 0 {
+            panic!("`samples` of `SampledLruCache` must be greater than zero.")
+        }
+        let state = SeedableState::new();
+        SampledLruCache {
+            rng: hasher::rng_seed(&state, size),
+            store: HashMap::with_capacity_and_hasher(size, state),
+            entries: Vec::with_capacity(size),
+            capacity: size,
+            samples,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Like `with_size`, but returns an error instead of panicking if size is 0
+    pub fn try_with_size(size: usize) -> Result<SampledLruCache<K, V>, ConfigError> {
+        Self::try_with_size_and_samples(size, DEFAULT_SAMPLES)
+    }
+
+    /// Like `with_size_and_samples`, but returns an error instead of panicking if size
+    /// or samples is 0
+    pub fn try_with_size_and_samples(
+        size: usize,
+        samples: usize,
+    ) -> Result<SampledLruCache<K, V>, ConfigError> {
+        if size == 0 {
+            return Err(ConfigError::ZeroSize);
+        }
+        if samples == 0 {
+            return Err(ConfigError::ZeroSamples);
+        }
+        Ok(Self::with_size_and_samples(size, samples))
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`.
+    /// This also seeds the sampling of eviction candidates, so evictions are reproducible.
+    pub fn with_hash_seed(mut self, seed: u64) -> SampledLruCache<K, V> {
+        hasher::reseed(&mut self.store, seed);
+        self.rng = hasher::rng_seed(self.store.hasher(), self.capacity);
+        self
+    }
+
+    /// Return the number of entries sampled per eviction
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn next_random(&mut self) -> u64 {
+        hasher::next_random(&mut self.rng)
+    }
+
+    /// Remove the entry at `index`, moving the last entry into its place
+    fn remove_index(&mut self, index: usize) -> SampledEntry<K, V> {
+        let entry = self.entries.swap_remove(index);
+        self.store.remove(&entry.key);
+        if let Some(moved) = self.entries.get(index) {
+            *self
+                .store
+                .get_mut(&moved.key)
+                .expect("SampledLruCache entries out of sync with store") = index;
+        }
+        entry
+    }
+
+    fn check_capacity(&mut self) {
+        if self.entries.len() >= self.capacity {
+            // store has reached capacity, evict the oldest of a few random entries.
+            // store capacity cannot be zero, so there must be entries to sample.
+            let len = self.entries.len() as u64;
+            let mut oldest = (self.next_random() % len) as usize;
+            for _ in 1..self.samples {
+                let candidate = (self.next_random() % len) as usize;
+                if self.entries[candidate].last_used < self.entries[oldest].last_used {
+                    oldest = candidate;
+                }
+            }
+            self.remove_index(oldest);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for SampledLruCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get(key) {
+            Some(&index) => {
+                self.hits += 1;
+                let tick = self.next_tick();
+                let entry = &mut self.entries[index];
+                entry.last_used = tick;
+                Some(&entry.value)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get(key).map(|&index| &self.entries[index].value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.get(key).map(|&index| {
+            let entry = &self.entries[index];
+            (&entry.key, &entry.value)
+        })
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get(key) {
+            Some(&index) => {
+                self.hits += 1;
+                let tick = self.next_tick();
+                let entry = &mut self.entries[index];
+                entry.last_used = tick;
+                Some(&mut entry.value)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let tick = self.next_tick();
+        if let Some(&index) = self.store.get(&key) {
+            let entry = &mut self.entries[index];
+            entry.last_used = tick;
+            return Some(std::mem::replace(&mut entry.value, val));
+        }
+        self.check_capacity();
+        self.store.insert(key.clone(), self.entries.len());
+        self.entries.push(SampledEntry {
+            key,
+            value: val,
+            last_used: tick,
+        });
+        None
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        if self.store.contains_key(&key) {
+            return false;
+        }
+        self.cache_set(key, val);
+        true
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let tick = self.next_tick();
+        let index = match self.store.get(&key) {
+            Some(&index) => {
+                self.hits += 1;
+                index
+            }
+            None => {
+                self.misses += 1;
+                self.check_capacity();
+                let index = self.entries.len();
+                self.store.insert(key.clone(), index);
+                self.entries.push(SampledEntry {
+                    key,
+                    value: f(),
+                    last_used: tick,
+                });
+                index
+            }
+        };
+        let entry = &mut self.entries[index];
+        entry.last_used = tick;
+        &mut entry.value
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = *self.store.get(k)?;
+        Some(self.remove_index(index).value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.entries.len();
+        // entries are swapped down from the back, which was already visited
+        for index in (0..before).rev() {
+            if f(&self.entries[index].key) {
+                self.remove_index(index);
+            }
+        }
+        Some(before - self.entries.len())
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let before = self.entries.len();
+        for index in (0..before).rev() {
+            let entry = &self.entries[index];
+            if !f(&entry.key, &entry.value) {
+                self.remove_index(index);
+            }
+        }
+        Some(before - self.entries.len())
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.entries.clear();
+    }
+    fn cache_reset(&mut self) {
+        // SampledLruCache uses cache_clear because capacity is fixed.
+        self.cache_clear();
+    }
+    fn cache_size(&self) -> usize {
+        self.entries.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq + Clone, V> SampledLruCache<K, V> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq + Clone, V> CachedAsync<K, V> for SampledLruCache<K, V> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let tick = self.next_tick();
+        let index = match self.store.get(&key) {
+            Some(&index) => {
+                self.hits += 1;
+                index
+            }
+            None => {
+                self.misses += 1;
+                let value = f().await?;
+                self.check_capacity();
+                let index = self.entries.len();
+                self.store.insert(key.clone(), index);
+                self.entries.push(SampledEntry {
+                    key,
+                    value,
+                    last_used: tick,
+                });
+                index
+            }
+        };
+        let entry = &mut self.entries[index];
+        entry.last_used = tick;
+        Ok(&mut entry.value)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::SampledLruCache;
+
+    #[test]
+    fn sampled_lru_cache() {
+        let mut c = SampledLruCache::with_size(5);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(1, c.cache_misses().unwrap());
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert!(c.cache_get(&1).is_some());
+        assert_eq!(1, c.cache_hits().unwrap());
+        assert_eq!(1, c.cache_misses().unwrap());
+
+        for i in 2..=5 {
+            assert_eq!(c.cache_set(i, 100), None);
+        }
+        assert_eq!(5, c.cache_size());
+        assert_eq!(c.cache_set(5, 500), Some(100));
+        assert_eq!(5, c.cache_size());
+
+        for i in 6..=20 {
+            assert_eq!(c.cache_set(i, 100), None);
+            assert_eq!(5, c.cache_size());
+        }
+        // eviction happens before insertion, so the newest entry is always present
+        assert!(c.cache_get(&20).is_some());
+        assert_eq!(Some(5), c.cache_capacity());
+    }
+
+    #[test]
+    fn sampled_lru_cache_exact_with_full_sampling() {
+        // sampling far more entries than the cache holds behaves like an exact LRU
+        let mut c = SampledLruCache::with_size_and_samples(3, 64);
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert!(c.cache_get(&1).is_some());
+
+        assert_eq!(c.cache_set(4, 400), None);
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&3), Some(&300));
+        assert_eq!(c.cache_get(&4), Some(&400));
+    }
+
+    #[test]
+    fn sampled_lru_cache_peek() {
+        let mut c = SampledLruCache::with_size_and_samples(3, 64);
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        // peeking doesn't make 1 recently used, it's still evicted first
+        assert_eq!(c.cache_peek(&1), Some(&100));
+        assert_eq!(c.cache_peek_key_value(&2), Some((&2, &200)));
+        assert_eq!(Some(0), c.cache_hits());
+
+        assert_eq!(c.cache_set(4, 400), None);
+        assert_eq!(c.cache_peek(&1), None);
+    }
+
+    #[test]
+    fn sampled_lru_cache_seeded_evictions_are_reproducible() {
+        let survivors = || {
+            let mut c = SampledLruCache::with_size(8).with_hash_seed(42);
+            for i in 0..64 {
+                c.cache_set(i, i);
+            }
+            (0..64)
+                .filter(|i| c.cache_get(i).is_some())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(survivors(), survivors());
+    }
+
+    #[test]
+    fn sampled_lru_cache_remove() {
+        let mut c = SampledLruCache::with_size(3);
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+
+        assert_eq!(Some(100), c.cache_remove(&1));
+        assert_eq!(None, c.cache_remove(&1));
+        assert_eq!(2, c.cache_size());
+        // the entry moved into the removed slot is still reachable
+        assert_eq!(c.cache_get(&3), Some(&300));
+        assert_eq!(c.cache_get(&2), Some(&200));
+
+        assert_eq!(c.cache_get_or_set_with(4, || 400), &400);
+        assert_eq!(c.cache_get_or_set_with(4, || 0), &400);
+        assert_eq!(3, c.cache_size());
+
+        c.cache_clear();
+        assert_eq!(0, c.cache_size());
+        assert!(c.cache_get(&2).is_none());
+    }
+}
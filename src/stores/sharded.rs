@@ -0,0 +1,259 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, PoisonError};
+
+use super::{Cached, SeedableState};
+
+/// Cache split into shards, each behind its own lock
+///
+/// Keys are spread over the shards by hash, so threads using different keys mostly
+/// lock different shards instead of contending for a single lock around the whole
+/// cache. Each shard is a separate store, e.g. a `SizedCache` bound to its share of
+/// the size:
+///
+/// ```rust
+/// use cached::{Cached, ShardedCache, SizedCache};
+///
+/// let cache = ShardedCache::new(16, || SizedCache::with_size(64));
+/// cache.shard(&1).lock().unwrap().cache_set(1, "one");
+/// assert_eq!(cache.shard(&1).lock().unwrap().cache_get(&1), Some(&"one"));
+/// ```
+///
+/// `ShardedCache` implements `Cached` as well, for exclusive access without locking.
+/// Sizes and counters are summed over the shards, and a size limit applies per shard,
+/// so values are evicted before the cache as a whole is full if keys hash unevenly.
+/// It can't `cache_peek`, values can't be borrowed past the lock of their shard.
+#[derive(Debug)]
+pub struct ShardedCache<C> {
+    shards: Box<[Mutex<C>]>,
+    hash_builder: SeedableState,
+}
+
+impl<C> ShardedCache<C> {
+    /// Creates a new `ShardedCache` of `shards` stores built by `create`
+    ///
+    /// Will panic if shards is 0
+    pub fn new<F: FnMut() -> C>(shards: usize, mut create: F) -> ShardedCache<C> {
+        if shards == 0 {
+            panic!("`shards` of `ShardedCache` must be greater than zero.");
+        }
+        ShardedCache {
+            shards: (0..shards).map(|_| Mutex::new(create())).collect(),
+            hash_builder: SeedableState::new(),
+        }
+    }
+
+    /// Return the shard holding `key`
+    pub fn shard<K: Hash + ?Sized>(&self, key: &K) -> &Mutex<C> {
+        &self.shards[self.index(key)]
+    }
+
+    /// Return all the shards
+    pub fn shards(&self) -> &[Mutex<C>] {
+        &self.shards
+    }
+
+    fn index<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        (self.hash_builder.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    /// Return the store of the shard holding `key`, without locking it
+    fn shard_mut<K: Hash + ?Sized>(&mut self, key: &K) -> &mut C {
+        let index = self.index(key);
+        self.shards[index]
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sum `f` over every shard, `None` if any shard returns `None`
+    fn sum<T: std::iter::Sum<T>>(&self, f: impl Fn(&C) -> Option<T>) -> Option<T> {
+        self.shards
+            .iter()
+            .map(|shard| f(&shard.lock().unwrap_or_else(PoisonError::into_inner)))
+            .sum()
+    }
+}
+
+impl<K: Hash, V, C: Cached<K, V>> Cached<K, V> for ShardedCache<C> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard_mut(key).cache_get(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard_mut(key).cache_get_mut(key)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.shard_mut(&key).cache_set(key, val)
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        self.shard_mut(&key)
+            .cache_set_with_lifespan(key, val, seconds)
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        self.shard_mut(&key).cache_insert_if_absent(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        self.shard_mut(&key).cache_get_or_set_with(key, f)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard_mut(key).cache_remove(key)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_remove_where(&mut f)
+            })
+            .sum()
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_retain(&mut f)
+            })
+            .sum()
+    }
+
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_flush_expired()
+            })
+            .sum()
+    }
+
+    fn cache_clear(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .cache_clear();
+        }
+    }
+
+    fn cache_reset(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .cache_reset();
+        }
+    }
+
+    fn cache_size(&self) -> usize {
+        self.sum(|shard| Some(shard.cache_size())).unwrap_or(0)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.sum(C::cache_hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.sum(C::cache_misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.sum(C::cache_capacity)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.shards[0]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_lifespan()
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_set_lifespan(seconds)
+            })
+            .fold(None, |old, shard_old| old.or(shard_old))
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        self.shards
+            .iter_mut()
+            .map(|shard| {
+                shard
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cache_unset_lifespan()
+            })
+            .fold(None, |old, shard_old| old.or(shard_old))
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::super::SizedCache;
+    use super::Cached;
+    use super::ShardedCache;
+
+    #[test]
+    fn sharded_cache() {
+        let mut c = ShardedCache::new(4, || SizedCache::with_size(10));
+        assert_eq!(4, c.shards().len());
+        assert!(c.cache_get(&1).is_none());
+        for i in 0..8 {
+            assert_eq!(c.cache_set(i, i * 100), None);
+        }
+        assert_eq!(8, c.cache_size());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(
+            c.shard(&2).lock().unwrap().cache_get_or_set_with(2, || 0),
+            &200
+        );
+        assert_eq!(Some(2), c.cache_hits());
+        assert_eq!(Some(1), c.cache_misses());
+        assert_eq!(Some(40), c.cache_capacity());
+
+        assert_eq!(c.cache_remove(&1), Some(100));
+        assert_eq!(Some(3), c.cache_remove_where(|k| *k >= 5));
+        assert_eq!(Some(1), c.cache_retain(|_, v| *v != 200));
+        assert_eq!(3, c.cache_size());
+        c.cache_clear();
+        assert_eq!(0, c.cache_size());
+    }
+
+    #[test]
+    #[should_panic]
+    fn sharded_cache_zero_shards() {
+        ShardedCache::new(0, || SizedCache::<u32, u32>::with_size(1));
+    }
+}
@@ -0,0 +1,422 @@
+use std::borrow::Borrow;
+use std::cmp::Eq;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+
+use hashbrown::HashTable;
+
+#[cfg(feature = "async")]
+use super::CachedAsync;
+use super::{Cached, ConfigError, LRUList, SeedableState};
+
+#[derive(Clone, Debug)]
+struct SieveEntry<K, V> {
+    key: K,
+    value: V,
+    /// Set on hits, cleared when the hand passes over the entry
+    visited: bool,
+}
+
+/// SIEVE Cache
+///
+/// Stores up to a specified size before beginning to evict entries using the
+/// SIEVE policy: hits only mark an entry as visited instead of moving it in a
+/// recency list, and a hand sweeps from the oldest towards the newest entries,
+/// evicting the first one that wasn't visited since the hand last passed it.
+///
+/// Hits are cheaper than with `SizedCache`, which makes them shorter under a
+/// lock, and values read once, e.g. by a scan, are evicted quickly while
+/// values read again survive:
+///
+/// ```rust
+/// use cached::{Cached, SieveCache};
+///
+/// let mut cache = SieveCache::with_size(2);
+/// cache.cache_set(1, "hot");
+/// cache.cache_set(2, "scanned");
+/// cache.cache_get(&1);
+/// cache.cache_set(3, "scanned");
+/// // 2 is evicted although 1 is older
+/// assert_eq!(cache.cache_get(&1), Some(&"hot"));
+/// assert_eq!(cache.cache_get(&2), None);
+/// ```
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct SieveCache<K, V> {
+    store: HashTable<usize>,
+    hash_builder: SeedableState,
+    order: LRUList<SieveEntry<K, V>>,
+    /// Index of the next eviction candidate, `LRUList::OCCUPIED` to start from the oldest
+    hand: usize,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K, V> PartialEq for SieveCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &SieveCache<K, V>) -> bool {
+        self.store.len() == other.store.len()
+            && self.order.iter().all(|entry| {
+                other
+                    .find(&entry.key)
+                    .is_some_and(|index| other.order.get(index).value == entry.value)
+            })
+    }
+}
+
+impl<K, V> Eq for SieveCache<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+}
+
+impl<K: Hash + Eq, V> SieveCache<K, V> {
+    const START: usize = LRUList::<SieveEntry<K, V>>::OCCUPIED;
+
+    /// Creates a new `SieveCache` with a given size limit and pre-allocated backing data
+    pub fn with_size(size: usize) -> SieveCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `SieveCache` must be greater than zero.")
+        }
+        SieveCache {
+            store: HashTable::with_capacity(size),
+            hash_builder: SeedableState::new(),
+            order: LRUList::with_capacity(size),
+            hand: Self::START,
+            capacity: size,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Like `with_size`, but returns an error instead of panicking if size is 0
+    pub fn try_with_size(size: usize) -> Result<SieveCache<K, V>, ConfigError> {
+        if size == 0 {
+            return Err(ConfigError::ZeroSize);
+        }
+        Ok(Self::with_size(size))
+    }
+
+    /// Return an iterator of keys in insertion order, from newest to oldest
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.order.iter().map(|entry| &entry.key)
+    }
+
+    /// Find the index of `key` in the order list
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.store
+            .find(hash, |&i| self.order.get(i).key.borrow() == key)
+            .copied()
+    }
+
+    /// Mark the entry at `index` as visited, returning it
+    fn visit(&mut self, index: usize) -> &mut SieveEntry<K, V> {
+        let entry = self.order.get_mut(index);
+        entry.visited = true;
+        entry
+    }
+
+    /// Remove the entry at `index` from the order list, moving the hand off it
+    fn unlink(&mut self, index: usize) -> SieveEntry<K, V> {
+        if self.hand == index {
+            self.hand = self.order.prev(index);
+        }
+        self.order.remove(index)
+    }
+
+    /// Remove the entry at `index` from the lookup table and the order list
+    fn remove_at(&mut self, index: usize) -> SieveEntry<K, V> {
+        let hash = self.hash_builder.hash_one(&self.order.get(index).key);
+        self.store
+            .find_entry(hash, |&i| i == index)
+            .expect("SieveCache order out of sync with store")
+            .remove();
+        self.unlink(index)
+    }
+
+    fn check_capacity(&mut self) {
+        // store has reached capacity, sweep the hand towards the newest entries
+        // until it finds one that wasn't visited, clearing the marks it passes.
+        // store capacity cannot be zero, so there must be entries to sweep.
+        if self.store.len() < self.capacity {
+            return;
+        }
+        let mut index = self.hand;
+        loop {
+            if index == Self::START {
+                index = self.order.back();
+            }
+            let entry = self.order.get_mut(index);
+            if !entry.visited {
+                break;
+            }
+            entry.visited = false;
+            index = self.order.prev(index);
+        }
+        self.hand = index;
+        self.remove_at(index);
+    }
+
+    /// Push a new entry to the front of the order list and index it
+    fn insert_new(&mut self, key: K, value: V) -> usize {
+        self.check_capacity();
+        let hash = self.hash_builder.hash_one(&key);
+        let Self {
+            store,
+            hash_builder,
+            order,
+            ..
+        } = self;
+        let index = order.push_front(SieveEntry {
+            key,
+            value,
+            visited: false,
+        });
+        store.insert_unique(hash, index, |&i| hash_builder.hash_one(&order.get(i).key));
+        index
+    }
+}
+
+impl<K: Hash + Eq, V> Cached<K, V> for SieveCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache_get_mut(key).map(|value| &*value)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|index| &self.order.get(index).value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|index| {
+            let entry = self.order.get(index);
+            (&entry.key, &entry.value)
+        })
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
+                self.hits += 1;
+                Some(&mut self.visit(index).value)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        match self.find(&key) {
+            Some(index) => Some(std::mem::replace(&mut self.visit(index).value, val)),
+            None => {
+                self.insert_new(key, val);
+                None
+            }
+        }
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        if self.find(&key).is_some() {
+            return false;
+        }
+        self.insert_new(key, val);
+        true
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let index = match self.find(&key) {
+            Some(index) => {
+                self.hits += 1;
+                self.visit(index);
+                index
+            }
+            None => {
+                self.misses += 1;
+                self.insert_new(key, f())
+            }
+        };
+        &mut self.order.get_mut(index).value
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(k)?;
+        Some(self.remove_at(index).value)
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let Self {
+            store, order, hand, ..
+        } = self;
+        let before = store.len();
+        store.retain(|&mut index| {
+            if !f(&order.get(index).key) {
+                return true;
+            }
+            if *hand == index {
+                *hand = order.prev(index);
+            }
+            order.remove(index);
+            false
+        });
+        Some(before - store.len())
+    }
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let Self {
+            store, order, hand, ..
+        } = self;
+        let before = store.len();
+        store.retain(|&mut index| {
+            let entry = order.get(index);
+            if f(&entry.key, &entry.value) {
+                return true;
+            }
+            if *hand == index {
+                *hand = order.prev(index);
+            }
+            order.remove(index);
+            false
+        });
+        Some(before - store.len())
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.order.clear();
+        self.hand = Self::START;
+    }
+    fn cache_reset(&mut self) {
+        // SieveCache uses cache_clear because capacity is fixed.
+        self.cache_clear();
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V> SieveCache<K, V> {
+    /// Get or insert a key, value pair, awaiting the future returned by `f` on a miss
+    pub async fn cache_get_or_set_with_async<F, Fut>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        CachedAsync::get_or_set_with(self, key, f).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Hash + Eq, V> CachedAsync<K, V> for SieveCache<K, V> {
+    async fn try_get_or_set_with<'a, F, Fut, E>(&'a mut self, key: K, f: F) -> Result<&'a mut V, E>
+    where
+        V: 'a,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let index = match self.find(&key) {
+            Some(index) => {
+                self.hits += 1;
+                self.visit(index);
+                index
+            }
+            None => {
+                self.misses += 1;
+                let value = f().await?;
+                self.insert_new(key, value)
+            }
+        };
+        Ok(&mut self.order.get_mut(index).value)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::SieveCache;
+
+    #[test]
+    fn sieve_cache() {
+        let mut c = SieveCache::with_size(3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&3), Some(&300));
+
+        // the hand skips 1 and evicts 2, the oldest unvisited entry
+        assert_eq!(c.cache_set(4, 400), None);
+        assert!(c.cache_peek(&2).is_none());
+        assert_eq!(vec![&4, &3, &1], c.key_order().collect::<Vec<_>>());
+        // 3 lost its mark as the hand passed it, the new 4 wasn't visited
+        assert_eq!(c.cache_get_or_set_with(5, || 500), &500);
+        assert!(c.cache_peek(&4).is_none());
+        assert_eq!(vec![&5, &3, &1], c.key_order().collect::<Vec<_>>());
+        assert_eq!(Some(2), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+
+        // the hand wrapped around to 1, whose mark was cleared too
+        assert_eq!(c.cache_set(6, 600), None);
+        assert!(c.cache_peek(&1).is_none());
+        // removing the entry under the hand moves it along
+        assert_eq!(c.cache_remove(&3), Some(300));
+        assert_eq!(c.cache_set(7, 700), None);
+        assert_eq!(c.cache_get(&6), Some(&600));
+        assert_eq!(c.cache_set(8, 800), None);
+        assert_eq!(vec![&8, &7, &6], c.key_order().collect::<Vec<_>>());
+        assert!(!c.cache_insert_if_absent(8, 801));
+        assert_eq!(Some(2), c.cache_remove_where(|k| *k > 6));
+        assert_eq!(vec![&6], c.key_order().collect::<Vec<_>>());
+        assert_eq!(Some(1), c.cache_retain(|_, v| *v != 600));
+        c.cache_clear();
+        assert_eq!(0, c.cache_size());
+        assert_eq!(Some(3), c.cache_capacity());
+    }
+
+    #[test]
+    #[should_panic]
+    fn sieve_cache_zero_size() {
+        SieveCache::<u32, u32>::with_size(0);
+    }
+}
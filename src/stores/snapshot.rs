@@ -0,0 +1,243 @@
+//! `serde` support for the in-memory stores, enabled by the `serde` feature
+//!
+//! Stores are serialized as snapshots of their values and metadata: the recency order
+//! of a `SizedCache`, the age and lifespan of the values of a `TimedCache`, and the hit
+//! and miss counters. Configuration that can't be serialized, like eviction listeners,
+//! hash seeds or a `TimedCache`'s time source, is reset to its default on deserialization,
+//! and keys are hashed with a default hasher.
+
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::time_source::Stamp;
+use super::{SizedCache, TimeSource, TimedCache, TimedEntry, UnboundCache};
+
+#[derive(Serialize)]
+struct UnboundRef<'a, K, V> {
+    entries: Vec<(&'a K, &'a V)>,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Deserialize)]
+struct UnboundSnapshot<K, V> {
+    entries: Vec<(K, V)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Serialize, V: Serialize, H> Serialize for UnboundCache<K, V, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        UnboundRef {
+            entries: self.store.iter().collect(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K, V, H> Deserialize<'de> for UnboundCache<K, V, H>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    H: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = UnboundSnapshot::deserialize(deserializer)?;
+        let mut cache =
+            UnboundCache::with_capacity_and_hasher(snapshot.entries.len(), H::default());
+        cache.store.extend(snapshot.entries);
+        cache.hits = snapshot.hits;
+        cache.misses = snapshot.misses;
+        Ok(cache)
+    }
+}
+
+/// Entries are listed from the most to the least recently used
+#[derive(Serialize)]
+struct SizedRef<'a, K, V> {
+    capacity: usize,
+    entries: Vec<&'a (K, V)>,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Deserialize)]
+struct SizedSnapshot<K, V> {
+    capacity: usize,
+    entries: Vec<(K, V)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Serialize, V: Serialize, H> Serialize for SizedCache<K, V, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SizedRef {
+            capacity: self.capacity,
+            entries: self.order.iter().collect(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K, V, H> Deserialize<'de> for SizedCache<K, V, H>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    H: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = SizedSnapshot::deserialize(deserializer)?;
+        if snapshot.capacity == 0 {
+            return Err(serde::de::Error::custom(
+                "`size` of `SizedCache` must be greater than zero.",
+            ));
+        }
+        let mut cache = SizedCache::with_size_and_hasher(snapshot.capacity, H::default());
+        // insert the least recently used first, so they end up at the back.
+        // the most recently used entries are kept if there are too many.
+        for (key, value) in snapshot.entries.into_iter().take(snapshot.capacity).rev() {
+            super::Cached::cache_set(&mut cache, key, value);
+        }
+        cache.hits = snapshot.hits;
+        cache.misses = snapshot.misses;
+        Ok(cache)
+    }
+}
+
+/// Value of a `TimedCache` along with how long ago it was inserted
+#[derive(Serialize, Deserialize)]
+struct TimedRecord<K, V> {
+    key: K,
+    value: V,
+    age: Duration,
+    lifespan: Option<u64>,
+    pinned: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimedSnapshot<R> {
+    seconds: u64,
+    max_lifespan: Option<u64>,
+    refresh: bool,
+    /// Wall time of the snapshot, to age the values by the time until they're restored
+    saved_at: SystemTime,
+    entries: Vec<R>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Serialize, V: Serialize, H> Serialize for TimedCache<K, V, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let seconds = self.seconds;
+        TimedSnapshot {
+            seconds,
+            max_lifespan: self.max_lifespan,
+            refresh: self.refresh,
+            saved_at: SystemTime::now(),
+            entries: self
+                .store
+                .iter()
+                .filter(|(_, entry)| !entry.is_expired(seconds))
+                .map(|(key, entry)| TimedRecord {
+                    key,
+                    value: &entry.value,
+                    age: entry.stamp.elapsed(),
+                    lifespan: entry.lifespan,
+                    pinned: entry.pinned,
+                })
+                .collect(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K, V, H> Deserialize<'de> for TimedCache<K, V, H>
+where
+    K: Deserialize<'de> + Hash + Eq + Clone,
+    V: Deserialize<'de>,
+    H: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = TimedSnapshot::<TimedRecord<K, V>>::deserialize(deserializer)?;
+        let downtime = snapshot.saved_at.elapsed().unwrap_or_default();
+        let mut cache =
+            TimedCache::new(snapshot.seconds, Some(snapshot.entries.len()), H::default());
+        cache.max_lifespan = snapshot.max_lifespan;
+        cache.refresh = snapshot.refresh;
+        for record in snapshot.entries {
+            let entry = TimedEntry {
+                stamp: Stamp::ago(TimeSource::Monotonic, record.age + downtime),
+                lifespan: record.lifespan,
+                pinned: record.pinned,
+                value: record.value,
+            };
+            // values that expired while the cache was down are dropped
+            if !entry.is_expired(cache.seconds) {
+                cache.store.insert(record.key, entry);
+            }
+        }
+        cache.reschedule();
+        cache.hits = snapshot.hits;
+        cache.misses = snapshot.misses;
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::super::{Cached, SizedCache, TimedCache, UnboundCache};
+
+    #[test]
+    fn unbound_cache_round_trip() {
+        let mut c = UnboundCache::new();
+        c.cache_set(1, "one".to_string());
+        c.cache_get(&1);
+        let json = serde_json::to_string(&c).unwrap();
+        let mut c: UnboundCache<u32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(Some(1), c.cache_hits());
+        assert_eq!(c.cache_get(&1).map(String::as_str), Some("one"));
+    }
+
+    #[test]
+    fn sized_cache_round_trip() {
+        let mut c = SizedCache::with_size(3);
+        for i in 1..=3 {
+            c.cache_set(i, i * 100);
+        }
+        c.cache_get(&1);
+        let json = serde_json::to_string(&c).unwrap();
+        let c: SizedCache<u32, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(vec![&1, &3, &2], c.key_order().collect::<Vec<_>>());
+        assert_eq!(Some(3), c.cache_capacity());
+        assert_eq!(Some(1), c.cache_hits());
+        assert!(serde_json::from_str::<SizedCache<u32, u32>>(
+            r#"{"capacity":0,"entries":[],"hits":0,"misses":0}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn timed_cache_round_trip() {
+        let mut c = TimedCache::with_lifespan(60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        let json = serde_json::to_string(&c).unwrap();
+        let mut c: TimedCache<u32, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(1, c.cache_size());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(Some(60), c.cache_lifespan());
+        // values that expired before they're restored are dropped
+        let json = json.replace(r#""age":{"secs":0"#, r#""age":{"secs":60"#);
+        let c: TimedCache<u32, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(0, c.cache_size());
+    }
+}
@@ -0,0 +1,291 @@
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+use hashbrown::hash_map::Entry;
+use hashbrown::HashMap;
+
+use super::Cached;
+#[cfg(feature = "std")]
+use super::SeedableState;
+
+/// Value of a `TickedCache` along with the tick it was inserted at
+#[derive(Clone, Debug)]
+struct TickedEntry<V> {
+    tick: u64,
+    value: V,
+}
+
+impl<V> TickedEntry<V> {
+    fn is_expired(&self, now: u64, lifespan: u64) -> bool {
+        now - self.tick >= lifespan
+    }
+}
+
+/// Cache store bound by ticks of a clock advanced by the caller
+///
+/// Values expire `lifespan` ticks after they were inserted. The cache doesn't read a
+/// clock, the caller counts time with `tick` or `advance`, e.g. on every timer interrupt
+/// or iteration of a main loop. This makes it usable without `std`, where `TimedCache`
+/// isn't available, and its expiries reproducible in tests.
+///
+/// ```rust
+/// use cached::{Cached, TickedCache};
+///
+/// let mut cache = TickedCache::with_lifespan(2);
+/// cache.cache_set(1, 100);
+/// cache.tick();
+/// assert_eq!(cache.cache_get(&1), Some(&100));
+/// cache.tick();
+/// assert_eq!(cache.cache_get(&1), None);
+/// ```
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct TickedCache<
+    K,
+    V,
+    #[cfg(feature = "std")] S = SeedableState,
+    #[cfg(not(feature = "std"))] S,
+> {
+    store: HashMap<K, TickedEntry<V>, S>,
+    lifespan: u64,
+    now: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[cfg(feature = "std")]
+impl<K: Hash + Eq, V> TickedCache<K, V> {
+    /// Creates a new `TickedCache` whose values expire `ticks` ticks after they're inserted
+    pub fn with_lifespan(ticks: u64) -> TickedCache<K, V> {
+        Self::with_lifespan_and_hasher(ticks, SeedableState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> TickedCache<K, V, S> {
+    /// Creates a new `TickedCache` whose values expire `ticks` ticks after they're
+    /// inserted, hashing keys with `hasher`. Without `std`, this is how a cache is created.
+    pub fn with_lifespan_and_hasher(ticks: u64, hasher: S) -> TickedCache<K, V, S> {
+        TickedCache {
+            store: HashMap::with_hasher(hasher),
+            lifespan: ticks,
+            now: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Advance the cache's clock by one tick
+    pub fn tick(&mut self) {
+        self.advance(1);
+    }
+
+    /// Advance the cache's clock by `ticks` ticks
+    pub fn advance(&mut self, ticks: u64) {
+        self.now = self.now.saturating_add(ticks);
+    }
+
+    /// Return the number of ticks the cache's clock was advanced by
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Return the number of ticks values are kept for
+    pub fn lifespan(&self) -> u64 {
+        self.lifespan
+    }
+
+    /// Set the number of ticks values are kept for, returning the old one.
+    /// Values already in the cache expire by the new lifespan.
+    pub fn set_lifespan(&mut self, ticks: u64) -> u64 {
+        core::mem::replace(&mut self.lifespan, ticks)
+    }
+
+    /// Return an iterator of the unexpired key, value pairs, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let (now, lifespan) = (self.now, self.lifespan);
+        self.store
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(now, lifespan))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Remove the value for `key` if it expired, returning whether there's still one
+    fn live<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.get(key) {
+            Some(entry) if entry.is_expired(self.now, self.lifespan) => {
+                self.store.remove(key);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cached<K, V> for TickedCache<K, V, S> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.live(key) {
+            self.hits += 1;
+            self.store.get(key).map(|entry| &entry.value)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.live(key) {
+            self.hits += 1;
+            self.store.get_mut(key).map(|entry| &mut entry.value)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(key)
+            .filter(|entry| !entry.is_expired(self.now, self.lifespan))
+            .map(|entry| &entry.value)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let entry = TickedEntry {
+            tick: self.now,
+            value: val,
+        };
+        let (now, lifespan) = (self.now, self.lifespan);
+        self.store
+            .insert(key, entry)
+            .filter(|old| !old.is_expired(now, lifespan))
+            .map(|old| old.value)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let (now, lifespan) = (self.now, self.lifespan);
+        match self.store.entry(key) {
+            Entry::Occupied(mut occupied) => {
+                if occupied.get().is_expired(now, lifespan) {
+                    self.misses += 1;
+                    occupied.insert(TickedEntry {
+                        tick: now,
+                        value: f(),
+                    });
+                } else {
+                    self.hits += 1;
+                }
+                &mut occupied.into_mut().value
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                &mut vacant
+                    .insert(TickedEntry {
+                        tick: now,
+                        value: f(),
+                    })
+                    .value
+            }
+        }
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (now, lifespan) = (self.now, self.lifespan);
+        self.store
+            .remove(key)
+            .filter(|entry| !entry.is_expired(now, lifespan))
+            .map(|entry| entry.value)
+    }
+
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        let (now, lifespan) = (self.now, self.lifespan);
+        let before = self.store.len();
+        self.store
+            .retain(|_, entry| !entry.is_expired(now, lifespan));
+        Some(before - self.store.len())
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.store = HashMap::with_hasher(self.store.hasher().clone());
+    }
+
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::TickedCache;
+
+    #[test]
+    fn ticked_cache() {
+        let mut c = TickedCache::with_lifespan(2);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        c.tick();
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_set(2, 200), None);
+        c.tick();
+        assert_eq!(c.now(), 2);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_peek(&2), Some(&200));
+        assert_eq!(c.iter().collect::<Vec<_>>(), [(&2, &200)]);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+
+        c.advance(5);
+        assert_eq!(c.cache_set(2, 201), None);
+        assert_eq!(*c.cache_get_or_set_with(2, || 202), 201);
+        assert_eq!(c.set_lifespan(10), 2);
+        c.advance(9);
+        assert_eq!(c.cache_remove(&2), Some(201));
+    }
+
+    #[test]
+    fn ticked_cache_flush_expired() {
+        let mut c = TickedCache::with_lifespan(1);
+        c.cache_set(1, 100);
+        c.tick();
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_flush_expired(), Some(1));
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(*c.cache_get_or_set_with(1, || 101), 101);
+    }
+}
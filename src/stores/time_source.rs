@@ -0,0 +1,181 @@
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::time::{Duration, Instant, SystemTime};
+
+use once_cell::sync::Lazy;
+
+/// Origin of the monotonic timeline the timing wheels of the std clocks run on
+static ORIGIN: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Clock used by the timed stores to stamp and expire values
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TimeSource {
+    /// `Instant`, unaffected by changes to the system clock. On most platforms it
+    /// doesn't advance while the system is suspended, so values outlive their
+    /// lifespan by the time spent suspended.
+    #[default]
+    Monotonic,
+    /// `SystemTime`, so that values expire at an absolute wall time even across
+    /// suspend and resume. Values are considered expired if the system clock jumps
+    /// past their expiry, and live longer if it's set back.
+    WallClock,
+    /// A clock returning the time elapsed since any fixed point, e.g. for targets
+    /// without `Instant` and `SystemTime` such as `wasm32-unknown-unknown`, or to
+    /// control expiry in tests:
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use cached::{Cached, TimeSource, TimedCache};
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(0);
+    /// fn now() -> Duration {
+    ///     Duration::from_secs(NOW.load(Ordering::SeqCst))
+    /// }
+    ///
+    /// let mut cache = TimedCache::with_lifespan(60).with_time_source(TimeSource::Custom(now));
+    /// cache.cache_set(1, "one");
+    /// NOW.store(60, Ordering::SeqCst);
+    /// assert_eq!(cache.cache_get(&1), None);
+    /// ```
+    ///
+    /// The clock must never go backwards.
+    Custom(fn() -> Duration),
+}
+
+// custom clocks are compared by the address of their function
+impl PartialEq for TimeSource {
+    fn eq(&self, other: &TimeSource) -> bool {
+        match (self, other) {
+            (TimeSource::Custom(a), TimeSource::Custom(b)) => *a as usize == *b as usize,
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for TimeSource {}
+
+impl Hash for TimeSource {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        if let TimeSource::Custom(clock) = self {
+            (*clock as usize).hash(state);
+        }
+    }
+}
+
+impl TimeSource {
+    /// Stamp the current time
+    pub(super) fn now(self) -> Stamp {
+        match self {
+            TimeSource::Monotonic => Stamp::Monotonic(Instant::now()),
+            TimeSource::WallClock => Stamp::WallClock(SystemTime::now()),
+            TimeSource::Custom(clock) => Stamp::Custom(clock(), clock),
+        }
+    }
+
+    /// The current time on the timeline the store's timing wheel runs on
+    pub(super) fn timeline(self) -> Duration {
+        match self {
+            TimeSource::Monotonic | TimeSource::WallClock => ORIGIN.elapsed(),
+            TimeSource::Custom(clock) => clock(),
+        }
+    }
+}
+
+/// Time a value was inserted at, according to its store's `TimeSource`
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Stamp {
+    Monotonic(Instant),
+    WallClock(SystemTime),
+    Custom(Duration, fn() -> Duration),
+}
+
+impl Stamp {
+    /// Time elapsed since the stamp, zero if the system clock was set back before it
+    pub(super) fn elapsed(&self) -> Duration {
+        match self {
+            Stamp::Monotonic(instant) => instant.elapsed(),
+            Stamp::WallClock(time) => time.elapsed().unwrap_or_default(),
+            Stamp::Custom(at, clock) => clock().saturating_sub(*at),
+        }
+    }
+
+    /// The current time on the timeline of the stamp's source, see `TimeSource::timeline`
+    pub(super) fn timeline(&self) -> Duration {
+        match self {
+            Stamp::Monotonic(_) | Stamp::WallClock(_) => ORIGIN.elapsed(),
+            Stamp::Custom(_, clock) => clock(),
+        }
+    }
+
+    /// Deadline on the timeline of the stamp's source for a lifespan of `seconds`
+    /// from the stamp, `None` if it's too far out to be represented
+    pub(super) fn deadline(&self, seconds: u64) -> Option<Duration> {
+        let lifespan = Duration::from_secs(seconds);
+        self.timeline()
+            .checked_add(lifespan.saturating_sub(self.elapsed()))
+    }
+
+    /// The same point in time according to `source`
+    pub(super) fn to_source(self, source: TimeSource) -> Stamp {
+        Stamp::ago(source, self.elapsed())
+    }
+
+    /// A stamp `elapsed` before now according to `source`
+    pub(super) fn ago(source: TimeSource, elapsed: Duration) -> Stamp {
+        match source {
+            TimeSource::Monotonic => Stamp::Monotonic(
+                Instant::now()
+                    .checked_sub(elapsed)
+                    .unwrap_or_else(Instant::now),
+            ),
+            TimeSource::WallClock => Stamp::WallClock(
+                SystemTime::now()
+                    .checked_sub(elapsed)
+                    .unwrap_or(SystemTime::UNIX_EPOCH),
+            ),
+            TimeSource::Custom(clock) => Stamp::Custom(clock().saturating_sub(elapsed), clock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    use super::{Stamp, TimeSource};
+
+    #[test]
+    fn wall_clock_stamps() {
+        let stamp = Stamp::WallClock(SystemTime::now() - Duration::from_secs(30));
+        assert!(stamp.elapsed() >= Duration::from_secs(30));
+        let monotonic = stamp.to_source(TimeSource::Monotonic);
+        assert!(monotonic.elapsed() >= Duration::from_secs(30));
+
+        // set back, not elapsed at all
+        let stamp = Stamp::WallClock(SystemTime::now() + Duration::from_secs(30));
+        assert_eq!(Duration::from_secs(0), stamp.elapsed());
+    }
+
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    fn now() -> Duration {
+        Duration::from_secs(NOW.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn custom_stamps() {
+        let source = TimeSource::Custom(now);
+        assert_eq!(source, TimeSource::Custom(now));
+        assert_ne!(source, TimeSource::Monotonic);
+        let stamp = source.now();
+        assert_eq!(Some(Duration::from_secs(110)), stamp.deadline(10));
+        NOW.store(104, Ordering::SeqCst);
+        assert_eq!(Duration::from_secs(4), stamp.elapsed());
+        assert_eq!(Some(Duration::from_secs(110)), stamp.deadline(10));
+        let monotonic = stamp.to_source(TimeSource::Monotonic);
+        assert!(monotonic.elapsed() >= Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,491 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Duration;
+
+use super::{
+    schedule_expiry, Cached, ConfigError, EvictionReason, SizedCache, TimeSource, TimedEntry,
+    TimingWheel,
+};
+
+/// Cache store bound by both size and time
+///
+/// Values are evicted in least recently used order once the cache is full, like a
+/// `SizedCache`, and are treated as missing once their lifespan passed, like a
+/// `TimedCache`:
+///
+/// ```rust
+/// use cached::{Cached, TimedSizedCache};
+///
+/// let mut cache = TimedSizedCache::with_size_and_lifespan(100, 60);
+/// cache.cache_set(1, "one");
+/// assert_eq!(cache.cache_get(&1), Some(&"one"));
+/// ```
+///
+/// Expired values are removed when they're accessed, evicted as the least recently used
+/// ones, or purged as new values are inserted, so they count towards `cache_size` until
+/// then. Values are stamped and expired with the store's `TimeSource`, like a `TimedCache`.
+#[derive(Clone, Debug)]
+pub struct TimedSizedCache<K, V> {
+    store: SizedCache<K, TimedEntry<V>>,
+    expiry: TimingWheel<K>,
+    seconds: u64,
+    hits: u64,
+    misses: u64,
+    time_source: TimeSource,
+}
+
+impl<K: Hash + Eq, V> TimedSizedCache<K, V> {
+    /// Creates a new `TimedSizedCache` holding at most `size` values,
+    /// each for `seconds`
+    ///
+    /// Will panic if size is 0
+    pub fn with_size_and_lifespan(size: usize, seconds: u64) -> TimedSizedCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `TimedSizedCache` must be greater than zero.");
+        }
+        TimedSizedCache {
+            store: SizedCache::with_size(size),
+            expiry: TimingWheel::new(Duration::from_secs(1)),
+            seconds,
+            hits: 0,
+            misses: 0,
+            time_source: TimeSource::Monotonic,
+        }
+    }
+
+    /// Like `with_size_and_lifespan`, but returns an error instead of panicking if size
+    /// is 0, or if seconds is 0 and values would expire right away
+    pub fn try_with_size_and_lifespan(
+        size: usize,
+        seconds: u64,
+    ) -> Result<TimedSizedCache<K, V>, ConfigError> {
+        if size == 0 {
+            return Err(ConfigError::ZeroSize);
+        }
+        if seconds == 0 {
+            return Err(ConfigError::ZeroLifespan);
+        }
+        Ok(Self::with_size_and_lifespan(size, seconds))
+    }
+
+    /// Return how long the value cached for `key` has left before it expires, `None` if
+    /// there's no such value or it expired. The recency order isn't updated.
+    pub fn cache_ttl_remaining<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_peek(key)?.remaining(self.seconds)
+    }
+
+    /// Return an iterator of the unexpired key, value pairs in the current order
+    /// from most to least recently used
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let seconds = self.seconds;
+        self.store
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(seconds))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Return an iterator of the keys of unexpired values, from most to least recently used
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Return an iterator of the unexpired values, from most to least recently used
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Return the clock used to stamp and expire values
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Remove the value cached for `key` if it expired, returning whether
+    /// an unexpired value is left
+    fn check<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.store.cache_peek(key) {
+            Some(entry) if entry.is_expired(self.seconds) => {
+                self.store.cache_remove(key);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
+    /// Stamp and expire values using `source`, see `TimeSource`.
+    /// Values already in the cache keep their age.
+    pub fn with_time_source(mut self, source: TimeSource) -> TimedSizedCache<K, V> {
+        self.time_source = source;
+        self.store
+            .for_each_value_mut(|entry| entry.stamp = entry.stamp.to_source(source));
+        // the new source's clock is on another timeline
+        self.expiry = TimingWheel::new(Duration::from_secs(1));
+        self.reschedule();
+        self
+    }
+
+    fn insert(&mut self, key: K, value: V, lifespan: Option<u64>) -> Option<V> {
+        self.purge_expired();
+        let entry = self.new_entry(&key, value, lifespan);
+        self.store.cache_set(key, entry).map(|old| old.value)
+    }
+
+    /// Stamp a new value, scheduling its expiry
+    fn new_entry(&mut self, key: &K, value: V, lifespan: Option<u64>) -> TimedEntry<V> {
+        let stamp = self.time_source.now();
+        schedule_expiry(
+            &mut self.expiry,
+            key.clone(),
+            stamp,
+            lifespan.unwrap_or(self.seconds),
+        );
+        TimedEntry {
+            stamp,
+            lifespan,
+            pinned: false,
+            value,
+        }
+    }
+
+    /// Remove the values that expired since the last purge.
+    /// Keys are scheduled on every insert, so a key popping out of the wheel
+    /// may have been re-inserted or removed since and is checked before removal.
+    fn purge_expired(&mut self) {
+        if self.expiry.len() == 0 {
+            return;
+        }
+        let seconds = self.seconds;
+        for key in self.expiry.advance(self.time_source.timeline()) {
+            if self
+                .store
+                .cache_peek(&key)
+                .is_some_and(|entry| entry.is_expired(seconds))
+            {
+                self.store.cache_remove(&key);
+            }
+        }
+    }
+
+    /// Reschedule the expiry of every value after lifespans changed
+    fn reschedule(&mut self) {
+        self.expiry.clear();
+        for (key, entry) in self.store.iter() {
+            schedule_expiry(
+                &mut self.expiry,
+                key.clone(),
+                entry.stamp,
+                entry.lifespan(self.seconds),
+            );
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.check(key) {
+            self.hits += 1;
+            self.store.cache_get(key).map(|entry| &entry.value)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_peek(key)
+            .filter(|entry| !entry.is_expired(self.seconds))
+            .map(|entry| &entry.value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_peek_key_value(key)
+            .filter(|(_, entry)| !entry.is_expired(self.seconds))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    fn cache_pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.check(key) && self.store.cache_pin(key)
+    }
+
+    fn cache_unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_unpin(key)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.check(key) {
+            self.hits += 1;
+            self.store.cache_get_mut(key).map(|entry| &mut entry.value)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.insert(key, val, None)
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        self.insert(key, val, Some(seconds))
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        if self.check(&key) {
+            self.hits += 1;
+            return &mut self.store.cache_get_mut(&key).unwrap().value;
+        }
+        self.misses += 1;
+        self.purge_expired();
+        let entry = self.new_entry(&key, f(), None);
+        let entry = self.store.cache_get_or_set_with(key, || entry);
+        &mut entry.value
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let seconds = self.seconds;
+        self.store
+            .cache_remove(key)
+            .filter(|entry| !entry.is_expired(seconds))
+            .map(|entry| entry.value)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, f: F) -> Option<usize> {
+        self.store.cache_remove_where(f)
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        self.store.cache_retain(|key, entry| f(key, &entry.value))
+    }
+
+    fn cache_flush_expired(&mut self) -> Option<usize> {
+        let seconds = self.seconds;
+        Some(self.store.remove_entries_where(
+            |_, entry| entry.is_expired(seconds),
+            EvictionReason::Expired,
+        ))
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.cache_clear();
+        self.expiry.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.store.cache_reset();
+        self.expiry.clear();
+    }
+
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.store.cache_capacity()
+    }
+
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        self.store.cache_set_capacity(size)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        super::lifespan(self.seconds)
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.cache_lifespan();
+        self.seconds = seconds;
+        self.reschedule();
+        old
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        self.cache_set_lifespan(super::UNSET_LIFESPAN)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::Cached;
+    use super::{TimeSource, TimedSizedCache};
+
+    #[test]
+    fn timed_sized_cache() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(2, 2);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        // 2 is the least recently used
+        c.cache_set(3, 300);
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(2, c.cache_size());
+        assert_eq!(Some(1), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+
+        c.cache_set_with_lifespan(4, 400, 4);
+        sleep(Duration::from_secs(2));
+        assert!(c.cache_get(&3).is_none());
+        assert_eq!(c.cache_peek(&4), Some(&400));
+        assert_eq!(c.cache_get_or_set_with(3, || 301), &301);
+        assert_eq!(Some(2), c.cache_capacity());
+        assert_eq!(Some(2), c.cache_set_lifespan(1));
+        sleep(Duration::from_secs(1));
+        assert!(c.cache_remove(&3).is_none());
+        assert_eq!(c.cache_remove(&4), Some(400));
+    }
+
+    #[test]
+    fn timed_sized_cache_iter() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        c.cache_set(3, 300);
+        assert_eq!(c.iter().collect::<Vec<_>>(), [(&3, &300), (&1, &100)]);
+        assert_eq!(c.keys().collect::<Vec<_>>(), [&3, &1]);
+        assert_eq!(c.values().collect::<Vec<_>>(), [&300, &100]);
+    }
+
+    #[test]
+    fn timed_sized_cache_ttl_remaining() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(2, 60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        let remaining = c.cache_ttl_remaining(&1).unwrap();
+        assert!(remaining > Duration::from_secs(59) && remaining <= Duration::from_secs(60));
+        assert_eq!(None, c.cache_ttl_remaining(&2));
+        assert_eq!(None, c.cache_ttl_remaining(&3));
+    }
+
+    #[test]
+    fn timed_sized_cache_unset_lifespan() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(2, 1);
+        c.cache_set(1, 100);
+        assert_eq!(Some(1), c.cache_unset_lifespan());
+        assert_eq!(None, c.cache_lifespan());
+        sleep(Duration::new(1, 0));
+        assert_eq!(Some(&100), c.cache_get(&1));
+        assert!(c.cache_ttl_remaining(&1).is_some());
+        assert_eq!(None, c.cache_set_lifespan(1));
+        sleep(Duration::new(1, 0));
+        assert_eq!(None, c.cache_get(&1));
+    }
+
+    #[test]
+    fn timed_sized_cache_flush_expired() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        assert_eq!(2, c.cache_size());
+        assert_eq!(Some(1), c.cache_flush_expired());
+        assert_eq!(1, c.cache_size());
+        assert_eq!(Some(0), c.cache_flush_expired());
+    }
+
+    #[test]
+    fn timed_sized_cache_pinned() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(2, 60);
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 0);
+        assert!(c.cache_pin(&1));
+        // expired values can't be pinned
+        assert!(!c.cache_pin(&2));
+        c.cache_set(3, 300);
+        c.cache_set(4, 400);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert!(c.cache_peek(&3).is_none());
+
+        assert!(c.cache_unpin(&1));
+        assert!(!c.cache_unpin(&1));
+
+        // pinned values still expire
+        assert!(c.cache_pin(&1));
+        c.cache_set_with_lifespan(1, 101, 0);
+        assert!(c.cache_get(&1).is_none());
+    }
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    fn now() -> Duration {
+        Duration::from_secs(NOW.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn timed_sized_cache_time_source() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 60)
+            .with_time_source(TimeSource::Custom(now));
+        assert_eq!(TimeSource::Custom(now), c.time_source());
+        c.cache_set(1, 100);
+        c.cache_set_with_lifespan(2, 200, 120);
+        NOW.store(60, Ordering::SeqCst);
+        assert_eq!(c.cache_peek(&1), None);
+        assert_eq!(c.cache_peek(&2), Some(&200));
+        // inserting purges the expired value
+        c.cache_set(3, 300);
+        assert_eq!(2, c.cache_size());
+        assert_eq!(c.cache_get(&2), Some(&200));
+
+        // resetting keeps the counters
+        c.cache_reset();
+        assert_eq!(0, c.cache_size());
+        assert_eq!(Some(1), c.cache_hits());
+    }
+
+    #[test]
+    #[should_panic]
+    fn timed_sized_cache_zero_size() {
+        TimedSizedCache::<u32, u32>::with_size_and_lifespan(0, 1);
+    }
+}
@@ -0,0 +1,239 @@
+/*!
+Hierarchical timing wheel used by the timed stores to schedule expiry
+
+Items are scheduled at a deadline and handed back once the wheel is advanced
+past it. Scheduling and expiring an item are both O(1) (amortized over the
+cascades between levels), so a store can purge everything that expired since
+its last write without scanning all of its entries.
+
+Time is measured as a `Duration` on the clock of the store's `TimeSource`, the
+wheel's origin is the first time it's given, so creating one doesn't read a clock.
+
+The wheel doesn't support cancellation: stores schedule their keys on every
+write and double check that an expired key wasn't written again (or removed)
+since it was scheduled.
+*/
+
+use std::time::Duration;
+
+/// Number of bits of a tick consumed by each level
+const LEVEL_BITS: u32 = 6;
+/// Number of slots in each level
+const SLOTS: usize = 1 << LEVEL_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+/// Number of levels, spanning `64^6` ticks (over 2000 years of 1 second ticks).
+/// Deadlines further out are clamped to the end of the wheel, so their items fire
+/// early: stores find their values unexpired then and leave them to expire when
+/// they're next accessed.
+const LEVELS: usize = 6;
+
+#[derive(Clone, Debug)]
+struct Level<T> {
+    len: usize,
+    slots: Vec<Vec<(u64, T)>>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct TimingWheel<T> {
+    /// Set the first time the wheel is given a time
+    origin: Option<Duration>,
+    tick: Duration,
+    /// The next tick to be processed
+    current: u64,
+    len: usize,
+    /// Levels are allocated as they're needed, most stores only ever use the first one
+    levels: Vec<Level<T>>,
+}
+
+impl<T> TimingWheel<T> {
+    /// Creates an empty wheel with a given tick resolution
+    pub(crate) fn new(tick: Duration) -> TimingWheel<T> {
+        assert!(
+            tick > Duration::new(0, 0),
+            "timing wheel tick must be non-zero"
+        );
+        TimingWheel {
+            origin: None,
+            tick,
+            current: 0,
+            len: 0,
+            levels: Vec::new(),
+        }
+    }
+
+    /// Number of items waiting to expire
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Schedule `item` to be expired once the wheel is advanced past `deadline`, `now`
+    /// is the current time
+    pub(crate) fn schedule(&mut self, item: T, deadline: Duration, now: Duration) {
+        let origin = *self.origin.get_or_insert(now);
+        // round up so that items never fire before their deadline
+        let elapsed = deadline.saturating_sub(origin).as_nanos();
+        let tick = self.tick.as_nanos();
+        let deadline = elapsed.div_ceil(tick).min(u128::from(u64::MAX)) as u64;
+        self.insert(deadline, item);
+        self.len += 1;
+    }
+
+    /// Advance the wheel to `now`, returning all the items whose deadline has passed
+    pub(crate) fn advance(&mut self, now: Duration) -> Vec<T> {
+        self.origin.get_or_insert(now);
+        let target = self.tick_of(now);
+        let mut expired = Vec::new();
+        while self.current <= target {
+            if self.len == 0 {
+                // nothing left to expire, skip straight to the target
+                self.current = target + 1;
+                break;
+            }
+            if self.current & SLOT_MASK == 0 {
+                self.cascade();
+            }
+            match self.levels.first_mut() {
+                Some(level) if level.len > 0 => {
+                    let slot = &mut level.slots[(self.current & SLOT_MASK) as usize];
+                    level.len -= slot.len();
+                    self.len -= slot.len();
+                    expired.extend(slot.drain(..).map(|(_, item)| item));
+                    self.current += 1;
+                }
+                // nothing in the first level, skip to where the next level cascades
+                _ => self.current = ((self.current | SLOT_MASK) + 1).min(target + 1),
+            }
+        }
+        expired
+    }
+
+    /// Drop all scheduled items
+    pub(crate) fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            level.slots.iter_mut().for_each(Vec::clear);
+            level.len = 0;
+        }
+        self.len = 0;
+    }
+
+    fn tick_of(&self, time: Duration) -> u64 {
+        let elapsed = time
+            .saturating_sub(self.origin.unwrap_or_default())
+            .as_nanos();
+        (elapsed / self.tick.as_nanos()).min(u128::from(u64::MAX - 1)) as u64
+    }
+
+    /// Place an item in the level covering the distance between `current` and `deadline`
+    fn insert(&mut self, deadline: u64, item: T) {
+        let max_deadline = self
+            .current
+            .saturating_add((1 << (LEVEL_BITS * LEVELS as u32)) - 1);
+        // past due items go in the current slot to be expired on the next tick
+        let deadline = deadline.clamp(self.current, max_deadline);
+        let level = match deadline ^ self.current {
+            0 => 0,
+            diff => ((63 - diff.leading_zeros()) / LEVEL_BITS) as usize,
+        }
+        .min(LEVELS - 1);
+        while self.levels.len() <= level {
+            self.levels.push(Level {
+                len: 0,
+                slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            });
+        }
+        let slot = (deadline >> (LEVEL_BITS * level as u32)) & SLOT_MASK;
+        let level = &mut self.levels[level];
+        level.slots[slot as usize].push((deadline, item));
+        level.len += 1;
+    }
+
+    /// Move items from the higher levels down now that `current` entered their range
+    fn cascade(&mut self) {
+        for level in 1..self.levels.len() {
+            let slot = (self.current >> (LEVEL_BITS * level as u32)) & SLOT_MASK;
+            let items = std::mem::take(&mut self.levels[level].slots[slot as usize]);
+            self.levels[level].len -= items.len();
+            for (deadline, item) in items {
+                self.insert(deadline, item);
+            }
+            if slot != 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::TimingWheel;
+
+    #[test]
+    fn expires_in_deadline_order() {
+        let mut wheel = TimingWheel::new(Duration::from_secs(1));
+        let origin = Duration::from_secs(1000);
+        wheel.advance(origin);
+        let secs = Duration::from_secs;
+
+        wheel.schedule("a", origin + secs(1), origin);
+        wheel.schedule("b", origin + secs(70), origin);
+        wheel.schedule("c", origin + secs(5000), origin);
+        wheel.schedule("d", origin + secs(5), origin);
+        assert_eq!(4, wheel.len());
+
+        assert!(wheel.advance(origin).is_empty());
+        assert_eq!(wheel.advance(origin + secs(1)), vec!["a"]);
+        assert!(wheel.advance(origin + secs(4)).is_empty());
+        assert_eq!(wheel.advance(origin + secs(69)), vec!["d"]);
+        assert_eq!(wheel.advance(origin + secs(70)), vec!["b"]);
+        assert!(wheel.advance(origin + secs(4999)).is_empty());
+        assert_eq!(wheel.advance(origin + secs(6000)), vec!["c"]);
+        assert_eq!(0, wheel.len());
+    }
+
+    #[test]
+    fn rounds_up_and_handles_past_deadlines() {
+        let mut wheel = TimingWheel::new(Duration::from_secs(1));
+        let origin = Duration::from_secs(1000);
+        wheel.advance(origin);
+
+        wheel.schedule(1, origin + Duration::from_millis(1500), origin);
+        assert!(wheel
+            .advance(origin + Duration::from_millis(1900))
+            .is_empty());
+        assert_eq!(wheel.advance(origin + Duration::from_secs(2)), vec![1]);
+
+        // scheduling behind the wheel's position expires on the next tick
+        wheel.schedule(2, origin, origin);
+        assert!(wheel.advance(origin + Duration::from_secs(2)).is_empty());
+        assert_eq!(wheel.advance(origin + Duration::from_secs(3)), vec![2]);
+    }
+
+    #[test]
+    fn expires_across_every_level() {
+        let mut wheel = TimingWheel::new(Duration::from_secs(1));
+        let origin = Duration::from_secs(1000);
+        wheel.advance(origin);
+
+        let deadlines = [3u64, 64, 65, 4095, 4096, 4097, 300_000, 20_000_000];
+        for &deadline in deadlines.iter().rev() {
+            wheel.schedule(deadline, origin + Duration::from_secs(deadline), origin);
+        }
+        let mut expired = Vec::new();
+        for &deadline in deadlines.iter() {
+            assert!(wheel
+                .advance(origin + Duration::from_secs(deadline - 1))
+                .is_empty());
+            expired.extend(wheel.advance(origin + Duration::from_secs(deadline)));
+        }
+        assert_eq!(expired, deadlines);
+
+        wheel.schedule(1, origin + Duration::from_secs(30_000_000), origin);
+        wheel.clear();
+        assert_eq!(0, wheel.len());
+        assert!(wheel
+            .advance(origin + Duration::from_secs(40_000_000))
+            .is_empty());
+    }
+}
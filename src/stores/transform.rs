@@ -0,0 +1,202 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::Cached;
+
+type Encode<V, E> = dyn Fn(V) -> E + Send + Sync;
+type Decode<V, E> = dyn Fn(&E) -> V + Send + Sync;
+
+/// Wrapper encoding values as they're written to a store and decoding them as they're read
+///
+/// The inner store holds the encoded values, e.g. values compressed once they're over
+/// some size, or with fields that aren't needed stripped, bounding the memory used
+/// without moving values out of memory:
+///
+/// ```rust
+/// use cached::{Cached, SizedCache, TransformCache};
+///
+/// // keep only the first line of each value
+/// let mut cache = TransformCache::new(
+///     SizedCache::with_size(100),
+///     |text: String| text.lines().next().unwrap_or_default().to_string().into_boxed_str(),
+///     |line: &Box<str>| line.to_string(),
+/// );
+/// cache.cache_set(1, "summary\nlong details".to_string());
+/// assert_eq!(cache.cache_get(&1), Some(&"summary".to_string()));
+/// ```
+///
+/// Reads return a reference to the value last decoded, which the wrapper holds on to.
+/// Changes made through `cache_get_mut` are therefore not written back to the store,
+/// use `cache_set` instead.
+pub struct TransformCache<K, V, E, C> {
+    inner: C,
+    encode: Arc<Encode<V, E>>,
+    decode: Arc<Decode<V, E>>,
+    scratch: Option<V>,
+    _marker: PhantomData<fn(&K)>,
+}
+
+impl<K, V, E, C> TransformCache<K, V, E, C> {
+    /// Creates a wrapper around `inner`, which should be empty, storing values as
+    /// `encode` returns them and reading them back with `decode`
+    pub fn new<F, G>(inner: C, encode: F, decode: G) -> TransformCache<K, V, E, C>
+    where
+        F: Fn(V) -> E + Send + Sync + 'static,
+        G: Fn(&E) -> V + Send + Sync + 'static,
+    {
+        TransformCache {
+            inner,
+            encode: Arc::new(encode),
+            decode: Arc::new(decode),
+            scratch: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Return the inner store, holding encoded values
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Return the inner store mutably, holding encoded values
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<K, V, E, C: Clone> Clone for TransformCache<K, V, E, C> {
+    fn clone(&self) -> Self {
+        TransformCache {
+            inner: self.inner.clone(),
+            encode: self.encode.clone(),
+            decode: self.decode.clone(),
+            scratch: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, E, C: fmt::Debug> fmt::Debug for TransformCache<K, V, E, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransformCache")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<K, V, E, C: Cached<K, E>> Cached<K, V> for TransformCache<K, V, E, C> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let value = (self.decode)(self.inner.cache_get(key)?);
+        Some(self.scratch.insert(value))
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let value = (self.decode)(self.inner.cache_get(key)?);
+        Some(self.scratch.insert(value))
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.inner.cache_set(key, (self.encode)(val))?;
+        Some((self.decode)(&old))
+    }
+
+    fn cache_set_with_lifespan(&mut self, key: K, val: V, seconds: u64) -> Option<V> {
+        let old = self
+            .inner
+            .cache_set_with_lifespan(key, (self.encode)(val), seconds)?;
+        Some((self.decode)(&old))
+    }
+
+    fn cache_insert_if_absent(&mut self, key: K, val: V) -> bool {
+        self.inner.cache_insert_if_absent(key, (self.encode)(val))
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let encode = &self.encode;
+        let value = (self.decode)(self.inner.cache_get_or_set_with(key, || encode(f())));
+        self.scratch.insert(value)
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let old = self.inner.cache_remove(key)?;
+        Some((self.decode)(&old))
+    }
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, f: F) -> Option<usize> {
+        self.inner.cache_remove_where(f)
+    }
+    fn cache_clear(&mut self) {
+        self.inner.cache_clear();
+        self.scratch = None;
+    }
+    fn cache_reset(&mut self) {
+        self.inner.cache_reset();
+        self.scratch = None;
+    }
+    fn cache_size(&self) -> usize {
+        self.inner.cache_size()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+    fn cache_set_capacity(&mut self, size: usize) -> Option<usize> {
+        self.inner.cache_set_capacity(size)
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+    fn cache_unset_lifespan(&mut self) -> Option<u64> {
+        self.inner.cache_unset_lifespan()
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::Cached;
+    use super::TransformCache;
+    use crate::stores::UnboundCache;
+
+    #[test]
+    fn transform_cache() {
+        let mut c = TransformCache::new(
+            UnboundCache::new(),
+            |n: u32| n.to_string(),
+            |s: &String| s.parse().unwrap(),
+        );
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(1, 101), Some(100));
+        assert_eq!(c.inner().cache_peek(&1), Some(&"101".to_string()));
+        assert_eq!(c.cache_get(&1), Some(&101));
+        assert_eq!(c.cache_get_or_set_with(2, || 200), &200);
+        assert!(!c.cache_insert_if_absent(2, 201));
+        assert_eq!(c.cache_remove(&2), Some(200));
+        assert_eq!(1, c.cache_size());
+        assert_eq!(Some(1), c.cache_hits());
+        assert_eq!(Some(2), c.cache_misses());
+    }
+}
@@ -0,0 +1,220 @@
+use std::borrow::Borrow;
+use std::cmp::Eq;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+
+use super::hasher::{self, SeedableState};
+
+/// Number of entries below which inserting never prunes dead entries
+const MIN_PRUNE_AT: usize = 16;
+
+/// Weak-value cache
+///
+/// Stores a `Weak` reference to each value, so the cache never keeps a value alive on its
+/// own: once the last `Arc` to a value outside of the cache is dropped, its entry is gone.
+/// Useful for interning, where equal values are shared for as long as something uses them:
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use cached::WeakCache;
+///
+/// let mut cache: WeakCache<String, str> = WeakCache::new();
+/// let a = cache.cache_get_or_set_with("name".to_string(), || Arc::from("name"));
+/// let b = cache.cache_get_or_set_with("name".to_string(), || unreachable!());
+/// assert!(Arc::ptr_eq(&a, &b));
+///
+/// drop((a, b));
+/// assert!(cache.cache_get("name").is_none());
+/// ```
+///
+/// Values are returned as `Arc`s upgraded from the stored references, so this store
+/// doesn't implement `Cached`, which lends references to values held by the store.
+/// Dead entries are removed when looked up, when `cache_prune` is called, and by inserts
+/// once the store doubled in size since the last prune.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct WeakCache<K, V: ?Sized> {
+    store: HashMap<K, Weak<V>, SeedableState>,
+    prune_at: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Hash + Eq, V: ?Sized> Default for WeakCache<K, V> {
+    fn default() -> WeakCache<K, V> {
+        WeakCache::new()
+    }
+}
+
+impl<K: Hash + Eq, V: ?Sized> WeakCache<K, V> {
+    /// Creates an empty `WeakCache`
+    pub fn new() -> WeakCache<K, V> {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty `WeakCache` with a given pre-allocated capacity
+    pub fn with_capacity(size: usize) -> WeakCache<K, V> {
+        WeakCache {
+            store: HashMap::with_capacity_and_hasher(size, SeedableState::new()),
+            prune_at: MIN_PRUNE_AT,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Hash keys deterministically using `seed`, see `SeedableState`
+    pub fn with_hash_seed(mut self, seed: u64) -> WeakCache<K, V> {
+        hasher::reseed(&mut self.store, seed);
+        self
+    }
+
+    /// Attempt to retrieve a value that is still alive, removing its entry if it's not
+    pub fn cache_get<Q>(&mut self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let val = match self.store.get(key) {
+            Some(weak) => match weak.upgrade() {
+                Some(val) => Some(val),
+                None => {
+                    self.store.remove(key);
+                    None
+                }
+            },
+            None => None,
+        };
+        match val {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        val
+    }
+
+    /// Insert a weak reference to `val`, returning the previous value if it was still alive
+    pub fn cache_set(&mut self, key: K, val: &Arc<V>) -> Option<Arc<V>> {
+        self.prune_if_grown();
+        self.store
+            .insert(key, Arc::downgrade(val))
+            .and_then(|old| old.upgrade())
+    }
+
+    /// Get the value of `key` if it's still alive, or insert a weak reference to the one
+    /// returned by `f`
+    pub fn cache_get_or_set_with<F: FnOnce() -> Arc<V>>(&mut self, key: K, f: F) -> Arc<V> {
+        self.prune_if_grown();
+        match self.store.entry(key) {
+            Entry::Occupied(mut occupied) => {
+                if let Some(val) = occupied.get().upgrade() {
+                    self.hits += 1;
+                    return val;
+                }
+                self.misses += 1;
+                let val = f();
+                occupied.insert(Arc::downgrade(&val));
+                val
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                let val = f();
+                vacant.insert(Arc::downgrade(&val));
+                val
+            }
+        }
+    }
+
+    /// Remove a cached value, returning it if it was still alive
+    pub fn cache_remove<Q>(&mut self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.remove(key).and_then(|weak| weak.upgrade())
+    }
+
+    /// Remove the entries of values that are no longer alive, returning how many were removed
+    pub fn cache_prune(&mut self) -> usize {
+        let before = self.store.len();
+        self.store.retain(|_, weak| weak.strong_count() > 0);
+        self.prune_at = (self.store.len() * 2).max(MIN_PRUNE_AT);
+        before - self.store.len()
+    }
+
+    /// Remove all entries
+    pub fn cache_clear(&mut self) {
+        self.store.clear();
+        self.prune_at = MIN_PRUNE_AT;
+    }
+
+    /// Return the number of entries, including those of values that are no longer alive
+    /// but weren't pruned yet
+    pub fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Return the number of times a value was found alive
+    pub fn cache_hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Return the number of times a value wasn't found, or was no longer alive
+    pub fn cache_misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn prune_if_grown(&mut self) {
+        if self.store.len() >= self.prune_at {
+            self.cache_prune();
+        }
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::sync::Arc;
+
+    use super::WeakCache;
+
+    #[test]
+    fn weak_cache() {
+        let mut c = WeakCache::new();
+        let one = Arc::new(100);
+        assert_eq!(c.cache_set(1, &one), None);
+        assert_eq!(c.cache_get(&1), Some(one.clone()));
+        assert_eq!(c.cache_get_or_set_with(1, || unreachable!()), one);
+
+        drop(one);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_hits(), 2);
+        assert_eq!(c.cache_misses(), 1);
+
+        let two = c.cache_get_or_set_with(2, || Arc::new(200));
+        assert_eq!(c.cache_remove(&2), Some(two));
+        assert_eq!(c.cache_remove(&2), None);
+    }
+
+    #[test]
+    fn weak_cache_prune() {
+        let mut c = WeakCache::new();
+        let kept: Vec<_> = (0..4).map(Arc::new).collect();
+        for (k, v) in kept.iter().enumerate() {
+            c.cache_set(k, v);
+        }
+        for k in 4..16 {
+            c.cache_set(k, &Arc::new(k));
+        }
+        assert_eq!(c.cache_size(), 16);
+        // inserting into a store that doubled in size prunes it
+        c.cache_set(16, &kept[0]);
+        assert_eq!(c.cache_size(), 5);
+        assert_eq!(c.cache_prune(), 0);
+        drop(kept);
+        assert_eq!(c.cache_prune(), 5);
+    }
+}
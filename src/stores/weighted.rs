@@ -0,0 +1,302 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::{Cached, MemSize, SizedCache};
+
+type Weigher<K, V> = dyn Fn(&K, &V) -> usize + Send + Sync;
+
+/// Cache store bound by the total weight of its values
+///
+/// Each value is weighed by a user function when it's inserted, e.g. by its size in
+/// bytes, and the least recently used values are evicted once the total weight goes
+/// over the budget:
+///
+/// ```rust
+/// use cached::{Cached, WeightedCache};
+///
+/// let mut cache = WeightedCache::new(10, |_key: &u32, value: &String| value.len());
+/// cache.cache_set(1, "four".to_string());
+/// cache.cache_set(2, "eight...".to_string());
+/// // 1 is evicted to stay within 10 bytes
+/// assert_eq!(cache.cache_get(&1), None);
+/// assert_eq!(8, cache.weight());
+/// ```
+///
+/// A value weighing more than the whole budget is kept on its own until the next
+/// insertion. Values are only weighed when they're inserted, changes made through
+/// `cache_get_mut` don't update their weight.
+pub struct WeightedCache<K, V> {
+    store: SizedCache<K, (usize, V)>,
+    weigher: Arc<Weigher<K, V>>,
+    budget: usize,
+    weight: usize,
+}
+
+impl<K: Hash + Eq, V> WeightedCache<K, V> {
+    /// Creates a new `WeightedCache` keeping the total weight of its values, as
+    /// returned by `weigher`, within `budget`
+    ///
+    /// Will panic if budget is 0
+    pub fn new<F>(budget: usize, weigher: F) -> WeightedCache<K, V>
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        if budget == 0 {
+            panic!("`budget` of `WeightedCache` must be greater than zero.");
+        }
+        let mut store = SizedCache::with_size(1);
+        // entries are evicted by weight instead
+        store.capacity = usize::MAX;
+        WeightedCache {
+            store,
+            weigher: Arc::new(weigher),
+            budget,
+            weight: 0,
+        }
+    }
+
+    /// Creates a new `WeightedCache` keeping the estimated memory used by its keys and
+    /// values within `bytes`, see `MemSize`
+    ///
+    /// Will panic if bytes is 0
+    pub fn with_memory_budget(bytes: usize) -> WeightedCache<K, V>
+    where
+        K: MemSize,
+        V: MemSize,
+    {
+        Self::new(bytes, |key: &K, value: &V| {
+            key.mem_size() + value.mem_size()
+        })
+    }
+
+    /// Return the total weight of the cached values
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Return the budget the total weight is kept within
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Change the budget, evicting the least recently used values above it
+    pub fn set_budget(&mut self, budget: usize) -> usize {
+        let old = self.budget;
+        self.budget = budget;
+        self.evict();
+        old
+    }
+
+    /// Evict the least recently used values until the total weight is within the
+    /// budget, keeping the most recently used one regardless
+    fn evict(&mut self) {
+        while self.weight > self.budget && self.store.cache_size() > 1 {
+            match self.store.evict_lru() {
+                Some((_, (weight, _))) => self.weight -= weight,
+                None => break,
+            }
+        }
+    }
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let weight = (self.weigher)(&key, &val);
+        self.weight += weight;
+        let old = self
+            .store
+            .cache_set(key, (weight, val))
+            .map(|(weight, old)| {
+                self.weight -= weight;
+                old
+            });
+        self.evict();
+        old
+    }
+}
+
+impl<K, V: Clone> Clone for WeightedCache<K, V>
+where
+    K: Clone,
+{
+    fn clone(&self) -> Self {
+        WeightedCache {
+            store: self.store.clone(),
+            weigher: self.weigher.clone(),
+            budget: self.budget,
+            weight: self.weight,
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for WeightedCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeightedCache")
+            .field("store", &self.store)
+            .field("budget", &self.budget)
+            .field("weight", &self.weight)
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq, V> Cached<K, V> for WeightedCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_get(key).map(|(_, value)| value)
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_get_mut(key).map(|(_, value)| value)
+    }
+
+    fn cache_peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.cache_peek(key).map(|(_, value)| value)
+    }
+
+    fn cache_peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .cache_peek_key_value(key)
+            .map(|(key, (_, value))| (key, value))
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.insert(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let mut inserted = false;
+        self.store.cache_get_or_set_with(key, || {
+            inserted = true;
+            (0, f())
+        });
+        // the value is now the most recently used, it's never evicted
+        if inserted {
+            let front = self.store.order.front();
+            let (key, (weight, value)) = self.store.order.get_mut(front);
+            *weight = (self.weigher)(key, value);
+            self.weight += *weight;
+            self.evict();
+        }
+        let front = self.store.order.front();
+        &mut self.store.order.get_mut(front).1 .1
+    }
+
+    fn cache_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (weight, value) = self.store.cache_remove(key)?;
+        self.weight -= weight;
+        Some(value)
+    }
+
+    fn cache_remove_where<F: FnMut(&K) -> bool>(&mut self, f: F) -> Option<usize> {
+        let removed = self.store.cache_remove_where(f);
+        self.weight = self.store.value_order().map(|(weight, _)| weight).sum();
+        removed
+    }
+
+    fn cache_retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Option<usize> {
+        let removed = self.store.cache_retain(|key, (_, value)| f(key, value));
+        self.weight = self.store.value_order().map(|(weight, _)| weight).sum();
+        removed
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.cache_clear();
+        self.weight = 0;
+    }
+
+    fn cache_reset(&mut self) {
+        self.store.cache_reset();
+        self.weight = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        self.store.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.store.cache_hits()
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.store.cache_misses()
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::mem::size_of;
+
+    use super::Cached;
+    use super::WeightedCache;
+
+    #[test]
+    fn weighted_cache() {
+        let mut c = WeightedCache::new(10, |_: &u32, v: &Vec<u8>| v.len());
+        assert_eq!(c.cache_set(1, vec![0; 4]), None);
+        assert_eq!(c.cache_set(2, vec![0; 4]), None);
+        assert_eq!(8, c.weight());
+        assert!(c.cache_get(&1).is_some());
+
+        // 2 is the least recently used
+        assert_eq!(c.cache_get_or_set_with(3, || vec![0; 5]).len(), 5);
+        assert!(c.cache_peek(&2).is_none());
+        assert_eq!(9, c.weight());
+
+        // replacing a value swaps its weight
+        assert_eq!(c.cache_set(1, vec![0; 1]).map(|v| v.len()), Some(4));
+        assert_eq!(6, c.weight());
+
+        // an oversized value is kept alone
+        c.cache_set(4, vec![0; 20]);
+        assert_eq!(1, c.cache_size());
+        assert_eq!(20, c.weight());
+        assert_eq!(c.cache_remove(&4).map(|v| v.len()), Some(20));
+        assert_eq!(0, c.weight());
+
+        c.cache_set(5, vec![0; 2]);
+        c.cache_set(6, vec![0; 3]);
+        assert_eq!(Some(1), c.cache_remove_where(|k| *k == 5));
+        assert_eq!(3, c.weight());
+        assert_eq!(10, c.set_budget(2));
+        assert_eq!(1, c.cache_size());
+    }
+
+    #[test]
+    fn weighted_cache_memory_budget() {
+        let entry = size_of::<u32>() + size_of::<String>();
+        let mut c = WeightedCache::with_memory_budget(2 * entry + 10);
+        c.cache_set(1, String::with_capacity(5));
+        c.cache_set(2, String::with_capacity(5));
+        assert_eq!(2 * entry + 10, c.weight());
+        c.cache_set(3, String::with_capacity(1));
+        // 1 is evicted to stay within the budget
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(2 * entry + 6, c.weight());
+    }
+
+    #[test]
+    #[should_panic]
+    fn weighted_cache_zero_budget() {
+        WeightedCache::<u32, u32>::new(0, |_, _| 1);
+    }
+}
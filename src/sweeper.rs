@@ -0,0 +1,142 @@
+/*!
+Background thread purging the expired values of timed caches
+
+Stores like `TimedCache` only notice that a value expired when it's accessed or when
+other values are inserted, so a long-lived process caching keys that are never looked
+up again keeps their values around. A `Sweeper` periodically calls
+`Cached::cache_flush_expired` on the caches registered with it instead:
+
+```rust
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use cached::sweeper::Sweeper;
+use cached::{Cached, TimedCache};
+
+let cache = Arc::new(Mutex::new(TimedCache::<u32, u32>::with_lifespan(60)));
+let sweeper = Sweeper::every(Duration::from_secs(10));
+sweeper.register(cache.clone());
+cache.lock().unwrap().cache_set(1, 1);
+```
+
+The thread stops when the `Sweeper` is dropped. Requires the `sweeper` feature.
+*/
+
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Cached;
+
+/// Type-erased access to a registered cache
+trait Flush: Send {
+    fn flush(&self);
+}
+
+struct Handle<K, V, H> {
+    cache: H,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V, C, H> Flush for Handle<K, V, H>
+where
+    C: Cached<K, V>,
+    H: Deref<Target = Mutex<C>> + Send,
+{
+    fn flush(&self) {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_flush_expired();
+    }
+}
+
+/// Handle to a background thread purging expired values, see the module docs
+#[derive(Debug)]
+pub struct Sweeper {
+    sender: Mutex<Sender<Box<dyn Flush>>>,
+}
+
+impl Sweeper {
+    /// Spawn a thread purging the expired values of the registered caches every `interval`
+    ///
+    /// Will panic if the thread can't be spawned
+    pub fn every(interval: Duration) -> Sweeper {
+        let (sender, receiver) = mpsc::channel::<Box<dyn Flush>>();
+        thread::Builder::new()
+            .name("cached-sweeper".to_string())
+            .spawn(move || {
+                let mut caches = Vec::new();
+                let mut deadline = Instant::now() + interval;
+                loop {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok(cache) => caches.push(cache),
+                        Err(RecvTimeoutError::Timeout) => {
+                            caches.iter().for_each(|cache| cache.flush());
+                            deadline = Instant::now() + interval;
+                        }
+                        // the `Sweeper` was dropped
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+            })
+            .expect("failed to spawn the cached sweeper thread");
+        Sweeper {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// Purge the expired values of `cache` on every sweep, for as long as the
+    /// `Sweeper` lives. `cache` is either a `&'static Mutex` or an `Arc<Mutex>`.
+    /// Stores whose values don't expire are locked but left unchanged.
+    pub fn register<K, V, C, H>(&self, cache: H)
+    where
+        K: 'static,
+        V: 'static,
+        C: Cached<K, V> + 'static,
+        H: Deref<Target = Mutex<C>> + Send + 'static,
+    {
+        let handle = Box::new(Handle {
+            cache,
+            _marker: PhantomData,
+        });
+        // the thread only stops if flushing a cache panicked, there's no one to sweep for then
+        let _ = self
+            .sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .send(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::Sweeper;
+    use crate::{Cached, TimedCache, UnboundCache};
+
+    #[test]
+    fn sweep_expired() {
+        let timed = Arc::new(Mutex::new(TimedCache::with_lifespan(60)));
+        let unbound = Arc::new(Mutex::new(UnboundCache::new()));
+        let sweeper = Sweeper::every(Duration::from_millis(10));
+        sweeper.register(timed.clone());
+        sweeper.register(unbound.clone());
+        {
+            let mut timed = timed.lock().unwrap();
+            timed.cache_set(1, 100);
+            timed.cache_set_with_lifespan(2, 200, 0);
+        }
+        unbound.lock().unwrap().cache_set(1, 100);
+        sleep(Duration::from_millis(100));
+        assert_eq!(1, timed.lock().unwrap().cache_size());
+        assert_eq!(1, unbound.lock().unwrap().cache_size());
+        drop(sweeper);
+    }
+}
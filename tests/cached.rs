@@ -1,12 +1,20 @@
 /*!
-Full tests of macro-defined functions
+Full tests of macro-defined functions, see `disable_cache.rs` for those without caching
 */
+#![cfg(not(feature = "disable_cache"))]
+// `cached_control!` tests spell out the `match` that `?` would replace
+#![allow(clippy::question_mark)]
 #[macro_use]
 extern crate cached;
 
-use cached::{proc_macro::cached, Cached, SizedCache, TimedCache, UnboundCache};
+use cached::{
+    proc_macro::{cached, io_cached, once},
+    Cached, CachedArc, NoCache, SizedCache, TimedCache, UnboundCache,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread::{self, sleep};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 cached! {
     UNBOUND_FIB;
@@ -366,3 +374,1602 @@ cached_key_result! {
         Ok(n)
     }
 }
+
+#[cached]
+fn proc_cached_impl_future(n: u32) -> impl std::future::Future<Output = Vec<u32>> {
+    async move { vec![n] }
+}
+
+#[async_std::test]
+async fn test_proc_cached_impl_future() {
+    assert_eq!(proc_cached_impl_future(1).await, vec![1]);
+    assert_eq!(proc_cached_impl_future(1).await, vec![1]);
+    assert_eq!(proc_cached_impl_future(2).await, vec![2]);
+    {
+        let cache = PROC_CACHED_IMPL_FUTURE.lock().await;
+        assert_eq!(2, cache.cache_size());
+        assert_eq!(1, cache.cache_hits().unwrap());
+        assert_eq!(2, cache.cache_misses().unwrap());
+    }
+}
+
+#[cached(result = true)]
+fn proc_cached_impl_future_result(n: u32) -> impl std::future::Future<Output = Result<u32, ()>> {
+    async move {
+        if n < 5 {
+            Ok(n)
+        } else {
+            Err(())
+        }
+    }
+}
+
+#[async_std::test]
+async fn test_proc_cached_impl_future_result() {
+    assert!(proc_cached_impl_future_result(2).await.is_ok());
+    assert!(proc_cached_impl_future_result(6).await.is_err());
+    assert!(proc_cached_impl_future_result(2).await.is_ok());
+    assert!(proc_cached_impl_future_result(6).await.is_err());
+    {
+        let cache = PROC_CACHED_IMPL_FUTURE_RESULT.lock().await;
+        assert_eq!(1, cache.cache_size());
+        assert_eq!(1, cache.cache_hits().unwrap());
+        assert_eq!(3, cache.cache_misses().unwrap());
+    }
+}
+
+#[cached]
+fn proc_cached_impl_stream(n: u32) -> impl cached::futures::Stream<Item = u32> {
+    cached::futures::stream::iter(0..n)
+}
+
+#[async_std::test]
+async fn test_proc_cached_impl_stream() {
+    use cached::futures::StreamExt;
+
+    let items: Vec<u32> = proc_cached_impl_stream(3).collect().await;
+    assert_eq!(items, vec![0, 1, 2]);
+    let items: Vec<u32> = proc_cached_impl_stream(3).collect().await;
+    assert_eq!(items, vec![0, 1, 2]);
+    let items: Vec<u32> = proc_cached_impl_stream(0).collect().await;
+    assert!(items.is_empty());
+    {
+        let cache = PROC_CACHED_IMPL_STREAM.lock().await;
+        assert_eq!(2, cache.cache_size());
+        assert_eq!(1, cache.cache_hits().unwrap());
+        assert_eq!(2, cache.cache_misses().unwrap());
+    }
+}
+
+#[cached(type = "NoCache<u32, u32>", create = "{ NoCache::new() }")]
+fn proc_no_cache(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_proc_no_cache() {
+    assert_eq!(proc_no_cache(1), 2);
+    assert_eq!(proc_no_cache(1), 2);
+    {
+        let cache = PROC_NO_CACHE.lock().unwrap();
+        assert_eq!(0, cache.cache_size());
+        assert_eq!(Some(0), cache.cache_hits());
+        assert_eq!(Some(2), cache.cache_misses());
+    }
+}
+
+#[cached(size = 2, wrap = "arc")]
+fn proc_cached_arc(n: u32) -> Vec<u32> {
+    vec![n; 100]
+}
+
+#[test]
+fn test_proc_cached_arc() {
+    let first: Arc<Vec<u32>> = proc_cached_arc(1);
+    let second = proc_cached_arc(1);
+    assert!(Arc::ptr_eq(&first, &second));
+    {
+        let mut cache = PROC_CACHED_ARC.lock().unwrap();
+        let cached = cache.cache_get_arc(&1).unwrap();
+        assert!(Arc::ptr_eq(&first, &cached));
+        assert_eq!(1, cache.cache_misses().unwrap());
+    }
+}
+
+#[cached(result = true, wrap = "arc")]
+fn proc_cached_arc_result(n: u32) -> Result<Vec<u32>, NoClone> {
+    if n < 5 {
+        Ok(vec![n])
+    } else {
+        Err(NoClone {})
+    }
+}
+
+#[test]
+fn test_proc_cached_arc_result() {
+    let first = proc_cached_arc_result(1).ok().unwrap();
+    let second = proc_cached_arc_result(1).ok().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(proc_cached_arc_result(5).is_err());
+}
+
+#[cached(time = 3600, with_ttl = true)]
+fn proc_cached_with_ttl(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_with_ttl() {
+    assert_eq!(proc_cached_with_ttl_with_ttl(1, 1), 1);
+    assert_eq!(proc_cached_with_ttl(2), 2);
+    assert_eq!(proc_cached_with_ttl(1), 1);
+    {
+        let cache = PROC_CACHED_WITH_TTL.lock().unwrap();
+        assert_eq!(1, cache.cache_hits().unwrap());
+    }
+    sleep(Duration::new(1, 0));
+    assert_eq!(proc_cached_with_ttl(1), 1);
+    assert_eq!(proc_cached_with_ttl(2), 2);
+    {
+        let cache = PROC_CACHED_WITH_TTL.lock().unwrap();
+        assert_eq!(3, cache.cache_misses().unwrap());
+        assert_eq!(2, cache.cache_hits().unwrap());
+    }
+}
+
+#[cached(time = 3600, result = true, with_ttl = true)]
+async fn proc_cached_with_ttl_async(n: u32) -> Result<u32, ()> {
+    Ok(n)
+}
+
+#[async_std::test]
+async fn test_proc_cached_with_ttl_async() {
+    assert_eq!(proc_cached_with_ttl_async_with_ttl(1, 1).await, Ok(1));
+    sleep(Duration::new(1, 0));
+    assert_eq!(proc_cached_with_ttl_async(1).await, Ok(1));
+    {
+        let cache = PROC_CACHED_WITH_TTL_ASYNC.lock().await;
+        assert_eq!(2, cache.cache_misses().unwrap());
+    }
+}
+
+#[cached(size = 2, with_age = true)]
+fn proc_cached_with_age(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_with_age() {
+    let first = proc_cached_with_age(1);
+    assert_eq!(*first, 1);
+    assert!(!first.was_cached);
+    assert_eq!(first.age, Duration::from_secs(0));
+
+    sleep(Duration::from_millis(10));
+    let second = proc_cached_with_age(1);
+    assert_eq!(second.value, 1);
+    assert!(second.was_cached);
+    assert!(second.is_stale(Duration::from_millis(5)));
+    assert!(!second.is_stale(Duration::from_secs(60)));
+}
+
+#[cached(time = 60, time_source = "wall_clock", with_age = true)]
+fn proc_cached_with_age_wall_clock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_with_age_wall_clock() {
+    proc_cached_with_age_wall_clock(1);
+    sleep(Duration::from_millis(10));
+    let second = proc_cached_with_age_wall_clock(1);
+    assert!(second.was_cached);
+    assert!(second.is_stale(Duration::from_millis(5)));
+    // the value is stamped on the clock it expires by
+    let cache = PROC_CACHED_WITH_AGE_WALL_CLOCK.lock().unwrap();
+    let (stamp, _): &(SystemTime, u32) = cache.cache_peek(&1).unwrap();
+    assert!(stamp.elapsed().unwrap() >= Duration::from_millis(10));
+}
+
+#[cached(option = true, with_age = true, wrap = "arc")]
+fn proc_cached_with_age_option(n: u32) -> Option<u32> {
+    if n > 0 {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_proc_cached_with_age_option() {
+    let first: Option<cached::Return<Arc<u32>>> = proc_cached_with_age_option(1);
+    assert!(!first.unwrap().was_cached);
+    let second = proc_cached_with_age_option(1).unwrap();
+    assert!(second.was_cached);
+    assert_eq!(*second.value, 1);
+    assert!(proc_cached_with_age_option(0).is_none());
+}
+
+#[cached(result = true, with_cached_flag = true)]
+fn proc_cached_with_cached_flag(n: u32) -> Result<u32, String> {
+    if n > 0 {
+        Ok(n)
+    } else {
+        Err("zero".to_string())
+    }
+}
+
+#[test]
+fn test_proc_cached_with_cached_flag() {
+    let first: Result<cached::Return<u32>, String> = proc_cached_with_cached_flag(1);
+    assert!(!first.unwrap().was_cached);
+    let second = proc_cached_with_cached_flag(1).unwrap();
+    assert!(second.was_cached);
+    assert_eq!(second.value, 1);
+    assert!(proc_cached_with_cached_flag(0).is_err());
+    // values are stored as is
+    let cache = PROC_CACHED_WITH_CACHED_FLAG.lock().unwrap();
+    assert_eq!(cache.cache_peek(&1), Some(&1));
+}
+
+#[test]
+fn test_cache_read_write_through() {
+    use cached::{Cache, WritePolicy};
+    use std::sync::Mutex;
+
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let sink = written.clone();
+    let cache = Arc::new(
+        Cache::new(SizedCache::with_size(2), |n: &u32| n * 10)
+            .writer(move |k: &u32, v: &u32| sink.lock().unwrap().push((*k, *v))),
+    );
+    let handle = {
+        let cache = cache.clone();
+        thread::spawn(move || cache.get(&1))
+    };
+    assert_eq!(10, handle.join().unwrap());
+    assert_eq!(10, cache.get(&1));
+    assert_eq!(Some(1), cache.store().cache_hits());
+
+    cache.put(2, 25);
+    assert_eq!(25, cache.get(&2));
+    assert_eq!(*written.lock().unwrap(), [(2, 25)]);
+
+    let cache =
+        Cache::new(UnboundCache::new(), |n: &u32| n * 10).write_policy(WritePolicy::Invalidate);
+    assert_eq!(30, cache.get(&3));
+    cache.put(3, 35);
+    assert_eq!(0, cache.store().cache_size());
+    cache.invalidate_all();
+}
+
+#[cached(time = 3600, time_source = "wall_clock")]
+fn proc_cached_wall_clock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_wall_clock() {
+    assert_eq!(1, proc_cached_wall_clock(1));
+    assert_eq!(1, proc_cached_wall_clock(1));
+    let cache = PROC_CACHED_WALL_CLOCK.lock().unwrap();
+    assert_eq!(cached::TimeSource::WallClock, cache.time_source());
+    assert_eq!(Some(1), cache.cache_hits());
+}
+
+#[cached(time = 60, jitter = 0.25)]
+fn proc_cached_jitter(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_jitter() {
+    assert_eq!(1, proc_cached_jitter(1));
+    let cache = PROC_CACHED_JITTER.lock().unwrap();
+    assert_eq!(0.25, cache.jitter());
+    assert_eq!(Some(60), cache.cache_lifespan());
+}
+
+static PROC_CACHED_EVICTED: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(
+    size = 1,
+    on_evict = "|_, value, _| { PROC_CACHED_EVICTED.fetch_add(*value as usize, Ordering::SeqCst); }"
+)]
+fn proc_cached_on_evict(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_on_evict() {
+    proc_cached_on_evict(2);
+    proc_cached_on_evict(3);
+    assert_eq!(2, PROC_CACHED_EVICTED.load(Ordering::SeqCst));
+    proc_cached_on_evict(4);
+    assert_eq!(5, PROC_CACHED_EVICTED.load(Ordering::SeqCst));
+}
+
+#[cached(size = 3)]
+fn proc_cached_info(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_info() {
+    proc_cached_info(1);
+    proc_cached_info(1);
+    proc_cached_info(2);
+    let info = proc_cached_info_cache_info();
+    assert_eq!(2, info.size);
+    assert_eq!(Some(3), info.capacity);
+    assert_eq!(Some(1), info.hits);
+    assert_eq!(Some(2), info.misses);
+    assert_eq!(None, info.lifespan);
+    assert_eq!(Some(1.0 / 3.0), info.hit_ratio());
+}
+
+#[cached(time = 60)]
+async fn proc_cached_info_async(n: u32) -> u32 {
+    n
+}
+
+#[async_std::test]
+async fn test_proc_cached_info_async() {
+    proc_cached_info_async(1).await;
+    let info = proc_cached_info_async_cache_info().await;
+    assert_eq!(1, info.size);
+    assert_eq!(Some(60), info.lifespan);
+}
+
+#[cached(sync = "rwlock")]
+fn proc_cached_rwlock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_rwlock() {
+    assert_eq!(1, proc_cached_rwlock(1));
+    assert_eq!(1, proc_cached_rwlock(1));
+    assert_eq!(2, proc_cached_rwlock(2));
+    // the hit was served under the read lock, without counting it
+    let info = proc_cached_rwlock_cache_info();
+    assert_eq!(2, info.size);
+    assert_eq!(Some(0), info.hits);
+    assert_eq!(Some(2), info.misses);
+    assert_eq!(PROC_CACHED_RWLOCK.read().unwrap().cache_peek(&1), Some(&1));
+}
+
+#[cfg(feature = "parking_lot")]
+#[cached(sync = "parking_lot")]
+fn proc_cached_parking_lot(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "parking_lot")]
+#[test]
+fn test_proc_cached_parking_lot() {
+    assert_eq!(1, proc_cached_parking_lot(1));
+    assert_eq!(1, proc_cached_parking_lot(1));
+    assert_eq!(Some(1), PROC_CACHED_PARKING_LOT.lock().cache_hits());
+}
+
+#[cfg(feature = "parking_lot")]
+#[cached(sync = "parking_lot_rwlock", sync_writes = true)]
+fn proc_cached_parking_lot_rwlock(n: u32) -> u32 {
+    if n == 0 {
+        panic!("no zero");
+    }
+    n
+}
+
+#[cfg(feature = "parking_lot")]
+#[test]
+fn test_proc_cached_parking_lot_rwlock() {
+    assert_eq!(1, proc_cached_parking_lot_rwlock(1));
+    assert_eq!(1, proc_cached_parking_lot_rwlock(1));
+    // a panic while computing under the write lock doesn't poison it
+    assert!(thread::spawn(|| proc_cached_parking_lot_rwlock(0))
+        .join()
+        .is_err());
+    assert_eq!(2, proc_cached_parking_lot_rwlock(2));
+    // the hit was served under the read lock, without counting it
+    let info = proc_cached_parking_lot_rwlock_cache_info();
+    assert_eq!(2, info.size);
+    assert_eq!(Some(0), info.hits);
+    assert_eq!(
+        PROC_CACHED_PARKING_LOT_RWLOCK.read().cache_peek(&1),
+        Some(&1)
+    );
+}
+
+#[cfg(feature = "spin")]
+#[cached(sync = "spin")]
+fn proc_cached_spin(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "spin")]
+#[test]
+fn test_proc_cached_spin() {
+    assert_eq!(1, proc_cached_spin(1));
+    assert_eq!(1, proc_cached_spin(1));
+    assert_eq!(Some(1), PROC_CACHED_SPIN.lock().cache_hits());
+}
+
+#[cfg(feature = "tokio")]
+#[cached(sync = "tokio")]
+async fn proc_cached_tokio(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_proc_cached_tokio() {
+    assert_eq!(1, proc_cached_tokio(1).await);
+    assert_eq!(1, proc_cached_tokio(1).await);
+    assert_eq!(Some(1), proc_cached_tokio_cache_info().await.hits);
+}
+
+#[cached(
+    type = "cached::HashedKeyCache<String, SizedCache<u128, usize>>",
+    create = "{ cached::HashedKeyCache::new(SizedCache::with_size(10)) }"
+)]
+fn proc_cached_hashed_key(text: String) -> usize {
+    text.len()
+}
+
+#[test]
+fn test_proc_cached_hashed_key() {
+    let text = "a long string key".repeat(10);
+    assert_eq!(170, proc_cached_hashed_key(text.clone()));
+    assert_eq!(170, proc_cached_hashed_key(text));
+    assert_eq!(Some(1), proc_cached_hashed_key_cache_info().hits);
+}
+
+#[cached(size = 10, initial = "[(0, 0), (1, 1), (10, 55)]")]
+fn proc_cached_seeded_fib(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    proc_cached_seeded_fib(n - 1) + proc_cached_seeded_fib(n - 2)
+}
+
+#[test]
+fn test_proc_cached_initial() {
+    {
+        let cache = PROC_CACHED_SEEDED_FIB.lock().unwrap();
+        assert_eq!(3, cache.cache_size());
+    }
+    assert_eq!(55, proc_cached_seeded_fib(10));
+    assert_eq!(Some(1), proc_cached_seeded_fib_cache_info().hits);
+    assert_eq!(8, proc_cached_seeded_fib(6));
+}
+
+/// Poison the lock of `cache` by panicking while holding it
+fn poison<T: Send + Sync>(cache: &'static std::sync::Mutex<T>) {
+    let _ = std::thread::spawn(move || {
+        let _cache = cache.lock().unwrap();
+        panic!("poisoning the cache");
+    })
+    .join();
+    assert!(cache.is_poisoned());
+}
+
+#[cached(on_poison = "recover")]
+fn proc_cached_poison_recover(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_poison_recover() {
+    assert_eq!(1, proc_cached_poison_recover(1));
+    poison(&PROC_CACHED_POISON_RECOVER);
+    assert_eq!(2, proc_cached_poison_recover(2));
+    let mut cache = PROC_CACHED_POISON_RECOVER.lock().unwrap();
+    // cleared when recovered
+    assert_eq!(1, cache.cache_size());
+    assert_eq!(cache.cache_get(&2), Some(&2));
+}
+
+#[cached(on_poison = "bypass")]
+fn proc_cached_poison_bypass(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_poison_bypass() {
+    poison(&PROC_CACHED_POISON_BYPASS);
+    assert_eq!(1, proc_cached_poison_bypass(1));
+    assert_eq!(1, proc_cached_poison_bypass(1));
+    assert_eq!(0, proc_cached_poison_bypass_cache_info().size);
+}
+
+#[derive(Debug, PartialEq)]
+enum PoisonError {
+    Poisoned,
+}
+
+impl From<cached::LockPoisoned> for PoisonError {
+    fn from(_: cached::LockPoisoned) -> PoisonError {
+        PoisonError::Poisoned
+    }
+}
+
+#[cached(result = true, on_poison = "error")]
+fn proc_cached_poison_error(n: u32) -> Result<u32, PoisonError> {
+    Ok(n)
+}
+
+#[test]
+fn test_proc_cached_poison_error() {
+    assert_eq!(Ok(1), proc_cached_poison_error(1));
+    poison(&PROC_CACHED_POISON_ERROR);
+    assert_eq!(Err(PoisonError::Poisoned), proc_cached_poison_error(1));
+}
+
+#[cached(sync = "rwlock", on_poison = "bypass")]
+fn proc_cached_rwlock_poison_bypass(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_rwlock_poison_bypass() {
+    assert_eq!(1, proc_cached_rwlock_poison_bypass(1));
+    assert_eq!(1, proc_cached_rwlock_poison_bypass(1));
+    assert_eq!(1, proc_cached_rwlock_poison_bypass_cache_info().size);
+}
+
+#[cached(size = 10)]
+fn proc_cached_user_page(user: u32, page: u32) -> String {
+    format!("user {} page {}", user, page)
+}
+
+#[test]
+fn test_proc_cached_evict_prefix() {
+    proc_cached_user_page(1, 1);
+    proc_cached_user_page(1, 2);
+    proc_cached_user_page(2, 1);
+    assert_eq!(Some(2), proc_cached_user_page_evict_prefix(&1));
+    assert_eq!(Some(0), proc_cached_user_page_evict_prefix(&1));
+    let cache = PROC_CACHED_USER_PAGE.lock().unwrap();
+    assert_eq!(cache.key_order().collect::<Vec<_>>(), [&(2, 1)]);
+}
+
+#[cached(size = 10, file = "path")]
+fn proc_cached_file_len(path: std::path::PathBuf) -> usize {
+    std::fs::read(&path).map_or(0, |bytes| bytes.len())
+}
+
+#[test]
+fn test_proc_cached_file() {
+    let path = std::env::temp_dir().join(format!("cached-file-len-{}", std::process::id()));
+    std::fs::write(&path, "one").unwrap();
+    assert_eq!(3, proc_cached_file_len(path.clone()));
+    assert_eq!(3, proc_cached_file_len(path.clone()));
+    assert_eq!(Some(1), proc_cached_file_len_cache_info().hits);
+    std::fs::write(&path, "three").unwrap();
+    assert_eq!(5, proc_cached_file_len(path.clone()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cached(size = 2, time = 60)]
+fn proc_cached_timed_sized(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_proc_cached_timed_sized() {
+    assert_eq!(2, proc_cached_timed_sized(1));
+    assert_eq!(4, proc_cached_timed_sized(2));
+    assert_eq!(6, proc_cached_timed_sized(3));
+    let cache = PROC_CACHED_TIMED_SIZED.lock().unwrap();
+    assert_eq!(2, cache.cache_size());
+    assert_eq!(Some(60), cache.cache_lifespan());
+    assert!(cache.cache_peek(&1).is_none());
+}
+
+struct Account {
+    id: u32,
+    balance: u64,
+}
+
+impl Account {
+    #[cached(key = "(u32, u64)", convert = "{ (self.id, rate) }")]
+    fn interest(&self, rate: u64) -> u64 {
+        self.balance * rate / 100
+    }
+
+    #[cached(
+        key = "(u32, u64)",
+        convert = "{ (self.id, months) }",
+        sync_writes = "by_key"
+    )]
+    fn projected(&self, months: u64) -> u64 {
+        self.balance + self.balance * months / 100
+    }
+}
+
+#[test]
+fn test_proc_cached_method() {
+    let a = Account {
+        id: 1,
+        balance: 200,
+    };
+    let b = Account {
+        id: 2,
+        balance: 500,
+    };
+    assert_eq!(10, a.interest(5));
+    assert_eq!(25, b.interest(5));
+    assert_eq!(10, a.interest(5));
+    let cache = Account::interest_cache().lock().unwrap();
+    assert_eq!(2, cache.cache_size());
+    assert_eq!(Some(1), cache.cache_hits());
+    drop(cache);
+    assert_eq!(Some(1), Account::interest_cache_info().hits);
+
+    assert_eq!(212, a.projected(6));
+    assert_eq!(212, a.projected(6));
+    assert_eq!(Some(1), Account::projected_cache_info().hits);
+    assert!(Account::projected_gates().is_empty());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rate(u64);
+
+impl Rate {
+    #[cached(size = 10, in_impl = "Rate")]
+    fn parse(input: String, scale: u64) -> Self {
+        Self(input.parse::<u64>().unwrap() * scale)
+    }
+}
+
+#[test]
+fn test_proc_cached_associated_fn() {
+    assert_eq!(Rate(50), Rate::parse("5".to_string(), 10));
+    assert_eq!(Rate(50), Rate::parse("5".to_string(), 10));
+    assert_eq!(Rate(5), Rate::parse_no_cache("5".to_string(), 1));
+    assert_eq!(Some(1), Rate::parse_cache_info().hits);
+    assert_eq!(Some(1), Rate::parse_evict_prefix(&"5".to_string()));
+    assert_eq!(0, Rate::parse_cache().lock().unwrap().cache_size());
+}
+
+#[derive(Clone)]
+struct Token {
+    value: String,
+    expires_in: u64,
+}
+
+#[cached(time = 3600, lifespan = "result.expires_in")]
+fn proc_cached_token(user: u32) -> Token {
+    Token {
+        value: format!("token-{}", user),
+        expires_in: if user == 1 { 1 } else { 3600 },
+    }
+}
+
+#[test]
+fn test_proc_cached_lifespan() {
+    assert_eq!("token-1", proc_cached_token(1).value);
+    assert_eq!("token-2", proc_cached_token(2).value);
+    sleep(Duration::from_secs(1));
+    let mut cache = PROC_CACHED_TOKEN.lock().unwrap();
+    assert!(cache.cache_get(&1).is_none());
+    assert_eq!(3600, cache.cache_get(&2).unwrap().expires_in);
+}
+
+#[cached(key = "String", convert = r#"{ format!("{:?}", value) }"#)]
+fn proc_cached_debug_len<T: std::fmt::Debug>(value: T) -> usize {
+    format!("{:?}", value).len()
+}
+
+#[test]
+fn test_proc_cached_generic() {
+    assert_eq!(3, proc_cached_debug_len(100));
+    assert_eq!(5, proc_cached_debug_len("abc"));
+    assert_eq!(3, proc_cached_debug_len(100u8));
+    assert_eq!(Some(1), proc_cached_debug_len_cache_info().hits);
+}
+
+static UPSTREAM_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(time = 60, result, negative_time = 1)]
+fn proc_cached_upstream(up: bool) -> Result<u32, String> {
+    UPSTREAM_CALLS.fetch_add(1, Ordering::SeqCst);
+    if up {
+        Ok(1)
+    } else {
+        Err("upstream is down".to_string())
+    }
+}
+
+#[test]
+fn test_proc_cached_negative_time() {
+    assert!(proc_cached_upstream(false).is_err());
+    assert!(proc_cached_upstream(false).is_err());
+    assert_eq!(Ok(1), proc_cached_upstream(true));
+    assert_eq!(Ok(1), proc_cached_upstream(true));
+    assert_eq!(2, UPSTREAM_CALLS.load(Ordering::SeqCst));
+    // the error expires first
+    sleep(Duration::from_secs(1));
+    assert!(proc_cached_upstream(false).is_err());
+    assert_eq!(Ok(1), proc_cached_upstream(true));
+    assert_eq!(3, UPSTREAM_CALLS.load(Ordering::SeqCst));
+}
+
+static SLOW_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(sync_writes = true)]
+fn proc_cached_slow_sync_writes(n: u32) -> u32 {
+    SLOW_CALLS.fetch_add(1, Ordering::SeqCst);
+    sleep(Duration::from_millis(100));
+    n
+}
+
+#[test]
+fn test_proc_cached_sync_writes() {
+    let threads: Vec<_> = (0..8)
+        .map(|_| thread::spawn(|| proc_cached_slow_sync_writes(1)))
+        .collect();
+    for t in threads {
+        assert_eq!(1, t.join().unwrap());
+    }
+    assert_eq!(1, SLOW_CALLS.load(Ordering::SeqCst));
+}
+
+static SLOW_ASYNC_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(sync_writes = true)]
+async fn proc_cached_slow_async_sync_writes(n: u32) -> u32 {
+    SLOW_ASYNC_CALLS.fetch_add(1, Ordering::SeqCst);
+    async_std::task::sleep(Duration::from_millis(100)).await;
+    n
+}
+
+#[async_std::test]
+async fn test_proc_cached_async_sync_writes() {
+    let tasks: Vec<_> = (0..8)
+        .map(|_| async_std::task::spawn(proc_cached_slow_async_sync_writes(1)))
+        .collect();
+    for t in tasks {
+        assert_eq!(1, t.await);
+    }
+    assert_eq!(1, SLOW_ASYNC_CALLS.load(Ordering::SeqCst));
+}
+
+static BY_KEY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(sync_writes = "by_key")]
+fn proc_cached_slow_by_key(n: u32) -> u32 {
+    BY_KEY_CALLS.fetch_add(1, Ordering::SeqCst);
+    sleep(Duration::from_millis(200));
+    n
+}
+
+#[test]
+fn test_proc_cached_sync_writes_by_key() {
+    let start = std::time::Instant::now();
+    let threads: Vec<_> = (0..8)
+        .map(|i| thread::spawn(move || proc_cached_slow_by_key(i % 2)))
+        .collect();
+    for (i, t) in threads.into_iter().enumerate() {
+        assert_eq!(i as u32 % 2, t.join().unwrap());
+    }
+    assert_eq!(2, BY_KEY_CALLS.load(Ordering::SeqCst));
+    // both keys were computed at the same time
+    assert!(start.elapsed() < Duration::from_millis(400));
+}
+
+static TTL_BY_KEY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(time = 60, with_ttl = true, sync_writes = "by_key")]
+fn proc_cached_ttl_by_key(n: u32) -> u32 {
+    TTL_BY_KEY_CALLS.fetch_add(1, Ordering::SeqCst);
+    sleep(Duration::from_millis(200));
+    n
+}
+
+#[test]
+fn test_proc_cached_with_ttl_by_key() {
+    // the function and its `_with_ttl` variant wait for each other
+    let a = thread::spawn(|| proc_cached_ttl_by_key(1));
+    let b = thread::spawn(|| proc_cached_ttl_by_key_with_ttl(1, 30));
+    assert_eq!(1, a.join().unwrap());
+    assert_eq!(1, b.join().unwrap());
+    assert_eq!(1, TTL_BY_KEY_CALLS.load(Ordering::SeqCst));
+    assert!(PROC_CACHED_TTL_BY_KEY_GATES.is_empty());
+}
+
+#[cached(sync_writes = "by_key")]
+async fn proc_cached_async_by_key(n: u32) -> u32 {
+    async_std::task::sleep(Duration::from_millis(10)).await;
+    n * 2
+}
+
+#[async_std::test]
+async fn test_proc_cached_async_sync_writes_by_key() {
+    assert_eq!(4, proc_cached_async_by_key(2).await);
+    assert_eq!(4, proc_cached_async_by_key(2).await);
+    assert_eq!(Some(1), proc_cached_async_by_key_cache_info().await.hits);
+}
+
+#[cached(time = 2, refresh = true)]
+fn proc_cached_refreshed(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_refresh() {
+    proc_cached_refreshed(1);
+    for _ in 0..3 {
+        sleep(Duration::from_millis(800));
+        proc_cached_refreshed(1);
+    }
+    let cache = PROC_CACHED_REFRESHED.lock().unwrap();
+    assert!(cache.refresh());
+    assert_eq!(Some(3), cache.cache_hits());
+}
+
+#[cached(time = 2, refresh = true, sync = "rwlock")]
+fn proc_cached_refreshed_rwlock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_refresh_rwlock() {
+    proc_cached_refreshed_rwlock(1);
+    for _ in 0..3 {
+        sleep(Duration::from_millis(800));
+        proc_cached_refreshed_rwlock(1);
+    }
+    // hits went through the write lock, refreshing the value
+    assert_eq!(
+        Some(3),
+        PROC_CACHED_REFRESHED_RWLOCK.read().unwrap().cache_hits()
+    );
+}
+
+#[cached(size = 2, sync = "rwlock")]
+fn proc_cached_sized_rwlock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_sized_rwlock() {
+    proc_cached_sized_rwlock(1);
+    proc_cached_sized_rwlock(2);
+    // the hit makes 1 the most recently used, so 2 is evicted
+    proc_cached_sized_rwlock(1);
+    proc_cached_sized_rwlock(3);
+    let cache = PROC_CACHED_SIZED_RWLOCK.read().unwrap();
+    assert_eq!(Some(1), cache.cache_hits());
+    assert!(cache.cache_peek(&1).is_some());
+    assert!(cache.cache_peek(&2).is_none());
+}
+
+#[cached(
+    type = "SizedCache<u32, u32>",
+    create = "{ SizedCache::with_size(2) }",
+    sync = "rwlock"
+)]
+fn proc_cached_custom_sized_rwlock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_custom_sized_rwlock() {
+    proc_cached_custom_sized_rwlock(1);
+    proc_cached_custom_sized_rwlock(2);
+    // custom stores take the write lock on hits too, keeping 1 the most recently used
+    proc_cached_custom_sized_rwlock(1);
+    proc_cached_custom_sized_rwlock(3);
+    let cache = PROC_CACHED_CUSTOM_SIZED_RWLOCK.read().unwrap();
+    assert_eq!(Some(1), cache.cache_hits());
+    assert!(cache.cache_peek(&1).is_some());
+    assert!(cache.cache_peek(&2).is_none());
+}
+
+#[cached(size = 2, time = 60, time_source = "wall_clock")]
+fn proc_cached_timed_sized_wall_clock(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_timed_sized_wall_clock() {
+    proc_cached_timed_sized_wall_clock(1);
+    proc_cached_timed_sized_wall_clock(1);
+    let cache = PROC_CACHED_TIMED_SIZED_WALL_CLOCK.lock().unwrap();
+    assert_eq!(cached::TimeSource::WallClock, cache.time_source());
+    assert_eq!(Some(1), cache.cache_hits());
+}
+
+static MANAGED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(result)]
+fn proc_cached_managed(a: u32, b: u32) -> Result<u32, ()> {
+    MANAGED_CALLS.fetch_add(1, Ordering::SeqCst);
+    Ok(a + b)
+}
+
+#[test]
+fn test_proc_cached_management() {
+    assert_eq!(Ok(3), proc_cached_managed_prime_cache(1, 2));
+    assert_eq!(Ok(3), proc_cached_managed(1, 2));
+    assert_eq!(1, MANAGED_CALLS.load(Ordering::SeqCst));
+    // priming recomputes
+    assert_eq!(Ok(3), proc_cached_managed_prime_cache(1, 2));
+    assert_eq!(2, MANAGED_CALLS.load(Ordering::SeqCst));
+
+    assert!(proc_cached_managed_cache_remove(1, 2));
+    assert!(!proc_cached_managed_cache_remove(1, 2));
+    assert_eq!(Ok(3), proc_cached_managed(1, 2));
+    assert_eq!(Ok(5), proc_cached_managed(2, 3));
+    proc_cached_managed_cache_clear();
+    assert_eq!(0, proc_cached_managed_cache_info().size);
+}
+
+#[cached(budget = 10, weigher = "|_, value| value.len()")]
+fn proc_cached_weighted(n: usize) -> String {
+    "x".repeat(n)
+}
+
+#[test]
+fn test_proc_cached_weighted() {
+    proc_cached_weighted(4);
+    proc_cached_weighted(5);
+    {
+        let cache = PROC_CACHED_WEIGHTED.lock().unwrap();
+        assert_eq!(2, cache.cache_size());
+        assert_eq!(9, cache.weight());
+    }
+    // 4 is the least recently used
+    proc_cached_weighted(3);
+    let cache = PROC_CACHED_WEIGHTED.lock().unwrap();
+    assert!(cache.cache_peek(&4).is_none());
+    assert_eq!(8, cache.weight());
+}
+
+#[cached(size = 2, policy = "lfu")]
+fn proc_cached_lfu(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_lfu() {
+    proc_cached_lfu(1);
+    proc_cached_lfu(1);
+    // a scan over other keys doesn't evict 1
+    for n in 2..10 {
+        proc_cached_lfu(n);
+    }
+    let cache = PROC_CACHED_LFU.lock().unwrap();
+    assert_eq!(Some(2), cache.uses(&1));
+    assert_eq!(Some(&9), cache.cache_peek(&9));
+    assert_eq!(2, cache.cache_size());
+}
+
+#[cached(size = 2, policy = "sieve")]
+fn proc_cached_sieve(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_sieve() {
+    proc_cached_sieve(1);
+    proc_cached_sieve(1);
+    proc_cached_sieve(2);
+    // 2 is evicted instead of the older 1, which was read again
+    proc_cached_sieve(3);
+    let cache = PROC_CACHED_SIEVE.lock().unwrap();
+    assert_eq!(Some(&1), cache.cache_peek(&1));
+    assert!(cache.cache_peek(&2).is_none());
+}
+
+#[derive(Clone)]
+struct ExpiringToken {
+    id: usize,
+    expires_at: std::time::Instant,
+}
+
+impl cached::CanExpire for ExpiringToken {
+    fn is_expired(&self) -> bool {
+        std::time::Instant::now() >= self.expires_at
+    }
+}
+
+static TOKEN_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(size = 10, expiring = true)]
+fn proc_cached_expiring_token(ttl: u64) -> ExpiringToken {
+    ExpiringToken {
+        id: TOKEN_CALLS.fetch_add(1, Ordering::SeqCst),
+        expires_at: std::time::Instant::now() + Duration::from_millis(ttl),
+    }
+}
+
+#[test]
+fn test_proc_cached_expiring() {
+    let token = proc_cached_expiring_token(500);
+    assert_eq!(token.id, proc_cached_expiring_token(500).id);
+    sleep(Duration::from_millis(600));
+    // the token's own expiry evicted it
+    assert_ne!(token.id, proc_cached_expiring_token(500).id);
+    assert_eq!(2, TOKEN_CALLS.load(Ordering::SeqCst));
+}
+
+#[cached(size = 16, shards = 4)]
+fn proc_cached_sharded(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_proc_cached_sharded() {
+    let handles: Vec<_> = (0..4)
+        .map(|t| thread::spawn(move || (0..8).map(|n| proc_cached_sharded(n + t)).sum::<u32>()))
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(11, proc_cached_sharded_cache_info().size);
+    let shard = PROC_CACHED_SHARDED.shard(&8).lock().unwrap();
+    assert_eq!(Some(&16), shard.cache_peek(&8));
+    drop(shard);
+    assert!(proc_cached_sharded_cache_remove(1));
+    proc_cached_sharded_cache_clear();
+    assert_eq!(0, proc_cached_sharded_cache_info().size);
+}
+
+static CONCURRENT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(concurrent = true)]
+fn proc_cached_concurrent(n: u32) -> u32 {
+    CONCURRENT_CALLS.fetch_add(1, Ordering::SeqCst);
+    n * 2
+}
+
+#[test]
+fn test_proc_cached_concurrent() {
+    let handles: Vec<_> = (0..4)
+        .map(|_| thread::spawn(|| (0..8).map(proc_cached_concurrent).sum::<u32>()))
+        .collect();
+    for handle in handles {
+        assert_eq!(56, handle.join().unwrap());
+    }
+    assert_eq!(8, proc_cached_concurrent_cache_info().size);
+    assert_eq!(Some(6), PROC_CACHED_CONCURRENT.get(&3));
+    // hits are served from the shards without calling the function
+    let calls = CONCURRENT_CALLS.load(Ordering::SeqCst);
+    proc_cached_concurrent(3);
+    assert_eq!(calls, CONCURRENT_CALLS.load(Ordering::SeqCst));
+    proc_cached_concurrent_cache_clear();
+    assert_eq!(0, proc_cached_concurrent_cache_info().size);
+}
+
+#[cached(key_args = "user_id, page")]
+fn proc_cached_key_args(user_id: u32, calls: &AtomicUsize, page: u32) -> u32 {
+    calls.fetch_add(1, Ordering::SeqCst);
+    user_id * 100 + page
+}
+
+#[test]
+fn test_proc_cached_key_args() {
+    let calls = AtomicUsize::new(0);
+    assert_eq!(101, proc_cached_key_args(1, &calls, 1));
+    assert_eq!(102, proc_cached_key_args(1, &calls, 2));
+    // the counter isn't part of the key
+    assert_eq!(101, proc_cached_key_args(1, &AtomicUsize::new(0), 1));
+    assert_eq!(2, calls.load(Ordering::SeqCst));
+    {
+        let cache = PROC_CACHED_KEY_ARGS.lock().unwrap();
+        assert_eq!(Some(&102), cache.cache_peek(&(1, 2)));
+    }
+    assert_eq!(Some(2), proc_cached_key_args_evict_prefix(&1));
+}
+
+#[cached(size = 10, hash_key = true)]
+fn proc_cached_hash_key(text: String, words: Vec<String>) -> usize {
+    text.len() + words.len()
+}
+
+#[test]
+fn test_proc_cached_hash_key() {
+    let words = vec!["a".to_string(), "b".to_string()];
+    assert_eq!(7, proc_cached_hash_key("hello".to_string(), words.clone()));
+    assert_eq!(7, proc_cached_hash_key("hello".to_string(), words.clone()));
+    assert_eq!(5, proc_cached_hash_key("hello".to_string(), vec![]));
+    let cache = PROC_CACHED_HASH_KEY.lock().unwrap();
+    assert_eq!(2, cache.cache_size());
+    assert_eq!(Some(1), cache.cache_hits());
+    let key = cached::hash_key(&(&"hello".to_string(), &words));
+    assert_eq!(Some(&7), cache.cache_peek(&key));
+}
+
+/// Store failing while `down` is set, standing in for a remote one
+struct FlakyStore {
+    values: std::sync::Mutex<std::collections::HashMap<u32, u32>>,
+    down: std::sync::atomic::AtomicBool,
+}
+
+impl FlakyStore {
+    fn new() -> FlakyStore {
+        FlakyStore {
+            values: Default::default(),
+            down: Default::default(),
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.down.load(Ordering::SeqCst) {
+            Err("store is down".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl cached::IOCached<u32, u32> for FlakyStore {
+    type Error = String;
+
+    fn cache_get(&self, k: &u32) -> Result<Option<u32>, String> {
+        self.check()?;
+        Ok(self.values.lock().unwrap().get(k).copied())
+    }
+
+    fn cache_set(&self, k: u32, v: u32) -> Result<Option<u32>, String> {
+        self.check()?;
+        Ok(self.values.lock().unwrap().insert(k, v))
+    }
+
+    fn cache_remove(&self, k: &u32) -> Result<Option<u32>, String> {
+        self.check()?;
+        Ok(self.values.lock().unwrap().remove(k))
+    }
+}
+
+impl cached::IOCachedAsync<u32, u32> for FlakyStore {
+    type Error = String;
+
+    async fn cache_get(&self, k: &u32) -> Result<Option<u32>, String> {
+        cached::IOCached::cache_get(self, k)
+    }
+
+    async fn cache_set(&self, k: u32, v: u32) -> Result<Option<u32>, String> {
+        cached::IOCached::cache_set(self, k, v)
+    }
+
+    async fn cache_remove(&self, k: &u32) -> Result<Option<u32>, String> {
+        cached::IOCached::cache_remove(self, k)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum IOError {
+    Store(String),
+    Zero,
+}
+
+#[io_cached(
+    type = "FlakyStore",
+    create = "FlakyStore::new()",
+    map_error = r##"|e| IOError::Store(e)"##
+)]
+fn proc_io_cached(n: u32) -> Result<u32, IOError> {
+    if n == 0 {
+        return Err(IOError::Zero);
+    }
+    Ok(n * 2)
+}
+
+#[test]
+fn test_proc_io_cached() {
+    assert_eq!(Ok(2), proc_io_cached(1));
+    assert_eq!(Err(IOError::Zero), proc_io_cached(0));
+    assert_eq!(
+        Some(2),
+        PROC_IO_CACHED.values.lock().unwrap().get(&1).copied()
+    );
+    PROC_IO_CACHED.down.store(true, Ordering::SeqCst);
+    assert_eq!(
+        Err(IOError::Store("store is down".to_string())),
+        proc_io_cached(1)
+    );
+}
+
+#[io_cached(
+    type = "FlakyStore",
+    create = "FlakyStore::new()",
+    convert = "{ n }",
+    map_error = r##"|e| IOError::Store(e)"##,
+    with_cached_flag = true
+)]
+async fn proc_io_cached_async(n: u32) -> Result<u32, IOError> {
+    Ok(n * 2)
+}
+
+#[async_std::test]
+async fn test_proc_io_cached_async() {
+    let first = proc_io_cached_async(1).await.unwrap();
+    assert!(!first.was_cached);
+    let second = proc_io_cached_async(1).await.unwrap();
+    assert!(second.was_cached);
+    assert_eq!(2, second.value);
+}
+
+#[io_cached(
+    type = "cached::TieredCache<SizedCache<u32, u32>, FlakyStore>",
+    create = "cached::TieredCache::new(SizedCache::with_size(10), FlakyStore::new())",
+    convert = "{ n }",
+    map_error = r##"|e| IOError::Store(e)"##
+)]
+fn proc_io_cached_tiered(n: u32) -> Result<u32, IOError> {
+    Ok(n * 2)
+}
+
+#[test]
+fn test_proc_io_cached_tiered() {
+    assert_eq!(Ok(2), proc_io_cached_tiered(1));
+    let back = PROC_IO_CACHED_TIERED.back();
+    assert_eq!(Some(2), back.values.lock().unwrap().get(&1).copied());
+    // hits are served by the front store while the back store is down
+    back.down.store(true, Ordering::SeqCst);
+    assert_eq!(Ok(2), proc_io_cached_tiered(1));
+    assert_eq!(Some(1), PROC_IO_CACHED_TIERED.front().cache_hits());
+    assert!(proc_io_cached_tiered(2).is_err());
+}
+
+static FLAKY_STORES_CREATED: AtomicUsize = AtomicUsize::new(0);
+
+async fn connect_flaky_store() -> FlakyStore {
+    FLAKY_STORES_CREATED.fetch_add(1, Ordering::SeqCst);
+    FlakyStore::new()
+}
+
+#[io_cached(
+    type = "FlakyStore",
+    create = "connect_flaky_store()",
+    create_async = true,
+    map_error = r##"|e| IOError::Store(e)"##
+)]
+async fn proc_io_cached_create_async(n: u32) -> Result<u32, IOError> {
+    Ok(n * 2)
+}
+
+#[async_std::test]
+async fn test_proc_io_cached_create_async() {
+    assert!(PROC_IO_CACHED_CREATE_ASYNC.get().is_none());
+    let store = proc_io_cached_create_async_init_cache().await;
+    assert_eq!(FLAKY_STORES_CREATED.load(Ordering::SeqCst), 1);
+    assert_eq!(Ok(2), proc_io_cached_create_async(1).await);
+    assert_eq!(Ok(2), proc_io_cached_create_async(1).await);
+    assert_eq!(Some(2), store.values.lock().unwrap().get(&1).copied());
+    assert_eq!(FLAKY_STORES_CREATED.load(Ordering::SeqCst), 1);
+}
+
+#[cached(size = 10, key_args = "tpl, items")]
+fn proc_cached_render<'a, F>(tpl: &'a str, items: &[u32], sep: F) -> String
+where
+    F: Fn() -> &'a str,
+{
+    let items = items.iter().map(u32::to_string).collect::<Vec<_>>();
+    format!("{}: {}", tpl, items.join(sep()))
+}
+
+#[test]
+fn test_proc_cached_reference_args() {
+    let tpl = String::from("nums");
+    assert_eq!(proc_cached_render(&tpl, &[1, 2], || ","), "nums: 1,2");
+    assert_eq!(proc_cached_render("nums", &[1, 2], || ","), "nums: 1,2");
+    assert_eq!(proc_cached_render("nums", &[1], || ","), "nums: 1");
+    let cache = PROC_CACHED_RENDER.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.cache_misses(), Some(2));
+}
+
+#[cached(size = 10, thread_local = true)]
+fn proc_cached_thread_local(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    proc_cached_thread_local(n - 1) + proc_cached_thread_local(n - 2)
+}
+
+#[test]
+fn test_proc_cached_thread_local() {
+    assert_eq!(proc_cached_thread_local(10), 55);
+    assert_eq!(
+        PROC_CACHED_THREAD_LOCAL.with(|c| c.borrow().cache_size()),
+        10
+    );
+    // other threads start with an empty cache of their own
+    std::thread::spawn(|| {
+        assert_eq!(proc_cached_thread_local_cache_info().size, 0);
+        assert_eq!(proc_cached_thread_local(3), 2);
+        assert_eq!(proc_cached_thread_local_cache_info().size, 4);
+    })
+    .join()
+    .unwrap();
+    assert!(proc_cached_thread_local_cache_remove(10));
+    proc_cached_thread_local_cache_clear();
+    assert_eq!(proc_cached_thread_local_cache_info().size, 0);
+}
+
+static BYPASS_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+struct BypassRequest {
+    id: u32,
+    no_cache: bool,
+}
+
+#[cached(
+    size = 10,
+    key = "u32",
+    convert = "{ req.id }",
+    bypass = "req.no_cache"
+)]
+fn proc_cached_bypass(req: &BypassRequest) -> usize {
+    BYPASS_CALLS.fetch_add(1, Ordering::SeqCst) + req.id as usize
+}
+
+#[test]
+fn test_proc_cached_bypass() {
+    let cached = BypassRequest {
+        id: 10,
+        no_cache: false,
+    };
+    let fresh = BypassRequest {
+        id: 10,
+        no_cache: true,
+    };
+    assert_eq!(proc_cached_bypass(&cached), 10);
+    assert_eq!(proc_cached_bypass(&cached), 10);
+    // skips the lookup, and caches the new value
+    assert_eq!(proc_cached_bypass(&fresh), 11);
+    assert_eq!(proc_cached_bypass(&cached), 11);
+    let cache = PROC_CACHED_BYPASS.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(2));
+    assert_eq!(cache.cache_misses(), Some(1));
+}
+
+#[cached(size = 10, option = true, cache_if = "|hits| !hits.is_empty()")]
+fn proc_cached_cache_if(query: u32) -> Option<Vec<u32>> {
+    Some((1..query).filter(|n| query.is_multiple_of(*n)).collect())
+}
+
+#[test]
+fn test_proc_cached_cache_if() {
+    assert_eq!(proc_cached_cache_if(1), Some(vec![]));
+    assert_eq!(proc_cached_cache_if(6), Some(vec![1, 2, 3]));
+    let cache = PROC_CACHED_CACHE_IF.lock().unwrap();
+    assert_eq!(cache.cache_peek(&1), None);
+    assert_eq!(cache.cache_peek(&6), Some(&vec![1, 2, 3]));
+}
+
+type FixedState = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+#[cached(hasher = "FixedState")]
+fn proc_cached_hasher_unbound(n: u32) -> u32 {
+    n * 2
+}
+
+#[cached(size = 2, hasher = "FixedState")]
+fn proc_cached_hasher_sized(n: u32) -> u32 {
+    n * 3
+}
+
+#[cached(time = 60, refresh = true, hasher = "FixedState")]
+fn proc_cached_hasher_timed(n: u32) -> u32 {
+    n * 4
+}
+
+#[test]
+fn test_proc_cached_hasher() {
+    assert_eq!(proc_cached_hasher_unbound(1), 2);
+    assert_eq!(proc_cached_hasher_unbound(1), 2);
+    let cache: &UnboundCache<u32, u32, FixedState> = &PROC_CACHED_HASHER_UNBOUND.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+
+    assert_eq!(proc_cached_hasher_sized(1), 3);
+    assert_eq!(proc_cached_hasher_sized(2), 6);
+    assert_eq!(proc_cached_hasher_sized(3), 9);
+    let cache: &SizedCache<u32, u32, FixedState> = &PROC_CACHED_HASHER_SIZED.lock().unwrap();
+    assert_eq!(cache.key_order().copied().collect::<Vec<_>>(), [3, 2]);
+
+    assert_eq!(proc_cached_hasher_timed(1), 4);
+    assert_eq!(proc_cached_hasher_timed(1), 4);
+    let cache: &TimedCache<u32, u32, FixedState> = &PROC_CACHED_HASHER_TIMED.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert!(cache.refresh());
+}
+
+fn proc_cached_configured_size() -> usize {
+    std::env::var("CACHED_TEST_CONFIGURED_SIZE").map_or(2, |size| size.parse().unwrap())
+}
+
+#[cached(size = "proc_cached_configured_size()")]
+fn proc_cached_configured(n: u32) -> u32 {
+    n
+}
+
+#[cached(time = "60 * 60")]
+fn proc_cached_configured_time(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_proc_cached_configured() {
+    for n in 0..3 {
+        proc_cached_configured(n);
+    }
+    let cache = PROC_CACHED_CONFIGURED.lock().unwrap();
+    assert_eq!(cache.cache_capacity(), Some(2));
+    assert_eq!(cache.cache_size(), 2);
+
+    proc_cached_configured_time(1);
+    assert_eq!(
+        PROC_CACHED_CONFIGURED_TIME.lock().unwrap().cache_lifespan(),
+        Some(3600)
+    );
+}
+
+static NO_CACHE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(size = 2, wrap = "arc")]
+fn proc_cached_no_cache(n: u32) -> u32 {
+    NO_CACHE_CALLS.fetch_add(1, Ordering::SeqCst);
+    n * 2
+}
+
+#[test]
+fn test_proc_cached_no_cache() {
+    assert_eq!(proc_cached_no_cache(1), Arc::new(2));
+    assert_eq!(proc_cached_no_cache(1), Arc::new(2));
+    assert_eq!(NO_CACHE_CALLS.load(Ordering::SeqCst), 1);
+    let value: u32 = proc_cached_no_cache_no_cache(1);
+    assert_eq!(value, 2);
+    assert_eq!(proc_cached_no_cache_no_cache(3), 6);
+    assert_eq!(NO_CACHE_CALLS.load(Ordering::SeqCst), 3);
+    let cache = PROC_CACHED_NO_CACHE.lock().unwrap();
+    assert_eq!(cache.cache_size(), 1);
+    assert_eq!(cache.cache_hits(), Some(1));
+}
+
+#[cached(size = 2)]
+fn proc_cached_with_ref(n: usize) -> Vec<usize> {
+    vec![n; n]
+}
+
+#[test]
+fn test_proc_cached_with_ref() {
+    proc_cached_with_ref(3);
+    assert_eq!(
+        Some(3),
+        proc_cached_with_ref_with_ref(3, |value| value.len())
+    );
+    assert_eq!(None, proc_cached_with_ref_with_ref(4, |value| value.len()));
+    let cache = PROC_CACHED_WITH_REF.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.cache_misses(), Some(2));
+}
+
+#[cached(wrap = "arc", thread_local = true)]
+fn proc_cached_with_ref_local(n: usize) -> Vec<usize> {
+    vec![n; n]
+}
+
+#[test]
+fn test_proc_cached_with_ref_local() {
+    proc_cached_with_ref_local(2);
+    // values are passed as stored, in their `Arc`
+    assert_eq!(
+        Some(2),
+        proc_cached_with_ref_local_with_ref(2, |value: &Arc<Vec<usize>>| value.len())
+    );
+}
+
+#[cached]
+async fn proc_cached_with_ref_async(n: usize) -> Vec<usize> {
+    vec![n; n]
+}
+
+#[async_std::test]
+async fn test_proc_cached_with_ref_async() {
+    proc_cached_with_ref_async(2).await;
+    assert_eq!(
+        Some(vec![2, 2]),
+        proc_cached_with_ref_async_with_ref(2, |value| value.clone()).await
+    );
+}
+
+static ONCE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[once(time = 1)]
+fn proc_once() -> Vec<usize> {
+    vec![ONCE_CALLS.fetch_add(1, Ordering::SeqCst)]
+}
+
+#[test]
+fn test_proc_once() {
+    assert_eq!(proc_once(), vec![0]);
+    assert_eq!(proc_once(), vec![0]);
+    sleep(Duration::new(1, 0));
+    assert_eq!(proc_once(), vec![1]);
+    *PROC_ONCE.write().unwrap() = None;
+    assert_eq!(proc_once(), vec![2]);
+}
+
+#[once(result = true, sync_writes = true)]
+fn proc_once_result(fail: bool) -> Result<u32, String> {
+    if fail {
+        return Err("failed".to_string());
+    }
+    Ok(1)
+}
+
+#[test]
+fn test_proc_once_result() {
+    assert!(proc_once_result(true).is_err());
+    assert!(PROC_ONCE_RESULT.get().is_none());
+    assert_eq!(proc_once_result(false), Ok(1));
+    // the value is held regardless of the arguments
+    assert_eq!(proc_once_result(true), Ok(1));
+}
+
+static ONCE_PLAIN_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[once]
+fn proc_once_plain(n: u32) -> u32 {
+    ONCE_PLAIN_CALLS.fetch_add(1, Ordering::SeqCst);
+    n
+}
+
+#[once(option = true, sync_writes = true)]
+fn proc_once_option(n: u32) -> Option<u32> {
+    Some(n).filter(|&n| n > 0)
+}
+
+#[test]
+fn test_proc_once_cell() {
+    assert_eq!(proc_once_plain(1), 1);
+    assert_eq!(proc_once_plain(2), 1);
+    assert_eq!(1, ONCE_PLAIN_CALLS.load(Ordering::SeqCst));
+    assert_eq!(PROC_ONCE_PLAIN.get(), Some(&1));
+
+    assert_eq!(proc_once_option(0), None);
+    assert!(PROC_ONCE_OPTION.get().is_none());
+    assert_eq!(proc_once_option(2), Some(2));
+    assert_eq!(proc_once_option(3), Some(2));
+}
+
+#[once(option = true)]
+async fn proc_once_async(n: u32) -> Option<u32> {
+    Some(n)
+}
+
+#[async_std::test]
+async fn test_proc_once_async() {
+    assert_eq!(proc_once_async(1).await, Some(1));
+    assert_eq!(proc_once_async(2).await, Some(1));
+    assert_eq!(*PROC_ONCE_ASYNC.lock().await, Some(1));
+}
+
+#[cfg(feature = "serde")]
+#[cached(size = 10, preload = "target/test_proc_cached_preload.json")]
+fn proc_cached_preload(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_proc_cached_preload() {
+    let path = "target/test_proc_cached_preload.json";
+    let mut snapshot = SizedCache::with_size(10);
+    snapshot.cache_set(1, 100);
+    cached::dump(&snapshot, path).unwrap();
+
+    assert_eq!(proc_cached_preload(1), 100);
+    assert_eq!(proc_cached_preload(2), 2);
+    std::fs::remove_file(path).unwrap();
+    let cache = PROC_CACHED_PRELOAD.lock().unwrap();
+    assert_eq!(cache.cache_size(), 2);
+    assert_eq!(cache.cache_hits(), Some(1));
+}
@@ -0,0 +1,126 @@
+/*!
+Tests of `#[cached]` functions with the `disable_cache` feature enabled
+*/
+#![cfg(feature = "disable_cache")]
+
+use cached::proc_macro::{cached, io_cached};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(size = 10)]
+fn counted(n: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    n
+}
+
+#[test]
+fn test_disabled_cache_always_calls() {
+    assert_eq!(counted(1), 1);
+    assert_eq!(counted(1), 1);
+    assert_eq!(2, CALLS.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_disabled_cache_info() {
+    assert_eq!(counted_cache_info(), cached::CacheInfo::default());
+}
+
+#[test]
+fn test_disabled_cache_static() {
+    use cached::Cached;
+    counted(3);
+    assert_eq!(0, COUNTED.lock().unwrap().cache_size());
+}
+
+#[test]
+fn test_disabled_cache_management() {
+    assert_eq!(counted_prime_cache(2), 2);
+    assert!(!counted_cache_remove(2));
+    counted_cache_clear();
+}
+
+#[cached(size = 10, with_age = true)]
+fn with_age(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_disabled_cache_with_age() {
+    let value = with_age(1);
+    assert_eq!(*value, 1);
+    assert!(!value.was_cached);
+    assert_eq!(value.age, std::time::Duration::from_secs(0));
+    assert!(!with_age(1).was_cached);
+    assert!(!with_age_prime_cache(1).was_cached);
+}
+
+#[cached(size = 10, wrap = "arc")]
+fn wrap_arc(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_disabled_cache_wrap_arc() {
+    let value: std::sync::Arc<u32> = wrap_arc(1);
+    assert_eq!(*value, 1);
+    assert!(!std::sync::Arc::ptr_eq(&value, &wrap_arc(1)));
+}
+
+#[cached(size = 10, result = true, with_cached_flag = true)]
+fn with_cached_flag(n: u32) -> Result<u32, ()> {
+    Ok(n)
+}
+
+#[test]
+fn test_disabled_cache_with_cached_flag() {
+    let value: cached::Return<u32> = with_cached_flag(1).unwrap();
+    assert_eq!(*value, 1);
+    assert!(!value.was_cached);
+    assert!(!with_cached_flag(1).unwrap().was_cached);
+}
+
+#[cached(time = 10, with_ttl = true)]
+fn with_ttl(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_disabled_cache_with_ttl() {
+    assert_eq!(with_ttl_with_ttl(1, 60), 1);
+    assert_eq!(with_ttl(1), 1);
+}
+
+#[io_cached(
+    type = "std::collections::HashMap<u32, u32>",
+    create = "std::collections::HashMap::new()",
+    map_error = r##"|e| e"##,
+    with_cached_flag = true
+)]
+fn io_with_cached_flag(n: u32) -> Result<u32, String> {
+    Ok(n)
+}
+
+#[test]
+fn test_disabled_io_cached_with_cached_flag() {
+    let value: cached::Return<u32> = io_with_cached_flag(1).unwrap();
+    assert_eq!(*value, 1);
+    assert!(!io_with_cached_flag(1).unwrap().was_cached);
+}
+
+#[io_cached(
+    type = "std::collections::HashMap<u32, u32>",
+    create = "async { std::collections::HashMap::new() }",
+    map_error = r##"|e| e"##,
+    create_async = true
+)]
+async fn io_create_async(n: u32) -> Result<u32, String> {
+    Ok(n)
+}
+
+#[async_std::test]
+async fn test_disabled_io_cached_create_async() {
+    assert!(io_create_async_init_cache().await.is_empty());
+    assert_eq!(io_create_async(1).await, Ok(1));
+    assert!(IO_WITH_CACHED_FLAG.is_empty());
+}
@@ -0,0 +1,130 @@
+/*!
+Tests of the on-disk store
+*/
+#![cfg(all(feature = "disk_store", not(feature = "disable_cache")))]
+
+use std::thread;
+use std::time::Duration;
+
+use cached::proc_macro::io_cached;
+use cached::{DiskCache, DiskCacheError, IOCached};
+
+#[test]
+fn disk_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache: DiskCache<String, Vec<u32>> = DiskCache::open(dir.path()).unwrap();
+    assert!(cache.is_empty());
+    assert_eq!(cache.cache_get(&"a".to_string()).unwrap(), None);
+    assert_eq!(cache.cache_set("a".to_string(), vec![1]).unwrap(), None);
+    assert_eq!(
+        cache.cache_set("a".to_string(), vec![1, 2]).unwrap(),
+        Some(vec![1])
+    );
+    assert_eq!(cache.cache_get(&"a".to_string()).unwrap(), Some(vec![1, 2]));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(
+        cache.cache_remove(&"a".to_string()).unwrap(),
+        Some(vec![1, 2])
+    );
+    assert_eq!(cache.cache_get(&"a".to_string()).unwrap(), None);
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn disk_cache_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let cache: DiskCache<u32, String> = DiskCache::open(dir.path()).unwrap();
+        cache.cache_set(1, "one".to_string()).unwrap();
+        cache.cache_set(2, "two".to_string()).unwrap();
+        cache.cache_remove(&2).unwrap();
+    }
+    let cache: DiskCache<u32, String> = DiskCache::open(dir.path()).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.cache_get(&1).unwrap(), Some("one".to_string()));
+    assert_eq!(cache.cache_get(&2).unwrap(), None);
+}
+
+#[test]
+fn disk_cache_lifespan() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let cache: DiskCache<u32, u32> = DiskCache::open(dir.path()).unwrap().with_lifespan(1);
+        assert_eq!(cache.cache_lifespan(), Some(1));
+        cache.cache_set(1, 1).unwrap();
+    }
+    // the lifespan is kept with each value, reopening without one doesn't extend it
+    let cache: DiskCache<u32, u32> = DiskCache::open(dir.path()).unwrap();
+    cache.cache_set(2, 2).unwrap();
+    assert_eq!(cache.cache_get(&1).unwrap(), Some(1));
+    thread::sleep(Duration::from_millis(2100));
+    assert_eq!(cache.cache_get(&1).unwrap(), None);
+    assert_eq!(cache.cache_get(&2).unwrap(), Some(2));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn disk_cache_max_size() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let cache: DiskCache<u32, u32> = DiskCache::open(dir.path()).unwrap().with_max_size(3);
+        for i in 0..5 {
+            cache.cache_set(i, i).unwrap();
+        }
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.cache_get(&1).unwrap(), None);
+        // setting a value again makes it the most recent
+        cache.cache_set(2, 20).unwrap();
+        cache.cache_set(5, 5).unwrap();
+        assert_eq!(cache.cache_get(&3).unwrap(), None);
+        assert_eq!(cache.cache_get(&2).unwrap(), Some(20));
+    }
+    // a smaller size evicts the oldest values left by the previous run
+    let cache: DiskCache<u32, u32> = DiskCache::open(dir.path()).unwrap().with_max_size(1);
+    assert_eq!(cache.len(), 3);
+    cache.cache_set(6, 6).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.cache_get(&6).unwrap(), Some(6));
+    assert_eq!(cache.cache_get(&5).unwrap(), None);
+}
+
+#[test]
+fn disk_cache_undecodable() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let cache: DiskCache<u32, String> = DiskCache::open(dir.path()).unwrap();
+        cache.cache_set(1, "one".to_string()).unwrap();
+    }
+    let numbers: DiskCache<u32, u32> = DiskCache::open(dir.path()).unwrap();
+    assert!(matches!(
+        numbers.cache_get(&1),
+        Err(DiskCacheError::Json(_))
+    ));
+}
+
+static DIR: cached::once_cell::sync::Lazy<tempfile::TempDir> =
+    cached::once_cell::sync::Lazy::new(|| tempfile::tempdir().unwrap());
+
+#[derive(Debug, PartialEq)]
+enum Error {
+    Cache(String),
+}
+
+#[io_cached(
+    type = "DiskCache<u32, String>",
+    create = r#"{ DiskCache::open(DIR.path()).unwrap().with_lifespan(60) }"#,
+    map_error = r#"|e| Error::Cache(format!("{}", e))"#
+)]
+fn io_cached_disk(id: u32) -> Result<String, Error> {
+    Ok(format!("user {}", id))
+}
+
+#[test]
+fn test_io_cached_disk() {
+    assert_eq!(io_cached_disk(7), Ok("user 7".to_string()));
+    assert_eq!(io_cached_disk(7), Ok("user 7".to_string()));
+    assert_eq!(
+        IO_CACHED_DISK.cache_get(&7).unwrap(),
+        Some("user 7".to_string())
+    );
+}
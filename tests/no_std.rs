@@ -0,0 +1,29 @@
+/*!
+Tests of `#[cached(sync = "spin")]` functions in a `no_std` crate, run with
+`cargo test --no-default-features --features spin --test no_std`
+*/
+#![no_std]
+#![cfg(all(feature = "spin", not(feature = "std")))]
+
+use cached::proc_macro::cached;
+use cached::{ArrayCache, Cached};
+
+#[cached(
+    type = "ArrayCache<u32, u32, 2>",
+    create = "{ ArrayCache::new() }",
+    sync = "spin"
+)]
+fn spin_double(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_spin_double() {
+    assert_eq!(2, spin_double(1));
+    assert_eq!(2, spin_double(1));
+    assert_eq!(4, spin_double(2));
+    assert_eq!(6, spin_double(3));
+    let cache = SPIN_DOUBLE.lock();
+    assert_eq!(Some(1), cache.cache_hits());
+    assert_eq!(2, cache.cache_size());
+}
@@ -0,0 +1,573 @@
+/*!
+Tests of the Redis stores, against a server speaking enough of the Redis protocol for them
+*/
+#![cfg(all(feature = "redis", not(feature = "disable_cache")))]
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cached::proc_macro::io_cached;
+use cached::registry::{self, Health};
+#[cfg(feature = "redis_tls")]
+use cached::RedisTls;
+#[cfg(feature = "redis_async")]
+use cached::{AsyncRedisCache, IOCachedAsync};
+use cached::{
+    Cached, IOCached, RedisCache, RedisCacheError, RedisInvalidator, SizedCache, TieredCache,
+};
+
+/// State of a `FakeRedis`
+#[derive(Default)]
+struct Store {
+    /// Values with their expiry
+    values: HashMap<String, (String, Option<Instant>)>,
+    /// Patterns subscribed to, with the channel of their connection's messages
+    subscribers: Vec<(String, mpsc::Sender<String>)>,
+    /// Number of connections accepted
+    connections: usize,
+}
+
+type Data = Arc<Mutex<Store>>;
+
+/// In-process Redis server handling the commands the stores send
+struct FakeRedis {
+    url: String,
+    data: Data,
+}
+
+impl FakeRedis {
+    fn start() -> FakeRedis {
+        FakeRedis::start_on(TcpListener::bind("127.0.0.1:0").unwrap())
+    }
+
+    fn start_on(listener: TcpListener) -> FakeRedis {
+        let url = format!("redis://{}/", listener.local_addr().unwrap());
+        let data = Data::default();
+        let server_data = data.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let data = server_data.clone();
+                data.lock().unwrap().connections += 1;
+                thread::spawn(move || serve(stream.unwrap(), data));
+            }
+        });
+        FakeRedis { url, data }
+    }
+
+    /// Start a server accepting TLS connections with the certificate of `tests/tls`,
+    /// requiring clients to authenticate with a certificate signed by its CA
+    #[cfg(feature = "redis_tls")]
+    fn start_tls() -> FakeRedis {
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use rustls::server::WebPkiClientVerifier;
+        use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+        let certs = |pem: &[u8]| {
+            rustls_pemfile::certs(&mut &*pem)
+                .collect::<Result<Vec<CertificateDer>, _>>()
+                .unwrap()
+        };
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(certs(CA));
+        let key: PrivateKeyDer = rustls_pemfile::private_key(&mut &*SERVER_KEY)
+            .unwrap()
+            .unwrap();
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_client_cert_verifier(
+                    WebPkiClientVerifier::builder(Arc::new(roots))
+                        .build()
+                        .unwrap(),
+                )
+                .with_single_cert(certs(SERVER_CERT), key)
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("rediss://{}/", listener.local_addr().unwrap());
+        let data = Data::default();
+        let server_data = data.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let data = server_data.clone();
+                let tls = ServerConnection::new(config.clone()).unwrap();
+                thread::spawn(move || serve(StreamOwned::new(tls, stream.unwrap()), data));
+            }
+        });
+        FakeRedis { url, data }
+    }
+
+    /// Return the value stored under `key` and its remaining lifespan
+    fn get(&self, key: &str) -> Option<(String, Option<Duration>)> {
+        let data = self.data.lock().unwrap();
+        let (value, expiry) = data.values.get(key)?;
+        Some((
+            value.clone(),
+            expiry.map(|at| at.saturating_duration_since(Instant::now())),
+        ))
+    }
+
+    /// Return the number of connections accepted
+    #[cfg(feature = "redis_async")]
+    fn connections(&self) -> usize {
+        self.data.lock().unwrap().connections
+    }
+}
+
+/// Reply to the commands sent on `stream` until it's closed
+fn serve(stream: impl Read + Write, data: Data) {
+    let mut reader = BufReader::new(stream);
+    let mut queued: Option<Vec<Vec<String>>> = None;
+    while let Ok(command) = read_command(&mut reader) {
+        let reply = match (command[0].to_uppercase().as_str(), &mut queued) {
+            ("MULTI", _) => {
+                queued = Some(Vec::new());
+                "+OK\r\n".to_string()
+            }
+            ("PSUBSCRIBE", None) => {
+                let (sender, messages) = mpsc::channel();
+                let pattern = command[1].clone();
+                data.lock()
+                    .unwrap()
+                    .subscribers
+                    .push((pattern.clone(), sender));
+                let reply = format!(
+                    "*3\r\n{}{}:1\r\n",
+                    bulk(Some("psubscribe")),
+                    bulk(Some(&pattern))
+                );
+                // a subscribed connection only receives messages
+                let writer = reader.get_mut();
+                for message in std::iter::once(reply).chain(messages) {
+                    if writer.write_all(message.as_bytes()).is_err() || writer.flush().is_err() {
+                        return;
+                    }
+                }
+                return;
+            }
+            ("EXEC", _) => {
+                let commands = queued.take().unwrap();
+                let replies: String = commands.iter().map(|c| execute(c, &data)).collect();
+                format!("*{}\r\n{}", commands.len(), replies)
+            }
+            (_, Some(commands)) => {
+                commands.push(command);
+                "+QUEUED\r\n".to_string()
+            }
+            (_, None) => execute(&command, &data),
+        };
+        let writer = reader.get_mut();
+        if writer.write_all(reply.as_bytes()).is_err() || writer.flush().is_err() {
+            return;
+        }
+    }
+}
+
+/// Read a command sent as an array of bulk strings
+fn read_command(reader: &mut impl BufRead) -> io::Result<Vec<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let count: usize = line.trim_end()[1..].parse().unwrap();
+    (0..count)
+        .map(|_| {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let len: usize = line.trim_end()[1..].parse().unwrap();
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg)?;
+            arg.truncate(len);
+            Ok(String::from_utf8(arg).unwrap())
+        })
+        .collect()
+}
+
+fn bulk(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+        None => "$-1\r\n".to_string(),
+    }
+}
+
+/// Publish the keyspace notification of `event` on `key` to the matching subscribers
+fn notify(store: &Store, key: &str, event: &str) {
+    let channel = format!("__keyspace@0__:{}", key);
+    for (pattern, sender) in &store.subscribers {
+        // the stores subscribe to an escaped prefix followed by `*`
+        let prefix = pattern.trim_end_matches('*').replace('\\', "");
+        if channel.starts_with(&prefix) {
+            let _ = sender.send(format!(
+                "*4\r\n{}{}{}{}",
+                bulk(Some("pmessage")),
+                bulk(Some(pattern)),
+                bulk(Some(&channel)),
+                bulk(Some(event))
+            ));
+        }
+    }
+}
+
+/// Run `command` on `data` and return its encoded reply
+fn execute(command: &[String], data: &Data) -> String {
+    let mut data = data.lock().unwrap();
+    let now = Instant::now();
+    data.values
+        .retain(|_, (_, expiry)| !matches!(expiry, Some(at) if *at <= now));
+    match command[0].to_uppercase().as_str() {
+        "GET" => bulk(
+            data.values
+                .get(&command[1])
+                .map(|(value, _)| value.as_str()),
+        ),
+        "SET" => {
+            data.values
+                .insert(command[1].clone(), (command[2].clone(), None));
+            notify(&data, &command[1], "set");
+            "+OK\r\n".to_string()
+        }
+        "SETEX" => {
+            let lifespan = Duration::from_secs(command[2].parse().unwrap());
+            data.values.insert(
+                command[1].clone(),
+                (command[3].clone(), Some(now + lifespan)),
+            );
+            notify(&data, &command[1], "set");
+            "+OK\r\n".to_string()
+        }
+        "DEL" => {
+            let removed = data.values.remove(&command[1]).is_some();
+            if removed {
+                notify(&data, &command[1], "del");
+            }
+            format!(":{}\r\n", removed as u8)
+        }
+        "PING" => "+PONG\r\n".to_string(),
+        // connection setup, e.g. `CLIENT SETINFO`
+        _ => "+OK\r\n".to_string(),
+    }
+}
+
+#[test]
+fn redis_cache() {
+    let server = FakeRedis::start();
+    let cache: RedisCache<u32, Vec<String>> = RedisCache::open(server.url.as_str(), "test:")
+        .unwrap()
+        .with_lifespan(60);
+    assert_eq!(cache.cache_lifespan(), Some(60));
+    assert_eq!(cache.cache_get(&1).unwrap(), None);
+    assert_eq!(cache.cache_set(1, vec!["one".to_string()]).unwrap(), None);
+    assert_eq!(cache.cache_get(&1).unwrap(), Some(vec!["one".to_string()]));
+
+    // values are stored as JSON, expiring with SETEX
+    let (value, lifespan) = server.get("test:1").unwrap();
+    assert_eq!(value, r#"["one"]"#);
+    assert!(lifespan.unwrap() > Duration::from_secs(59));
+
+    assert_eq!(
+        cache.cache_set(1, vec!["uno".to_string()]).unwrap(),
+        Some(vec!["one".to_string()])
+    );
+    assert_eq!(
+        cache.cache_remove(&1).unwrap(),
+        Some(vec!["uno".to_string()])
+    );
+    assert_eq!(cache.cache_get(&1).unwrap(), None);
+    assert!(server.get("test:1").is_none());
+}
+
+#[test]
+fn redis_cache_without_lifespan() {
+    let server = FakeRedis::start();
+    let cache: RedisCache<&str, u32> = RedisCache::open(server.url.as_str(), "").unwrap();
+    cache.cache_set("answer", 42).unwrap();
+    assert_eq!(server.get("answer"), Some(("42".to_string(), None)));
+}
+
+#[test]
+fn redis_cache_undecodable() {
+    let server = FakeRedis::start();
+    let cache: RedisCache<u32, String> = RedisCache::open(server.url.as_str(), "").unwrap();
+    let numbers: RedisCache<u32, u32> = RedisCache::open(server.url.as_str(), "").unwrap();
+    cache.cache_set(1, "one".to_string()).unwrap();
+    assert!(matches!(
+        numbers.cache_get(&1),
+        Err(RedisCacheError::Json(_))
+    ));
+}
+
+#[test]
+fn redis_cache_unreachable() {
+    // nothing listens on the port of a dropped listener
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let cache: RedisCache<u32, u32> =
+        RedisCache::open(format!("redis://{}/", addr).as_str(), "").unwrap();
+    assert!(matches!(
+        cache.cache_get(&1),
+        Err(RedisCacheError::Redis(_))
+    ));
+}
+
+#[test]
+fn redis_cache_health() {
+    let server = FakeRedis::start();
+    let cache: Arc<RedisCache<u32, u32>> =
+        Arc::new(RedisCache::open(server.url.as_str(), "").unwrap());
+    assert_eq!(cache.health(), Health::Up);
+    let check = cache.clone();
+    registry::register_health("redis_cache_health", move || check.health());
+    assert_eq!(registry::health("redis_cache_health"), Some(Health::Up));
+    assert!(registry::unregister("redis_cache_health"));
+}
+
+#[test]
+fn redis_cache_reconnect() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let cache: RedisCache<u32, u32> = RedisCache::open(format!("redis://{}/", addr).as_str(), "")
+        .unwrap()
+        .with_backoff(Duration::from_millis(300), Duration::from_secs(1));
+    assert_eq!(cache.health(), Health::Down);
+
+    // the server comes up, but the cache waits for its backoff before reconnecting
+    let _server = FakeRedis::start_on(TcpListener::bind(addr).unwrap());
+    let failed = Instant::now();
+    assert!(cache.cache_set(1, 1).is_err());
+    assert_eq!(cache.health(), Health::Down);
+    while failed.elapsed() < Duration::from_millis(400) {
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(cache.health(), Health::Up);
+    assert_eq!(cache.cache_set(1, 1).unwrap(), None);
+}
+
+#[test]
+fn redis_invalidator() {
+    let server = FakeRedis::start();
+    let open =
+        || -> RedisCache<u32, String> { RedisCache::open(server.url.as_str(), "shared:").unwrap() };
+    let tiered = Arc::new(TieredCache::new(SizedCache::with_size(10), open()));
+    let _invalidator = RedisInvalidator::spawn(tiered.clone()).unwrap();
+    tiered.cache_set(1, "one".to_string()).unwrap();
+    assert_eq!(tiered.cache_get(&1).unwrap(), Some("one".to_string()));
+
+    // another instance changes the value behind the front store
+    let other = open();
+    other.cache_set(1, "uno".to_string()).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while tiered.front().cache_get(&1).is_some() {
+        assert!(Instant::now() < deadline, "the front copy was not evicted");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(tiered.cache_get(&1).unwrap(), Some("uno".to_string()));
+
+    other.cache_remove(&1).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while tiered.front().cache_get(&1).is_some() {
+        assert!(Instant::now() < deadline, "the front copy was not evicted");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(tiered.cache_get(&1).unwrap(), None);
+}
+
+#[test]
+fn redis_invalidator_unreachable() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let redis: RedisCache<u32, u32> =
+        RedisCache::open(format!("redis://{}/", addr).as_str(), "").unwrap();
+    let tiered = Arc::new(TieredCache::new(SizedCache::with_size(10), redis));
+    assert!(RedisInvalidator::spawn(tiered).is_err());
+}
+
+static SERVER: cached::once_cell::sync::Lazy<FakeRedis> =
+    cached::once_cell::sync::Lazy::new(FakeRedis::start);
+
+#[derive(Debug, PartialEq)]
+enum Error {
+    Cache(String),
+}
+
+#[io_cached(
+    type = "RedisCache<u32, String>",
+    create = r#"{ RedisCache::open(SERVER.url.as_str(), "names:").unwrap().with_lifespan(60) }"#,
+    map_error = r#"|e| Error::Cache(format!("{}", e))"#
+)]
+fn io_cached_redis(id: u32) -> Result<String, Error> {
+    Ok(format!("user {}", id))
+}
+
+#[test]
+fn test_io_cached_redis() {
+    assert_eq!(io_cached_redis(7), Ok("user 7".to_string()));
+    assert_eq!(
+        SERVER.get("names:7").map(|(value, _)| value),
+        Some(r#""user 7""#.to_string())
+    );
+    assert_eq!(io_cached_redis(7), Ok("user 7".to_string()));
+}
+
+#[cfg(feature = "redis_tls")]
+const CA: &[u8] = include_bytes!("tls/ca.pem");
+#[cfg(feature = "redis_tls")]
+const SERVER_CERT: &[u8] = include_bytes!("tls/server.pem");
+#[cfg(feature = "redis_tls")]
+const SERVER_KEY: &[u8] = include_bytes!("tls/server.key");
+#[cfg(feature = "redis_tls")]
+const CLIENT_CERT: &[u8] = include_bytes!("tls/client.pem");
+#[cfg(feature = "redis_tls")]
+const CLIENT_KEY: &[u8] = include_bytes!("tls/client.key");
+
+#[cfg(feature = "redis_tls")]
+#[test]
+fn redis_cache_tls() {
+    let server = FakeRedis::start_tls();
+    let tls = RedisTls::new()
+        .with_root_cert(CA)
+        .with_client_auth(CLIENT_CERT, CLIENT_KEY);
+    let cache: RedisCache<u32, u32> =
+        RedisCache::open_tls(server.url.as_str(), "tls:", tls).unwrap();
+    assert_eq!(cache.cache_set(1, 100).unwrap(), None);
+    assert_eq!(cache.cache_get(&1).unwrap(), Some(100));
+    assert_eq!(server.get("tls:1"), Some(("100".to_string(), None)));
+}
+
+#[cfg(feature = "redis_tls")]
+#[test]
+fn redis_cache_tls_rejected() {
+    let server = FakeRedis::start_tls();
+    // the server requires a client certificate
+    let tls = RedisTls::new().with_root_cert(CA);
+    let cache: RedisCache<u32, u32> = RedisCache::open_tls(server.url.as_str(), "", tls).unwrap();
+    assert!(matches!(
+        cache.cache_get(&1),
+        Err(RedisCacheError::Redis(_))
+    ));
+    // the server's certificate isn't signed by a system root
+    let tls = RedisTls::new().with_client_auth(CLIENT_CERT, CLIENT_KEY);
+    let cache: RedisCache<u32, u32> = RedisCache::open_tls(server.url.as_str(), "", tls).unwrap();
+    assert!(matches!(
+        cache.cache_get(&1),
+        Err(RedisCacheError::Redis(_))
+    ));
+}
+
+#[cfg(feature = "redis_tls")]
+#[test]
+fn redis_cache_tls_plain_url() {
+    let tls = RedisTls::new().with_root_cert(CA);
+    assert!(RedisCache::<u32, u32>::open_tls("redis://127.0.0.1/", "", tls).is_err());
+}
+
+#[cfg(feature = "redis_async")]
+#[tokio::test]
+async fn async_redis_cache() {
+    let server = FakeRedis::start();
+    let cache: AsyncRedisCache<u32, Vec<String>> =
+        AsyncRedisCache::open(server.url.as_str(), "async:")
+            .await
+            .unwrap()
+            .with_lifespan(60);
+    assert_eq!(cache.cache_lifespan(), Some(60));
+    assert_eq!(cache.cache_get(&1).await.unwrap(), None);
+    assert_eq!(
+        cache.cache_set(1, vec!["one".to_string()]).await.unwrap(),
+        None
+    );
+    assert_eq!(
+        cache.cache_get(&1).await.unwrap(),
+        Some(vec!["one".to_string()])
+    );
+    let (value, lifespan) = server.get("async:1").unwrap();
+    assert_eq!(value, r#"["one"]"#);
+    assert!(lifespan.unwrap() > Duration::from_secs(59));
+    assert_eq!(
+        cache.cache_remove(&1).await.unwrap(),
+        Some(vec!["one".to_string()])
+    );
+    assert!(server.get("async:1").is_none());
+    assert_eq!(cache.health().await, Health::Up);
+}
+
+#[cfg(feature = "redis_async")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn async_redis_cache_pool() {
+    let server = FakeRedis::start();
+    let cache: Arc<AsyncRedisCache<u32, u32>> = Arc::new(
+        AsyncRedisCache::open_with_pool(server.url.as_str(), "", 4, Duration::from_secs(5))
+            .await
+            .unwrap(),
+    );
+    // connections are opened on demand
+    assert_eq!(cache.connections(), 0);
+    let tasks: Vec<_> = (0..32)
+        .map(|i| {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.cache_set(i, i * 2).await.unwrap() })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), None);
+    }
+    for i in 0..32 {
+        assert_eq!(cache.cache_get(&i).await.unwrap(), Some(i * 2));
+    }
+    // the calls shared the pool's connections instead of connecting each time
+    assert!((1..=4).contains(&cache.connections()));
+    assert!((1..=4).contains(&server.connections()));
+}
+
+#[cfg(feature = "redis_async")]
+#[tokio::test]
+async fn async_redis_cache_unreachable() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let cache: AsyncRedisCache<u32, u32> = AsyncRedisCache::open_with_pool(
+        format!("redis://{}/", addr).as_str(),
+        "",
+        4,
+        Duration::from_millis(100),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        cache.cache_get(&1).await,
+        Err(RedisCacheError::Redis(_))
+    ));
+    assert_eq!(cache.health().await, Health::Down);
+}
+
+#[cfg(feature = "redis_async")]
+#[io_cached(
+    type = "AsyncRedisCache<u32, String>",
+    create = r#"async { AsyncRedisCache::open(SERVER.url.as_str(), "async_names:").await.unwrap() }"#,
+    create_async = true,
+    map_error = r#"|e| Error::Cache(format!("{}", e))"#
+)]
+async fn io_cached_async_redis(id: u32) -> Result<String, Error> {
+    Ok(format!("user {}", id))
+}
+
+#[cfg(feature = "redis_async")]
+#[tokio::test]
+async fn test_io_cached_async_redis() {
+    assert_eq!(io_cached_async_redis(7).await, Ok("user 7".to_string()));
+    assert_eq!(
+        SERVER.get("async_names:7").map(|(value, _)| value),
+        Some(r#""user 7""#.to_string())
+    );
+    assert_eq!(io_cached_async_redis(7).await, Ok("user 7".to_string()));
+}
@@ -0,0 +1,88 @@
+/*!
+Tests of `#[cached]` functions with the `tracing` feature enabled
+*/
+#![cfg(all(feature = "tracing", not(feature = "disable_cache")))]
+
+use cached::proc_macro::cached;
+use cached::tracing::field::{Field, Visit};
+use cached::tracing::span::{Attributes, Id, Record};
+use cached::tracing::{subscriber, Event, Metadata, Subscriber};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Subscriber collecting the messages and keys of events
+#[derive(Clone, Default)]
+struct Events(Arc<Mutex<Vec<String>>>);
+
+struct Fields(String, String);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.0 = format!("{:?}", value),
+            "key" => self.1 = format!("{:?}", value),
+            _ => (),
+        }
+    }
+}
+
+impl Subscriber for Events {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields(String::new(), String::new());
+        event.record(&mut fields);
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", fields.0, fields.1));
+    }
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[cached(size = 10, trace_key = true)]
+fn traced(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_traced_hits_and_misses() {
+    let events = Events::default();
+    subscriber::with_default(events.clone(), || {
+        traced(1);
+        traced(1);
+    });
+    assert_eq!(
+        *events.0.lock().unwrap(),
+        ["cache miss 1", "computed missing value 1", "cache hit 1"]
+    );
+}
+
+/// Key without a `Debug` implementation
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Opaque(u32);
+
+#[cached(size = 10)]
+fn traced_opaque(id: Opaque) -> u32 {
+    id.0
+}
+
+#[test]
+fn test_traced_without_key() {
+    let events = Events::default();
+    subscriber::with_default(events.clone(), || {
+        traced_opaque(Opaque(1));
+        traced_opaque(Opaque(1));
+    });
+    assert_eq!(
+        *events.0.lock().unwrap(),
+        ["cache miss ", "computed missing value ", "cache hit "]
+    );
+}